@@ -1,3 +1,3 @@
-//\! Request Handlers
+//! Request Handlers
 
 // TODO: Implement handlers
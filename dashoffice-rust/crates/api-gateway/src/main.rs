@@ -1,13 +1,13 @@
-//\! # API Gateway
-//\! 
-//\! REST API principal de DashOffice
+//! # API Gateway
+//! 
+//! REST API principal de DashOffice
 
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
 use actix_cors::Cors;
 use sqlx::PgPool;
 use redis::Client as RedisClient;
 use std::sync::Arc;
-use tracing::{info, error};
+use tracing::info;
 
 mod routes;
 mod handlers;
@@ -31,8 +31,8 @@ async fn main() -> std::io::Result<()> {
     // Load config
     dotenvy::dotenv().ok();
 
-    info\!("🦀 Starting DashOffice API Gateway");
-    info\!("📊 Version: {}", shared::VERSION);
+    info!("🦀 Starting DashOffice API Gateway");
+    info!("📊 Version: {}", shared::VERSION);
 
     // Database
     let database_url = std::env::var("DATABASE_URL")
@@ -42,7 +42,7 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to connect to database");
 
-    info\!("✅ Database connected");
+    info!("✅ Database connected");
 
     // Redis
     let redis_url = std::env::var("REDIS_URL")
@@ -51,7 +51,7 @@ async fn main() -> std::io::Result<()> {
     let redis = RedisClient::open(redis_url)
         .expect("Failed to connect to Redis");
 
-    info\!("✅ Redis connected");
+    info!("✅ Redis connected");
 
     let state = AppState {
         db: db.clone(),
@@ -66,7 +66,7 @@ async fn main() -> std::io::Result<()> {
         .parse::<u16>()
         .expect("Invalid PORT");
 
-    info\!("🚀 Starting server on {}:{}", host, port);
+    info!("🚀 Starting server on {}:{}", host, port);
 
     HttpServer::new(move || {
         let cors = Cors::permissive();
@@ -77,6 +77,13 @@ async fn main() -> std::io::Result<()> {
             .service(
                 web::scope("/api")
                     .route("/health", web::get().to(health_check))
+                    .configure(routes::analytics::config)
+                    .configure(routes::bots::config)
+                    .configure(routes::customers::config)
+                    .configure(routes::orders::config)
+                    .configure(routes::products::config)
+                    .configure(routes::sellers::config)
+                    .configure(routes::users::config)
             )
             .route("/health", web::get().to(health_check))
     })
@@ -87,7 +94,7 @@ async fn main() -> std::io::Result<()> {
 }
 
 async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json\!({
+    HttpResponse::Ok().json(serde_json::json!({
         "status": "ok",
         "version": shared::VERSION,
         "timestamp": chrono::Utc::now().timestamp(),
@@ -1,3 +1,3 @@
-//\! Middleware
+//! Middleware
 
 // TODO: Implement middleware (auth, logging, etc.)
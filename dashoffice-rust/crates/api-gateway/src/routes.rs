@@ -1,7 +1,8 @@
-//\! API Routes
+//! API Routes
 
 pub mod analytics;
 pub mod bots;
+pub mod customers;
 pub mod orders;
 pub mod products;
 pub mod sellers;
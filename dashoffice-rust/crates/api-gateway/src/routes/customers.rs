@@ -0,0 +1,104 @@
+//! Customers Routes
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use shared::Id;
+
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/customers")
+            .route("/{id}", web::patch().to(update))
+    );
+}
+
+/// Campos editables; los ausentes no se tocan
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateCustomerRequest {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub address: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub notes: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct Customer {
+    id: Id,
+    phone_number: String,
+    name: Option<String>,
+    email: Option<String>,
+    address: Option<String>,
+    city: Option<String>,
+    country: Option<String>,
+    notes: Option<String>,
+    metadata: Option<serde_json::Value>,
+}
+
+/// Actualizar un cliente (la usa la acción `update_customer` de bot-orchestrator)
+///
+/// `metadata` se mezcla con la existente en lugar de reemplazarla.
+async fn update(
+    state: web::Data<AppState>,
+    id: web::Path<Id>,
+    fields: web::Json<UpdateCustomerRequest>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let fields = fields.into_inner();
+
+    let updated = sqlx::query_as::<_, Customer>(
+        r#"
+        UPDATE customers SET
+            name = COALESCE($2, name),
+            email = COALESCE($3, email),
+            address = COALESCE($4, address),
+            city = COALESCE($5, city),
+            country = COALESCE($6, country),
+            notes = COALESCE($7, notes),
+            metadata = COALESCE(metadata, '{}'::jsonb) || COALESCE($8, '{}'::jsonb),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, phone_number, name, email, address, city, country, notes, metadata
+        "#,
+    )
+    .bind(id)
+    .bind(fields.name)
+    .bind(fields.email)
+    .bind(fields.address)
+    .bind(fields.city)
+    .bind(fields.country)
+    .bind(fields.notes)
+    .bind(fields.metadata)
+    .fetch_optional(&state.db)
+    .await;
+
+    match updated {
+        Ok(Some(customer)) => {
+            tracing::info!("👤 Customer {} updated", id);
+            HttpResponse::Ok().json(customer)
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({ "error": format!("Customer {} not found", id) })),
+        Err(e) => {
+            tracing::error!("❌ Failed to update customer {}: {}", id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to update customer" }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_request_rejects_unknown_fields() {
+        let fields: UpdateCustomerRequest = serde_json::from_str(r#"{ "name": "Ana", "city": "Caracas" }"#).unwrap();
+        assert_eq!(fields.name.as_deref(), Some("Ana"));
+        assert!(fields.email.is_none());
+
+        assert!(serde_json::from_str::<UpdateCustomerRequest>(r#"{ "phone_number": "+58" }"#).is_err());
+    }
+}
@@ -1,11 +1,16 @@
 //! Orders Routes
 
 use actix_web::{web, HttpResponse, Responder};
+use shared::{CreateOrderRequest, Id, OrderItemRequest};
+use std::collections::HashMap;
+
+use crate::AppState;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/orders")
             .route("", web::get().to(list))
+            .route("", web::post().to(create))
     );
 }
 
@@ -15,3 +20,186 @@ async fn list() -> impl Responder {
         "total": 0
     }))
 }
+
+/// Línea de la orden con el precio vigente del producto
+#[derive(Debug, Clone, PartialEq)]
+struct PricedItem {
+    product_id: Id,
+    quantity: i32,
+    unit_price: f64,
+    subtotal: f64,
+}
+
+/// Crear una orden (la usa la acción `create_order` de bot-orchestrator)
+///
+/// Los precios salen de `products` (solo activos), nunca del request.
+/// Responde `201 { id, order_number, total }`.
+async fn create(
+    state: web::Data<AppState>,
+    order: web::Json<CreateOrderRequest>,
+) -> HttpResponse {
+    let order = order.into_inner();
+
+    match create_order(&state, &order).await {
+        Ok(Ok(created)) => HttpResponse::Created().json(created),
+        Ok(Err(reason)) => HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": reason })),
+        Err(e) => {
+            tracing::error!("❌ Failed to create order for customer {}: {}", order.customer_id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Failed to create order" }))
+        }
+    }
+}
+
+/// `Ok(Err(motivo))` si la orden no es válida (cliente o productos desconocidos)
+async fn create_order(
+    state: &AppState,
+    order: &CreateOrderRequest,
+) -> Result<Result<serde_json::Value, String>, sqlx::Error> {
+    let product_ids: Vec<Id> = order.items.iter().map(|item| item.product_id).collect();
+
+    let mut tx = state.db.begin().await?;
+
+    let customer_exists = sqlx::query("SELECT 1 FROM customers WHERE id = $1")
+        .bind(order.customer_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .is_some();
+    if !customer_exists {
+        return Ok(Err(format!("Unknown customer {}", order.customer_id)));
+    }
+
+    let prices: HashMap<Id, f64> = sqlx::query_as::<_, (Id, f64)>(
+        "SELECT id, price::float8 FROM products WHERE id = ANY($1) AND is_active = true",
+    )
+    .bind(&product_ids)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+
+    let (items, total) = match price_items(&order.items, &prices) {
+        Ok(priced) => priced,
+        Err(reason) => return Ok(Err(reason)),
+    };
+
+    let order_id = Id::new_v4();
+    let order_number = order_number(order_id);
+
+    sqlx::query(
+        r#"
+        INSERT INTO orders (id, order_number, customer_id, status, total, subtotal, shipping_address)
+        VALUES ($1, $2, $3, 'pending', $4, $4, $5)
+        "#,
+    )
+    .bind(order_id)
+    .bind(&order_number)
+    .bind(order.customer_id)
+    .bind(total)
+    .bind(&order.shipping_address)
+    .execute(&mut *tx)
+    .await?;
+
+    for item in &items {
+        sqlx::query(
+            r#"
+            INSERT INTO order_items (order_id, product_id, quantity, unit_price, subtotal)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(order_id)
+        .bind(item.product_id)
+        .bind(item.quantity)
+        .bind(item.unit_price)
+        .bind(item.subtotal)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    tracing::info!("🛒 Order {} created ({} items, total {:.2})", order_number, items.len(), total);
+
+    Ok(Ok(serde_json::json!({
+        "id": order_id,
+        "order_number": order_number,
+        "total": total,
+    })))
+}
+
+/// Calcular subtotales y total con los precios de `prices`
+fn price_items(
+    items: &[OrderItemRequest],
+    prices: &HashMap<Id, f64>,
+) -> Result<(Vec<PricedItem>, f64), String> {
+    if items.is_empty() {
+        return Err("An order needs at least one item".to_string());
+    }
+
+    let items = items.iter()
+        .map(|item| {
+            if item.quantity <= 0 {
+                return Err(format!("Invalid quantity {} for product {}", item.quantity, item.product_id));
+            }
+            let unit_price = *prices.get(&item.product_id)
+                .ok_or_else(|| format!("Unknown or inactive product {}", item.product_id))?;
+
+            Ok(PricedItem {
+                product_id: item.product_id,
+                quantity: item.quantity,
+                unit_price,
+                subtotal: round_cents(unit_price * item.quantity as f64),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let total = round_cents(items.iter().map(|item| item.subtotal).sum());
+    Ok((items, total))
+}
+
+fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// `ORD-20260315-1A2B3C4D`: fecha más el inicio del id de la orden
+fn order_number(order_id: Id) -> String {
+    let suffix = order_id.simple().to_string()[..8].to_uppercase();
+    format!("ORD-{}-{}", chrono::Utc::now().format("%Y%m%d"), suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(product_id: Id, quantity: i32) -> OrderItemRequest {
+        OrderItemRequest { product_id, quantity }
+    }
+
+    #[test]
+    fn test_price_items_uses_catalog_prices() {
+        let pan = Id::new_v4();
+        let torta = Id::new_v4();
+        let prices = HashMap::from([(pan, 0.35), (torta, 12.5)]);
+
+        let (items, total) = price_items(&[item(pan, 3), item(torta, 1)], &prices).unwrap();
+        assert_eq!(items[0].subtotal, 1.05);
+        assert_eq!(items[1].unit_price, 12.5);
+        assert_eq!(total, 13.55);
+    }
+
+    #[test]
+    fn test_price_items_rejects_invalid_orders() {
+        let pan = Id::new_v4();
+        let prices = HashMap::from([(pan, 0.35)]);
+
+        assert!(price_items(&[], &prices).is_err());
+        assert!(price_items(&[item(pan, 0)], &prices).is_err());
+        assert!(price_items(&[item(Id::new_v4(), 1)], &prices).unwrap_err().contains("Unknown"));
+    }
+
+    #[test]
+    fn test_order_number_format() {
+        let number = order_number(Id::new_v4());
+        assert!(number.starts_with("ORD-"));
+        assert_eq!(number.len(), "ORD-20260315-1A2B3C4D".len());
+    }
+}
@@ -1,26 +1,20 @@
-//\! Tests de API Gateway
-//\! Tests end-to-end de todos los endpoints
-
-use actix_web::{test, App, web};
+//! Tests de API Gateway
+//! Tests end-to-end de todos los endpoints
 
 #[cfg(test)]
 mod health_tests {
-    use super::*;
-    
     #[actix_web::test]
     async fn test_health_endpoint() {
         // TODO: Implementar cuando tengamos el handler
         // let app = test::init_service(App::new().route("/health", web::get().to(health_check))).await;
         // let req = test::TestRequest::get().uri("/health").to_request();
         // let resp = test::call_service(&app, req).await;
-        // assert\!(resp.status().is_success());
+        // assert!(resp.status().is_success());
     }
 }
 
 #[cfg(test)]
 mod auth_tests {
-    use super::*;
-    
     #[actix_web::test]
     async fn test_login_success() {
         // Test de login exitoso
@@ -42,8 +36,6 @@ mod auth_tests {
 
 #[cfg(test)]
 mod bots_tests {
-    use super::*;
-    
     #[actix_web::test]
     async fn test_list_bots() {
         // Test de listar bots
@@ -71,8 +63,6 @@ mod bots_tests {
 
 #[cfg(test)]
 mod rate_limiting_tests {
-    use super::*;
-    
     #[actix_web::test]
     async fn test_rate_limit_exceeded() {
         // Test de rate limiting
@@ -88,15 +78,15 @@ mod validation_tests {
     #[test]
     fn test_email_validation() {
         // Test de validación de email
-        assert\!(validate_email("test@example.com"));
-        assert\!(\!validate_email("invalid-email"));
+        assert!(validate_email("test@example.com"));
+        assert!(!validate_email("invalid-email"));
     }
     
     #[test]
     fn test_phone_validation() {
         // Test de validación de teléfono
-        assert\!(validate_phone("+1234567890"));
-        assert\!(\!validate_phone("123"));
+        assert!(validate_phone("+1234567890"));
+        assert!(!validate_phone("123"));
     }
 }
 
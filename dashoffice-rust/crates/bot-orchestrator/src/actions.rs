@@ -0,0 +1,466 @@
+//! Actions - Ejecutores de `FlowStep::Action`
+//!
+//! Cada `ActionType` se resuelve a un `ActionHandler` registrado en el
+//! `ActionRegistry`. Handlers incluidos:
+//! - `api_call`: HTTP con URL/body con plantillas y mapeo de la respuesta al
+//!   contexto (los valores en la URL se codifican, ver `render_url`)
+//! - `send_email`: envío a través de email-service
+//! - `create_order`: arma un `shared::CreateOrderRequest` desde el contexto
+//! - `update_customer`: actualiza el cliente en el API gateway
+//!
+//! `database_query` no tiene handler por defecto: los flows los editan personas
+//! sin acceso a la base de datos. Las acciones `Custom(nombre)` se registran con
+//! `ActionRegistry::register_custom`.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::flow_engine::ActionType;
+use super::template::{render_json, render_template, render_url};
+use super::state_machine::ConversationState;
+
+/// Ejecutor de un tipo de acción
+#[async_trait]
+pub trait ActionHandler: Send + Sync {
+    /// Ejecutar la acción. Los resultados se guardan como variables de la conversación.
+    ///
+    /// `variables` son las mismas que ven los textos del flow (contexto más
+    /// `bot.*` y `system.*`, ver `conversation_variables`).
+    async fn execute(
+        &self,
+        action: &ActionType,
+        parameters: &HashMap<String, Value>,
+        variables: &HashMap<String, Value>,
+        conversation: &mut ConversationState,
+    ) -> Result<()>;
+}
+
+/// URLs de los servicios que usan los handlers incluidos
+#[derive(Debug, Clone)]
+pub struct ActionConfig {
+    pub email_service_url: String,
+    pub api_gateway_url: String,
+}
+
+impl ActionConfig {
+    pub fn from_env() -> Self {
+        Self {
+            email_service_url: std::env::var("EMAIL_SERVICE_URL")
+                .unwrap_or_else(|_| "http://localhost:3016".to_string()),
+            api_gateway_url: std::env::var("API_GATEWAY_URL")
+                .unwrap_or_else(|_| "http://localhost:3009".to_string()),
+        }
+    }
+}
+
+/// Registro de handlers por tipo de acción (ver `ActionType::key`)
+pub struct ActionRegistry {
+    handlers: RwLock<HashMap<String, Arc<dyn ActionHandler>>>,
+}
+
+impl ActionRegistry {
+    pub fn new() -> Self {
+        Self {
+            handlers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Registro con los handlers incluidos
+    pub fn with_builtin_handlers(config: &ActionConfig) -> Self {
+        let registry = Self::new();
+        let client = reqwest::Client::new();
+
+        registry.register("api_call", Arc::new(ApiCallHandler { client: client.clone() }));
+        registry.register("send_email", Arc::new(SendEmailHandler {
+            client: client.clone(),
+            base_url: config.email_service_url.clone(),
+        }));
+        registry.register("create_order", Arc::new(CreateOrderHandler {
+            client: client.clone(),
+            base_url: config.api_gateway_url.clone(),
+        }));
+        registry.register("update_customer", Arc::new(UpdateCustomerHandler {
+            client,
+            base_url: config.api_gateway_url.clone(),
+        }));
+
+        registry
+    }
+
+    /// Registrar (o reemplazar) el handler de un tipo de acción
+    pub fn register(&self, key: &str, handler: Arc<dyn ActionHandler>) {
+        self.handlers.write().insert(key.to_string(), handler);
+    }
+
+    /// Registrar el handler de `ActionType::Custom(name)`
+    pub fn register_custom(&self, name: &str, handler: Arc<dyn ActionHandler>) {
+        self.register(&format!("custom:{}", name), handler);
+    }
+
+    pub fn get(&self, action: &ActionType) -> Option<Arc<dyn ActionHandler>> {
+        self.handlers.read().get(&action.key()).cloned()
+    }
+}
+
+// ==================== Handlers incluidos ====================
+
+/// `ApiCall { url, method }`
+///
+/// Parámetros opcionales:
+/// - `headers`: objeto de headers (con plantillas)
+/// - `body`: JSON a enviar (con plantillas en los textos)
+/// - `response_mapping`: `{ "variable": "ruta.en.la.respuesta" }`
+/// - `result_variable`: variable donde guardar la respuesta completa
+/// - `timeout_ms`: por defecto 10000
+pub struct ApiCallHandler {
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl ActionHandler for ApiCallHandler {
+    async fn execute(
+        &self,
+        action: &ActionType,
+        parameters: &HashMap<String, Value>,
+        variables: &HashMap<String, Value>,
+        conversation: &mut ConversationState,
+    ) -> Result<()> {
+        let ActionType::ApiCall { url, method } = action else {
+            anyhow::bail!("ApiCallHandler cannot run {:?}", action);
+        };
+
+        let url = render_url(url, variables);
+        let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())
+            .with_context(|| format!("Invalid HTTP method '{}'", method))?;
+        let timeout = parameters.get("timeout_ms")
+            .and_then(Value::as_u64)
+            .unwrap_or(10_000);
+
+        tracing::info!("🌐 API Call: {} {}", method, url);
+
+        let mut request = self.client
+            .request(method, &url)
+            .timeout(Duration::from_millis(timeout));
+
+        if let Some(Value::Object(headers)) = parameters.get("headers") {
+            for (name, value) in headers {
+                if let Some(value) = value.as_str() {
+                    request = request.header(name.as_str(), render_template(value, variables));
+                }
+            }
+        }
+
+        if let Some(body) = parameters.get("body") {
+            request = request.json(&render_json(body, variables));
+        }
+
+        let response = request.send().await
+            .with_context(|| format!("API call to {} failed", url))?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            anyhow::bail!("API call to {} returned {}: {}", url, status, text);
+        }
+
+        let body: Value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+
+        if let Some(Value::Object(mapping)) = parameters.get("response_mapping") {
+            for (variable, path) in mapping {
+                let value = path.as_str()
+                    .and_then(|path| json_path(&body, path))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                conversation.set_variable(variable, value);
+            }
+        }
+
+        if let Some(variable) = parameters.get("result_variable").and_then(Value::as_str) {
+            conversation.set_variable(variable, body);
+        }
+
+        Ok(())
+    }
+}
+
+/// `SendEmail { to, template }` a través de email-service (`POST /send`)
+///
+/// Parámetros opcionales: `subject`, `body`, `template_data` (por defecto, todas
+/// las variables de texto de la conversación).
+pub struct SendEmailHandler {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+#[async_trait]
+impl ActionHandler for SendEmailHandler {
+    async fn execute(
+        &self,
+        action: &ActionType,
+        parameters: &HashMap<String, Value>,
+        variables: &HashMap<String, Value>,
+        conversation: &mut ConversationState,
+    ) -> Result<()> {
+        let ActionType::SendEmail { to, template } = action else {
+            anyhow::bail!("SendEmailHandler cannot run {:?}", action);
+        };
+
+        let to = render_template(to, variables);
+        let template_data: HashMap<String, String> = match parameters.get("template_data") {
+            Some(Value::Object(data)) => data.iter()
+                .map(|(k, v)| (k.clone(), value_to_string(&render_json(v, variables))))
+                .collect(),
+            _ => conversation.context.iter()
+                .filter(|(_, v)| !v.is_object() && !v.is_array())
+                .map(|(k, v)| (k.clone(), value_to_string(v)))
+                .collect(),
+        };
+        let text_param = |name: &str| {
+            parameters.get(name)
+                .and_then(Value::as_str)
+                .map(|text| render_template(text, variables))
+                .unwrap_or_default()
+        };
+
+        let request = serde_json::json!({
+            "to": [to],
+            "subject": text_param("subject"),
+            "body": text_param("body"),
+            "template": template,
+            "template_data": template_data,
+            "priority": "normal",
+        });
+
+        tracing::info!("📧 Send email to: {} (template {})", to, template);
+
+        let response = self.client
+            .post(format!("{}/send", self.base_url))
+            .json(&request)
+            .send()
+            .await
+            .context("email-service unreachable")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("email-service returned {}", response.status());
+        }
+
+        if let Ok(body) = response.json::<Value>().await {
+            if let Some(message_id) = body.get("message_id") {
+                conversation.set_variable("email_message_id", message_id.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `CreateOrder`: `POST /api/orders` en el API gateway
+///
+/// Toma del contexto `customer_id`, el carrito (`cart`, lista de
+/// `{ product_id, quantity }`) y `shipping_address`; los nombres de las variables
+/// se pueden cambiar con los parámetros `customer_variable`, `items_variable` y
+/// `address_variable`. Guarda `order_id` y `order_number` en el contexto.
+pub struct CreateOrderHandler {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+/// Armar la orden a partir del contexto de la conversación
+pub fn build_order_request(
+    parameters: &HashMap<String, Value>,
+    context: &HashMap<String, Value>,
+) -> Result<shared::CreateOrderRequest> {
+    let variable = |param: &str, default: &'static str| -> String {
+        parameters.get(param)
+            .and_then(Value::as_str)
+            .unwrap_or(default)
+            .to_string()
+    };
+
+    let customer_variable = variable("customer_variable", "customer_id");
+    let customer_id = context.get(&customer_variable)
+        .and_then(Value::as_str)
+        .with_context(|| format!("Missing '{}' in conversation", customer_variable))?
+        .parse()
+        .with_context(|| format!("'{}' is not a valid id", customer_variable))?;
+
+    let items_variable = variable("items_variable", "cart");
+    let items = context.get(&items_variable)
+        .cloned()
+        .with_context(|| format!("Missing '{}' in conversation", items_variable))?;
+    let items: Vec<shared::OrderItemRequest> = serde_json::from_value(items)
+        .with_context(|| format!("'{}' must be a list of {{ product_id, quantity }}", items_variable))?;
+
+    if items.is_empty() {
+        anyhow::bail!("Cannot create an order with an empty '{}'", items_variable);
+    }
+
+    let shipping_address = context.get(&variable("address_variable", "shipping_address"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    Ok(shared::CreateOrderRequest {
+        customer_id,
+        items,
+        shipping_address,
+    })
+}
+
+#[async_trait]
+impl ActionHandler for CreateOrderHandler {
+    async fn execute(
+        &self,
+        _action: &ActionType,
+        parameters: &HashMap<String, Value>,
+        _variables: &HashMap<String, Value>,
+        conversation: &mut ConversationState,
+    ) -> Result<()> {
+        let order = build_order_request(parameters, &conversation.context)?;
+
+        tracing::info!("🛒 Create order for customer {} ({} items)", order.customer_id, order.items.len());
+
+        let response = self.client
+            .post(format!("{}/api/orders", self.base_url))
+            .json(&order)
+            .send()
+            .await
+            .context("API gateway unreachable")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Order creation failed with status {}", response.status());
+        }
+
+        let created: Value = response.json().await?;
+        for key in ["id", "order_number", "total"] {
+            if let Some(value) = created.get(key) {
+                let variable = if key == "id" { "order_id".to_string() } else { format!("order_{}", key) };
+                conversation.set_variable(&variable, value.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `UpdateCustomer`: `PATCH /api/customers/{customer_id}` con los campos del
+/// parámetro `fields` (con plantillas)
+pub struct UpdateCustomerHandler {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+#[async_trait]
+impl ActionHandler for UpdateCustomerHandler {
+    async fn execute(
+        &self,
+        _action: &ActionType,
+        parameters: &HashMap<String, Value>,
+        variables: &HashMap<String, Value>,
+        conversation: &mut ConversationState,
+    ) -> Result<()> {
+        let customer_id: uuid::Uuid = conversation.get_variable("customer_id")
+            .and_then(Value::as_str)
+            .context("Missing 'customer_id' in conversation")?
+            .parse()
+            .context("'customer_id' is not a valid id")?;
+        let fields = parameters.get("fields")
+            .map(|fields| render_json(fields, variables))
+            .context("update_customer requires a 'fields' parameter")?;
+
+        tracing::info!("👤 Update customer {}", customer_id);
+
+        let response = self.client
+            .patch(format!("{}/api/customers/{}", self.base_url, customer_id))
+            .json(&fields)
+            .send()
+            .await
+            .context("API gateway unreachable")?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Customer update failed with status {}", response.status());
+        }
+
+        Ok(())
+    }
+}
+
+// ==================== Helpers ====================
+
+/// Ruta con puntos dentro de un JSON (`data.items.0.name`); vacía = todo el JSON
+fn json_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    value.pointer(&format!("/{}", path.replace('.', "/")))
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_path() {
+        let body = json!({ "data": { "items": [{ "name": "Torta" }] } });
+
+        assert_eq!(json_path(&body, "data.items.0.name"), Some(&json!("Torta")));
+        assert_eq!(json_path(&body, "data.missing"), None);
+        assert_eq!(json_path(&body, ""), Some(&body));
+    }
+
+    #[test]
+    fn test_build_order_request_from_context() {
+        let customer_id = uuid::Uuid::new_v4();
+        let product_id = uuid::Uuid::new_v4();
+        let mut context = HashMap::new();
+        context.insert("customer_id".to_string(), json!(customer_id.to_string()));
+        context.insert("carrito".to_string(), json!([{ "product_id": product_id, "quantity": 2 }]));
+        context.insert("shipping_address".to_string(), json!("Av. Bolívar, Caracas"));
+
+        let mut parameters = HashMap::new();
+        parameters.insert("items_variable".to_string(), json!("carrito"));
+
+        let order = build_order_request(&parameters, &context).unwrap();
+        assert_eq!(order.customer_id, customer_id);
+        assert_eq!(order.items.len(), 1);
+        assert_eq!(order.items[0].quantity, 2);
+        assert_eq!(order.shipping_address.as_deref(), Some("Av. Bolívar, Caracas"));
+    }
+
+    #[test]
+    fn test_build_order_request_requires_items() {
+        let mut context = HashMap::new();
+        context.insert("customer_id".to_string(), json!(uuid::Uuid::new_v4().to_string()));
+        context.insert("cart".to_string(), json!([]));
+
+        assert!(build_order_request(&HashMap::new(), &context).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_customer_rejects_invalid_id() {
+        let handler = UpdateCustomerHandler {
+            client: reqwest::Client::new(),
+            base_url: "http://127.0.0.1:9".to_string(),
+        };
+        let mut conversation = ConversationState::new("c1".to_string(), uuid::Uuid::new_v4(), "+58".to_string());
+        conversation.set_variable("customer_id", json!("x/../../admin?y="));
+        let mut parameters = HashMap::new();
+        parameters.insert("fields".to_string(), json!({ "name": "Ana" }));
+
+        let error = handler.execute(&ActionType::UpdateCustomer, &parameters, &HashMap::new(), &mut conversation).await.unwrap_err();
+        assert!(error.to_string().contains("not a valid id"));
+    }
+}
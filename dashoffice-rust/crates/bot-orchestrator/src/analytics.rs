@@ -1,14 +1,602 @@
-//\! Analytics en tiempo real
+//! Analytics en tiempo real
+//!
+//! Los `BotEvent` se guardan en la colección `analytics_events` de MongoDB,
+//! la que agrega analytics-engine (`Aggregator::aggregate_hourly`):
+//! `{ _id, event_type, value, timestamp, dimensions: { bot_id, tenant, ... } }`.
+//! `value` es 1 para los conteos y la duración en segundos de los handoffs.
+//!
+//! - El writer recibe los eventos por una suscripción sin pérdidas del
+//!   `EventBus` y los inserta en lotes (`BATCH_SIZE` o cada `FLUSH_INTERVAL`)
+//! - La suscripción admite `CHANNEL_CAPACITY` eventos en espera; si el writer
+//!   se atrasa (Mongo lento, reenvío del spill), los demás se escriben directo
+//!   en el spill (`spill_overflow`), así la memoria no crece. La escritura la
+//!   hace una tarea aparte con su propia cola de `SPILL_QUEUE_CAPACITY`:
+//!   `EventBus::send` corre en el camino de los mensajes y no debería esperar
+//!   al disco. Solo si esa cola también se llena se escribe en el momento
+//! - Cada evento tiene su propio `_id`: al reintentar un lote, los que ya
+//!   estaban insertados se ignoran (al menos una vez, sin duplicados)
+//! - Si Mongo falla o tarda más de `INSERT_TIMEOUT`, los lotes van a un
+//!   archivo local (`ANALYTICS_SPILL_FILE`, una línea JSON por evento) y se
+//!   reenvían cuando Mongo vuelve; mientras tanto no se le espera
 
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use mongodb::bson::{doc, Document};
+use mongodb::error::{BulkWriteFailure, ErrorKind};
+use mongodb::options::InsertManyOptions;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BotAnalytics {
-    pub bot_id: uuid::Uuid,
-    pub total_conversations: u64,
-    pub active_conversations: u64,
-    pub messages_sent_today: u64,
-    pub messages_received_today: u64,
-    pub avg_response_time_ms: u64,
-    pub conversion_rate: f64,
+use super::event_bus::Overflow;
+use super::{BotEvent, BotInstance};
+
+const COLLECTION: &str = "analytics_events";
+
+/// Eventos por inserción
+const BATCH_SIZE: usize = 500;
+
+/// Eventos en espera del writer antes de mandarlos directo al spill
+pub const CHANNEL_CAPACITY: usize = 10_000;
+
+/// Eventos en espera de la tarea que escribe el overflow en el spill
+const SPILL_QUEUE_CAPACITY: usize = 10_000;
+
+/// Cada cuánto se insertan los eventos acumulados (y se reintenta el spill)
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+const INSERT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tiempo sin intentar con Mongo después de un fallo
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Código de MongoDB para `_id` duplicado
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Evento tal como lo lee analytics-engine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsEvent {
+    pub id: Uuid,
+    pub event_type: String,
+    pub value: f64,
+    pub timestamp: DateTime<Utc>,
+    pub dimensions: BTreeMap<String, String>,
+}
+
+impl AnalyticsEvent {
+    /// Convertir un `BotEvent`; `tenant_of` da el tenant de cada bot
+    pub fn from_bot_event(event: &BotEvent, tenant_of: impl Fn(Uuid) -> Option<String>) -> Self {
+        let now = Utc::now();
+        let mut dimensions = BTreeMap::new();
+
+        let (event_type, bot_id, value, timestamp) = match event {
+            BotEvent::MessageReceived { bot_id, timestamp, .. } => ("message_received".to_string(), Some(*bot_id), 1.0, *timestamp),
+            BotEvent::MessageSent { bot_id, timestamp, .. } => ("message_sent".to_string(), Some(*bot_id), 1.0, *timestamp),
+            BotEvent::MessageFailed { bot_id, timestamp, .. } => ("message_failed".to_string(), Some(*bot_id), 1.0, *timestamp),
+            BotEvent::MessageStatus { bot_id, status, timestamp, .. } => (format!("message_{}", status), Some(*bot_id), 1.0, *timestamp),
+            BotEvent::ConversationStarted { bot_id, .. } => ("conversation_started".to_string(), Some(*bot_id), 1.0, now),
+            BotEvent::ConversationEnded { conversation_id, reason } => {
+                dimensions.insert("reason".to_string(), reason.clone());
+                ("conversation_ended".to_string(), bot_of(conversation_id), 1.0, now)
+            }
+            BotEvent::FlowTransition { conversation_id, to_step, .. } => {
+                dimensions.insert("step".to_string(), to_step.clone());
+                ("flow_transition".to_string(), bot_of(conversation_id), 1.0, now)
+            }
+            BotEvent::HandoffStarted { bot_id, queued_until, timestamp, .. } => {
+                dimensions.insert("queued".to_string(), queued_until.is_some().to_string());
+                ("handoff_started".to_string(), Some(*bot_id), 1.0, *timestamp)
+            }
+            BotEvent::HandoffAssigned { bot_id, agent_id, timestamp, .. } => {
+                dimensions.insert("agent_id".to_string(), agent_id.clone());
+                ("handoff_assigned".to_string(), Some(*bot_id), 1.0, *timestamp)
+            }
+            BotEvent::HandoffMessage { bot_id, timestamp, .. } => ("handoff_message".to_string(), Some(*bot_id), 1.0, *timestamp),
+            BotEvent::HandoffEnded { bot_id, reason, duration_seconds, timestamp, .. } => {
+                dimensions.insert("reason".to_string(), reason.clone());
+                ("handoff_ended".to_string(), Some(*bot_id), *duration_seconds as f64, *timestamp)
+            }
+        };
+
+        if let Some(bot_id) = bot_id {
+            dimensions.insert("bot_id".to_string(), bot_id.to_string());
+            if let Some(tenant) = tenant_of(bot_id) {
+                dimensions.insert("tenant".to_string(), tenant);
+            }
+        }
+
+        Self {
+            id: Uuid::new_v4(),
+            event_type,
+            value,
+            timestamp,
+            dimensions,
+        }
+    }
+
+    fn to_document(&self) -> Document {
+        let dimensions: Document = self.dimensions.iter()
+            .map(|(key, value)| (key.clone(), value.clone().into()))
+            .collect();
+
+        doc! {
+            "_id": self.id.to_string(),
+            "event_type": &self.event_type,
+            "value": self.value,
+            "timestamp": mongodb::bson::DateTime::from_millis(self.timestamp.timestamp_millis()),
+            "dimensions": dimensions,
+        }
+    }
+}
+
+/// Bot de una conversación (`{bot_id}:{teléfono}`)
+fn bot_of(conversation_id: &str) -> Option<Uuid> {
+    conversation_id.split(':').next()?.parse().ok()
+}
+
+/// Destino de los eventos
+#[async_trait]
+pub trait AnalyticsSink: Send + Sync {
+    async fn insert(&self, events: &[AnalyticsEvent]) -> Result<()>;
+}
+
+pub struct MongoSink {
+    collection: mongodb::Collection<Document>,
+}
+
+impl MongoSink {
+    /// `MONGODB_URI` y `MONGODB_DATABASE` (`dashoffice`, como analytics-engine)
+    pub async fn from_env() -> Result<Self> {
+        let uri = std::env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
+        let database = std::env::var("MONGODB_DATABASE").unwrap_or_else(|_| "dashoffice".to_string());
+
+        let client = mongodb::Client::with_uri_str(&uri).await.context("Invalid MongoDB URI")?;
+        Ok(Self {
+            collection: client.database(&database).collection(COLLECTION),
+        })
+    }
+}
+
+#[async_trait]
+impl AnalyticsSink for MongoSink {
+    async fn insert(&self, events: &[AnalyticsEvent]) -> Result<()> {
+        let documents: Vec<Document> = events.iter().map(AnalyticsEvent::to_document).collect();
+        let options = InsertManyOptions::builder().ordered(false).build();
+
+        match self.collection.insert_many(documents, options).await {
+            Ok(_) => Ok(()),
+            // Reintento de un lote ya (parcialmente) insertado
+            Err(e) if only_duplicates(&e.kind) => Ok(()),
+            Err(e) => Err(e).context("Failed to insert analytics events"),
+        }
+    }
+}
+
+fn only_duplicates(kind: &ErrorKind) -> bool {
+    match kind {
+        ErrorKind::BulkWrite(BulkWriteFailure { write_errors: Some(errors), write_concern_error: None, .. }) => {
+            errors.iter().all(|error| error.code == DUPLICATE_KEY)
+        }
+        _ => false,
+    }
+}
+
+/// Archivo local con los eventos que no se pudieron insertar
+#[derive(Clone)]
+pub struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// `ANALYTICS_SPILL_FILE` (por defecto `./data/analytics-spill.jsonl`)
+    pub fn from_env() -> Self {
+        let path = std::env::var("ANALYTICS_SPILL_FILE").unwrap_or_else(|_| "./data/analytics-spill.jsonl".to_string());
+        Self::new(PathBuf::from(path))
+    }
+
+    pub async fn append(&self, events: &[AnalyticsEvent]) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        let lines = to_lines(events)?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// `append` sin esperar al runtime, para cuando la cola de `spill_overflow` está llena
+    pub fn append_blocking(&self, events: &[AnalyticsEvent]) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let lines = to_lines(events)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+
+    /// Copia que se está reenviando; `path` sigue recibiendo eventos nuevos
+    fn draining_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".draining");
+        PathBuf::from(path)
+    }
+
+    /// Reenviar los eventos guardados en lotes; el archivo se borra solo si
+    /// se insertaron todos. Devuelve cuántos se reenviaron.
+    ///
+    /// Antes se mueve a `draining_path` (salvo que quede uno de un reenvío
+    /// anterior sin terminar), para no borrar lo que se agregue mientras tanto.
+    pub async fn drain(&self, sink: &dyn AnalyticsSink) -> Result<usize> {
+        let draining = self.draining_path();
+        if tokio::fs::metadata(&draining).await.is_err() {
+            match tokio::fs::rename(&self.path, &draining).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let file = tokio::fs::File::open(&draining).await?;
+
+        let mut lines = BufReader::new(file).lines();
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut sent = 0;
+        while let Some(line) = lines.next_line().await? {
+            // Una línea cortada (p. ej. por un apagón a mitad de escritura) no se puede recuperar
+            match serde_json::from_str(&line) {
+                Ok(event) => batch.push(event),
+                Err(e) => warn!("Skipping unreadable analytics event in {}: {}", self.path.display(), e),
+            }
+            if batch.len() == BATCH_SIZE {
+                insert_with_timeout(sink, &batch).await?;
+                sent += batch.len();
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            insert_with_timeout(sink, &batch).await?;
+            sent += batch.len();
+        }
+
+        tokio::fs::remove_file(&draining).await?;
+        Ok(sent)
+    }
+}
+
+/// Una línea JSON por evento
+fn to_lines(events: &[AnalyticsEvent]) -> Result<String> {
+    let mut lines = String::new();
+    for event in events {
+        lines.push_str(&serde_json::to_string(event)?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// `Overflow` del `EventBus` para analytics: lo que no cabe en el canal del
+/// writer se escribe en el spill y se reenvía con el resto
+///
+/// El `EventBus` solo lo pasa a una tarea que escribe en el spill lo que se
+/// haya acumulado mientras tanto; hay que llamarla dentro del runtime de tokio.
+/// Si la tarea se atrasa más de `SPILL_QUEUE_CAPACITY` eventos, se escriben
+/// en el momento (bloqueando) en lugar de acumularse en memoria.
+pub fn spill_overflow(bots: Arc<DashMap<Uuid, BotInstance>>, spill: SpillFile) -> Overflow {
+    spill_overflow_with_queue(bots, spill, SPILL_QUEUE_CAPACITY)
+}
+
+fn spill_overflow_with_queue(bots: Arc<DashMap<Uuid, BotInstance>>, spill: SpillFile, capacity: usize) -> Overflow {
+    let (sender, mut receiver) = mpsc::channel::<AnalyticsEvent>(capacity);
+    let writer = spill.clone();
+
+    tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            let mut batch = vec![event];
+            while let Ok(event) = receiver.try_recv() {
+                batch.push(event);
+            }
+            if let Err(e) = writer.append(&batch).await {
+                error!("❌ Lost {} analytics events: {:#}", batch.len(), e);
+            }
+        }
+    });
+
+    Arc::new(move |event| {
+        let event = AnalyticsEvent::from_bot_event(&event, |bot_id| bots.get(&bot_id).map(|bot| bot.tenant_id.clone()));
+        let event = match sender.try_send(event) {
+            Ok(()) => return,
+            Err(mpsc::error::TrySendError::Full(event)) => {
+                warn!("Analytics spill queue is full, writing event {} synchronously", event.event_type);
+                event
+            }
+            Err(mpsc::error::TrySendError::Closed(event)) => event,
+        };
+        if let Err(e) = spill.append_blocking(std::slice::from_ref(&event)) {
+            error!("❌ Lost analytics event {}: {:#}", event.event_type, e);
+        }
+    })
+}
+
+async fn insert_with_timeout(sink: &dyn AnalyticsSink, events: &[AnalyticsEvent]) -> Result<()> {
+    tokio::time::timeout(INSERT_TIMEOUT, sink.insert(events))
+        .await
+        .context("Analytics insert timed out")?
+}
+
+/// Writer de `analytics_events`
+pub struct AnalyticsWriter {
+    /// `None` si no hay Mongo configurado: todo va al spill
+    sink: Option<Arc<dyn AnalyticsSink>>,
+    spill: SpillFile,
+    bots: Arc<DashMap<Uuid, BotInstance>>,
+    /// Después de un fallo no se intenta con Mongo hasta este momento
+    paused_until: Option<Instant>,
+}
+
+impl AnalyticsWriter {
+    pub fn new(sink: Option<Arc<dyn AnalyticsSink>>, spill: SpillFile, bots: Arc<DashMap<Uuid, BotInstance>>) -> Self {
+        Self {
+            sink,
+            spill,
+            bots,
+            paused_until: None,
+        }
+    }
+
+    pub async fn run(mut self, mut events: mpsc::Receiver<BotEvent>) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => {
+                        batch.push(self.convert(&event));
+                        if batch.len() >= BATCH_SIZE {
+                            self.flush(&mut batch).await;
+                        }
+                    }
+                    None => {
+                        self.flush(&mut batch).await;
+                        break;
+                    }
+                },
+                _ = ticker.tick() => {
+                    self.flush(&mut batch).await;
+                    self.retry_spill().await;
+                }
+            }
+        }
+    }
+
+    fn convert(&self, event: &BotEvent) -> AnalyticsEvent {
+        AnalyticsEvent::from_bot_event(event, |bot_id| self.bots.get(&bot_id).map(|bot| bot.tenant_id.clone()))
+    }
+
+    fn mongo(&self) -> Option<&dyn AnalyticsSink> {
+        let paused = self.paused_until.is_some_and(|until| Instant::now() < until);
+        self.sink.as_deref().filter(|_| !paused)
+    }
+
+    async fn flush(&mut self, batch: &mut Vec<AnalyticsEvent>) {
+        if batch.is_empty() {
+            return;
+        }
+        let events = std::mem::take(batch);
+
+        if let Some(sink) = self.mongo() {
+            match insert_with_timeout(sink, &events).await {
+                Ok(()) => return,
+                Err(e) => {
+                    warn!("Analytics store unavailable, spilling to {}: {:#}", self.spill.path.display(), e);
+                    self.paused_until = Some(Instant::now() + RETRY_AFTER);
+                }
+            }
+        }
+
+        if let Err(e) = self.spill.append(&events).await {
+            error!("❌ Lost {} analytics events: {:#}", events.len(), e);
+        }
+    }
+
+    async fn retry_spill(&mut self) {
+        let Some(sink) = self.mongo() else { return };
+
+        match self.spill.drain(sink).await {
+            Ok(0) => {}
+            Ok(sent) => {
+                info!("📊 Sent {} spilled analytics events", sent);
+                self.paused_until = None;
+            }
+            Err(e) => {
+                warn!("Could not send spilled analytics events: {:#}", e);
+                self.paused_until = Some(Instant::now() + RETRY_AFTER);
+            }
+        }
+    }
+}
+
+/// Arrancar el writer con Mongo (`MONGODB_URI`) y el spill de `ANALYTICS_SPILL_FILE`
+pub fn spawn_analytics_worker(bots: Arc<DashMap<Uuid, BotInstance>>, events: mpsc::Receiver<BotEvent>) {
+    tokio::spawn(async move {
+        info!("📊 Analytics worker started");
+
+        let sink: Option<Arc<dyn AnalyticsSink>> = match MongoSink::from_env().await {
+            Ok(sink) => Some(Arc::new(sink)),
+            Err(e) => {
+                error!("Analytics store disabled, events will be spilled: {:#}", e);
+                None
+            }
+        };
+
+        AnalyticsWriter::new(sink, SpillFile::from_env(), bots).run(events).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    /// Sink en memoria que puede simular una caída
+    #[derive(Default)]
+    struct MemorySink {
+        events: Mutex<Vec<AnalyticsEvent>>,
+        down: Mutex<bool>,
+    }
+
+    #[async_trait]
+    impl AnalyticsSink for MemorySink {
+        async fn insert(&self, events: &[AnalyticsEvent]) -> Result<()> {
+            if *self.down.lock() {
+                anyhow::bail!("connection refused");
+            }
+            self.events.lock().extend_from_slice(events);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_event_schema() {
+        let bot_id = Uuid::new_v4();
+        let event = BotEvent::HandoffEnded {
+            conversation_id: format!("{}:+58", bot_id),
+            bot_id,
+            agent_id: None,
+            reason: "released".to_string(),
+            duration_seconds: 90,
+            timestamp: Utc::now(),
+        };
+
+        let analytics = AnalyticsEvent::from_bot_event(&event, |_| Some("floristeria".to_string()));
+        assert_eq!(analytics.event_type, "handoff_ended");
+        assert_eq!(analytics.value, 90.0);
+
+        let document = analytics.to_document();
+        assert!(document.get_datetime("timestamp").is_ok());
+        let dimensions = document.get_document("dimensions").unwrap();
+        assert_eq!(dimensions.get_str("bot_id").unwrap(), bot_id.to_string());
+        assert_eq!(dimensions.get_str("tenant").unwrap(), "floristeria");
+
+        // Sin bot_id en el evento, sale del id de la conversación
+        let ended = BotEvent::ConversationEnded { conversation_id: format!("{}:+58", bot_id), reason: "timeout".to_string() };
+        let analytics = AnalyticsEvent::from_bot_event(&ended, |_| None);
+        assert_eq!(analytics.dimensions.get("bot_id"), Some(&bot_id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_spill_while_store_is_down() {
+        let path = std::env::temp_dir().join(format!("analytics-spill-{}.jsonl", Uuid::new_v4()));
+        let sink = Arc::new(MemorySink::default());
+        *sink.down.lock() = true;
+
+        let mut writer = AnalyticsWriter::new(Some(sink.clone()), SpillFile::new(path.clone()), Arc::new(DashMap::new()));
+        let received = BotEvent::MessageReceived {
+            bot_id: Uuid::new_v4(),
+            from: "+58".to_string(),
+            message: "hola".to_string(),
+            timestamp: Utc::now(),
+        };
+
+        let mut batch = vec![writer.convert(&received), writer.convert(&received)];
+        writer.flush(&mut batch).await;
+        assert!(sink.events.lock().is_empty());
+
+        // Mientras está en pausa ni se intenta: directo al spill
+        *sink.down.lock() = false;
+        let mut batch = vec![writer.convert(&received)];
+        writer.flush(&mut batch).await;
+        assert!(sink.events.lock().is_empty());
+
+        // Al volver se reenvía todo y el spill desaparece
+        writer.paused_until = None;
+        writer.retry_spill().await;
+        assert_eq!(sink.events.lock().len(), 3);
+        assert!(!path.exists());
+        assert!(!writer.spill.draining_path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_spills_instead_of_buffering() {
+        let path = std::env::temp_dir().join(format!("analytics-spill-{}.jsonl", Uuid::new_v4()));
+        let spill = SpillFile::new(path.clone());
+        let mut bus = crate::event_bus::EventBus::new(10);
+        let mut events = bus.subscribe_durable(1, spill_overflow(Arc::new(DashMap::new()), spill.clone()));
+
+        for _ in 0..3 {
+            bus.send(BotEvent::ConversationStarted {
+                conversation_id: "c1".to_string(),
+                bot_id: Uuid::new_v4(),
+                user_phone: "+58".to_string(),
+            });
+        }
+
+        assert!(events.try_recv().is_ok());
+        assert!(events.try_recv().is_err());
+
+        // El spill se escribe en segundo plano
+        for _ in 0..100 {
+            if std::fs::read_to_string(&path).is_ok_and(|text| text.lines().count() == 2) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let sink = MemorySink::default();
+        assert_eq!(spill.drain(&sink).await.unwrap(), 2);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_full_spill_queue_writes_synchronously() {
+        let path = std::env::temp_dir().join(format!("analytics-spill-{}.jsonl", Uuid::new_v4()));
+        let spill = SpillFile::new(path.clone());
+        let overflow = spill_overflow_with_queue(Arc::new(DashMap::new()), spill.clone(), 1);
+
+        for _ in 0..3 {
+            overflow(BotEvent::ConversationStarted {
+                conversation_id: "c1".to_string(),
+                bot_id: Uuid::new_v4(),
+                user_phone: "+58".to_string(),
+            });
+        }
+
+        // La tarea todavía no corrió: uno espera en la cola y los otros ya están en el archivo
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        for _ in 0..100 {
+            if std::fs::read_to_string(&path).is_ok_and(|text| text.lines().count() == 3) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let sink = MemorySink::default();
+        assert_eq!(spill.drain(&sink).await.unwrap(), 3);
+    }
 }
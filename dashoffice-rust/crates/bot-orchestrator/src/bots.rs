@@ -0,0 +1,716 @@
+//! Bots - Alta, cambios, pausa y baja de bots sin reiniciar el orchestrator
+//!
+//! - Los bots viven en la tabla `bots` de Postgres (`shared::Bot`); `config`
+//!   guarda `{ provider_config, flows, settings }` del `BotInstance`
+//! - Cada operación actualiza primero Postgres y después el `DashMap` en memoria
+//! - Cambiar el provider (o su configuración) cierra la sesión anterior en
+//!   whatsapp-adapter y abre la nueva
+//! - Un bot pausado conserva su sesión pero no atiende mensajes nuevos
+//! - Cada tenant tiene un máximo de bots (`MAX_BOTS_PER_TENANT`, o
+//!   `TENANT_BOT_LIMITS=tenant_a=3,tenant_b=50` para tenants concretos)
+//! - Los bots borrados quedan con `status = 'deleted'`: los pedidos los referencian
+
+use actix_web::{web, HttpResponse, Responder};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use shared::Bot;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::expiry;
+use super::whatsapp::WhatsAppClient;
+use super::{BotInstance, BotSettings, BotStats, FlowConfig, OrchestratorState};
+
+/// Providers que acepta whatsapp-adapter
+const PROVIDERS: &[&str] = &["venom", "wwebjs", "baileys", "official", "meta", "twilio"];
+
+const DEFAULT_MAX_BOTS_PER_TENANT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BotStatus {
+    #[default]
+    Active,
+    Paused,
+}
+
+impl BotStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            BotStatus::Active => "active",
+            BotStatus::Paused => "paused",
+        }
+    }
+
+    /// Los bots creados fuera del orchestrator quedan en `initializing`: se atienden
+    fn from_db(status: &str) -> Self {
+        match status {
+            "paused" => BotStatus::Paused,
+            _ => BotStatus::Active,
+        }
+    }
+}
+
+/// Lo que se guarda en `bots.config`
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    #[serde(default)]
+    provider_config: serde_json::Value,
+    #[serde(default)]
+    flows: FlowConfig,
+    #[serde(default)]
+    settings: BotSettings,
+}
+
+impl TryFrom<Bot> for BotInstance {
+    type Error = anyhow::Error;
+
+    fn try_from(bot: Bot) -> anyhow::Result<Self> {
+        let config: StoredConfig = serde_json::from_value(bot.config)
+            .with_context(|| format!("Invalid config of bot {}", bot.id))?;
+
+        Ok(BotInstance {
+            id: bot.id,
+            tenant_id: bot.tenant_id,
+            name: bot.name,
+            phone_number: bot.phone_number,
+            provider: bot.provider,
+            provider_config: config.provider_config,
+            flows: config.flows,
+            settings: config.settings,
+            status: BotStatus::from_db(&bot.status),
+            stats: BotStats::default(),
+        })
+    }
+}
+
+impl BotInstance {
+    fn to_record(&self) -> anyhow::Result<Bot> {
+        let config = serde_json::to_value(StoredConfig {
+            provider_config: self.provider_config.clone(),
+            flows: self.flows.clone(),
+            settings: self.settings.clone(),
+        })?;
+        let now = Utc::now();
+
+        Ok(Bot {
+            id: self.id,
+            tenant_id: self.tenant_id.clone(),
+            name: self.name.clone(),
+            phone_number: self.phone_number.clone(),
+            provider: self.provider.clone(),
+            status: self.status.as_str().to_string(),
+            config,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+}
+
+/// Persistencia de los bots
+#[async_trait]
+pub trait BotStore: Send + Sync {
+    /// Todos los bots no borrados
+    async fn load_all(&self) -> anyhow::Result<Vec<Bot>>;
+
+    async fn insert(&self, bot: &Bot) -> anyhow::Result<()>;
+
+    async fn update(&self, bot: &Bot) -> anyhow::Result<()>;
+
+    async fn delete(&self, bot_id: Uuid) -> anyhow::Result<()>;
+}
+
+/// Tabla `bots` de Postgres (`DATABASE_URL`)
+pub struct PostgresBotStore {
+    pool: PgPool,
+}
+
+impl PostgresBotStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Sin `DATABASE_URL` los bots solo viven en memoria
+    pub fn from_env() -> Arc<dyn BotStore> {
+        let url = match std::env::var("DATABASE_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => {
+                warn!("DATABASE_URL not set, bots will not be persisted");
+                return Arc::new(NoBotStore);
+            }
+        };
+
+        match PgPool::connect_lazy(&url) {
+            Ok(pool) => Arc::new(Self::new(pool)),
+            Err(e) => {
+                warn!("Invalid DATABASE_URL, bots will not be persisted: {}", e);
+                Arc::new(NoBotStore)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BotStore for PostgresBotStore {
+    async fn load_all(&self) -> anyhow::Result<Vec<Bot>> {
+        let bots = sqlx::query_as::<_, Bot>(
+            r#"
+            SELECT id, tenant_id, name, phone_number, provider,
+                   COALESCE(status, 'initializing') AS status, config,
+                   COALESCE(created_at, NOW()) AS created_at,
+                   COALESCE(updated_at, NOW()) AS updated_at
+            FROM bots
+            WHERE status IS DISTINCT FROM 'deleted'
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to load bots")?;
+
+        Ok(bots)
+    }
+
+    async fn insert(&self, bot: &Bot) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO bots (id, tenant_id, name, phone_number, provider, status, config, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(bot.id)
+        .bind(&bot.tenant_id)
+        .bind(&bot.name)
+        .bind(&bot.phone_number)
+        .bind(&bot.provider)
+        .bind(&bot.status)
+        .bind(&bot.config)
+        .bind(bot.created_at)
+        .bind(bot.updated_at)
+        .execute(&self.pool)
+        .await
+        .context("Failed to insert bot")?;
+
+        Ok(())
+    }
+
+    async fn update(&self, bot: &Bot) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE bots
+            SET name = $2, phone_number = $3, provider = $4, status = $5, config = $6, updated_at = $7
+            WHERE id = $1
+            "#,
+        )
+        .bind(bot.id)
+        .bind(&bot.name)
+        .bind(&bot.phone_number)
+        .bind(&bot.provider)
+        .bind(&bot.status)
+        .bind(&bot.config)
+        .bind(bot.updated_at)
+        .execute(&self.pool)
+        .await
+        .context("Failed to update bot")?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, bot_id: Uuid) -> anyhow::Result<()> {
+        sqlx::query("UPDATE bots SET status = 'deleted', updated_at = NOW() WHERE id = $1")
+            .bind(bot_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete bot")?;
+
+        Ok(())
+    }
+}
+
+/// Sin base de datos: no carga nada y no guarda nada
+pub struct NoBotStore;
+
+#[async_trait]
+impl BotStore for NoBotStore {
+    async fn load_all(&self) -> anyhow::Result<Vec<Bot>> {
+        Ok(Vec::new())
+    }
+
+    async fn insert(&self, _bot: &Bot) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn update(&self, _bot: &Bot) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn delete(&self, _bot_id: Uuid) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sesiones de los providers (whatsapp-adapter)
+#[async_trait]
+pub trait ProviderSessions: Send + Sync {
+    async fn start(&self, provider: &str, provider_config: &serde_json::Value) -> anyhow::Result<()>;
+
+    async fn stop(&self, provider: &str, provider_config: &serde_json::Value) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl ProviderSessions for WhatsAppClient {
+    async fn start(&self, provider: &str, provider_config: &serde_json::Value) -> anyhow::Result<()> {
+        self.start_session(provider, provider_config).await
+    }
+
+    async fn stop(&self, provider: &str, provider_config: &serde_json::Value) -> anyhow::Result<()> {
+        self.stop_session(provider, provider_config).await
+    }
+}
+
+/// Máximo de bots por tenant
+#[derive(Debug, Clone)]
+pub struct BotLimits {
+    pub default: usize,
+    pub tenants: HashMap<String, usize>,
+}
+
+impl BotLimits {
+    pub fn from_env() -> Self {
+        let default = std::env::var("MAX_BOTS_PER_TENANT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BOTS_PER_TENANT);
+
+        let tenants = std::env::var("TENANT_BOT_LIMITS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let (tenant, limit) = entry.split_once('=')?;
+                Some((tenant.trim().to_string(), limit.trim().parse().ok()?))
+            })
+            .collect();
+
+        Self { default, tenants }
+    }
+
+    pub fn for_tenant(&self, tenant_id: &str) -> usize {
+        self.tenants.get(tenant_id).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BotError {
+    #[error("Bot not found")]
+    NotFound,
+    #[error("Tenant {tenant_id} already has the maximum of {limit} bots")]
+    LimitReached { tenant_id: String, limit: usize },
+    #[error("Invalid bot: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Bot nuevo
+#[derive(Debug, Deserialize)]
+pub struct CreateBot {
+    pub tenant_id: String,
+    pub name: String,
+    pub phone_number: Option<String>,
+    pub provider: String,
+    #[serde(default)]
+    pub provider_config: serde_json::Value,
+    #[serde(default)]
+    pub flows: FlowConfig,
+    #[serde(default)]
+    pub settings: BotSettings,
+}
+
+/// Cambios a un bot; los campos ausentes se mantienen
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateBot {
+    pub name: Option<String>,
+    pub phone_number: Option<String>,
+    pub provider: Option<String>,
+    pub provider_config: Option<serde_json::Value>,
+    pub flows: Option<FlowConfig>,
+    pub settings: Option<BotSettings>,
+}
+
+/// Ciclo de vida de los bots
+pub struct BotManager {
+    bots: Arc<DashMap<Uuid, BotInstance>>,
+    store: Arc<dyn BotStore>,
+    sessions: Arc<dyn ProviderSessions>,
+    limits: BotLimits,
+    /// Una operación a la vez: así el límite por tenant y los cambios de sesión no se pisan
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl BotManager {
+    pub fn new(
+        bots: Arc<DashMap<Uuid, BotInstance>>,
+        store: Arc<dyn BotStore>,
+        sessions: Arc<dyn ProviderSessions>,
+        limits: BotLimits,
+    ) -> Self {
+        Self {
+            bots,
+            store,
+            sessions,
+            limits,
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Cargar los bots guardados; los que tienen una configuración inválida se saltan
+    pub async fn load(&self) -> anyhow::Result<usize> {
+        let _guard = self.lock.lock().await;
+
+        let mut loaded = 0;
+        for record in self.store.load_all().await? {
+            match BotInstance::try_from(record) {
+                Ok(bot) => {
+                    self.bots.insert(bot.id, bot);
+                    loaded += 1;
+                }
+                Err(e) => warn!("Skipping bot: {:#}", e),
+            }
+        }
+        Ok(loaded)
+    }
+
+    pub async fn create(&self, request: CreateBot) -> Result<BotInstance, BotError> {
+        let _guard = self.lock.lock().await;
+
+        let limit = self.limits.for_tenant(&request.tenant_id);
+        let existing = self.bots.iter().filter(|bot| bot.tenant_id == request.tenant_id).count();
+        if existing >= limit {
+            return Err(BotError::LimitReached { tenant_id: request.tenant_id, limit });
+        }
+
+        let bot = BotInstance {
+            id: Uuid::new_v4(),
+            tenant_id: request.tenant_id,
+            name: request.name,
+            phone_number: request.phone_number,
+            provider: request.provider,
+            provider_config: request.provider_config,
+            flows: request.flows,
+            settings: request.settings,
+            status: BotStatus::Active,
+            stats: BotStats::default(),
+        };
+        validate(&bot)?;
+
+        self.store.insert(&bot.to_record()?).await?;
+        self.bots.insert(bot.id, bot.clone());
+        info!("🤖 Bot {} ({}) created for tenant {}", bot.id, bot.name, bot.tenant_id);
+
+        self.start_session(&bot).await;
+        Ok(bot)
+    }
+
+    pub async fn update(&self, bot_id: Uuid, changes: UpdateBot) -> Result<BotInstance, BotError> {
+        let _guard = self.lock.lock().await;
+
+        let previous = self.get(bot_id)?;
+        let mut bot = previous.clone();
+        if let Some(name) = changes.name {
+            bot.name = name;
+        }
+        if let Some(phone_number) = changes.phone_number {
+            bot.phone_number = Some(phone_number);
+        }
+        if let Some(provider) = changes.provider {
+            bot.provider = provider;
+        }
+        if let Some(provider_config) = changes.provider_config {
+            bot.provider_config = provider_config;
+        }
+        if let Some(flows) = changes.flows {
+            bot.flows = flows;
+        }
+        if let Some(settings) = changes.settings {
+            bot.settings = settings;
+        }
+        validate(&bot)?;
+
+        let bot = self.replace(bot).await?;
+        info!("🤖 Bot {} updated", bot_id);
+
+        // Los mensajes nuevos ya salen por el provider nuevo
+        if bot.provider != previous.provider || bot.provider_config != previous.provider_config {
+            info!("🔌 Bot {} switched provider {} -> {}", bot_id, previous.provider, bot.provider);
+            self.stop_session(&previous).await;
+            self.start_session(&bot).await;
+        }
+        Ok(bot)
+    }
+
+    pub async fn set_status(&self, bot_id: Uuid, status: BotStatus) -> Result<BotInstance, BotError> {
+        let _guard = self.lock.lock().await;
+
+        let mut bot = self.get(bot_id)?;
+        if bot.status == status {
+            return Ok(bot);
+        }
+        bot.status = status;
+
+        let bot = self.replace(bot).await?;
+        info!("🤖 Bot {} is now {}", bot_id, status.as_str());
+        Ok(bot)
+    }
+
+    /// Borrar un bot; devuelve cómo estaba (sus conversaciones terminan con él)
+    pub async fn delete(&self, bot_id: Uuid) -> Result<BotInstance, BotError> {
+        let _guard = self.lock.lock().await;
+
+        let bot = self.get(bot_id)?;
+        self.store.delete(bot_id).await?;
+        self.bots.remove(&bot_id);
+        info!("🗑️ Bot {} deleted", bot_id);
+
+        self.stop_session(&bot).await;
+        Ok(bot)
+    }
+
+    fn get(&self, bot_id: Uuid) -> Result<BotInstance, BotError> {
+        self.bots.get(&bot_id).map(|bot| bot.clone()).ok_or(BotError::NotFound)
+    }
+
+    /// Guardar los cambios; las stats siguen siendo las del bot en memoria
+    async fn replace(&self, mut bot: BotInstance) -> Result<BotInstance, BotError> {
+        self.store.update(&bot.to_record()?).await?;
+
+        match self.bots.get_mut(&bot.id) {
+            Some(mut current) => {
+                bot.stats = current.stats.clone();
+                *current = bot.clone();
+                Ok(bot)
+            }
+            None => Err(BotError::NotFound),
+        }
+    }
+
+    // La sesión no es parte del alta: si falla, el bridge la vuelve a crear en el primer envío
+
+    async fn start_session(&self, bot: &BotInstance) {
+        if let Err(e) = self.sessions.start(&bot.provider, &bot.provider_config).await {
+            warn!("Could not start {} session of bot {}: {:#}", bot.provider, bot.id, e);
+        }
+    }
+
+    async fn stop_session(&self, bot: &BotInstance) {
+        if let Err(e) = self.sessions.stop(&bot.provider, &bot.provider_config).await {
+            warn!("Could not stop {} session of bot {}: {:#}", bot.provider, bot.id, e);
+        }
+    }
+}
+
+fn validate(bot: &BotInstance) -> Result<(), BotError> {
+    if bot.name.trim().is_empty() {
+        return Err(BotError::Invalid("name is required".to_string()));
+    }
+    if bot.tenant_id.trim().is_empty() {
+        return Err(BotError::Invalid("tenant_id is required".to_string()));
+    }
+    if !PROVIDERS.contains(&bot.provider.as_str()) {
+        return Err(BotError::Invalid(format!("unknown provider '{}'", bot.provider)));
+    }
+    if !bot.provider_config.is_object() {
+        return Err(BotError::Invalid("provider_config must be an object".to_string()));
+    }
+    if bot.settings.max_conversation_timeout_seconds == 0 {
+        return Err(BotError::Invalid("max_conversation_timeout_seconds must be positive".to_string()));
+    }
+    if let Some(nudge) = &bot.settings.expiry.nudge {
+        if nudge.before_seconds >= bot.settings.max_conversation_timeout_seconds {
+            return Err(BotError::Invalid("expiry.nudge.before_seconds must be less than max_conversation_timeout_seconds".to_string()));
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_create(
+    state: web::Data<OrchestratorState>,
+    body: web::Json<CreateBot>,
+) -> impl Responder {
+    match state.bot_manager.create(body.into_inner()).await {
+        Ok(bot) => HttpResponse::Created().json(bot),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn handle_update(
+    state: web::Data<OrchestratorState>,
+    path: web::Path<Uuid>,
+    body: web::Json<UpdateBot>,
+) -> impl Responder {
+    match state.bot_manager.update(path.into_inner(), body.into_inner()).await {
+        Ok(bot) => HttpResponse::Ok().json(bot),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn handle_pause(state: web::Data<OrchestratorState>, path: web::Path<Uuid>) -> impl Responder {
+    match state.bot_manager.set_status(path.into_inner(), BotStatus::Paused).await {
+        Ok(bot) => HttpResponse::Ok().json(bot),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn handle_resume(state: web::Data<OrchestratorState>, path: web::Path<Uuid>) -> impl Responder {
+    match state.bot_manager.set_status(path.into_inner(), BotStatus::Active).await {
+        Ok(bot) => HttpResponse::Ok().json(bot),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn handle_delete(state: web::Data<OrchestratorState>, path: web::Path<Uuid>) -> impl Responder {
+    let bot_id = path.into_inner();
+    match state.bot_manager.delete(bot_id).await {
+        Ok(bot) => {
+            state.metrics.remove(bot_id);
+            expiry::end_bot_conversations(&state, bot);
+            HttpResponse::Ok().json(serde_json::json!({ "status": "deleted" }))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(error: BotError) -> HttpResponse {
+    let body = serde_json::json!({ "error": error.to_string() });
+    match error {
+        BotError::NotFound => HttpResponse::NotFound().json(body),
+        BotError::LimitReached { .. } => HttpResponse::Forbidden().json(body),
+        BotError::Invalid(_) => HttpResponse::UnprocessableEntity().json(body),
+        BotError::Internal(e) => {
+            tracing::error!("Bot operation failed: {:#}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({ "error": "Bot operation failed" }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    /// Registra las sesiones que se abren y se cierran
+    #[derive(Default)]
+    struct RecordingSessions {
+        calls: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ProviderSessions for RecordingSessions {
+        async fn start(&self, provider: &str, _provider_config: &serde_json::Value) -> anyhow::Result<()> {
+            self.calls.lock().push(format!("start {}", provider));
+            Ok(())
+        }
+
+        async fn stop(&self, provider: &str, _provider_config: &serde_json::Value) -> anyhow::Result<()> {
+            self.calls.lock().push(format!("stop {}", provider));
+            Ok(())
+        }
+    }
+
+    fn manager(sessions: Arc<RecordingSessions>, limit: usize) -> BotManager {
+        let limits = BotLimits { default: limit, tenants: HashMap::new() };
+        BotManager::new(Arc::new(DashMap::new()), Arc::new(NoBotStore), sessions, limits)
+    }
+
+    fn create_request(tenant_id: &str) -> CreateBot {
+        CreateBot {
+            tenant_id: tenant_id.to_string(),
+            name: "Floristería".to_string(),
+            phone_number: None,
+            provider: "venom".to_string(),
+            provider_config: serde_json::json!({ "bridge_url": "http://localhost:3013", "session_name": "flores" }),
+            flows: FlowConfig::default(),
+            settings: BotSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut bot = BotInstance::try_from(Bot {
+            id: Uuid::new_v4(),
+            tenant_id: "flores".to_string(),
+            name: "Floristería".to_string(),
+            phone_number: None,
+            provider: "baileys".to_string(),
+            // Bot creado por otro servicio: solo con la configuración del provider
+            status: "initializing".to_string(),
+            config: serde_json::json!({ "provider_config": { "session_id": "flores" } }),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }).unwrap();
+        assert_eq!(bot.status, BotStatus::Active);
+        assert_eq!(bot.settings.timezone, "UTC");
+
+        bot.status = BotStatus::Paused;
+        let record = bot.to_record().unwrap();
+        assert_eq!(record.status, "paused");
+
+        let restored = BotInstance::try_from(record).unwrap();
+        assert_eq!(restored.status, BotStatus::Paused);
+        assert_eq!(restored.provider_config, bot.provider_config);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_limit() {
+        let manager = manager(Arc::default(), 1);
+
+        manager.create(create_request("flores")).await.unwrap();
+        let error = manager.create(create_request("flores")).await.unwrap_err();
+        assert!(matches!(error, BotError::LimitReached { limit: 1, .. }));
+
+        // Otros tenants no cuentan
+        manager.create(create_request("panaderia")).await.unwrap();
+
+        let invalid = CreateBot { provider: "telegram".to_string(), ..create_request("libreria") };
+        assert!(matches!(manager.create(invalid).await, Err(BotError::Invalid(_))));
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle() {
+        let sessions = Arc::new(RecordingSessions::default());
+        let manager = manager(sessions.clone(), 10);
+
+        let bot = manager.create(create_request("flores")).await.unwrap();
+        manager.bots.get_mut(&bot.id).unwrap().stats.messages_received = 7;
+
+        // Cambiar solo el nombre no toca la sesión
+        let changes = UpdateBot { name: Some("Flores Express".to_string()), ..Default::default() };
+        let updated = manager.update(bot.id, changes).await.unwrap();
+        assert_eq!(updated.name, "Flores Express");
+        assert_eq!(updated.stats.messages_received, 7);
+        assert_eq!(*sessions.calls.lock(), ["start venom"]);
+
+        let changes = UpdateBot {
+            provider: Some("official".to_string()),
+            provider_config: Some(serde_json::json!({ "access_token": "token", "phone_number_id": "123" })),
+            ..Default::default()
+        };
+        manager.update(bot.id, changes).await.unwrap();
+        assert_eq!(*sessions.calls.lock(), ["start venom", "stop venom", "start official"]);
+        assert_eq!(manager.bots.get(&bot.id).unwrap().provider, "official");
+
+        let paused = manager.set_status(bot.id, BotStatus::Paused).await.unwrap();
+        assert_eq!(paused.status, BotStatus::Paused);
+        assert_eq!(manager.bots.get(&bot.id).unwrap().status, BotStatus::Paused);
+        manager.set_status(bot.id, BotStatus::Active).await.unwrap();
+
+        manager.delete(bot.id).await.unwrap();
+        assert!(manager.bots.is_empty());
+        assert_eq!(sessions.calls.lock().last().unwrap(), "stop official");
+        assert!(matches!(manager.delete(bot.id).await, Err(BotError::NotFound)));
+    }
+}
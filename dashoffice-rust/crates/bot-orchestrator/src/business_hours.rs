@@ -0,0 +1,374 @@
+//! Business Hours - Horario de atención de un bot
+//!
+//! Horario semanal con turnos por día (`"09:00-13:00"`, `"22:00-02:00"` cruza
+//! la medianoche, `"00:00-24:00"` es el día completo) y feriados que cierran
+//! el día o lo reemplazan por un horario especial. Todo se evalúa en la zona
+//! horaria del bot.
+//!
+//! ```yaml
+//! business_hours:
+//!   weekly:
+//!     monday: ["09:00-13:00", "14:00-18:00"]
+//!     saturday: ["09:00-13:00"]
+//!   holidays:
+//!     - { date: 2026-12-25, yearly: true, name: Navidad }
+//!     - { date: 2026-12-24, hours: ["09:00-12:00"] }
+//!   after_hours:
+//!     message: "Ya cerramos 🌙. Te responderemos {{bot.business_hours.next_open_text}}."
+//! ```
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::clock::Clock;
+use super::template::WEEKDAYS;
+
+/// Hasta dónde se busca la próxima apertura
+const LOOKAHEAD_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BusinessHours {
+    #[serde(default)]
+    pub weekly: WeeklySchedule,
+    #[serde(default)]
+    pub holidays: Vec<Holiday>,
+    #[serde(default)]
+    pub after_hours: AfterHours,
+}
+
+/// Turnos de cada día de la semana; un día sin turnos está cerrado
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WeeklySchedule {
+    #[serde(default)]
+    pub monday: Vec<TimeRange>,
+    #[serde(default)]
+    pub tuesday: Vec<TimeRange>,
+    #[serde(default)]
+    pub wednesday: Vec<TimeRange>,
+    #[serde(default)]
+    pub thursday: Vec<TimeRange>,
+    #[serde(default)]
+    pub friday: Vec<TimeRange>,
+    #[serde(default)]
+    pub saturday: Vec<TimeRange>,
+    #[serde(default)]
+    pub sunday: Vec<TimeRange>,
+}
+
+/// Turno `HH:MM-HH:MM`. Si cierra antes de abrir, termina al día siguiente.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holiday {
+    pub date: NaiveDate,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Se repite todos los años en el mismo día y mes
+    #[serde(default)]
+    pub yearly: bool,
+    /// Horario especial de ese día; vacío = cerrado
+    #[serde(default)]
+    pub hours: Vec<TimeRange>,
+}
+
+/// Qué hace el bot con los mensajes que llegan fuera de horario
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AfterHours {
+    /// Flow que reemplaza al welcome flow en las conversaciones que empiezan cerrado
+    #[serde(default)]
+    pub flow_id: Option<Uuid>,
+    /// Respuesta automática (template), una sola vez por cada cierre
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Estado del horario en un momento dado
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleStatus {
+    pub open: bool,
+    /// Próxima apertura, si está cerrado y hay alguna en el próximo año
+    pub next_open: Option<DateTime<Tz>>,
+    pub checked_at: DateTime<Tz>,
+}
+
+impl BusinessHours {
+    pub fn status(&self, timezone: Tz, clock: &dyn Clock) -> ScheduleStatus {
+        let now = clock.now().with_timezone(&timezone);
+        let open = self.is_open_at(&now);
+
+        ScheduleStatus {
+            open,
+            next_open: if open { None } else { self.next_open_after(&now) },
+            checked_at: now,
+        }
+    }
+
+    /// Turnos de una fecha: los del feriado si lo es, si no los de su día de la semana
+    fn ranges_on(&self, date: NaiveDate) -> &[TimeRange] {
+        match self.holidays.iter().find(|holiday| holiday.matches(date)) {
+            Some(holiday) => &holiday.hours,
+            None => self.weekly.day(date.weekday()),
+        }
+    }
+
+    fn is_open_at(&self, local: &DateTime<Tz>) -> bool {
+        let date = local.date_naive();
+        let time = local.time();
+
+        let today = self.ranges_on(date).iter().any(|range| range.opens_before(time));
+        let from_yesterday = date.pred_opt()
+            .is_some_and(|yesterday| self.ranges_on(yesterday).iter().any(|range| range.spills_past(time)));
+
+        today || from_yesterday
+    }
+
+    fn next_open_after(&self, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = now.timezone();
+        let today = now.date_naive();
+
+        (0..=LOOKAHEAD_DAYS)
+            .filter_map(|offset| today.checked_add_signed(Duration::days(offset)))
+            .flat_map(|date| {
+                let mut opens: Vec<NaiveTime> = self.ranges_on(date).iter().map(|range| range.open).collect();
+                opens.sort();
+                opens.into_iter().map(move |time| date.and_time(time))
+            })
+            // Una hora que no existe por el cambio de horario se salta
+            .filter_map(|local| timezone.from_local_datetime(&local).earliest())
+            .find(|candidate| candidate > now)
+    }
+}
+
+impl WeeklySchedule {
+    pub fn day(&self, weekday: Weekday) -> &[TimeRange] {
+        match weekday {
+            Weekday::Mon => &self.monday,
+            Weekday::Tue => &self.tuesday,
+            Weekday::Wed => &self.wednesday,
+            Weekday::Thu => &self.thursday,
+            Weekday::Fri => &self.friday,
+            Weekday::Sat => &self.saturday,
+            Weekday::Sun => &self.sunday,
+        }
+    }
+}
+
+impl TimeRange {
+    fn crosses_midnight(&self) -> bool {
+        self.close <= self.open
+    }
+
+    /// ¿Abierto a esta hora del mismo día en que empieza el turno?
+    fn opens_before(&self, time: NaiveTime) -> bool {
+        if self.crosses_midnight() {
+            time >= self.open
+        } else {
+            time >= self.open && time < self.close
+        }
+    }
+
+    /// ¿Sigue abierto a esta hora del día siguiente?
+    fn spills_past(&self, time: NaiveTime) -> bool {
+        self.crosses_midnight() && time < self.close
+    }
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time range '{}', expected HH:MM-HH:MM", value);
+        let (open, close) = value.split_once('-').ok_or_else(invalid)?;
+
+        let open = NaiveTime::parse_from_str(open.trim(), "%H:%M").map_err(|_| invalid())?;
+        let close = match close.trim() {
+            "24:00" => NaiveTime::MIN,
+            close => NaiveTime::parse_from_str(close, "%H:%M").map_err(|_| invalid())?,
+        };
+
+        Ok(TimeRange { open, close })
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(range: TimeRange) -> Self {
+        let close = if range.close == NaiveTime::MIN {
+            "24:00".to_string()
+        } else {
+            range.close.format("%H:%M").to_string()
+        };
+        format!("{}-{}", range.open.format("%H:%M"), close)
+    }
+}
+
+impl Holiday {
+    fn matches(&self, date: NaiveDate) -> bool {
+        if self.yearly {
+            self.date.month() == date.month() && self.date.day() == date.day()
+        } else {
+            self.date == date
+        }
+    }
+}
+
+impl ScheduleStatus {
+    /// Variables `bot.business_hours.*`: `open`, `next_open` (RFC 3339) y
+    /// `next_open_text` ("mañana a las 09:00")
+    pub fn template_variables(&self) -> serde_json::Value {
+        serde_json::json!({
+            "open": self.open,
+            "next_open": self.next_open.map(|next| next.to_rfc3339()),
+            "next_open_text": self.next_open.map(|next| describe_opening(&next, &self.checked_at)),
+        })
+    }
+}
+
+/// Próxima apertura en palabras, relativa a `now`
+pub fn describe_opening(next_open: &DateTime<Tz>, now: &DateTime<Tz>) -> String {
+    let time = next_open.format("%H:%M");
+
+    match (next_open.date_naive() - now.date_naive()).num_days() {
+        0 => format!("hoy a las {}", time),
+        1 => format!("mañana a las {}", time),
+        2..=6 => {
+            let weekday = WEEKDAYS[next_open.weekday().num_days_from_monday() as usize];
+            format!("el {} a las {}", weekday, time)
+        }
+        _ => format!("el {} a las {}", next_open.format("%d/%m"), time),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use chrono::Utc;
+
+    const CARACAS: Tz = chrono_tz::America::Caracas;
+
+    fn range(value: &str) -> TimeRange {
+        TimeRange::try_from(value.to_string()).unwrap()
+    }
+
+    fn office_hours() -> BusinessHours {
+        let weekday = vec![range("09:00-13:00"), range("14:00-18:00")];
+        BusinessHours {
+            weekly: WeeklySchedule {
+                monday: weekday.clone(),
+                tuesday: weekday.clone(),
+                wednesday: weekday.clone(),
+                thursday: weekday.clone(),
+                friday: weekday,
+                saturday: vec![range("09:00-13:00")],
+                sunday: vec![],
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Hora local de Caracas (UTC-4) como instante UTC
+    fn caracas(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        CARACAS.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_weekly_schedule_in_bot_timezone() {
+        let hours = office_hours();
+        // Lunes 16/03/2026
+        let clock = FixedClock::new(caracas(2026, 3, 16, 10, 0));
+        assert!(hours.status(CARACAS, &clock).open);
+
+        // 13:30 es la pausa del mediodía
+        clock.set(caracas(2026, 3, 16, 13, 30));
+        let status = hours.status(CARACAS, &clock);
+        assert!(!status.open);
+        assert_eq!(status.next_open.unwrap().time(), NaiveTime::from_hms_opt(14, 0, 0).unwrap());
+        assert_eq!(status.template_variables()["next_open_text"], "hoy a las 14:00");
+
+        // 13:30 UTC son las 09:30 en Caracas
+        clock.set(Utc.with_ymd_and_hms(2026, 3, 16, 13, 30, 0).unwrap());
+        assert!(hours.status(CARACAS, &clock).open);
+
+        // Sábado en la tarde: abre el lunes
+        clock.set(caracas(2026, 3, 21, 15, 0));
+        let status = hours.status(CARACAS, &clock);
+        assert!(!status.open);
+        assert_eq!(status.template_variables()["next_open_text"], "el lunes a las 09:00");
+    }
+
+    #[test]
+    fn test_holidays_close_or_replace_the_day() {
+        let mut hours = office_hours();
+        hours.holidays = vec![
+            Holiday {
+                date: NaiveDate::from_ymd_opt(2020, 12, 25).unwrap(),
+                name: Some("Navidad".to_string()),
+                yearly: true,
+                hours: vec![],
+            },
+            Holiday {
+                date: NaiveDate::from_ymd_opt(2026, 12, 24).unwrap(),
+                name: None,
+                yearly: false,
+                hours: vec![range("09:00-12:00")],
+            },
+        ];
+
+        // Jueves 24/12 con horario especial
+        let clock = FixedClock::new(caracas(2026, 12, 24, 11, 0));
+        assert!(hours.status(CARACAS, &clock).open);
+        clock.set(caracas(2026, 12, 24, 15, 0));
+        assert!(!hours.status(CARACAS, &clock).open);
+
+        // Viernes 25/12 cerrado: abre el sábado
+        clock.set(caracas(2026, 12, 25, 10, 0));
+        let status = hours.status(CARACAS, &clock);
+        assert!(!status.open);
+        assert_eq!(status.template_variables()["next_open_text"], "mañana a las 09:00");
+    }
+
+    #[test]
+    fn test_ranges_across_midnight() {
+        let hours = BusinessHours {
+            weekly: WeeklySchedule {
+                friday: vec![range("20:00-02:00")],
+                saturday: vec![range("00:00-24:00")],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Viernes 20/03/2026 en la noche y la madrugada del sábado
+        let clock = FixedClock::new(caracas(2026, 3, 20, 23, 0));
+        assert!(hours.status(CARACAS, &clock).open);
+        clock.set(caracas(2026, 3, 21, 1, 0));
+        assert!(hours.status(CARACAS, &clock).open);
+        clock.set(caracas(2026, 3, 21, 23, 59));
+        assert!(hours.status(CARACAS, &clock).open);
+
+        clock.set(caracas(2026, 3, 22, 12, 0));
+        let status = hours.status(CARACAS, &clock);
+        assert!(!status.open);
+        assert_eq!(status.template_variables()["next_open_text"], "el viernes a las 20:00");
+
+        // Sin turnos nunca abre
+        assert_eq!(BusinessHours::default().status(CARACAS, &clock).next_open, None);
+    }
+
+    #[test]
+    fn test_time_range_format() {
+        let parsed: Vec<TimeRange> = serde_json::from_str(r#"["09:00-18:00", "22:00-24:00"]"#).unwrap();
+        assert_eq!(parsed[1].close, NaiveTime::MIN);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::json!(["09:00-18:00", "22:00-24:00"]));
+
+        assert!(serde_json::from_str::<TimeRange>(r#""9-18""#).is_err());
+        assert!(serde_json::from_str::<TimeRange>(r#""09:00-25:00""#).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+//! Clock - Hora actual inyectable
+//!
+//! Las reglas que dependen de la hora (horario de atención) la piden a un
+//! `Clock` en lugar de llamar a `Utc::now()`, para poder probarlas con una
+//! hora fija.
+
+use chrono::{DateTime, Utc};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Hora del sistema
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Reloj detenido en una hora dada, que los tests pueden mover
+#[cfg(test)]
+pub struct FixedClock(parking_lot::Mutex<DateTime<Utc>>);
+
+#[cfg(test)]
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(parking_lot::Mutex::new(now))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock() = now;
+    }
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock()
+    }
+}
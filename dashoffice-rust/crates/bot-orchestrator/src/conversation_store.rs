@@ -0,0 +1,221 @@
+//! Conversation Store - Persistencia de `ConversationState` en Redis
+//!
+//! - Cada conversación se guarda en `bot:conversation:{id}` con TTL igual al
+//!   timeout de conversación del bot
+//! - El estado se guarda dentro de un sobre versionado (`{"version": N, "state": ...}`)
+//!   para poder leer lo que escribió una release anterior después de un deploy
+//! - Las conversaciones se cargan de forma perezosa al llegar un mensaje
+
+use anyhow::{Context, Result};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use super::state_machine::ConversationState;
+
+/// Versión actual del formato guardado en Redis
+///
+/// Subirla al cambiar `ConversationState` de forma incompatible y añadir el
+/// paso correspondiente en `migrate`.
+pub const STATE_FORMAT_VERSION: u32 = 2;
+
+const KEY_PREFIX: &str = "bot:conversation:";
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    state: Value,
+}
+
+/// Serializar una conversación con el formato actual
+pub fn encode_state(conversation: &ConversationState) -> Result<String> {
+    let envelope = Envelope {
+        version: STATE_FORMAT_VERSION,
+        state: serde_json::to_value(conversation)?,
+    };
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+/// Leer una conversación escrita por esta release o por una anterior
+pub fn decode_state(data: &str) -> Result<ConversationState> {
+    let raw: Value = serde_json::from_str(data).context("Conversation state is not valid JSON")?;
+
+    // Antes del sobre versionado se guardaba el estado directamente (versión 0)
+    let (version, state) = match serde_json::from_value::<Envelope>(raw.clone()) {
+        Ok(envelope) => (envelope.version, envelope.state),
+        Err(_) => (0, raw),
+    };
+
+    if version > STATE_FORMAT_VERSION {
+        anyhow::bail!(
+            "Conversation state has format v{} but this release only reads up to v{}",
+            version, STATE_FORMAT_VERSION
+        );
+    }
+
+    let state = migrate(version, state)?;
+    serde_json::from_value(state).context("Conversation state does not match the current format")
+}
+
+/// Llevar un estado guardado con `version` hasta `STATE_FORMAT_VERSION`
+///
+/// Los campos nuevos con `#[serde(default)]` no necesitan migración.
+fn migrate(version: u32, mut state: Value) -> Result<Value> {
+    if version < 1 {
+        // v0 → v1: `context` y `metadata` podían faltar
+        if let Value::Object(fields) = &mut state {
+            for field in ["context", "metadata"] {
+                fields.entry(field).or_insert_with(|| Value::Object(Default::default()));
+            }
+            fields.entry("message_history").or_insert_with(|| Value::Array(Vec::new()));
+        }
+    }
+
+    if version < 2 {
+        // v1 → v2: el `content` del historial pasa de texto a `MessageContent`
+        if let Some(Value::Array(history)) = state.get_mut("message_history") {
+            for message in history {
+                if let Some(content) = message.get_mut("content") {
+                    if let Value::String(body) = content.take() {
+                        *content = serde_json::json!({ "type": "text", "body": body });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(state)
+}
+
+/// Almacén de conversaciones en Redis
+pub struct ConversationStore {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+}
+
+impl ConversationStore {
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            connection: OnceCell::new(),
+        }
+    }
+
+    /// Conexión compartida; se abre con el primer uso (y se reintenta si falla)
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connection = self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(connection.clone())
+    }
+
+    /// Guardar una conversación; expira tras `ttl` sin actividad
+    pub async fn save(&self, conversation: &ConversationState, ttl: Duration) -> Result<()> {
+        let data = encode_state(conversation)?;
+        let mut conn = self.connection().await?;
+        let _: () = conn
+            .set_ex(key(&conversation.id), data, ttl.as_secs().max(1))
+            .await
+            .with_context(|| format!("Failed to save conversation {}", conversation.id))?;
+        Ok(())
+    }
+
+    /// Cargar una conversación. `None` si no existe o ya expiró.
+    pub async fn load(&self, conversation_id: &str) -> Result<Option<ConversationState>> {
+        let mut conn = self.connection().await?;
+        let data: Option<String> = conn.get(key(conversation_id)).await?;
+
+        match data {
+            Some(data) => decode_state(&data)
+                .with_context(|| format!("Failed to read conversation {}", conversation_id))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn delete(&self, conversation_id: &str) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: () = conn.del(key(conversation_id)).await?;
+        Ok(())
+    }
+}
+
+fn key(conversation_id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, conversation_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_round_trip_keeps_flow_position() {
+        let mut conversation = ConversationState::new("bot:+58".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.current_flow_id = Some(Uuid::new_v4());
+        conversation.current_flow_version = Some(3);
+        conversation.current_step_id = Some("pedir_nombre".to_string());
+        conversation.set_variable("nombre", serde_json::json!("Ana"));
+
+        let data = encode_state(&conversation).unwrap();
+        assert!(data.starts_with(&format!("{{\"version\":{}", STATE_FORMAT_VERSION)));
+
+        let restored = decode_state(&data).unwrap();
+        assert_eq!(restored.current_flow_id, conversation.current_flow_id);
+        assert_eq!(restored.current_flow_version, Some(3));
+        assert_eq!(restored.current_step_id.as_deref(), Some("pedir_nombre"));
+        assert_eq!(restored.get_variable("nombre"), Some(&serde_json::json!("Ana")));
+    }
+
+    #[test]
+    fn test_reads_unversioned_state() {
+        let data = serde_json::json!({
+            "id": "bot:+58",
+            "bot_id": Uuid::new_v4(),
+            "user_phone": "+58",
+            "current_flow_id": null,
+            "current_step_id": null,
+            "created_at": "2026-01-10T12:00:00Z",
+            "last_activity": "2026-01-10T12:05:00Z",
+        });
+
+        let restored = decode_state(&data.to_string()).unwrap();
+        assert_eq!(restored.user_phone, "+58");
+        assert!(restored.context.is_empty());
+        assert!(restored.attempts.is_empty());
+    }
+
+    #[test]
+    fn test_migrates_text_history_from_v1() {
+        let data = serde_json::json!({
+            "version": 1,
+            "state": {
+                "id": "bot:+58",
+                "bot_id": Uuid::new_v4(),
+                "user_phone": "+58",
+                "current_flow_id": null,
+                "current_step_id": null,
+                "context": {},
+                "metadata": {},
+                "message_history": [
+                    { "role": "user", "content": "hola", "timestamp": "2026-01-10T12:00:00Z" }
+                ],
+                "created_at": "2026-01-10T12:00:00Z",
+                "last_activity": "2026-01-10T12:05:00Z",
+            }
+        });
+
+        let restored = decode_state(&data.to_string()).unwrap();
+        assert_eq!(restored.message_history[0].content, crate::message::MessageContent::text("hola"));
+    }
+
+    #[test]
+    fn test_rejects_state_from_newer_release() {
+        let data = serde_json::json!({ "version": STATE_FORMAT_VERSION + 1, "state": {} });
+        assert!(decode_state(&data.to_string()).is_err());
+    }
+}
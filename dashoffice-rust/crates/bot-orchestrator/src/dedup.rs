@@ -0,0 +1,223 @@
+//! Dedup - Idempotencia de webhooks
+//!
+//! Los providers reintentan los webhooks que no reciben respuesta a tiempo.
+//! Cada mensaje se identifica por el id que le asigna el provider o, si no
+//! trae uno, por un hash de remitente/texto y el timestamp del provider (nunca
+//! la hora de llegada, que cambia en cada reintento). Sin id ni timestamp no
+//! se puede distinguir un reintento de un cliente que vuelve a escribir lo
+//! mismo ("1", "si"), así que esos mensajes no se deduplican. La primera vez
+//! que se ve una clave se procesa; las repeticiones dentro del TTL se descartan.
+//!
+//! Las claves se guardan en Redis (`SET NX EX`, compartido entre instancias)
+//! y en una caché local acotada que evita ir a Redis en los reintentos
+//! inmediatos y sirve de respaldo si Redis no está disponible.
+
+use parking_lot::Mutex;
+use redis::aio::ConnectionManager;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+const KEY_PREFIX: &str = "bot:webhook:seen:";
+
+/// Configuración por defecto: un día de memoria, 10.000 claves locales
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const DEFAULT_LOCAL_CAPACITY: usize = 10_000;
+
+/// Clave de idempotencia de un mensaje entrante; `None` si el mensaje no
+/// trae ni id ni timestamp del provider
+pub fn dedup_key(
+    bot_id: &Uuid,
+    provider_message_id: Option<&str>,
+    from: &str,
+    provider_timestamp: Option<i64>,
+    body: &str,
+) -> Option<String> {
+    if let Some(id) = provider_message_id.filter(|id| !id.is_empty()) {
+        return Some(format!("{}:id:{}", bot_id, id));
+    }
+
+    let timestamp = provider_timestamp?.to_string();
+    let mut hasher = Sha256::new();
+    for part in [from, &timestamp, body] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    Some(format!("{}:hash:{}", bot_id, hex::encode(hasher.finalize())))
+}
+
+pub struct MessageDeduplicator {
+    redis: Option<redis::Client>,
+    connection: OnceCell<ConnectionManager>,
+    local: Mutex<LocalCache>,
+    ttl: Duration,
+}
+
+impl MessageDeduplicator {
+    /// `redis: None` deja solo la caché local (una única instancia)
+    pub fn new(redis: Option<redis::Client>, ttl: Duration, local_capacity: usize) -> Self {
+        Self {
+            redis,
+            connection: OnceCell::new(),
+            local: Mutex::new(LocalCache::new(local_capacity, ttl)),
+            ttl,
+        }
+    }
+
+    /// `true` la primera vez que se ve `key` dentro del TTL
+    pub async fn first_seen(&self, key: &str) -> bool {
+        if self.local.lock().contains(key, Instant::now()) {
+            return false;
+        }
+
+        let first = match self.claim_in_redis(key).await {
+            Ok(first) => first,
+            Err(e) => {
+                tracing::warn!("Webhook dedup without Redis ({:#}), using local cache only", e);
+                true
+            }
+        };
+
+        // Otro hilo pudo reclamarla localmente mientras se consultaba Redis
+        let first_locally = self.local.lock().insert(key, Instant::now());
+        first && first_locally
+    }
+
+    /// `SET key 1 NX EX ttl`: solo la primera instancia en verla la reclama
+    async fn claim_in_redis(&self, key: &str) -> anyhow::Result<bool> {
+        let Some(client) = &self.redis else {
+            return Ok(true);
+        };
+        let connection = self.connection
+            .get_or_try_init(|| ConnectionManager::new(client.clone()))
+            .await?;
+
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(format!("{}{}", KEY_PREFIX, key))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.ttl.as_secs().max(1))
+            .query_async(&mut connection.clone())
+            .await?;
+
+        Ok(claimed.is_some())
+    }
+}
+
+/// Caché local con TTL y un máximo de claves (se descartan las más antiguas)
+struct LocalCache {
+    seen: HashMap<String, Instant>,
+    order: VecDeque<(String, Instant)>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl LocalCache {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+            ttl,
+        }
+    }
+
+    fn contains(&mut self, key: &str, now: Instant) -> bool {
+        self.evict(now);
+        self.seen.contains_key(key)
+    }
+
+    /// Registrar una clave. `false` si ya estaba.
+    fn insert(&mut self, key: &str, now: Instant) -> bool {
+        self.evict(now);
+        if self.seen.contains_key(key) {
+            return false;
+        }
+
+        while self.seen.len() >= self.capacity {
+            match self.order.pop_front() {
+                Some((oldest, _)) => {
+                    self.seen.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+
+        self.seen.insert(key.to_string(), now);
+        self.order.push_back((key.to_string(), now));
+        true
+    }
+
+    fn evict(&mut self, now: Instant) {
+        while let Some((key, seen_at)) = self.order.front() {
+            if now.duration_since(*seen_at) < self.ttl {
+                break;
+            }
+            self.seen.remove(key);
+            self.order.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_prefers_provider_id() {
+        let bot_id = Uuid::new_v4();
+
+        let with_id = dedup_key(&bot_id, Some("wamid.1"), "+58", Some(1), "hola");
+        assert_eq!(with_id, dedup_key(&bot_id, Some("wamid.1"), "+58", Some(2), "otro texto"));
+        assert_eq!(with_id, dedup_key(&bot_id, Some("wamid.1"), "+58", None, "hola"));
+
+        let hashed = dedup_key(&bot_id, None, "+58", Some(1), "hola");
+        assert_eq!(hashed, dedup_key(&bot_id, Some(""), "+58", Some(1), "hola"));
+        assert_ne!(hashed, dedup_key(&bot_id, None, "+58", Some(2), "hola"));
+        assert_ne!(hashed, dedup_key(&Uuid::new_v4(), None, "+58", Some(1), "hola"));
+    }
+
+    #[tokio::test]
+    async fn test_repeat_without_id_or_timestamp_is_processed() {
+        let dedup = MessageDeduplicator::new(None, DEFAULT_TTL, 100);
+        let bot_id = Uuid::new_v4();
+
+        // Con timestamp del provider, el reintento tiene la misma clave
+        let first = dedup_key(&bot_id, None, "+58", Some(1), "1").unwrap();
+        assert!(dedup.first_seen(&first).await);
+        assert!(!dedup.first_seen(&dedup_key(&bot_id, None, "+58", Some(1), "1").unwrap()).await);
+
+        // Sin id ni timestamp, el cliente que vuelve a escribir "1" más tarde se atiende
+        assert_eq!(dedup_key(&bot_id, None, "+58", None, "1"), None);
+        assert_eq!(dedup_key(&bot_id, Some(""), "+58", None, "1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_detected() {
+        let dedup = MessageDeduplicator::new(None, DEFAULT_TTL, 100);
+
+        assert!(dedup.first_seen("a").await);
+        assert!(!dedup.first_seen("a").await);
+        assert!(dedup.first_seen("b").await);
+    }
+
+    #[test]
+    fn test_local_cache_is_bounded_and_expires() {
+        let mut cache = LocalCache::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(cache.insert("a", start));
+        assert!(cache.insert("b", start));
+        assert!(cache.insert("c", start));
+        // "a" se descartó por capacidad
+        assert!(!cache.contains("a", start));
+        assert!(cache.contains("c", start));
+
+        let later = start + Duration::from_secs(61);
+        assert!(!cache.contains("c", later));
+        assert!(cache.insert("c", later));
+    }
+}
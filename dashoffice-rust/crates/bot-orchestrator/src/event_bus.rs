@@ -0,0 +1,61 @@
+//! Event Bus - Difusión de los `BotEvent`
+//!
+//! - `subscribe`: `broadcast` para consumidores en vivo (forwarder de
+//!   handoffs...), que pueden perder eventos si se atrasan
+//! - `subscribe_durable`: canal propio y acotado para los que no pueden
+//!   perder ninguno (analytics); si se llena, el evento va a su `Overflow`
+//!   (p. ej. un archivo) en lugar de crecer en memoria
+
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::broadcast;
+
+use super::BotEvent;
+
+/// Destino de los eventos que no caben en el canal de un suscriptor durable;
+/// se llama desde `send`, así que solo debería bloquear en casos excepcionales (ver `analytics::spill_overflow`)
+pub type Overflow = Arc<dyn Fn(BotEvent) + Send + Sync>;
+
+#[derive(Clone)]
+struct Durable {
+    sender: mpsc::Sender<BotEvent>,
+    overflow: Overflow,
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    live: broadcast::Sender<BotEvent>,
+    durable: Vec<Durable>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (live, _) = broadcast::channel(capacity);
+        Self {
+            live,
+            durable: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BotEvent> {
+        self.live.subscribe()
+    }
+
+    /// Suscripción sin pérdidas de hasta `capacity` eventos en espera; solo
+    /// reciben las copias del bus creadas después
+    pub fn subscribe_durable(&mut self, capacity: usize, overflow: Overflow) -> mpsc::Receiver<BotEvent> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        self.durable.push(Durable { sender, overflow });
+        receiver
+    }
+
+    /// Publicar un evento; que no haya suscriptores en vivo no es un error
+    pub fn send(&self, event: BotEvent) {
+        for subscriber in &self.durable {
+            if let Err(TrySendError::Full(event)) = subscriber.sender.try_send(event.clone()) {
+                (subscriber.overflow)(event);
+            }
+        }
+        let _ = self.live.send(event);
+    }
+}
@@ -0,0 +1,708 @@
+//! Event Log - Historial de eventos de cada conversación (event sourcing)
+//!
+//! Cada cambio de una `ConversationState` se guarda como un evento en un
+//! stream de Redis por conversación (`bot:events:{id}`), solo de escritura:
+//!
+//! - Los eventos se obtienen comparando el estado antes y después de cada
+//!   comando del buzón (mensaje, timer, handoff), así ningún cambio se escapa;
+//!   los cambios de flow y de step se toman de `ConversationState::flow_events`,
+//!   que el engine llena paso a paso, para no perder los steps intermedios
+//! - El id de cada entrada del stream es la versión del evento (`{versión}-0`),
+//!   asignada por un script Lua al agregarlos
+//! - Las conversaciones anteriores al log empiezan con un `Snapshot`
+//! - Al terminar una conversación se agrega `ConversationEnded`; si el cliente
+//!   vuelve, la nueva sigue en el mismo stream desde `ConversationStarted`
+//! - `replay` reconstruye el estado en cualquier versión o momento, para
+//!   depurar, auditar y responder "¿por qué el bot dijo eso?"
+
+use actix_web::{web, HttpResponse, Responder};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use super::conversation_store::{decode_state, encode_state};
+use super::handoff::Handoff;
+use super::scheduler::PendingTimer;
+use super::state_machine::{CallFrame, ConversationMessage, ConversationState, StepAttempts};
+use super::{BotEvent, OrchestratorState};
+
+/// Versión del formato de los eventos guardados
+///
+/// Subirla al cambiar un evento de forma incompatible; los campos nuevos con
+/// `#[serde(default)]` no la necesitan.
+pub const EVENT_FORMAT_VERSION: u32 = 1;
+
+const KEY_PREFIX: &str = "bot:events:";
+
+/// Días que se conserva el log de una conversación sin eventos nuevos
+const DEFAULT_RETENTION_DAYS: u64 = 90;
+
+/// Agregar eventos con versiones consecutivas a partir de la última del stream
+const APPEND_SCRIPT: &str = r#"
+local last = redis.call('XREVRANGE', KEYS[1], '+', '-', 'COUNT', 1)
+local version = 0
+if last[1] then
+    version = tonumber(string.match(last[1][1], '^(%d+)'))
+end
+for i = 2, #ARGV do
+    version = version + 1
+    redis.call('XADD', KEYS[1], version .. '-0', 'event', ARGV[i])
+end
+redis.call('EXPIRE', KEYS[1], ARGV[1])
+return version
+"#;
+
+/// Cambio en una conversación
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConversationEvent {
+    ConversationStarted {
+        bot_id: Uuid,
+        user_phone: String,
+        created_at: DateTime<Utc>,
+    },
+    /// Estado completo (formato de `conversation_store`) de una conversación
+    /// que ya existía cuando empezó a registrarse
+    Snapshot {
+        state: String,
+    },
+    MessageReceived {
+        message: ConversationMessage,
+    },
+    /// Mensaje del bot o de un asesor (`message.role`)
+    MessageSent {
+        message: ConversationMessage,
+    },
+    VariableSet {
+        name: String,
+        value: Value,
+    },
+    VariableRemoved {
+        name: String,
+    },
+    /// Entró a un flow: uno nuevo, un sub-flow o la vuelta al caller
+    FlowStarted {
+        flow_id: Uuid,
+        flow_version: Option<u32>,
+        step_id: Option<String>,
+    },
+    StepTransition {
+        flow_id: Uuid,
+        from_step: Option<String>,
+        to_step: Option<String>,
+    },
+    FlowEnded {
+        flow_id: Uuid,
+    },
+    CallStackChanged {
+        call_stack: Vec<CallFrame>,
+    },
+    HandoffStarted {
+        handoff: Handoff,
+    },
+    HandoffUpdated {
+        handoff: Handoff,
+    },
+    HandoffEnded,
+    TimerChanged {
+        timer: Option<PendingTimer>,
+    },
+    AttemptsChanged {
+        step_key: String,
+        attempts: StepAttempts,
+    },
+    MetadataSet {
+        key: String,
+        value: Value,
+    },
+    MetadataRemoved {
+        key: String,
+    },
+    /// Terminó (ver `expiry`); si el cliente vuelve, empieza otra con el mismo id
+    ConversationEnded {
+        reason: String,
+    },
+}
+
+/// Evento tal como se guarda en el stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredEvent {
+    format: u32,
+    timestamp: DateTime<Utc>,
+    cause: String,
+    #[serde(flatten)]
+    event: ConversationEvent,
+}
+
+/// Evento leído del log
+#[derive(Debug, Clone, Serialize)]
+pub struct LoggedEvent {
+    /// Posición en el log de la conversación (1, 2, 3...)
+    pub version: u64,
+    pub timestamp: DateTime<Utc>,
+    /// Qué lo provocó: `message`, `timer` o `handoff`
+    pub cause: String,
+    #[serde(flatten)]
+    pub event: ConversationEvent,
+}
+
+/// Hasta dónde leer o reconstruir una conversación
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct EventRange {
+    #[serde(default)]
+    pub from_version: Option<u64>,
+    #[serde(default)]
+    pub to_version: Option<u64>,
+    /// Solo eventos ocurridos hasta este momento
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+pub struct EventLog {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    append: redis::Script,
+    retention: Duration,
+}
+
+impl EventLog {
+    pub fn new(client: redis::Client, retention: Duration) -> Self {
+        Self {
+            client,
+            connection: OnceCell::new(),
+            append: redis::Script::new(APPEND_SCRIPT),
+            retention,
+        }
+    }
+
+    /// Retención de `EVENT_LOG_RETENTION_DAYS` (90 días por defecto)
+    pub fn from_env(client: redis::Client) -> Self {
+        let days = std::env::var("EVENT_LOG_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        Self::new(client, Duration::from_secs(days * 24 * 3600))
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connection = self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(connection.clone())
+    }
+
+    /// Agregar eventos al log; devuelve la versión del último
+    pub async fn append(
+        &self,
+        conversation_id: &str,
+        cause: &str,
+        events: Vec<ConversationEvent>,
+    ) -> Result<u64> {
+        let timestamp = Utc::now();
+        let mut invocation = self.append.key(format!("{}{}", KEY_PREFIX, conversation_id));
+        invocation.arg(self.retention.as_secs());
+        for event in events {
+            invocation.arg(serde_json::to_string(&StoredEvent {
+                format: EVENT_FORMAT_VERSION,
+                timestamp,
+                cause: cause.to_string(),
+                event,
+            })?);
+        }
+
+        let mut conn = self.connection().await?;
+        invocation.invoke_async(&mut conn)
+            .await
+            .context("Failed to append conversation events")
+    }
+
+    /// Leer los eventos de una conversación en orden
+    pub async fn read(&self, conversation_id: &str, range: EventRange) -> Result<Vec<LoggedEvent>> {
+        let from = range.from_version.map(|v| format!("{}-0", v)).unwrap_or_else(|| "-".to_string());
+        let to = range.to_version.map(|v| format!("{}-0", v)).unwrap_or_else(|| "+".to_string());
+
+        let mut conn = self.connection().await?;
+        let entries: Vec<(String, Vec<String>)> = redis::cmd("XRANGE")
+            .arg(format!("{}{}", KEY_PREFIX, conversation_id))
+            .arg(from)
+            .arg(to)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to read conversation events")?;
+
+        let mut events = Vec::with_capacity(entries.len());
+        for (id, fields) in entries {
+            let event = decode_entry(&id, &fields)
+                .with_context(|| format!("Invalid event {} of {}", id, conversation_id))?;
+            if range.until.is_some_and(|until| event.timestamp > until) {
+                break;
+            }
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// Entrada del stream: id `{versión}-0` y campos `event <json>`
+fn decode_entry(id: &str, fields: &[String]) -> Result<LoggedEvent> {
+    let version = id.split('-').next()
+        .and_then(|version| version.parse().ok())
+        .context("Stream id is not an event version")?;
+    let data = fields.chunks(2)
+        .find(|field| field[0] == "event")
+        .and_then(|field| field.get(1))
+        .context("Entry has no event field")?;
+
+    let stored: StoredEvent = serde_json::from_str(data)?;
+    if stored.format > EVENT_FORMAT_VERSION {
+        anyhow::bail!(
+            "Event has format v{} but this release only reads up to v{}",
+            stored.format, EVENT_FORMAT_VERSION
+        );
+    }
+
+    Ok(LoggedEvent {
+        version,
+        timestamp: stored.timestamp,
+        cause: stored.cause,
+        event: stored.event,
+    })
+}
+
+/// Eventos que llevan una conversación de `before` (`None`: conversación nueva) a `after`
+///
+/// Los mensajes del cliente van primero y las respuestas al final, para leer
+/// el log en el orden en que ocurrió.
+pub fn diff(before: Option<&ConversationState>, after: &ConversationState) -> Vec<ConversationEvent> {
+    let mut events = Vec::new();
+
+    let empty;
+    let before = match before {
+        Some(before) => before,
+        None => {
+            events.push(ConversationEvent::ConversationStarted {
+                bot_id: after.bot_id,
+                user_phone: after.user_phone.clone(),
+                created_at: after.created_at,
+            });
+            empty = ConversationState::new(after.id.clone(), after.bot_id, after.user_phone.clone());
+            &empty
+        }
+    };
+
+    // El historial solo crece
+    let (received, sent): (Vec<_>, Vec<_>) = after.message_history
+        .iter()
+        .skip(before.message_history.len())
+        .partition(|message| message.role == "user");
+    events.extend(received.into_iter().map(|message| ConversationEvent::MessageReceived {
+        message: message.clone(),
+    }));
+
+    for name in changed_keys(&before.context, &after.context) {
+        events.push(match after.context.get(name) {
+            Some(value) => ConversationEvent::VariableSet { name: name.clone(), value: value.clone() },
+            None => ConversationEvent::VariableRemoved { name: name.clone() },
+        });
+    }
+
+    for step_key in changed_keys(&before.attempts, &after.attempts) {
+        if let Some(attempts) = after.attempts.get(step_key) {
+            events.push(ConversationEvent::AttemptsChanged {
+                step_key: step_key.clone(),
+                attempts: attempts.clone(),
+            });
+        }
+    }
+
+    // Lo que recorrió el engine y, si algo más movió la conversación, el salto
+    // hasta donde quedó
+    let mut position = FlowPointer::of(before);
+    for event in &after.flow_events {
+        position.apply(event);
+        events.push(event.clone());
+    }
+    events.extend(position.changes_to(&FlowPointer::of(after)));
+
+    if before.call_stack != after.call_stack {
+        events.push(ConversationEvent::CallStackChanged { call_stack: after.call_stack.clone() });
+    }
+
+    match (&before.handoff, &after.handoff) {
+        (None, Some(handoff)) => events.push(ConversationEvent::HandoffStarted { handoff: handoff.clone() }),
+        (Some(previous), Some(handoff)) if previous != handoff => {
+            events.push(ConversationEvent::HandoffUpdated { handoff: handoff.clone() })
+        }
+        (Some(_), None) => events.push(ConversationEvent::HandoffEnded),
+        _ => {}
+    }
+
+    if before.timer != after.timer {
+        events.push(ConversationEvent::TimerChanged { timer: after.timer.clone() });
+    }
+
+    for key in changed_keys(&before.metadata, &after.metadata) {
+        events.push(match after.metadata.get(key) {
+            Some(value) => ConversationEvent::MetadataSet { key: key.clone(), value: value.clone() },
+            None => ConversationEvent::MetadataRemoved { key: key.clone() },
+        });
+    }
+
+    events.extend(sent.into_iter().map(|message| ConversationEvent::MessageSent {
+        message: message.clone(),
+    }));
+    events
+}
+
+/// Flow, versión y step en los que está una conversación
+#[derive(Debug, PartialEq)]
+struct FlowPointer {
+    flow_id: Option<Uuid>,
+    flow_version: Option<u32>,
+    step_id: Option<String>,
+}
+
+impl FlowPointer {
+    fn of(conversation: &ConversationState) -> Self {
+        Self {
+            flow_id: conversation.current_flow_id,
+            flow_version: conversation.current_flow_version,
+            step_id: conversation.current_step_id.clone(),
+        }
+    }
+
+    fn apply(&mut self, event: &ConversationEvent) {
+        match event {
+            ConversationEvent::FlowStarted { flow_id, flow_version, step_id } => {
+                *self = Self { flow_id: Some(*flow_id), flow_version: *flow_version, step_id: step_id.clone() };
+            }
+            ConversationEvent::StepTransition { to_step, .. } => self.step_id = to_step.clone(),
+            ConversationEvent::FlowEnded { .. } => *self = Self { flow_id: None, flow_version: None, step_id: None },
+            _ => {}
+        }
+    }
+
+    /// Eventos para pasar de esta posición a `target`
+    fn changes_to(&self, target: &FlowPointer) -> Vec<ConversationEvent> {
+        let mut events = Vec::new();
+        if self.flow_id != target.flow_id || self.flow_version != target.flow_version {
+            if let Some(flow_id) = self.flow_id {
+                events.push(ConversationEvent::FlowEnded { flow_id });
+            }
+            if let Some(flow_id) = target.flow_id {
+                events.push(ConversationEvent::FlowStarted {
+                    flow_id,
+                    flow_version: target.flow_version,
+                    step_id: target.step_id.clone(),
+                });
+            }
+        } else if self.step_id != target.step_id {
+            if let Some(flow_id) = target.flow_id {
+                events.push(ConversationEvent::StepTransition {
+                    flow_id,
+                    from_step: self.step_id.clone(),
+                    to_step: target.step_id.clone(),
+                });
+            }
+        }
+        events
+    }
+}
+
+/// Claves agregadas, cambiadas o quitadas, en orden
+fn changed_keys<'a, T: PartialEq>(before: &'a HashMap<String, T>, after: &'a HashMap<String, T>) -> BTreeSet<&'a String> {
+    before.keys()
+        .chain(after.keys())
+        .filter(|key| before.get(*key) != after.get(*key))
+        .collect()
+}
+
+/// Aplicar un evento a la conversación (`None` antes del primer evento o si ya terminó)
+pub fn apply(conversation_id: &str, state: &mut Option<ConversationState>, event: &LoggedEvent) -> Result<()> {
+    match &event.event {
+        ConversationEvent::ConversationStarted { bot_id, user_phone, created_at } => {
+            let mut conversation = ConversationState::new(conversation_id.to_string(), *bot_id, user_phone.clone());
+            conversation.created_at = *created_at;
+            conversation.last_activity = *created_at;
+            *state = Some(conversation);
+        }
+        ConversationEvent::Snapshot { state: snapshot } => *state = Some(decode_state(snapshot)?),
+        ConversationEvent::ConversationEnded { .. } => {
+            *state = None;
+            return Ok(());
+        }
+        _ => {}
+    }
+    let Some(conversation) = state.as_mut() else {
+        anyhow::bail!("Event {} comes before the conversation started", event.version);
+    };
+
+    match &event.event {
+        ConversationEvent::ConversationStarted { .. }
+        | ConversationEvent::Snapshot { .. }
+        | ConversationEvent::ConversationEnded { .. } => {}
+        ConversationEvent::MessageReceived { message } => {
+            conversation.message_history.push(message.clone());
+            conversation.last_activity = message.timestamp;
+        }
+        ConversationEvent::MessageSent { message } => conversation.message_history.push(message.clone()),
+        ConversationEvent::VariableSet { name, value } => conversation.set_variable(name, value.clone()),
+        ConversationEvent::VariableRemoved { name } => {
+            conversation.context.remove(name);
+        }
+        ConversationEvent::FlowStarted { flow_id, flow_version, step_id } => {
+            conversation.current_flow_id = Some(*flow_id);
+            conversation.current_flow_version = *flow_version;
+            conversation.current_step_id = step_id.clone();
+        }
+        ConversationEvent::StepTransition { to_step, .. } => conversation.current_step_id = to_step.clone(),
+        ConversationEvent::FlowEnded { .. } => {
+            conversation.current_flow_id = None;
+            conversation.current_flow_version = None;
+            conversation.current_step_id = None;
+        }
+        ConversationEvent::CallStackChanged { call_stack } => conversation.call_stack = call_stack.clone(),
+        ConversationEvent::HandoffStarted { handoff } | ConversationEvent::HandoffUpdated { handoff } => {
+            conversation.handoff = Some(handoff.clone());
+        }
+        ConversationEvent::HandoffEnded => conversation.handoff = None,
+        ConversationEvent::TimerChanged { timer } => conversation.timer = timer.clone(),
+        ConversationEvent::AttemptsChanged { step_key, attempts } => {
+            conversation.attempts.insert(step_key.clone(), attempts.clone());
+        }
+        ConversationEvent::MetadataSet { key, value } => {
+            conversation.metadata.insert(key.clone(), value.clone());
+        }
+        ConversationEvent::MetadataRemoved { key } => {
+            conversation.metadata.remove(key);
+        }
+    }
+
+    conversation.event_version = event.version;
+    Ok(())
+}
+
+/// Reconstruir una conversación aplicando sus eventos en orden
+pub fn replay(conversation_id: &str, events: &[LoggedEvent]) -> Result<Option<ConversationState>> {
+    let mut state = None;
+    for event in events {
+        apply(conversation_id, &mut state, event)?;
+    }
+    Ok(state)
+}
+
+/// Registrar en el log los cambios de un comando sobre la conversación
+///
+/// Si falla, la conversación sigue igual (queda un aviso en los logs).
+pub async fn record(
+    state: &OrchestratorState,
+    cause: &str,
+    before: Option<&ConversationState>,
+    after: &mut ConversationState,
+) {
+    let mut events = diff(before, after);
+    after.flow_events.clear();
+    if events.is_empty() {
+        return;
+    }
+
+    // Conversación de antes del log: primero su estado completo
+    if let Some(before) = before.filter(|before| before.event_version == 0) {
+        match encode_state(before) {
+            Ok(snapshot) => events.insert(0, ConversationEvent::Snapshot { state: snapshot }),
+            Err(e) => warn!("Could not snapshot conversation {}: {:#}", before.id, e),
+        }
+    }
+
+    for event in &events {
+        if let ConversationEvent::StepTransition { from_step, to_step, .. } = event {
+            state.event_bus.send(BotEvent::FlowTransition {
+                conversation_id: after.id.clone(),
+                from_step: from_step.clone().unwrap_or_default(),
+                to_step: to_step.clone().unwrap_or_default(),
+            });
+        }
+    }
+
+    match state.event_log.append(&after.id, cause, events).await {
+        Ok(version) => after.event_version = version,
+        Err(e) => warn!("Could not record events of {}: {:#}", after.id, e),
+    }
+}
+
+// ==================== API ====================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReplayQuery {
+    /// Estado justo después de esta versión
+    #[serde(default)]
+    pub version: Option<u64>,
+    /// Estado en este momento
+    #[serde(default)]
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// `GET /conversations/{id}/events`: log de la conversación
+/// (`from_version`, `to_version` y `until` para acotarlo)
+pub async fn handle_events(
+    state: web::Data<OrchestratorState>,
+    path: web::Path<String>,
+    query: web::Query<EventRange>,
+) -> impl Responder {
+    let conversation_id = path.into_inner();
+
+    match state.event_log.read(&conversation_id, query.into_inner()).await {
+        Ok(events) => HttpResponse::Ok().json(serde_json::json!({
+            "conversation_id": conversation_id,
+            "total": events.len(),
+            "events": events,
+        })),
+        Err(e) => {
+            error!("Error reading events of {}: {:#}", conversation_id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read conversation events"
+            }))
+        }
+    }
+}
+
+/// `GET /conversations/{id}/replay?version=N` o `?at=<fecha>`: estado de la
+/// conversación en ese punto, con el último evento aplicado
+pub async fn handle_replay(
+    state: web::Data<OrchestratorState>,
+    path: web::Path<String>,
+    query: web::Query<ReplayQuery>,
+) -> impl Responder {
+    let conversation_id = path.into_inner();
+    let range = EventRange {
+        from_version: None,
+        to_version: query.version,
+        until: query.at,
+    };
+
+    let rebuilt = match state.event_log.read(&conversation_id, range).await {
+        Ok(events) => replay(&conversation_id, &events).map(|conversation| (conversation, events.last().cloned())),
+        Err(e) => Err(e),
+    };
+
+    match rebuilt {
+        Ok((Some(conversation), last_event)) => HttpResponse::Ok().json(serde_json::json!({
+            "conversation_id": conversation_id,
+            "version": conversation.event_version,
+            "last_event": last_event,
+            "state": conversation,
+        })),
+        Ok((None, _)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No events recorded for this conversation at that point"
+        })),
+        Err(e) => {
+            error!("Error replaying conversation {}: {:#}", conversation_id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to replay conversation"
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MessageContent;
+
+    /// Aplicar los cambios de cada paso como si se hubieran leído del log
+    fn log_changes(log: &mut Vec<LoggedEvent>, before: Option<&ConversationState>, after: &ConversationState) {
+        for event in diff(before, after) {
+            log.push(LoggedEvent {
+                version: log.len() as u64 + 1,
+                timestamp: Utc::now(),
+                cause: "message".to_string(),
+                event,
+            });
+        }
+    }
+
+    #[test]
+    fn test_replay_rebuilds_the_conversation() {
+        let mut log = Vec::new();
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.add_received_message(MessageContent::text("hola"), Some("wamid.1".to_string()), None);
+        conversation.current_flow_id = Some(Uuid::new_v4());
+        conversation.current_flow_version = Some(2);
+        conversation.current_step_id = Some("nombre".to_string());
+        conversation.add_outgoing_message("bot", MessageContent::text("¿Tu nombre?"), None);
+        log_changes(&mut log, None, &conversation);
+
+        let before = conversation.clone();
+        conversation.add_received_message(MessageContent::text("Ana"), None, None);
+        conversation.set_variable("nombre", serde_json::json!("Ana"));
+        conversation.record_failed_attempt("flow:nombre");
+        conversation.current_step_id = Some("fin".to_string());
+        conversation.metadata.insert("bot".to_string(), serde_json::json!({ "name": "Flores" }));
+        log_changes(&mut log, Some(&before), &conversation);
+
+        let events: Vec<&str> = log.iter()
+            .map(|event| match &event.event {
+                ConversationEvent::MessageReceived { .. } => "received",
+                ConversationEvent::MessageSent { .. } => "sent",
+                ConversationEvent::StepTransition { .. } => "step",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(events[events.len() - 5..], ["received", "other", "other", "step", "other"]);
+
+        let replayed = replay("c1", &log).unwrap().unwrap();
+        assert_eq!(replayed.event_version, log.len() as u64);
+        assert_eq!(replayed.message_history, conversation.message_history);
+        assert_eq!(replayed.context, conversation.context);
+        assert_eq!(replayed.attempts, conversation.attempts);
+        assert_eq!(replayed.metadata, conversation.metadata);
+        assert_eq!(replayed.current_step_id.as_deref(), Some("fin"));
+
+        // Hasta el primer paso: todavía en la pregunta
+        let first = log.iter().position(|event| matches!(event.event, ConversationEvent::MessageSent { .. })).unwrap();
+        let earlier = replay("c1", &log[..=first]).unwrap().unwrap();
+        assert_eq!(earlier.current_step_id.as_deref(), Some("nombre"));
+        assert_eq!(earlier.get_variable("nombre"), None);
+    }
+
+    #[test]
+    fn test_replay_from_snapshot() {
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.set_variable("total", serde_json::json!(25));
+
+        let mut log = vec![LoggedEvent {
+            version: 1,
+            timestamp: Utc::now(),
+            cause: "message".to_string(),
+            event: ConversationEvent::Snapshot { state: encode_state(&conversation).unwrap() },
+        }];
+        let before = conversation.clone();
+        conversation.context.remove("total");
+        log_changes(&mut log, Some(&before), &conversation);
+
+        assert!(replay("c1", &log[..1]).unwrap().unwrap().get_variable("total").is_some());
+        assert!(replay("c1", &log).unwrap().unwrap().get_variable("total").is_none());
+
+        // Sin inicio ni snapshot no hay desde dónde reconstruir
+        assert!(replay("c1", &log[1..]).is_err());
+
+        // Terminada: no queda conversación hasta que empiece otra
+        log.push(LoggedEvent {
+            version: log.len() as u64 + 1,
+            timestamp: Utc::now(),
+            cause: "expiry".to_string(),
+            event: ConversationEvent::ConversationEnded { reason: "expired".to_string() },
+        });
+        assert!(replay("c1", &log).unwrap().is_none());
+        log_changes(&mut log, None, &ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string()));
+        assert!(replay("c1", &log).unwrap().unwrap().get_variable("total").is_none());
+    }
+}
@@ -0,0 +1,737 @@
+//! Expiry - Fin de las conversaciones inactivas
+//!
+//! Una conversación termina cuando pasa `max_conversation_timeout_seconds`
+//! (ajuste de cada bot) sin mensajes de nadie. Las que atiende un asesor no
+//! vencen, y las que esperan un timer vencen como pronto cuando se dispara.
+//!
+//! - Con `expiry.nudge`, `before_seconds` antes del vencimiento se le pregunta
+//!   al cliente si sigue ahí (una vez por cada período de inactividad; el
+//!   aviso no cuenta como actividad)
+//! - Al terminar, la conversación completa (`message_history` incluido) se
+//!   archiva en la colección `conversation_archive` de MongoDB, se emite
+//!   `BotEvent::ConversationEnded` y se borra de memoria y de Redis. Si no se
+//!   pudo archivar, se reintenta a los `ARCHIVE_RETRY`
+//! - Después corren los hooks del bot (`expiry.hooks`): una encuesta de
+//!   satisfacción (un flow en una conversación nueva) o un webhook con la
+//!   conversación archivada (p. ej. para sincronizar el CRM)
+//! - Al borrar un bot, sus conversaciones terminan igual con `BOT_DELETED`
+//!   (las que solo están en Redis, en su próximo control o timer)
+//!
+//! El próximo control de cada conversación se registra al guardarla en un
+//! sorted set de Redis (`bot:expiry`, score = hora en ms) que se reclama igual
+//! que los timers (ver `scheduler`). El control pasa por el buzón de la
+//! conversación y se decide con su estado actual, así que uno atrasado o
+//! repetido no tiene efecto. Cada `SWEEP_INTERVAL` también se revisan las
+//! conversaciones en memoria, por si Redis no estaba disponible al guardarlas.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Document};
+use mongodb::options::ReplaceOptions;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::event_log::{self, ConversationEvent};
+use super::message::MessageContent;
+use super::metrics::{FlowOutcome, Metric};
+use super::scheduler::{CLAIM_BATCH, CLAIM_LEASE, CLAIM_SCRIPT};
+use super::state_machine::{ConversationMessage, ConversationState};
+use super::template;
+use super::{BotEvent, BotInstance, BotSettings, BotStatus, ConversationCommand, OrchestratorState};
+
+const EXPIRY_KEY: &str = "bot:expiry";
+
+/// Colección de MongoDB con las conversaciones terminadas
+const COLLECTION: &str = "conversation_archive";
+
+/// Cada cuánto se buscan conversaciones a revisar en Redis
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Cada cuánto se revisan las conversaciones en memoria
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Espera antes de volver a intentar archivar una conversación
+const ARCHIVE_RETRY: Duration = Duration::from_secs(60);
+
+/// Margen del TTL en Redis sobre el vencimiento: la conversación tiene que
+/// seguir ahí para archivarla
+pub const TTL_GRACE: Duration = Duration::from_secs(600);
+
+/// Motivo de fin de una conversación inactiva
+pub const EXPIRED: &str = "expired";
+
+/// Motivo de fin de las conversaciones de un bot borrado
+pub const BOT_DELETED: &str = "bot_deleted";
+
+/// Metadata con la posición en `message_history` del último aviso de inactividad
+const NUDGE_INDEX: &str = "expiry_nudge";
+
+/// Metadata de las conversaciones de encuesta, con el id archivado de la
+/// conversación encuestada
+const SURVEY_OF: &str = "survey_of";
+
+/// Fin de conversación de un bot (`BotSettings::expiry`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExpirySettings {
+    /// Aviso antes de terminar la conversación; sin él termina sin avisar
+    pub nudge: Option<Nudge>,
+    /// Acciones al terminar una conversación, en orden
+    pub hooks: Vec<ConversationHook>,
+}
+
+/// "¿Sigues ahí?"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nudge {
+    /// Segundos antes del vencimiento
+    pub before_seconds: u64,
+    /// Mensaje (plantilla, ver `template`)
+    pub message: String,
+}
+
+/// Acción al terminar una conversación
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConversationHook {
+    /// Encuesta de satisfacción: el flow empieza en una conversación nueva con
+    /// el mismo cliente (al terminar la encuesta no se pide otra)
+    Survey {
+        flow_id: Uuid,
+        /// Motivos de fin en los que corre; vacío: todos
+        #[serde(default)]
+        on: Vec<String>,
+    },
+    /// `POST` de la conversación archivada (CRM, data warehouse)
+    Webhook {
+        url: String,
+        #[serde(default)]
+        on: Vec<String>,
+    },
+}
+
+impl ConversationHook {
+    /// Si corre al terminar una conversación por `reason`; la encuesta le
+    /// escribe al cliente, así que no corre si el bot está pausado o se borró
+    pub fn runs_on(&self, reason: &str, paused: bool) -> bool {
+        if (paused || reason == BOT_DELETED) && matches!(self, Self::Survey { .. }) {
+            return false;
+        }
+        let (Self::Survey { on, .. } | Self::Webhook { on, .. }) = self;
+        on.is_empty() || on.iter().any(|on| on == reason)
+    }
+}
+
+// ============================================================================
+// Vencimiento
+// ============================================================================
+
+/// Qué hacer con una conversación en un control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryCheck {
+    /// Nada todavía: volver a revisarla a esa hora
+    Wait(DateTime<Utc>),
+    /// Preguntarle al cliente si sigue ahí
+    Nudge,
+    /// Terminarla
+    Expire,
+}
+
+fn nudge_index(conversation: &ConversationState) -> Option<usize> {
+    conversation.metadata.get(NUDGE_INDEX)?.as_u64().map(|index| index as usize)
+}
+
+/// Último momento con actividad: el último mensaje (sin contar el aviso de
+/// inactividad) o la última acción del cliente o del asesor
+pub fn idle_since(conversation: &ConversationState) -> DateTime<Utc> {
+    let nudge = nudge_index(conversation);
+    conversation.message_history.iter()
+        .enumerate()
+        .rev()
+        .find(|(index, _)| Some(*index) != nudge)
+        .map_or(conversation.last_activity, |(_, message)| message.timestamp.max(conversation.last_activity))
+}
+
+/// Ya se avisó en este período de inactividad: el aviso es el último mensaje
+fn nudged(conversation: &ConversationState) -> bool {
+    nudge_index(conversation).is_some_and(|index| index + 1 == conversation.message_history.len())
+}
+
+/// Próximo paso de una conversación con los ajustes de su bot
+pub fn next_check(conversation: &ConversationState, settings: &BotSettings, now: DateTime<Utc>) -> ExpiryCheck {
+    let timeout = chrono::Duration::seconds(settings.max_conversation_timeout_seconds as i64);
+
+    // La atiende un asesor: no vence mientras tanto
+    if conversation.handoff.is_some() {
+        return ExpiryCheck::Wait(now + timeout);
+    }
+
+    let expires_at = idle_since(conversation) + timeout;
+
+    // Un timer pendiente la mantiene hasta que se dispare (al dispararse se
+    // guarda y se vuelve a programar); uno trabado deja de contar
+    if let Some(timer) = &conversation.timer {
+        let timer_deadline = timer.fires_at + timeout;
+        if now < timer_deadline {
+            return ExpiryCheck::Wait(expires_at.max(timer_deadline));
+        }
+    }
+
+    if now >= expires_at {
+        return ExpiryCheck::Expire;
+    }
+    match settings.expiry.nudge.as_ref().filter(|_| !nudged(conversation)) {
+        Some(nudge) => {
+            let nudge_at = expires_at - chrono::Duration::seconds(nudge.before_seconds as i64);
+            if now >= nudge_at {
+                ExpiryCheck::Nudge
+            } else {
+                ExpiryCheck::Wait(nudge_at)
+            }
+        }
+        None => ExpiryCheck::Wait(expires_at),
+    }
+}
+
+/// Ajustes del bot de una conversación (los de por defecto si ya no existe)
+fn settings_of(state: &OrchestratorState, bot_id: Uuid) -> (BotSettings, bool) {
+    state.bots.get(&bot_id)
+        .map(|bot| (bot.settings.clone(), bot.status == BotStatus::Paused))
+        .unwrap_or_default()
+}
+
+/// Registrar en Redis el próximo control de una conversación recién guardada
+pub async fn schedule(state: &OrchestratorState, conversation: &ConversationState) -> Result<()> {
+    let (settings, _) = settings_of(state, conversation.bot_id);
+    let now = state.clock.now();
+    let at = match next_check(conversation, &settings, now) {
+        ExpiryCheck::Wait(at) => at,
+        ExpiryCheck::Nudge | ExpiryCheck::Expire => now,
+    };
+    state.expiry.schedule(&conversation.id, at).await
+}
+
+/// Control de una conversación (en su buzón): esperar, avisar o terminarla
+pub async fn process(state: &OrchestratorState, conversation_id: &str) -> Result<()> {
+    let Some(mut conversation) = super::find_conversation(state, conversation_id).await? else {
+        // Ya terminó, o expiró en Redis
+        return state.expiry.remove(conversation_id).await;
+    };
+
+    // El bot se borró mientras la conversación seguía viva
+    if !state.bots.contains_key(&conversation.bot_id) {
+        return end_conversation(state, conversation, BOT_DELETED).await;
+    }
+
+    // Un bot pausado no le escribe al cliente
+    let (mut settings, paused) = settings_of(state, conversation.bot_id);
+    if paused {
+        settings.expiry.nudge = None;
+    }
+
+    match next_check(&conversation, &settings, state.clock.now()) {
+        ExpiryCheck::Wait(at) => state.expiry.schedule(conversation_id, at).await,
+        ExpiryCheck::Nudge => {
+            let before = conversation.clone();
+            if let Some(nudge) = &settings.expiry.nudge {
+                let text = template::render_template(&nudge.message, &template::conversation_variables(&conversation, state.clock.now()));
+                super::send_reply(state, &mut conversation, "bot", MessageContent::text(text)).await;
+                let index = conversation.message_history.len() - 1;
+                conversation.metadata.insert(NUDGE_INDEX.to_string(), serde_json::json!(index));
+            }
+            event_log::record(state, "expiry", Some(&before), &mut conversation).await;
+            // Al guardarla queda programado el vencimiento
+            super::save_conversation(state, conversation).await;
+            Ok(())
+        }
+        ExpiryCheck::Expire => end_conversation(state, conversation, EXPIRED).await,
+    }
+}
+
+/// Terminar (cada una en su buzón) las conversaciones en memoria de un bot recién borrado
+pub fn end_bot_conversations(state: &OrchestratorState, bot: BotInstance) {
+    let bot = Arc::new(bot);
+    let conversation_ids: Vec<String> = state.conversations.iter()
+        .filter(|conv| conv.bot_id == bot.id)
+        .map(|conv| conv.id.clone())
+        .collect();
+
+    info!("🗑️ Ending {} conversations of deleted bot {}", conversation_ids.len(), bot.id);
+    for conversation_id in conversation_ids {
+        let key = conversation_id.clone();
+        super::dispatch_command(state, &key, ConversationCommand::BotDeleted { conversation_id, bot: bot.clone() });
+    }
+}
+
+/// Terminar una conversación de un bot borrado; `bot` es como estaba antes de
+/// borrarlo (ya no está en `state.bots`), para sus hooks
+pub async fn end_for_deleted_bot(state: &OrchestratorState, conversation_id: &str, bot: &BotInstance) -> Result<()> {
+    match super::find_conversation(state, conversation_id).await? {
+        Some(conversation) => end_with_bot(state, conversation, BOT_DELETED, Some(bot)).await,
+        None => state.expiry.remove(conversation_id).await,
+    }
+}
+
+/// Terminar una conversación: archivarla, avisar y borrarla, y correr los hooks del bot
+pub async fn end_conversation(state: &OrchestratorState, conversation: ConversationState, reason: &str) -> Result<()> {
+    let bot = state.bots.get(&conversation.bot_id).map(|bot| bot.clone());
+    end_with_bot(state, conversation, reason, bot.as_ref()).await
+}
+
+async fn end_with_bot(
+    state: &OrchestratorState,
+    conversation: ConversationState,
+    reason: &str,
+    bot: Option<&BotInstance>,
+) -> Result<()> {
+    let (tenant_id, hooks, paused) = bot
+        .map(|bot| (Some(bot.tenant_id.clone()), bot.settings.expiry.hooks.clone(), bot.status == BotStatus::Paused))
+        .unwrap_or_default();
+    let ended = ArchivedConversation::new(&conversation, tenant_id, reason, state.clock.now());
+
+    if let Err(e) = state.archive.store(&ended).await {
+        // Sigue guardada hasta el reintento
+        warn!("Could not archive conversation {}, retrying: {:#}", conversation.id, e);
+        state.conversation_store.save(&conversation, ARCHIVE_RETRY + TTL_GRACE).await?;
+        let retry_at = state.clock.now() + chrono::Duration::from_std(ARCHIVE_RETRY).unwrap_or_default();
+        return state.expiry.schedule(&conversation.id, retry_at).await;
+    }
+
+    info!("🏁 Conversation {} ended ({})", conversation.id, reason);
+
+    // El cliente dejó de responder a mitad de un flow
+    if conversation.current_flow_id.is_some() {
+        state.metrics.record(conversation.bot_id, Metric::Flow(FlowOutcome::Abandoned));
+    }
+
+    let ended_event = ConversationEvent::ConversationEnded { reason: reason.to_string() };
+    if let Err(e) = state.event_log.append(&conversation.id, "expiry", vec![ended_event]).await {
+        warn!("Could not record the end of {}: {:#}", conversation.id, e);
+    }
+
+    state.timers.sync(&conversation.id, conversation.timer.as_ref(), None).await;
+    state.conversations.remove(&conversation.id);
+    if let Err(e) = state.conversation_store.delete(&conversation.id).await {
+        warn!("Could not delete conversation {}: {:#}", conversation.id, e);
+    }
+    if let Err(e) = state.expiry.remove(&conversation.id).await {
+        warn!("Could not unschedule conversation {}: {:#}", conversation.id, e);
+    }
+
+    state.event_bus.send(BotEvent::ConversationEnded {
+        conversation_id: conversation.id.clone(),
+        reason: reason.to_string(),
+    });
+
+    for hook in hooks.iter().filter(|hook| hook.runs_on(reason, paused)) {
+        run_hook(state, hook, &ended).await;
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Hooks
+// ============================================================================
+
+async fn run_hook(state: &OrchestratorState, hook: &ConversationHook, ended: &ArchivedConversation) {
+    match hook {
+        ConversationHook::Survey { flow_id, .. } => {
+            // La encuesta no pide otra encuesta
+            if ended.metadata.contains_key(SURVEY_OF) {
+                return;
+            }
+            if let Err(e) = start_survey(state, ended, *flow_id).await {
+                warn!("Could not start survey for {}: {:#}", ended.conversation_id, e);
+            }
+        }
+        ConversationHook::Webhook { url, .. } => {
+            let url = url.clone();
+            let payload = serde_json::json!({
+                "event": "conversation_ended",
+                "conversation": ended,
+            });
+            tokio::spawn(async move {
+                let result = reqwest::Client::new()
+                    .post(&url)
+                    .timeout(Duration::from_secs(5))
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = result {
+                    warn!("Conversation hook {} failed: {}", url, e);
+                }
+            });
+        }
+    }
+}
+
+/// Empezar el flow de la encuesta en una conversación nueva con el mismo cliente
+async fn start_survey(state: &OrchestratorState, ended: &ArchivedConversation, flow_id: Uuid) -> Result<()> {
+    let mut survey = super::start_conversation(state, &ended.conversation_id, ended.bot_id, &ended.user_phone);
+    survey.metadata.insert(SURVEY_OF.to_string(), serde_json::json!(ended.id));
+    if let Some(bot_variables) = ended.metadata.get("bot") {
+        survey.metadata.insert("bot".to_string(), bot_variables.clone());
+    }
+
+    let reply = state.flow_engine.go_to(&mut survey, flow_id, None, None).await?;
+    state.timers.sync(&survey.id, None, survey.timer.as_ref()).await;
+    if let Some(content) = reply {
+        super::send_reply(state, &mut survey, "bot", content).await;
+    }
+
+    state.metrics.record_flows(&mut survey);
+    event_log::record(state, "survey", None, &mut survey).await;
+    super::save_conversation(state, survey).await;
+    Ok(())
+}
+
+// ============================================================================
+// Archivo
+// ============================================================================
+
+/// Conversación terminada, tal como se archiva y se envía a los webhooks
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedConversation {
+    /// `{conversation_id}:{created_at en ms}`: un mismo cliente tiene una
+    /// conversación por cada vez que vuelve
+    pub id: String,
+    pub conversation_id: String,
+    pub bot_id: Uuid,
+    pub tenant_id: Option<String>,
+    pub user_phone: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub context: HashMap<String, serde_json::Value>,
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub message_history: Vec<ConversationMessage>,
+}
+
+impl ArchivedConversation {
+    pub fn new(conversation: &ConversationState, tenant_id: Option<String>, reason: &str, ended_at: DateTime<Utc>) -> Self {
+        Self {
+            id: format!("{}:{}", conversation.id, conversation.created_at.timestamp_millis()),
+            conversation_id: conversation.id.clone(),
+            bot_id: conversation.bot_id,
+            tenant_id,
+            user_phone: conversation.user_phone.clone(),
+            reason: reason.to_string(),
+            created_at: conversation.created_at,
+            ended_at,
+            context: conversation.context.clone(),
+            metadata: conversation.metadata.clone(),
+            message_history: conversation.message_history.clone(),
+        }
+    }
+
+    fn to_document(&self) -> Result<Document> {
+        Ok(doc! {
+            "_id": &self.id,
+            "conversation_id": &self.conversation_id,
+            "bot_id": self.bot_id.to_string(),
+            "tenant_id": self.tenant_id.as_deref(),
+            "user_phone": &self.user_phone,
+            "reason": &self.reason,
+            "created_at": mongodb::bson::DateTime::from_millis(self.created_at.timestamp_millis()),
+            "ended_at": mongodb::bson::DateTime::from_millis(self.ended_at.timestamp_millis()),
+            "context": mongodb::bson::to_bson(&self.context)?,
+            "metadata": mongodb::bson::to_bson(&self.metadata)?,
+            "message_history": mongodb::bson::to_bson(&self.message_history)?,
+        })
+    }
+}
+
+/// Almacenamiento de largo plazo de las conversaciones terminadas
+#[async_trait]
+pub trait ConversationArchive: Send + Sync {
+    /// Guardar (o reemplazar, si se reintenta) una conversación terminada
+    async fn store(&self, conversation: &ArchivedConversation) -> Result<()>;
+}
+
+/// Sin MongoDB: las conversaciones terminadas no se archivan
+pub struct NoArchive;
+
+#[async_trait]
+impl ConversationArchive for NoArchive {
+    async fn store(&self, _conversation: &ArchivedConversation) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct MongoArchive {
+    collection: mongodb::Collection<Document>,
+}
+
+impl MongoArchive {
+    /// `MONGODB_URI` y `MONGODB_DATABASE`, como analytics; `NoArchive` si no se puede usar
+    pub async fn from_env() -> Arc<dyn ConversationArchive> {
+        let uri = std::env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
+        let database = std::env::var("MONGODB_DATABASE").unwrap_or_else(|_| "dashoffice".to_string());
+
+        match mongodb::Client::with_uri_str(&uri).await {
+            Ok(client) => Arc::new(Self {
+                collection: client.database(&database).collection(COLLECTION),
+            }),
+            Err(e) => {
+                error!("Conversation archive disabled, invalid MongoDB URI: {}", e);
+                Arc::new(NoArchive)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ConversationArchive for MongoArchive {
+    async fn store(&self, conversation: &ArchivedConversation) -> Result<()> {
+        let document = conversation.to_document()?;
+        let options = ReplaceOptions::builder().upsert(true).build();
+
+        self.collection
+            .replace_one(doc! { "_id": &conversation.id }, document, options)
+            .await
+            .context("Failed to archive conversation")?;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Scheduler
+// ============================================================================
+
+/// Próximo control de cada conversación, en Redis
+pub struct ExpiryScheduler {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    claim: redis::Script,
+}
+
+impl ExpiryScheduler {
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            connection: OnceCell::new(),
+            claim: redis::Script::new(CLAIM_SCRIPT),
+        }
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connection = self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(connection.clone())
+    }
+
+    /// Programar (o mover) el control de una conversación
+    pub async fn schedule(&self, conversation_id: &str, at: DateTime<Utc>) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: () = redis::cmd("ZADD")
+            .arg(EXPIRY_KEY)
+            .arg(at.timestamp_millis())
+            .arg(conversation_id)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to schedule conversation expiry")?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, conversation_id: &str) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: () = redis::cmd("ZREM")
+            .arg(EXPIRY_KEY)
+            .arg(conversation_id)
+            .query_async(&mut conn)
+            .await
+            .context("Failed to unschedule conversation expiry")?;
+        Ok(())
+    }
+
+    /// Conversaciones a revisar a `now`, reclamadas por esta instancia durante `CLAIM_LEASE`
+    pub async fn claim_due(&self, now: DateTime<Utc>) -> Result<Vec<String>> {
+        let mut conn = self.connection().await?;
+        let lease_until = now + chrono::Duration::from_std(CLAIM_LEASE).unwrap_or_default();
+
+        self.claim
+            .key(EXPIRY_KEY)
+            .arg(now.timestamp_millis())
+            .arg(lease_until.timestamp_millis())
+            .arg(CLAIM_BATCH)
+            .invoke_async(&mut conn)
+            .await
+            .context("Failed to claim due conversations")
+    }
+}
+
+/// Revisar en su buzón las conversaciones que llegaron a su próximo control
+pub fn spawn_expiry_worker(state: OrchestratorState) {
+    tokio::spawn(async move {
+        info!("⌛ Expiry worker started");
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+
+        loop {
+            let due = tokio::select! {
+                _ = poll.tick() => match state.expiry.claim_due(state.clock.now()).await {
+                    Ok(due) => due,
+                    Err(e) => {
+                        warn!("Could not poll conversation expiry: {:#}", e);
+                        continue;
+                    }
+                },
+                _ = sweep.tick() => {
+                    let now = state.clock.now();
+                    state.conversations.iter()
+                        .filter(|conv| {
+                            let (settings, _) = settings_of(&state, conv.bot_id);
+                            !matches!(next_check(conv.value(), &settings, now), ExpiryCheck::Wait(_))
+                        })
+                        .map(|conv| conv.id.clone())
+                        .collect()
+                }
+            };
+
+            for conversation_id in due {
+                let key = conversation_id.clone();
+                super::dispatch_command(&state, &key, ConversationCommand::Expire { conversation_id });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handoff::Handoff;
+    use crate::scheduler::PendingTimer;
+
+    fn settings(timeout_seconds: u64, nudge_before: Option<u64>) -> BotSettings {
+        BotSettings {
+            max_conversation_timeout_seconds: timeout_seconds,
+            expiry: ExpirySettings {
+                nudge: nudge_before.map(|before_seconds| Nudge {
+                    before_seconds,
+                    message: "¿Sigues ahí, {{nombre}}?".to_string(),
+                }),
+                hooks: Vec::new(),
+            },
+            ..BotSettings::default()
+        }
+    }
+
+    /// Conversación con un mensaje del cliente y la respuesta del bot, ambos en `at`
+    fn conversation_at(at: DateTime<Utc>) -> ConversationState {
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.add_received_message(MessageContent::text("hola"), None, None);
+        conversation.add_outgoing_message("bot", MessageContent::text("¿En qué te ayudo?"), None);
+        conversation.created_at = at;
+        conversation.last_activity = at;
+        for message in &mut conversation.message_history {
+            message.timestamp = at;
+        }
+        conversation
+    }
+
+    #[test]
+    fn test_expiry_uses_bot_timeout() {
+        let start = Utc::now();
+        let conversation = conversation_at(start);
+        let minutes = |m: i64| start + chrono::Duration::minutes(m);
+
+        // Sin aviso: vence a los 30 min, no a la hora fija de antes
+        assert_eq!(next_check(&conversation, &settings(1800, None), minutes(5)), ExpiryCheck::Wait(minutes(30)));
+        assert_eq!(next_check(&conversation, &settings(1800, None), minutes(30)), ExpiryCheck::Expire);
+        assert_eq!(next_check(&conversation, &settings(7200, None), minutes(90)), ExpiryCheck::Wait(minutes(120)));
+
+        // Con un asesor no vence
+        let mut handed_off = conversation.clone();
+        handed_off.handoff = Some(Handoff::waiting(start));
+        assert_eq!(next_check(&handed_off, &settings(1800, None), minutes(45)), ExpiryCheck::Wait(minutes(75)));
+
+        // Un timer pendiente la mantiene hasta que se dispare
+        let mut waiting = conversation.clone();
+        waiting.timer = Some(PendingTimer::new(Uuid::new_v4(), "recordatorio", minutes(60)));
+        assert_eq!(next_check(&waiting, &settings(1800, None), minutes(45)), ExpiryCheck::Wait(minutes(90)));
+        assert_eq!(next_check(&waiting, &settings(1800, None), minutes(95)), ExpiryCheck::Expire);
+    }
+
+    #[test]
+    fn test_nudge_once_per_idle_period() {
+        let start = Utc::now();
+        let mut conversation = conversation_at(start);
+        let minutes = |m: i64| start + chrono::Duration::minutes(m);
+        let settings = settings(1800, Some(300));
+
+        assert_eq!(next_check(&conversation, &settings, minutes(10)), ExpiryCheck::Wait(minutes(25)));
+        assert_eq!(next_check(&conversation, &settings, minutes(25)), ExpiryCheck::Nudge);
+
+        // El aviso no cuenta como actividad ni se repite
+        conversation.add_outgoing_message("bot", MessageContent::text("¿Sigues ahí?"), None);
+        conversation.metadata.insert(NUDGE_INDEX.to_string(), serde_json::json!(2));
+        assert_eq!(idle_since(&conversation), start);
+        assert_eq!(next_check(&conversation, &settings, minutes(26)), ExpiryCheck::Wait(minutes(30)));
+        assert_eq!(next_check(&conversation, &settings, minutes(30)), ExpiryCheck::Expire);
+
+        // El cliente respondió: nuevo período, y puede volver a avisarse
+        conversation.add_received_message(MessageContent::text("sí"), None, None);
+        conversation.last_activity = minutes(28);
+        conversation.message_history.last_mut().unwrap().timestamp = minutes(28);
+        assert_eq!(next_check(&conversation, &settings, minutes(30)), ExpiryCheck::Wait(minutes(53)));
+        assert_eq!(next_check(&conversation, &settings, minutes(53)), ExpiryCheck::Nudge);
+    }
+
+    #[test]
+    fn test_hook_settings() {
+        let settings: BotSettings = serde_json::from_value(serde_json::json!({
+            "max_conversation_timeout_seconds": 900,
+            "expiry": {
+                "nudge": { "before_seconds": 120, "message": "¿Sigues ahí?" },
+                "hooks": [
+                    { "type": "survey", "flow_id": "7c0e3b1e-2f57-4c3a-9d0b-6f7b8f1d9a11", "on": ["expired"] },
+                    { "type": "webhook", "url": "https://crm.example.com/conversations" }
+                ]
+            }
+        })).unwrap();
+
+        assert_eq!(settings.timezone, "UTC");
+        assert_eq!(settings.expiry.nudge.as_ref().map(|nudge| nudge.before_seconds), Some(120));
+        let [survey, webhook] = &settings.expiry.hooks[..] else { panic!("expected two hooks") };
+        assert!(survey.runs_on(EXPIRED, false));
+        assert!(!survey.runs_on(BOT_DELETED, false));
+        assert!(webhook.runs_on(BOT_DELETED, false));
+
+        // Sin `on` la encuesta corre siempre, salvo en un bot borrado
+        let any_reason = ConversationHook::Survey { flow_id: Uuid::new_v4(), on: Vec::new() };
+        assert!(any_reason.runs_on(EXPIRED, false));
+        assert!(!any_reason.runs_on(BOT_DELETED, false));
+
+        // Un bot pausado no le escribe al cliente, pero el webhook sí corre
+        assert!(!survey.runs_on(EXPIRED, true));
+        assert!(webhook.runs_on(EXPIRED, true));
+
+        // Bots guardados antes de estos ajustes
+        let old: BotSettings = serde_json::from_value(serde_json::json!({ "timezone": "America/Caracas" })).unwrap();
+        assert_eq!(old.expiry, ExpirySettings::default());
+    }
+
+    #[test]
+    fn test_archived_document() {
+        let start = Utc::now();
+        let mut conversation = conversation_at(start);
+        conversation.set_variable("nombre", serde_json::json!("Ana"));
+
+        let archived = ArchivedConversation::new(&conversation, Some("tenant-1".to_string()), EXPIRED, start);
+        assert_eq!(archived.id, format!("c1:{}", start.timestamp_millis()));
+
+        let document = archived.to_document().unwrap();
+        assert_eq!(document.get_str("reason").unwrap(), EXPIRED);
+        assert_eq!(document.get_array("message_history").unwrap().len(), 2);
+        assert_eq!(document.get_document("context").unwrap().get_str("nombre").unwrap(), "Ana");
+    }
+}
@@ -0,0 +1,622 @@
+//! Expression Evaluator - Condiciones de `FlowStep::Decision`
+//!
+//! Lenguaje de expresiones sobre el contexto de la conversación:
+//! - Comparaciones: `==`, `!=`, `<`, `<=`, `>`, `>=`
+//! - Lógicos: `&&`, `||`, `!`
+//! - Pertenencia: `metodo_pago in ["zelle", "pago_movil"]`
+//! - Búsqueda: `carrito contains "torta"`
+//! - Paréntesis y rutas con puntos: `cliente.direccion.ciudad == "Caracas"`
+//!
+//! Las expresiones se compilan una sola vez al registrar el flow.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Error de sintaxis en una expresión
+#[derive(Debug, Clone, Error, PartialEq)]
+#[error("{message} (posición {position})")]
+pub struct ExpressionError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl ExpressionError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+/// Expresión compilada, lista para evaluarse contra un contexto
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Value),
+    Path(Vec<String>),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Box<Expr>),
+    Contains(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expression {
+    /// Compilar una expresión
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(ExpressionError::new(
+                format!("Token inesperado '{}'", token.kind),
+                token.position,
+            ));
+        }
+
+        Ok(Self { root })
+    }
+
+    /// Evaluar contra el contexto de la conversación
+    pub fn evaluate(&self, context: &HashMap<String, Value>) -> bool {
+        truthy(&eval(&self.root, context))
+    }
+}
+
+// ==================== Tokenizer ====================
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Dot,
+    Comma,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Not,
+    And,
+    Or,
+    Op(CompareOp),
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Ident(name) => write!(f, "{}", name),
+            TokenKind::Number(n) => write!(f, "{}", n),
+            TokenKind::Str(s) => write!(f, "\"{}\"", s),
+            TokenKind::Dot => write!(f, "."),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::LParen => write!(f, "("),
+            TokenKind::RParen => write!(f, ")"),
+            TokenKind::LBracket => write!(f, "["),
+            TokenKind::RBracket => write!(f, "]"),
+            TokenKind::Not => write!(f, "!"),
+            TokenKind::And => write!(f, "&&"),
+            TokenKind::Or => write!(f, "||"),
+            TokenKind::Op(op) => write!(f, "{}", match op {
+                CompareOp::Eq => "==",
+                CompareOp::Ne => "!=",
+                CompareOp::Lt => "<",
+                CompareOp::Le => "<=",
+                CompareOp::Gt => ">",
+                CompareOp::Ge => ">=",
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ExpressionError> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+
+        let (kind, consumed) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('=', Some('=')) => (TokenKind::Op(CompareOp::Eq), 2),
+            ('!', Some('=')) => (TokenKind::Op(CompareOp::Ne), 2),
+            ('<', Some('=')) => (TokenKind::Op(CompareOp::Le), 2),
+            ('>', Some('=')) => (TokenKind::Op(CompareOp::Ge), 2),
+            ('&', Some('&')) => (TokenKind::And, 2),
+            ('|', Some('|')) => (TokenKind::Or, 2),
+            ('<', _) => (TokenKind::Op(CompareOp::Lt), 1),
+            ('>', _) => (TokenKind::Op(CompareOp::Gt), 1),
+            ('!', _) => (TokenKind::Not, 1),
+            ('.', _) => (TokenKind::Dot, 1),
+            (',', _) => (TokenKind::Comma, 1),
+            ('(', _) => (TokenKind::LParen, 1),
+            (')', _) => (TokenKind::RParen, 1),
+            ('[', _) => (TokenKind::LBracket, 1),
+            (']', _) => (TokenKind::RBracket, 1),
+            ('"', _) | ('\'', _) => {
+                let quote = c;
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => {
+                            return Err(ExpressionError::new("Texto sin cerrar", position));
+                        }
+                        Some((_, '\\')) => {
+                            if let Some((_, escaped)) = chars.get(j + 1) {
+                                value.push(*escaped);
+                            }
+                            j += 2;
+                        }
+                        Some((_, ch)) if *ch == quote => break,
+                        Some((_, ch)) => {
+                            value.push(*ch);
+                            j += 1;
+                        }
+                    }
+                }
+                (TokenKind::Str(value), j + 1 - i)
+            }
+            (c, _) if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                // Después de un '.' es un índice de la ruta (`items.1.2`): solo
+                // dígitos. Si no, un '.' es decimal solo si le sigue un dígito.
+                let mut decimal = matches!(tokens.last(), Some(Token { kind: TokenKind::Dot, .. }));
+                let mut j = i + 1;
+                while let Some((_, ch)) = chars.get(j) {
+                    let digit_follows = chars.get(j + 1).is_some_and(|(_, n)| n.is_ascii_digit());
+                    if ch.is_ascii_digit() {
+                        j += 1;
+                    } else if *ch == '.' && !decimal && digit_follows {
+                        decimal = true;
+                        j += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let text: String = chars[i..j].iter().map(|(_, c)| *c).collect();
+                let number = text
+                    .parse::<f64>()
+                    .map_err(|_| ExpressionError::new(format!("Número inválido '{}'", text), position))?;
+                (TokenKind::Number(number), j - i)
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let mut j = i + 1;
+                while let Some((_, ch)) = chars.get(j) {
+                    if ch.is_alphanumeric() || *ch == '_' {
+                        j += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let ident: String = chars[i..j].iter().map(|(_, c)| *c).collect();
+                (TokenKind::Ident(ident), j - i)
+            }
+            (c, _) => {
+                return Err(ExpressionError::new(format!("Carácter inesperado '{}'", c), position));
+            }
+        };
+
+        tokens.push(Token { kind, position });
+        i += consumed;
+    }
+
+    Ok(tokens)
+}
+
+// ==================== Parser ====================
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek_kind(), Some(TokenKind::Ident(name)) if name == keyword)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn end_position(&self) -> usize {
+        self.tokens
+            .last()
+            .map(|t| t.position + t.kind.to_string().len())
+            .unwrap_or(0)
+    }
+
+    fn expect(&mut self, expected: TokenKind) -> Result<(), ExpressionError> {
+        match self.advance() {
+            Some(token) if token.kind == expected => Ok(()),
+            Some(token) => Err(ExpressionError::new(
+                format!("Se esperaba '{}' y se encontró '{}'", expected, token.kind),
+                token.position,
+            )),
+            None => Err(ExpressionError::new(
+                format!("Se esperaba '{}' al final de la expresión", expected),
+                self.end_position(),
+            )),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ExpressionError> {
+        let mut left = self.parse_and()?;
+        while self.peek_kind() == Some(&TokenKind::Or) {
+            self.advance();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ExpressionError> {
+        let mut left = self.parse_unary()?;
+        while self.peek_kind() == Some(&TokenKind::And) {
+            self.advance();
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExpressionError> {
+        if self.peek_kind() == Some(&TokenKind::Not) {
+            self.advance();
+            let inner = self.parse_unary()?;
+            return Ok(Expr::Not(Box::new(inner)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ExpressionError> {
+        let left = self.parse_operand()?;
+
+        if let Some(TokenKind::Op(op)) = self.peek_kind().cloned() {
+            self.advance();
+            let right = self.parse_operand()?;
+            return Ok(Expr::Compare(op, Box::new(left), Box::new(right)));
+        }
+
+        if self.peek_keyword("in") {
+            self.advance();
+            let right = self.parse_operand()?;
+            return Ok(Expr::In(Box::new(left), Box::new(right)));
+        }
+
+        if self.peek_keyword("not") {
+            // `x not in [...]`
+            self.advance();
+            if !self.peek_keyword("in") {
+                let position = self.peek().map(|t| t.position).unwrap_or_else(|| self.end_position());
+                return Err(ExpressionError::new("Se esperaba 'in' después de 'not'", position));
+            }
+            self.advance();
+            let right = self.parse_operand()?;
+            return Ok(Expr::Not(Box::new(Expr::In(Box::new(left), Box::new(right)))));
+        }
+
+        if self.peek_keyword("contains") {
+            self.advance();
+            let right = self.parse_operand()?;
+            return Ok(Expr::Contains(Box::new(left), Box::new(right)));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr, ExpressionError> {
+        let token = match self.advance() {
+            Some(token) => token,
+            None => {
+                return Err(ExpressionError::new(
+                    "Expresión incompleta",
+                    self.end_position(),
+                ));
+            }
+        };
+
+        match token.kind {
+            TokenKind::Number(n) => Ok(Expr::Literal(Value::from(n))),
+            TokenKind::Str(s) => Ok(Expr::Literal(Value::String(s))),
+            TokenKind::LParen => {
+                let inner = self.parse_or()?;
+                self.expect(TokenKind::RParen)?;
+                Ok(inner)
+            }
+            TokenKind::LBracket => {
+                let mut items = Vec::new();
+                if self.peek_kind() == Some(&TokenKind::RBracket) {
+                    self.advance();
+                    return Ok(Expr::List(items));
+                }
+                loop {
+                    items.push(self.parse_operand()?);
+                    match self.advance() {
+                        Some(Token { kind: TokenKind::Comma, .. }) => continue,
+                        Some(Token { kind: TokenKind::RBracket, .. }) => break,
+                        Some(other) => {
+                            return Err(ExpressionError::new(
+                                format!("Se esperaba ',' o ']' y se encontró '{}'", other.kind),
+                                other.position,
+                            ));
+                        }
+                        None => {
+                            return Err(ExpressionError::new("Lista sin cerrar", token.position));
+                        }
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            TokenKind::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ => {
+                    let mut path = vec![name];
+                    while self.peek_kind() == Some(&TokenKind::Dot) {
+                        self.advance();
+                        match self.advance() {
+                            Some(Token { kind: TokenKind::Ident(segment), .. }) => path.push(segment),
+                            Some(Token { kind: TokenKind::Number(n), .. }) if n >= 0.0 && n.fract() == 0.0 => {
+                                path.push((n as u64).to_string())
+                            }
+                            Some(other) => {
+                                return Err(ExpressionError::new(
+                                    format!("Ruta inválida después de '.': '{}'", other.kind),
+                                    other.position,
+                                ));
+                            }
+                            None => {
+                                return Err(ExpressionError::new(
+                                    "Ruta incompleta después de '.'",
+                                    self.end_position(),
+                                ));
+                            }
+                        }
+                    }
+                    Ok(Expr::Path(path))
+                }
+            },
+            other => Err(ExpressionError::new(
+                format!("Token inesperado '{}'", other),
+                token.position,
+            )),
+        }
+    }
+}
+
+// ==================== Evaluación ====================
+
+fn eval(expr: &Expr, context: &HashMap<String, Value>) -> Value {
+    match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Path(path) => resolve_path(context, path).cloned().unwrap_or(Value::Null),
+        Expr::List(items) => Value::Array(items.iter().map(|item| eval(item, context)).collect()),
+        Expr::Not(inner) => Value::Bool(!truthy(&eval(inner, context))),
+        Expr::And(left, right) => {
+            Value::Bool(truthy(&eval(left, context)) && truthy(&eval(right, context)))
+        }
+        Expr::Or(left, right) => {
+            Value::Bool(truthy(&eval(left, context)) || truthy(&eval(right, context)))
+        }
+        Expr::Compare(op, left, right) => {
+            Value::Bool(compare(*op, &eval(left, context), &eval(right, context)))
+        }
+        Expr::In(needle, haystack) => {
+            Value::Bool(contains(&eval(haystack, context), &eval(needle, context)))
+        }
+        Expr::Contains(haystack, needle) => {
+            Value::Bool(contains(&eval(haystack, context), &eval(needle, context)))
+        }
+    }
+}
+
+/// Resolver una ruta con puntos (`pedido.items.0.nombre`) dentro del contexto
+pub fn resolve_path<'a, S: AsRef<str>>(
+    context: &'a HashMap<String, Value>,
+    path: &[S],
+) -> Option<&'a Value> {
+    let (first, rest) = path.split_first()?;
+    let mut current = context.get(first.as_ref())?;
+
+    for segment in rest {
+        let segment = segment.as_ref();
+        current = match current {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(current)
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// Valor numérico; los textos numéricos ("18") cuentan como número
+/// porque las respuestas de `Question` se guardan como texto.
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+fn loose_eq(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), _) | (_, Value::Number(_)) => {
+            match (as_number(left), as_number(right)) {
+                (Some(l), Some(r)) => l == r,
+                _ => false,
+            }
+        }
+        (Value::Bool(b), Value::String(s)) | (Value::String(s), Value::Bool(b)) => {
+            s.eq_ignore_ascii_case(if *b { "true" } else { "false" })
+        }
+        _ => left == right,
+    }
+}
+
+fn compare(op: CompareOp, left: &Value, right: &Value) -> bool {
+    match op {
+        CompareOp::Eq => loose_eq(left, right),
+        CompareOp::Ne => !loose_eq(left, right),
+        _ => {
+            let ordering = match (as_number(left), as_number(right)) {
+                (Some(l), Some(r)) => l.partial_cmp(&r),
+                _ => match (left.as_str(), right.as_str()) {
+                    (Some(l), Some(r)) => Some(l.cmp(r)),
+                    _ => None,
+                },
+            };
+
+            match ordering {
+                Some(ordering) => match op {
+                    CompareOp::Lt => ordering.is_lt(),
+                    CompareOp::Le => ordering.is_le(),
+                    CompareOp::Gt => ordering.is_gt(),
+                    CompareOp::Ge => ordering.is_ge(),
+                    CompareOp::Eq | CompareOp::Ne => unreachable!(),
+                },
+                None => false,
+            }
+        }
+    }
+}
+
+fn contains(haystack: &Value, needle: &Value) -> bool {
+    match haystack {
+        Value::Array(items) => items.iter().any(|item| loose_eq(item, needle)),
+        Value::String(s) => match needle {
+            Value::String(n) => s.to_lowercase().contains(&n.to_lowercase()),
+            Value::Number(n) => s.contains(&n.to_string()),
+            _ => false,
+        },
+        Value::Object(map) => needle.as_str().is_some_and(|key| map.contains_key(key)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> HashMap<String, Value> {
+        let mut ctx = HashMap::new();
+        ctx.insert("edad".to_string(), json!("21"));
+        ctx.insert("total".to_string(), json!(45.5));
+        ctx.insert("confirmado".to_string(), json!(true));
+        ctx.insert("metodo_pago".to_string(), json!("zelle"));
+        ctx.insert("carrito".to_string(), json!(["torta", "galletas"]));
+        ctx.insert("pedido".to_string(), json!({ "items": [{ "nombre": "torta" }, { "nombre": "quesillo" }] }));
+        ctx.insert("tallas".to_string(), json!([["S", "M"], ["L", "XL", "XXL"]]));
+        ctx.insert(
+            "cliente".to_string(),
+            json!({ "nombre": "Ana", "direccion": { "ciudad": "Caracas" } }),
+        );
+        ctx
+    }
+
+    fn eval_str(source: &str) -> bool {
+        Expression::parse(source).unwrap().evaluate(&context())
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert!(eval_str("edad >= 18"));
+        assert!(eval_str("total < 50"));
+        assert!(eval_str("metodo_pago != \"efectivo\""));
+        assert!(eval_str("confirmado == true"));
+        assert!(!eval_str("total > 100"));
+    }
+
+    #[test]
+    fn test_logic_and_parentheses() {
+        assert!(eval_str("confirmado && (total > 100 || metodo_pago == 'zelle')"));
+        assert!(!eval_str("!confirmado"));
+        assert!(eval_str("!(edad < 18) && confirmado"));
+    }
+
+    #[test]
+    fn test_in_and_contains() {
+        assert!(eval_str("metodo_pago in [\"zelle\", \"pago_movil\"]"));
+        assert!(eval_str("metodo_pago not in [\"efectivo\"]"));
+        assert!(eval_str("carrito contains \"torta\""));
+        assert!(!eval_str("carrito contains \"helado\""));
+    }
+
+    #[test]
+    fn test_dotted_paths() {
+        assert!(eval_str("cliente.direccion.ciudad == \"Caracas\""));
+        assert!(eval_str("carrito.0 == \"torta\""));
+        assert!(!eval_str("cliente.telefono"));
+
+        // Índices en medio de la ruta
+        assert!(eval_str("pedido.items.0.nombre == \"torta\""));
+        assert!(eval_str("pedido.items.1.nombre == 'quesillo'"));
+        assert!(eval_str("tallas.1.2 == \"XXL\""));
+        assert!(!eval_str("tallas.1.2 == \"M\""));
+        assert!(eval_str("total > 45.4 && total < 45.6"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expression::parse("edad >=").is_err());
+        assert!(Expression::parse("(edad > 18").is_err());
+        assert!(Expression::parse("edad = 18").is_err());
+        assert!(Expression::parse("metodo_pago in [\"zelle\"").is_err());
+        assert!(Expression::parse("nombre == \"Ana").is_err());
+    }
+}
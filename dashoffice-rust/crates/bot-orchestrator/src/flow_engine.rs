@@ -1,26 +1,47 @@
-//\! Flow Engine - Motor de flows conversacionales
-//\! 
-//\! Ejecuta flows de conversación con:
-//\! - Steps (mensaje, pregunta, decisión, acción)
-//\! - Variables y contexto
-//\! - Condiciones y bifurcaciones
-//\! - Integración con APIs
-//\! - Persistencia de estado
+//! Flow Engine - Motor de flows conversacionales
+//! 
+//! Ejecuta flows de conversación con:
+//! - Steps (mensaje, pregunta, decisión, acción)
+//! - Variables y contexto
+//! - Condiciones y bifurcaciones
+//! - Integración con APIs
+//! - Persistencia de estado
 
+use parking_lot::RwLock;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
 use uuid::Uuid;
 use anyhow::Result;
+use thiserror::Error;
 
-use super::state_machine::ConversationState;
+use super::actions::{ActionConfig, ActionHandler, ActionRegistry};
+use super::clock::{Clock, SystemClock};
+use super::event_log::ConversationEvent;
+use super::expression::Expression;
+use super::flow_validator::{find_recursive_calls, validate_flow, Diagnostic};
+use super::handoff::{Handoff, HandoffStatus};
+use super::input_validation::normalize_content;
+use super::menu::{self, MenuDisplay};
+use super::message::MessageContent;
+use super::metrics::FlowOutcome;
+use super::scheduler::PendingTimer;
+use super::state_machine::{CallFrame, ConversationState};
+use super::template::{conversation_timezone, conversation_variables, render_json, render_template};
+use super::FlowConfig;
 
 /// Flow conversacional completo
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Flow {
     pub id: Uuid,
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub steps: Vec<FlowStep>,
+    /// Valores por defecto de los parámetros cuando se llama como sub-flow (`CallFlow`)
+    #[serde(default)]
     pub variables: HashMap<String, serde_json::Value>,
 }
 
@@ -28,7 +49,7 @@ pub struct Flow {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum FlowStep {
-    /// Enviar mensaje
+    /// Enviar mensaje y continuar con `next_step` (o terminar el flow)
     Message {
         id: String,
         text: String,
@@ -42,6 +63,28 @@ pub enum FlowStep {
         variable_name: String,
         validation: Option<Validation>,
         next_step: Option<String>,
+        /// Respuestas inválidas permitidas (por defecto `DEFAULT_MAX_ATTEMPTS`)
+        #[serde(default)]
+        max_attempts: Option<u32>,
+        /// Step al que saltar cuando se agotan los intentos
+        #[serde(default)]
+        on_exhausted: Option<String>,
+        /// Segundos sin respuesta antes de saltar a `on_timeout`
+        #[serde(default)]
+        timeout_seconds: Option<u64>,
+        #[serde(default)]
+        on_timeout: Option<String>,
+    },
+    
+    /// Esperar `seconds` y continuar con `next_step` (p. ej. un seguimiento).
+    /// Si el cliente escribe antes, la espera se cancela y el mensaje va a
+    /// `on_reply` (o al fallback del bot si no hay).
+    Wait {
+        id: String,
+        seconds: u64,
+        next_step: Option<String>,
+        #[serde(default)]
+        on_reply: Option<String>,
     },
     
     /// Decisión basada en condición
@@ -56,18 +99,73 @@ pub enum FlowStep {
     Action {
         id: String,
         action_type: ActionType,
+        #[serde(default)]
         parameters: HashMap<String, serde_json::Value>,
         next_step: Option<String>,
+        /// Step al que saltar si la acción falla (el error queda en `last_error`)
+        #[serde(default)]
+        on_error: Option<String>,
     },
     
-    /// Mostrar menú de opciones
+    /// Mostrar menú de opciones (como texto numerado o interactivo, ver `menu`)
     Menu {
         id: String,
         text: String,
         options: Vec<MenuOption>,
+        #[serde(default)]
+        display: MenuDisplay,
+        /// Texto del botón que abre la lista (`display: interactive` con más de 3 opciones)
+        #[serde(default)]
+        button_text: Option<String>,
+        #[serde(default)]
+        max_attempts: Option<u32>,
+        #[serde(default)]
+        on_exhausted: Option<String>,
+        #[serde(default)]
+        timeout_seconds: Option<u64>,
+        #[serde(default)]
+        on_timeout: Option<String>,
+    },
+    
+    /// Ejecutar otro flow como sub-flow (p. ej. "pedir dirección"). Cuando el
+    /// sub-flow termina, la conversación vuelve a este flow en `return_step`
+    /// (sin `return_step` este flow también termina). El sub-flow ve las
+    /// variables del caller más `parameters`; lo que guarde se descarta al
+    /// volver, salvo las variables de `outputs`.
+    CallFlow {
+        id: String,
+        flow_id: Uuid,
+        return_step: Option<String>,
+        /// Variables para el sub-flow (los textos admiten templates)
+        #[serde(default)]
+        parameters: HashMap<String, serde_json::Value>,
+        /// Variables del sub-flow que se copian al caller (sub-flow -> caller)
+        #[serde(default)]
+        outputs: HashMap<String, String>,
     },
     
-    /// Fin del flow
+    /// Seguir en otro flow sin volver, en `step` o en su primer step. Dentro de
+    /// un sub-flow, el flow destino ocupa su lugar y vuelve al mismo caller.
+    GotoFlow {
+        id: String,
+        flow_id: Uuid,
+        #[serde(default)]
+        step: Option<String>,
+    },
+    
+    /// Pasar la conversación a un asesor humano (en cola hasta la apertura si
+    /// el bot está fuera de horario). El asesor puede devolverla a `next_step`,
+    /// que queda como `return_to` del handoff; sin `next_step` el flow termina.
+    Handoff {
+        id: String,
+        /// Respuesta mientras se busca asesor (fuera de horario se avisa cuándo abre)
+        #[serde(default)]
+        text: Option<String>,
+        #[serde(default)]
+        next_step: Option<String>,
+    },
+    
+    /// Fin del flow (o del sub-flow, que vuelve a su caller)
     End {
         id: String,
         message: Option<String>,
@@ -76,9 +174,16 @@ pub enum FlowStep {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MenuOption {
+    /// Lo que escribe el usuario y el id del botón/fila en menús interactivos
     pub key: String,
     pub label: String,
     pub next_step: String,
+    /// Descripción de la fila en menús de lista
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Sección de la lista en la que aparece la opción
+    #[serde(default)]
+    pub section: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -96,6 +201,14 @@ pub enum ValidationType {
     Text,
     Date,
     Regex(String),
+    /// El cliente debe enviar un mensaje de ese tipo (p. ej. la foto de un
+    /// comprobante); se guarda el contenido completo
+    Image,
+    Audio,
+    Video,
+    Document,
+    Location,
+    Contacts,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -109,255 +222,1002 @@ pub enum ActionType {
     Custom(String),
 }
 
+/// Errores al registrar un flow
+#[derive(Debug, Error)]
+pub enum FlowError {
+    #[error("Flow {flow_id} is invalid: {} problem(s) found", diagnostics.len())]
+    Invalid {
+        flow_id: Uuid,
+        diagnostics: Vec<Diagnostic>,
+    },
+}
+
+/// Versiones anteriores que se conservan por flow para conversaciones en curso
+const MAX_RETAINED_VERSIONS: usize = 20;
+
+/// Flow registrado, con sus condiciones ya compiladas
+#[derive(Debug, Clone)]
+pub struct CompiledFlow {
+    pub flow: Flow,
+    /// Versión: hash del contenido (ver `content_version`). El mismo contenido
+    /// tiene la misma versión tras un reinicio o una recarga, así una
+    /// conversación guardada en Redis sigue con el flow con el que empezó
+    pub version: u32,
+    conditions: HashMap<String, Expression>,
+    /// Regex de las validaciones `Regex` por step
+    regexes: HashMap<String, Regex>,
+}
+
+impl CompiledFlow {
+    /// Validar el flow y compilar las condiciones de sus `Decision` y las regex de sus `Question`
+    pub fn compile(flow: Flow) -> std::result::Result<Self, FlowError> {
+        let diagnostics = validate_flow(&flow);
+        if !diagnostics.is_empty() {
+            return Err(FlowError::Invalid {
+                flow_id: flow.id,
+                diagnostics,
+            });
+        }
+
+        // La validación ya rechazó condiciones y regex con errores de sintaxis
+        let mut conditions = HashMap::new();
+        let mut regexes = HashMap::new();
+        for step in &flow.steps {
+            match step {
+                FlowStep::Decision { id, condition, .. } => {
+                    if let Ok(expression) = Expression::parse(condition) {
+                        conditions.insert(id.clone(), expression);
+                    }
+                }
+                FlowStep::Question { id, validation: Some(validation), .. } => {
+                    if let ValidationType::Regex(pattern) = &validation.validation_type {
+                        if let Ok(regex) = Regex::new(pattern) {
+                            regexes.insert(id.clone(), regex);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            version: content_version(&flow),
+            flow,
+            conditions,
+            regexes,
+        })
+    }
+
+    fn step(&self, step_id: &str) -> Option<&FlowStep> {
+        self.flow.steps.iter().find(|s| s.id() == step_id)
+    }
+}
+
+/// Versión de un flow: los primeros 4 bytes del SHA-256 de su JSON con las
+/// claves ordenadas (los `HashMap` no tienen orden fijo)
+pub fn content_version(flow: &Flow) -> u32 {
+    let canonical = serde_json::to_value(flow).map(sort_keys).unwrap_or_default().to_string();
+    let digest = Sha256::digest(canonical.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(entries.into_iter().map(|(key, value)| (key, sort_keys(value))).collect())
+        }
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.into_iter().map(sort_keys).collect()),
+        other => other,
+    }
+}
+
+/// Palabras que llevan al menú principal del bot desde cualquier step
+const MENU_KEYWORDS: &[&str] = &["menu", "menú"];
+
+/// Atajo al menú principal; en un `Question` que lo acepta como respuesta
+/// (p. ej. una cantidad) es la respuesta
+const MENU_SHORTCUT: &str = "0";
+
+/// Palabras para abandonar el flow actual desde cualquier step
+const EXIT_KEYWORDS: &[&str] = &["salir", "cancelar"];
+
+/// Palabras para pedir un asesor humano desde cualquier step
+const AGENT_KEYWORDS: &[&str] = &["asesor", "agente", "humano"];
+
+/// Respuestas inválidas seguidas antes de sacar al usuario de un `Question`/`Menu`
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Sub-flows anidados permitidos en una conversación. La validación ya
+/// rechaza las llamadas recursivas; esto cubre versiones viejas de flows que
+/// siguen en uso.
+pub const MAX_CALL_DEPTH: usize = 8;
+
+/// Saltos entre flows (llamadas, `GotoFlow` y retornos) sin esperar al usuario
+const MAX_FLOW_JUMPS: usize = 32;
+
+/// Palabra clave que funciona en cualquier step
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EscapeKeyword {
+    Menu,
+    Exit,
+    Agent,
+}
+
+/// Versiones registradas de un flow
+struct FlowVersions {
+    latest: Arc<CompiledFlow>,
+    /// De la más vieja a la actual
+    history: VecDeque<Arc<CompiledFlow>>,
+}
+
 /// Motor de flows
 pub struct FlowEngine {
-    flows: HashMap<Uuid, Flow>,
+    flows: RwLock<HashMap<Uuid, FlowVersions>>,
+    actions: ActionRegistry,
+    /// Hora de los timers, los handoffs y las fechas relativas ("hoy", "mañana")
+    clock: Arc<dyn Clock>,
 }
 
 impl FlowEngine {
     pub fn new() -> Self {
+        Self::with_actions(ActionRegistry::with_builtin_handlers(&ActionConfig::from_env()))
+    }
+
+    pub fn with_actions(actions: ActionRegistry) -> Self {
         Self {
-            flows: HashMap::new(),
+            flows: RwLock::new(HashMap::new()),
+            actions,
+            clock: Arc::new(SystemClock),
         }
     }
+
+    /// Usar otro reloj (el del orchestrator; uno fijo en los tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Registrar el handler de una acción `Custom(name)`
+    pub fn register_custom_action(&self, name: &str, handler: Arc<dyn ActionHandler>) {
+        self.actions.register_custom(name, handler);
+    }
     
-    /// Registrar un flow
-    pub fn register_flow(&mut self, flow: Flow) {
-        self.flows.insert(flow.id, flow);
+    /// Registrar un flow (o una nueva versión de uno existente)
+    ///
+    /// Los flows con errores (referencias rotas, condiciones inválidas, ciclos,
+    /// sub-flows que se llaman a sí mismos...) se rechazan aquí, no cuando un
+    /// cliente llega a ese step. La nueva versión reemplaza a la anterior de
+    /// forma atómica; las conversaciones en curso siguen usando la versión con
+    /// la que empezaron. Volver a registrar el mismo contenido no crea otra versión.
+    pub fn register_flow(&self, flow: Flow) -> std::result::Result<u32, FlowError> {
+        let compiled = CompiledFlow::compile(flow)?;
+
+        let mut flows = self.flows.write();
+        let recursion = find_recursive_calls(&compiled.flow, |flow_id| {
+            flows.get(&flow_id)
+                .map(|versions| versions.latest.flow.steps.iter().filter_map(FlowStep::linked_flow).collect())
+                .unwrap_or_default()
+        });
+        if !recursion.is_empty() {
+            return Err(FlowError::Invalid {
+                flow_id: compiled.flow.id,
+                diagnostics: recursion,
+            });
+        }
+
+        let version = compiled.version;
+        let compiled = Arc::new(compiled);
+        let versions = flows
+            .entry(compiled.flow.id)
+            .or_insert_with(|| FlowVersions {
+                latest: compiled.clone(),
+                history: VecDeque::new(),
+            });
+        versions.history.retain(|previous| previous.version != version);
+        versions.latest = compiled.clone();
+        versions.history.push_back(compiled);
+
+        while versions.history.len() > MAX_RETAINED_VERSIONS {
+            versions.history.pop_front();
+        }
+
+        Ok(version)
+    }
+
+    /// Volver a cargar una versión anterior de un flow (guardada en `flow_store`)
+    /// para las conversaciones que la usan; no reemplaza a la versión actual
+    pub fn restore_version(&self, flow: Flow) -> std::result::Result<u32, FlowError> {
+        let compiled = Arc::new(CompiledFlow::compile(flow)?);
+        let version = compiled.version;
+
+        let mut flows = self.flows.write();
+        let versions = flows
+            .entry(compiled.flow.id)
+            .or_insert_with(|| FlowVersions {
+                latest: compiled.clone(),
+                history: VecDeque::new(),
+            });
+        if !versions.history.iter().any(|flow| flow.version == version) {
+            // Justo antes de la actual, para que sea de las últimas en descartarse
+            let position = versions.history.len().saturating_sub(1);
+            versions.history.insert(position, compiled);
+            while versions.history.len() > MAX_RETAINED_VERSIONS {
+                versions.history.pop_front();
+            }
+        }
+
+        Ok(version)
+    }
+
+    /// Versiones de flows que usa la conversación (flow actual, callers de
+    /// sub-flows y retorno de un handoff) y que no están cargadas
+    pub fn missing_versions(&self, conversation: &ConversationState) -> Vec<(Uuid, u32)> {
+        let current = conversation.current_flow_id.zip(conversation.current_flow_version);
+        let callers = conversation.call_stack.iter()
+            .filter_map(|frame| frame.flow_version.map(|version| (frame.flow_id, version)));
+        let handoff = conversation.handoff.as_ref()
+            .and_then(|handoff| handoff.return_to.as_ref())
+            .and_then(|position| position.flow_version.map(|version| (position.flow_id, version)));
+
+        let flows = self.flows.read();
+        let mut missing: Vec<(Uuid, u32)> = current.into_iter()
+            .chain(callers)
+            .chain(handoff)
+            .filter(|(flow_id, version)| {
+                !flows.get(flow_id)
+                    .is_some_and(|versions| versions.history.iter().any(|flow| flow.version == *version))
+            })
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    }
+
+    /// Todas las versiones cargadas, para guardarlas en `flow_store`
+    pub fn retained_versions(&self) -> Vec<Arc<CompiledFlow>> {
+        self.flows.read()
+            .values()
+            .flat_map(|versions| versions.history.iter().cloned())
+            .collect()
+    }
+
+    /// Obtener un flow; `version` fija la versión con la que empezó una conversación
+    ///
+    /// Si esa versión ya no está cargada (el archivo cambió antes de un
+    /// reinicio, o pasaron más de `MAX_RETAINED_VERSIONS` recargas) devuelve
+    /// `None`: seguir en otro contenido llevaría al cliente a steps que no son
+    /// los que vio. Al restaurar una conversación desde Redis, las versiones
+    /// que usa se recuperan antes con `flow_store::restore_pinned_versions`.
+    pub fn get_flow(&self, flow_id: Uuid, version: Option<u32>) -> Option<Arc<CompiledFlow>> {
+        let flows = self.flows.read();
+        let versions = flows.get(&flow_id)?;
+
+        match version {
+            Some(version) => {
+                let flow = versions.history.iter().rev().find(|flow| flow.version == version).cloned();
+                if flow.is_none() {
+                    tracing::warn!(
+                        "Flow {} version {:08x} is no longer loaded (current: {:08x})",
+                        flow_id, version, versions.latest.version
+                    );
+                }
+                flow
+            }
+            None => Some(versions.latest.clone()),
+        }
     }
     
     /// Procesar mensaje del usuario
+    ///
+    /// `entry_points` son los flows del bot: welcome para conversaciones nuevas,
+    /// menu para las palabras clave de `MENU_KEYWORDS` (y `MENU_SHORTCUT`) y fallback cuando el step
+    /// actual no puede manejar el mensaje. `salir` y `asesor` funcionan en
+    /// cualquier step.
+    ///
+    /// Mientras la conversación está en manos de un asesor (`handoff`) el bot
+    /// no responde; si aún nadie la tomó, `salir` o `menu` la sacan de la cola.
+    ///
+    /// Cualquier mensaje del cliente cancela el timer pendiente (`Wait`, `on_timeout`).
     pub async fn process(
         &self,
         conversation: &mut ConversationState,
-        user_message: &str,
-    ) -> Result<Option<String>> {
-        // Si no hay flow activo, iniciar welcome flow
-        if conversation.current_flow_id.is_none() {
-            return Ok(Some(self.start_welcome_flow(conversation)?));
+        entry_points: &FlowConfig,
+        message: &MessageContent,
+    ) -> Result<Option<MessageContent>> {
+        conversation.timer = None;
+
+        if let Some(handoff) = &conversation.handoff {
+            let keyword = match message {
+                MessageContent::Text { body } => escape_keyword(body),
+                _ => None,
+            };
+            return match keyword {
+                Some(keyword @ (EscapeKeyword::Exit | EscapeKeyword::Menu))
+                    if handoff.status != HandoffStatus::Active =>
+                {
+                    // El cliente deja la cola: el flow en pausa no se retoma (como en `release`)
+                    if conversation.end_handoff().is_some_and(|handoff| handoff.return_to.is_some()) {
+                        conversation.call_stack.clear();
+                        conversation.flow_outcomes.push(FlowOutcome::Abandoned);
+                    }
+                    self.handle_escape(conversation, entry_points, keyword).await
+                }
+                _ => Ok(None),
+            };
         }
+
+        // Palabras clave globales, solo en texto (salvo que el step actual acepte ese texto)
+        if let MessageContent::Text { body } = message {
+            if let Some(keyword) = escape_keyword(body) {
+                if !self.current_step_accepts(conversation, body) {
+                    if let Some(reply) = self.handle_escape(conversation, entry_points, keyword).await? {
+                        return Ok(Some(reply));
+                    }
+                }
+            }
+        }
+
+        // Si no hay flow activo, iniciar welcome flow
+        let flow_id = match conversation.current_flow_id {
+            Some(flow_id) => flow_id,
+            None => return self.start_welcome_flow(conversation, entry_points).await,
+        };
         
-        // Obtener flow actual
-        let flow_id = conversation.current_flow_id.unwrap();
-        let flow = self.flows.get(&flow_id)
-            .ok_or_else(|| anyhow::anyhow\!("Flow not found"))?;
-        
-        // Obtener step actual
-        let current_step_id = conversation.current_step_id.as_ref()
-            .ok_or_else(|| anyhow::anyhow\!("No current step"))?;
-        
-        let current_step = flow.steps.iter()
-            .find(|s| s.id() == current_step_id)
-            .ok_or_else(|| anyhow::anyhow\!("Step not found"))?;
+        // Obtener flow y step actual (pueden haber desaparecido tras una recarga,
+        // o la versión de la conversación ya no estar cargada)
+        let flow = self.get_flow(flow_id, conversation.current_flow_version);
+        let current_step = flow.as_deref().and_then(|flow| {
+            conversation.current_step_id.as_deref().and_then(|id| flow.step(id)).cloned()
+        });
+
+        let (flow, current_step) = match (flow, current_step) {
+            (Some(flow), Some(step)) => (flow, step),
+            _ => {
+                tracing::warn!(
+                    "Conversation {} points to a missing flow/step ({} / {:?}), restarting",
+                    conversation.id, flow_id, conversation.current_step_id
+                );
+                finish_flow(conversation, FlowOutcome::Abandoned);
+                return self.start_welcome_flow(conversation, entry_points).await;
+            }
+        };
         
         // Procesar según tipo de step
-        match current_step {
-            FlowStep::Question { variable_name, validation, next_step, .. } => {
-                // Validar respuesta
-                if let Some(val) = validation {
-                    if \!self.validate_input(user_message, val) {
-                        return Ok(Some(val.error_message.clone()));
-                    }
-                }
+        match &current_step {
+            FlowStep::Question { id, variable_name, validation, next_step, max_attempts, on_exhausted, .. } => {
+                // Validar y normalizar respuesta
+                let value = match validation {
+                    Some(val) => match self.validate_input(conversation, message, val, flow.regexes.get(id)) {
+                        Some(value) => value,
+                        None => {
+                            return self.handle_invalid_input(
+                                conversation, &flow, entry_points, id,
+                                *max_attempts, on_exhausted.as_deref(), &val.error_message,
+                            ).await;
+                        }
+                    },
+                    None => match message {
+                        MessageContent::Text { body } => serde_json::json!(body),
+                        other => serde_json::to_value(other)?,
+                    },
+                };
+                conversation.reset_attempts(&attempt_key(&flow, id));
                 
-                // Guardar respuesta en contexto
-                conversation.set_variable(variable_name, serde_json::json\!(user_message));
+                // Guardar respuesta (normalizada) en contexto
+                conversation.set_variable(variable_name, value);
                 
                 // Avanzar al siguiente step
-                if let Some(next) = next_step {
-                    return self.execute_step(conversation, flow, next).await;
+                match next_step {
+                    Some(next) => return self.execute_step(conversation, flow.clone(), next).await,
+                    None => return self.end_flow(conversation).await,
                 }
             }
             
-            FlowStep::Menu { options, .. } => {
+            FlowStep::Wait { on_reply: Some(on_reply), .. } => {
+                return self.execute_step(conversation, flow.clone(), on_reply).await;
+            }
+            
+            FlowStep::Menu { id, options, max_attempts, on_exhausted, .. } => {
                 // Buscar opción seleccionada
-                if let Some(option) = options.iter().find(|o| o.key == user_message || o.label.to_lowercase() == user_message.to_lowercase()) {
-                    return self.execute_step(conversation, flow, &option.next_step).await;
+                let option = message.as_text().and_then(|text| menu::find_option(options, text));
+                if let Some(option) = option {
+                    conversation.reset_attempts(&attempt_key(&flow, id));
+                    return self.execute_step(conversation, flow.clone(), &option.next_step).await;
                 } else {
-                    return Ok(Some("Opción inválida. Por favor selecciona una opción válida.".to_string()));
+                    return self.handle_invalid_input(
+                        conversation, &flow, entry_points, id,
+                        *max_attempts, on_exhausted.as_deref(),
+                        "Opción inválida. Por favor selecciona una opción válida.",
+                    ).await;
                 }
             }
             
-            _ => {
-                // Para otros types, continuar normalmente
-                if let Some(next) = current_step.next_step() {
-                    return self.execute_step(conversation, flow, next).await;
-                }
-            }
+            _ => {}
         }
         
-        Ok(None)
+        // El step actual no espera respuesta del usuario
+        self.start_fallback_flow(conversation, entry_points).await
     }
     
-    /// Ejecutar un step específico
-    async fn execute_step(
+    /// Respuesta inválida en un `Question`/`Menu`: repetir el error o, si se
+    /// agotaron los intentos, ir a `on_exhausted` (o al fallback del bot)
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_invalid_input(
         &self,
         conversation: &mut ConversationState,
-        flow: &Flow,
+        flow: &Arc<CompiledFlow>,
+        entry_points: &FlowConfig,
         step_id: &str,
-    ) -> Result<Option<String>> {
-        let step = flow.steps.iter()
-            .find(|s| s.id() == step_id)
-            .ok_or_else(|| anyhow::anyhow\!("Step not found: {}", step_id))?;
-        
-        conversation.current_step_id = Some(step_id.to_string());
-        
-        match step {
-            FlowStep::Message { text, next_step, .. } => {
-                let rendered_text = self.render_template(text, conversation);
-                
-                if let Some(next) = next_step {
-                    conversation.current_step_id = Some(next.clone());
+        max_attempts: Option<u32>,
+        on_exhausted: Option<&str>,
+        error_message: &str,
+    ) -> Result<Option<MessageContent>> {
+        let key = attempt_key(flow, step_id);
+        let failures = conversation.record_failed_attempt(&key);
+
+        if failures < max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS) {
+            return Ok(Some(MessageContent::text(error_message)));
+        }
+
+        tracing::info!(
+            "🔁 Conversation {} exhausted {} attempts at step '{}'",
+            conversation.id, failures, key
+        );
+        conversation.record_exhausted(&key);
+
+        if let Some(next) = on_exhausted {
+            return self.execute_step(conversation, flow.clone(), next).await;
+        }
+
+        finish_flow(conversation, FlowOutcome::Abandoned);
+        match self.start_fallback_flow(conversation, entry_points).await? {
+            Some(reply) => Ok(Some(reply)),
+            None => Ok(Some(MessageContent::text(
+                "Parece que no logro entenderte 😅. Escribe *menu* para ver las opciones \
+                 o *asesor* para hablar con una persona.",
+            ))),
+        }
+    }
+
+    /// Ejecutar una palabra clave global. `None` si el bot no tiene a dónde llevarla.
+    async fn handle_escape(
+        &self,
+        conversation: &mut ConversationState,
+        entry_points: &FlowConfig,
+        keyword: EscapeKeyword,
+    ) -> Result<Option<MessageContent>> {
+        match keyword {
+            EscapeKeyword::Menu => {
+                match entry_points.menu_flow_id.or(entry_points.welcome_flow_id) {
+                    Some(flow_id) if self.get_flow(flow_id, None).is_some() => {
+                        self.start_flow(conversation, flow_id).await
+                    }
+                    _ => Ok(None),
                 }
-                
-                Ok(Some(rendered_text))
-            }
-            
-            FlowStep::Question { text, .. } => {
-                let rendered_text = self.render_template(text, conversation);
-                Ok(Some(rendered_text))
             }
-            
-            FlowStep::Decision { condition, true_step, false_step, .. } => {
-                let result = self.evaluate_condition(condition, conversation)?;
-                let next_step = if result { true_step } else { false_step };
-                self.execute_step(conversation, flow, next_step).await
+            EscapeKeyword::Exit => {
+                finish_flow(conversation, FlowOutcome::Abandoned);
+                Ok(Some(MessageContent::text(
+                    "Listo, cancelamos lo que estábamos haciendo. Escríbenos cuando quieras 👋",
+                )))
             }
-            
-            FlowStep::Action { action_type, parameters, next_step, .. } => {
-                // Ejecutar acción
-                self.execute_action(action_type, parameters, conversation).await?;
-                
-                if let Some(next) = next_step {
-                    self.execute_step(conversation, flow, next).await
-                } else {
-                    Ok(None)
+            EscapeKeyword::Agent => Ok(Some(MessageContent::text(self.request_handoff(conversation, None)))),
+        }
+    }
+
+    /// Pasar la conversación a un asesor desde el step actual. Fuera de
+    /// horario el pedido queda en cola hasta la apertura. Devuelve la
+    /// respuesta para el cliente (`text`, o la de por defecto).
+    fn request_handoff(&self, conversation: &mut ConversationState, text: Option<String>) -> String {
+        let Some(hours) = closed_business_hours(conversation) else {
+            conversation.start_handoff(Handoff::waiting(self.clock.now()));
+            return text.unwrap_or_else(|| "En un momento un asesor te atenderá.".to_string());
+        };
+
+        let next_open = hours["next_open"].as_str()
+            .and_then(|next| chrono::DateTime::parse_from_rfc3339(next).ok())
+            .map(|next| next.with_timezone(&chrono::Utc));
+        conversation.start_handoff(Handoff::queued(self.clock.now(), next_open));
+
+        match hours["next_open_text"].as_str() {
+            Some(when) => format!(
+                "Nuestros asesores no están disponibles en este momento. Te atenderán {} 🙌",
+                when
+            ),
+            None => "Nuestros asesores no están disponibles en este momento. \
+                     Te atenderán apenas abramos 🙌".to_string(),
+        }
+    }
+
+    /// Ejecutar un step específico
+    ///
+    /// `Message`, `Decision` y `Action` encadenan al siguiente step sin esperar
+    /// al usuario, por eso se recorren en un loop en lugar de recursión. Los
+    /// mensajes encadenados se envían juntos en una sola respuesta. Lo mismo
+    /// con `CallFlow`/`GotoFlow` y el retorno de un sub-flow: el loop sigue en
+    /// el otro flow.
+    async fn execute_step(
+        &self,
+        conversation: &mut ConversationState,
+        flow: Arc<CompiledFlow>,
+        step_id: &str,
+    ) -> Result<Option<MessageContent>> {
+        let mut flow = flow;
+        let mut step_id = step_id.to_string();
+        let mut replies: Vec<String> = Vec::new();
+        let mut interactive = None;
+        let mut jumps = 0;
+
+        loop {
+            let current = flow.clone();
+            let step = current.step(&step_id)
+                .ok_or_else(|| anyhow::anyhow!("Step not found: {}", step_id))?;
+
+            move_to_step(conversation, &step_id);
+
+            match step {
+                FlowStep::Message { text, next_step: next, .. } => {
+                    replies.push(self.render_template(text, conversation));
+
+                    match self.advance(conversation, &current, next.as_ref())? {
+                        Some((next_flow, next)) => (flow, step_id) = (next_flow, next),
+                        None => break,
+                    }
                 }
-            }
-            
-            FlowStep::Menu { text, options, .. } => {
-                let mut message = self.render_template(text, conversation);
-                message.push_str("\n\n");
-                
-                for option in options {
-                    message.push_str(&format\!("{} - {}\n", option.key, option.label));
+
+                FlowStep::Question { text, .. } => {
+                    replies.push(self.render_template(text, conversation));
+                    break;
+                }
+
+                FlowStep::Wait { .. } => break,
+
+                FlowStep::Decision { id, true_step, false_step, .. } => {
+                    let result = self.evaluate_condition(&flow, id, conversation)?;
+                    step_id = if result { true_step.clone() } else { false_step.clone() };
+                }
+
+                FlowStep::Action { id, action_type, parameters, next_step: next, on_error } => {
+                    // Ejecutar acción
+                    if let Err(e) = self.execute_action(action_type, parameters, conversation).await {
+                        match on_error {
+                            Some(error_step) => {
+                                tracing::warn!("Action '{}' failed, going to '{}': {:#}", id, error_step, e);
+                                conversation.set_variable("last_error", serde_json::json!(e.to_string()));
+                                step_id = error_step.clone();
+                                continue;
+                            }
+                            None => return Err(e.context(format!("Action '{}' failed", id))),
+                        }
+                    }
+
+                    match self.advance(conversation, &current, next.as_ref())? {
+                        Some((next_flow, next)) => (flow, step_id) = (next_flow, next),
+                        None => break,
+                    }
+                }
+
+                FlowStep::Menu { text, options, display, button_text, .. } => {
+                    let body = self.render_template(text, conversation);
+
+                    match display {
+                        MenuDisplay::Text => replies.push(menu::numbered_text(&body, options)),
+                        MenuDisplay::Interactive => {
+                            // Los mensajes encadenados antes del menú van en su cuerpo
+                            replies.push(body);
+                            let body = std::mem::take(&mut replies).join("\n\n");
+                            interactive = Some(menu::interactive(body, options, button_text.as_deref()));
+                        }
+                    }
+                    break;
+                }
+
+                FlowStep::CallFlow { id, flow_id, return_step, parameters, outputs } => {
+                    if conversation.call_stack.len() >= MAX_CALL_DEPTH {
+                        anyhow::bail!("Step '{}' exceeds the maximum sub-flow depth ({})", id, MAX_CALL_DEPTH);
+                    }
+                    let callee = self.get_flow(*flow_id, None)
+                        .ok_or_else(|| anyhow::anyhow!("Flow not found: {}", flow_id))?;
+                    let arguments: Vec<(String, serde_json::Value)> = {
+                        let variables = conversation_variables(conversation, self.clock.now());
+                        parameters.iter()
+                            .map(|(name, value)| (name.clone(), render_json(value, &variables)))
+                            .collect()
+                    };
+
+                    conversation.call_stack.push(CallFrame {
+                        flow_id: flow.flow.id,
+                        flow_version: Some(flow.version),
+                        return_step: return_step.clone(),
+                        context: conversation.context.clone(),
+                        outputs: outputs.clone(),
+                    });
+                    conversation.context.extend(callee.flow.variables.clone());
+                    conversation.context.extend(arguments);
+
+                    tracing::debug!("↪️ Conversation {} calls flow {} from '{}'", conversation.id, flow_id, id);
+                    step_id = entry_step(&callee)?;
+                    enter_flow(conversation, &callee);
+                    flow = callee;
+                }
+
+                FlowStep::GotoFlow { flow_id, step, .. } => {
+                    let target = self.get_flow(*flow_id, None)
+                        .ok_or_else(|| anyhow::anyhow!("Flow not found: {}", flow_id))?;
+                    step_id = match step {
+                        Some(step) => step.clone(),
+                        None => entry_step(&target)?,
+                    };
+                    enter_flow(conversation, &target);
+                    flow = target;
+                }
+
+                FlowStep::Handoff { text, next_step: next, .. } => {
+                    // El handoff guarda como `return_to` el step con el que sigue el flow
+                    if let Some((next_flow, next)) = self.advance(conversation, &current, next.as_ref())? {
+                        enter_flow(conversation, &next_flow);
+                        move_to_step(conversation, &next);
+                    }
+                    let text = text.as_ref().map(|text| self.render_template(text, conversation));
+                    replies.push(self.request_handoff(conversation, text));
+                    break;
+                }
+
+                FlowStep::End { message, .. } => {
+                    match message {
+                        Some(msg) => replies.push(self.render_template(msg, conversation)),
+                        // La despedida por defecto solo al terminar la conversación, no un sub-flow
+                        None if conversation.call_stack.is_empty() => {
+                            replies.push("Gracias por tu tiempo. ¡Hasta pronto!".to_string());
+                        }
+                        None => {}
+                    }
+
+                    match self.advance(conversation, &current, None)? {
+                        Some((next_flow, next)) => (flow, step_id) = (next_flow, next),
+                        None => break,
+                    }
                 }
-                
-                Ok(Some(message))
             }
-            
-            FlowStep::End { message, .. } => {
-                if let Some(msg) = message {
-                    Ok(Some(self.render_template(msg, conversation)))
-                } else {
-                    Ok(Some("Gracias por tu tiempo. ¡Hasta pronto\!".to_string()))
+
+            // Llamadas, saltos y retornos (también al mismo flow)
+            if matches!(step, FlowStep::CallFlow { .. } | FlowStep::GotoFlow { .. }) || !Arc::ptr_eq(&flow, &current) {
+                jumps += 1;
+                if jumps > MAX_FLOW_JUMPS {
+                    anyhow::bail!("Too many flow jumps without waiting for the user (last: {})", flow.flow.id);
                 }
             }
         }
+
+        // El step en el que se detuvo puede programar un timer
+        if let Some(step) = conversation.current_step_id.as_deref().and_then(|id| flow.step(id)) {
+            if let Some(seconds) = step.timer_seconds() {
+                let fires_at = self.clock.now() + chrono::Duration::seconds(seconds as i64);
+                conversation.timer = Some(PendingTimer::new(flow.flow.id, step.id(), fires_at));
+            }
+        }
+
+        if interactive.is_some() {
+            Ok(interactive)
+        } else if replies.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(MessageContent::text(replies.join("\n\n"))))
+        }
     }
-    
-    /// Iniciar welcome flow
-    fn start_welcome_flow(&self, conversation: &mut ConversationState) -> Result<String> {
-        // TODO: Obtener welcome flow del bot
-        Ok("¡Hola\! Bienvenido a nuestro servicio. ¿En qué puedo ayudarte?".to_string())
+
+    /// Siguiente posición después de un step: `next` en el mismo flow o, si el
+    /// flow terminó, el retorno al caller (`None`: la conversación queda sin flow)
+    fn advance(
+        &self,
+        conversation: &mut ConversationState,
+        flow: &Arc<CompiledFlow>,
+        next: Option<&String>,
+    ) -> Result<Option<(Arc<CompiledFlow>, String)>> {
+        match next {
+            Some(next) => Ok(Some((flow.clone(), next.clone()))),
+            None => self.return_to_caller(conversation),
+        }
+    }
+
+    /// Terminó el flow actual: volver al `return_step` de su caller, o terminar
+    /// si no es un sub-flow (o el caller no tiene a dónde volver)
+    fn return_to_caller(
+        &self,
+        conversation: &mut ConversationState,
+    ) -> Result<Option<(Arc<CompiledFlow>, String)>> {
+        while let Some(frame) = conversation.return_from_call() {
+            let Some(return_step) = frame.return_step else {
+                continue;
+            };
+            let caller = self.get_flow(frame.flow_id, frame.flow_version)
+                .ok_or_else(|| anyhow::anyhow!("Flow not found: {}", frame.flow_id))?;
+            enter_flow(conversation, &caller);
+            return Ok(Some((caller, return_step)));
+        }
+
+        finish_flow(conversation, FlowOutcome::Completed);
+        Ok(None)
+    }
+
+    /// Terminar el flow actual fuera de `execute_step` (p. ej. un `Question` sin `next_step`)
+    async fn end_flow(&self, conversation: &mut ConversationState) -> Result<Option<MessageContent>> {
+        match self.return_to_caller(conversation)? {
+            Some((caller, step_id)) => self.execute_step(conversation, caller, &step_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Disparar el timer `timer_id` de la conversación: `Wait` continúa con su
+    /// `next_step` y `Question`/`Menu` saltan a `on_timeout`. Un timer que ya
+    /// no es el pendiente (el cliente respondió, se reprogramó...) no hace nada.
+    pub async fn fire_timer(
+        &self,
+        conversation: &mut ConversationState,
+        timer_id: Uuid,
+    ) -> Result<Option<MessageContent>> {
+        let Some(timer) = conversation.timer.clone().filter(|timer| timer.id == timer_id) else {
+            return Ok(None);
+        };
+        conversation.timer = None;
+
+        let at_step = conversation.current_flow_id == Some(timer.flow_id)
+            && conversation.current_step_id.as_deref() == Some(timer.step_id.as_str());
+        let flow = self.get_flow(timer.flow_id, conversation.current_flow_version);
+        let (Some(flow), true) = (flow, at_step) else {
+            return Ok(None);
+        };
+
+        tracing::info!("⏰ Timer of step '{}' fired for conversation {}", timer.step_id, conversation.id);
+
+        let target = match flow.step(&timer.step_id) {
+            Some(FlowStep::Wait { next_step, .. }) => next_step.clone(),
+            Some(FlowStep::Question { on_timeout, .. }) | Some(FlowStep::Menu { on_timeout, .. }) => on_timeout.clone(),
+            _ => return Ok(None),
+        };
+
+        match target {
+            Some(next) => self.execute_step(conversation, flow, &next).await,
+            None => self.end_flow(conversation).await,
+        }
+    }
+
+    /// Llevar la conversación a un step de un flow (o a su inicio si `step_id`
+    /// es `None`), p. ej. cuando un asesor la devuelve al bot. Yendo a un step
+    /// se conserva la pila de sub-flows y se usa la versión `version` (la que
+    /// tenía la conversación; `None`: la actual); empezando un flow se descarta
+    /// la pila y se usa la versión actual.
+    pub async fn go_to(
+        &self,
+        conversation: &mut ConversationState,
+        flow_id: Uuid,
+        version: Option<u32>,
+        step_id: Option<&str>,
+    ) -> Result<Option<MessageContent>> {
+        let Some(step_id) = step_id else {
+            return self.start_flow(conversation, flow_id).await;
+        };
+        let flow = self.get_flow(flow_id, version)
+            .ok_or_else(|| anyhow::anyhow!("Flow not found: {}", flow_id))?;
+
+        enter_flow(conversation, &flow);
+        self.execute_step(conversation, flow, step_id).await
+    }
+
+    /// Iniciar un flow (como flow principal) desde su primer step, fijando su versión actual
+    async fn start_flow(
+        &self,
+        conversation: &mut ConversationState,
+        flow_id: Uuid,
+    ) -> Result<Option<MessageContent>> {
+        let flow = self.get_flow(flow_id, None)
+            .ok_or_else(|| anyhow::anyhow!("Flow not found: {}", flow_id))?;
+        let entry_step = entry_step(&flow)?;
+
+        finish_flow(conversation, FlowOutcome::Abandoned);
+        enter_flow(conversation, &flow);
+        conversation.flow_outcomes.push(FlowOutcome::Started);
+
+        self.execute_step(conversation, flow, &entry_step).await
     }
     
-    /// Validar input del usuario
-    fn validate_input(&self, input: &str, validation: &Validation) -> bool {
-        match &validation.validation_type {
-            ValidationType::Phone => {
-                // Validar que sea un número de teléfono
-                input.chars().filter(|c| c.is_numeric()).count() >= 7
-            }
-            ValidationType::Email => {
-                // Validar email simple
-                input.contains('@') && input.contains('.')
-            }
-            ValidationType::Number => {
-                input.parse::<f64>().is_ok()
-            }
-            ValidationType::Text => {
-                \!input.trim().is_empty()
+    /// Iniciar welcome flow del bot (o el saludo por defecto si no tiene uno)
+    async fn start_welcome_flow(
+        &self,
+        conversation: &mut ConversationState,
+        entry_points: &FlowConfig,
+    ) -> Result<Option<MessageContent>> {
+        if let Some(flow_id) = entry_points.welcome_flow_id {
+            if self.get_flow(flow_id, None).is_some() {
+                return self.start_flow(conversation, flow_id).await;
             }
-            ValidationType::Date => {
-                // TODO: Validar formato de fecha
-                true
+            tracing::warn!("Welcome flow {} is not loaded, using default greeting", flow_id);
+        }
+
+        Ok(Some(MessageContent::text("¡Hola! Bienvenido a nuestro servicio. ¿En qué puedo ayudarte?")))
+    }
+
+    /// Iniciar fallback flow del bot cuando el step actual no puede manejar el mensaje
+    async fn start_fallback_flow(
+        &self,
+        conversation: &mut ConversationState,
+        entry_points: &FlowConfig,
+    ) -> Result<Option<MessageContent>> {
+        match entry_points.fallback_flow_id {
+            Some(flow_id) if self.get_flow(flow_id, None).is_some() => {
+                self.start_flow(conversation, flow_id).await
             }
-            ValidationType::Regex(pattern) => {
-                // TODO: Validar con regex
-                true
+            _ => {
+                tracing::debug!("No fallback flow for conversation {}", conversation.id);
+                Ok(None)
             }
         }
     }
+
+    /// ¿El step actual se queda con este texto en lugar de la palabra clave?
+    ///
+    /// Un menú con una opción así (p. ej. "0") no salta al menú principal. El
+    /// atajo `MENU_SHORTCUT` además es la respuesta de un `Question` cuya
+    /// validación lo acepta (una cantidad, "¿cuántos extras?").
+    fn current_step_accepts(&self, conversation: &ConversationState, user_message: &str) -> bool {
+        let (Some(flow_id), Some(step_id)) = (conversation.current_flow_id, conversation.current_step_id.as_deref()) else {
+            return false;
+        };
+        let Some(flow) = self.get_flow(flow_id, conversation.current_flow_version) else {
+            return false;
+        };
+
+        match flow.step(step_id) {
+            Some(FlowStep::Menu { options, .. }) => menu::exact_option(options, user_message).is_some(),
+            Some(FlowStep::Question { id, validation, .. }) if user_message.trim() == MENU_SHORTCUT => match validation {
+                Some(validation) => self
+                    .validate_input(conversation, &MessageContent::text(user_message), validation, flow.regexes.get(id))
+                    .is_some(),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+    
+    /// Validar input del usuario; devuelve el valor normalizado a guardar.
+    /// "hoy", "mañana" y los días de la semana son los de la zona del bot.
+    fn validate_input(
+        &self,
+        conversation: &ConversationState,
+        message: &MessageContent,
+        validation: &Validation,
+        regex: Option<&Regex>,
+    ) -> Option<serde_json::Value> {
+        let today = self.clock.now().with_timezone(&conversation_timezone(conversation)).date_naive();
+        normalize_content(message, &validation.validation_type, regex, today)
+    }
     
     /// Renderizar template con variables
     fn render_template(&self, template: &str, conversation: &ConversationState) -> String {
-        let mut result = template.to_string();
-        
-        // Reemplazar variables {{variable_name}}
-        for (key, value) in &conversation.context {
-            let placeholder = format\!("{{{{{}}}}}", key);
-            if let Some(val_str) = value.as_str() {
-                result = result.replace(&placeholder, val_str);
-            }
-        }
-        
-        result
+        render_template(template, &conversation_variables(conversation, self.clock.now()))
     }
     
-    /// Evaluar condición
-    fn evaluate_condition(&self, condition: &str, conversation: &ConversationState) -> Result<bool> {
-        // TODO: Implementar evaluador de expresiones
-        // Por ahora: condiciones simples tipo "variable == value"
-        
-        if let Some((var, value)) = condition.split_once("==") {
-            let var = var.trim();
-            let value = value.trim().trim_matches('"');
-            
-            if let Some(ctx_value) = conversation.context.get(var) {
-                if let Some(ctx_str) = ctx_value.as_str() {
-                    return Ok(ctx_str == value);
-                }
-            }
-        }
-        
-        Ok(false)
+    /// Evaluar la condición (ya compilada) de un step `Decision`
+    fn evaluate_condition(
+        &self,
+        flow: &CompiledFlow,
+        step_id: &str,
+        conversation: &ConversationState,
+    ) -> Result<bool> {
+        let expression = flow.conditions.get(step_id)
+            .ok_or_else(|| anyhow::anyhow!("No compiled condition for step: {}", step_id))?;
+
+        Ok(expression.evaluate(&conversation.context))
     }
     
-    /// Ejecutar acción
+    /// Ejecutar acción con el handler registrado para su tipo
     async fn execute_action(
         &self,
         action_type: &ActionType,
         parameters: &HashMap<String, serde_json::Value>,
         conversation: &mut ConversationState,
     ) -> Result<()> {
-        match action_type {
-            ActionType::ApiCall { url, method } => {
-                // TODO: Llamar API externa
-                tracing::info\!("API Call: {} {}", method, url);
-            }
-            ActionType::DatabaseQuery { query } => {
-                // TODO: Ejecutar query
-                tracing::info\!("DB Query: {}", query);
-            }
-            ActionType::SendEmail { to, template } => {
-                // TODO: Enviar email
-                tracing::info\!("Send email to: {}", to);
-            }
-            ActionType::CreateOrder => {
-                // TODO: Crear orden
-                tracing::info\!("Create order");
-            }
-            ActionType::UpdateCustomer => {
-                // TODO: Actualizar cliente
-                tracing::info\!("Update customer");
-            }
-            ActionType::Custom(action) => {
-                tracing::info\!("Custom action: {}", action);
-            }
+        let handler = self.actions.get(action_type)
+            .ok_or_else(|| anyhow::anyhow!("No handler registered for action '{}'", action_type.key()))?;
+
+        let variables = conversation_variables(conversation, self.clock.now());
+        handler.execute(action_type, parameters, &variables, conversation).await
+    }
+}
+
+fn escape_keyword(input: &str) -> Option<EscapeKeyword> {
+    let input = input.trim().to_lowercase();
+    let input = input.as_str();
+
+    if MENU_KEYWORDS.contains(&input) || input == MENU_SHORTCUT {
+        Some(EscapeKeyword::Menu)
+    } else if EXIT_KEYWORDS.contains(&input) {
+        Some(EscapeKeyword::Exit)
+    } else if AGENT_KEYWORDS.contains(&input) {
+        Some(EscapeKeyword::Agent)
+    } else {
+        None
+    }
+}
+
+/// Horario del bot (`bot.business_hours`, ver `BotSettings::schedule_status`) si está cerrado
+fn closed_business_hours(conversation: &ConversationState) -> Option<serde_json::Value> {
+    let hours = conversation.metadata.get("bot")?.get("business_hours")?;
+    (hours["open"] == serde_json::json!(false)).then(|| hours.clone())
+}
+
+/// Clave de los contadores de intentos: `flow_id:step_id`
+fn attempt_key(flow: &CompiledFlow, step_id: &str) -> String {
+    format!("{}:{}", flow.flow.id, step_id)
+}
+
+/// Terminar el flow actual (y los sub-flows pendientes); el próximo mensaje
+/// vuelve a empezar por el welcome flow
+fn finish_flow(conversation: &mut ConversationState, outcome: FlowOutcome) {
+    if let Some(flow_id) = conversation.current_flow_id {
+        conversation.flow_outcomes.push(outcome);
+        conversation.flow_events.push(ConversationEvent::FlowEnded { flow_id });
+    }
+    conversation.current_flow_id = None;
+    conversation.current_flow_version = None;
+    conversation.current_step_id = None;
+    conversation.call_stack.clear();
+}
+
+/// Pasar la conversación a un flow, con la versión indicada; el step lo fija
+/// `execute_step`
+fn enter_flow(conversation: &mut ConversationState, flow: &CompiledFlow) {
+    if conversation.current_flow_id == Some(flow.flow.id) && conversation.current_flow_version == Some(flow.version) {
+        return;
+    }
+    if let Some(flow_id) = conversation.current_flow_id {
+        conversation.flow_events.push(ConversationEvent::FlowEnded { flow_id });
+    }
+    conversation.current_flow_id = Some(flow.flow.id);
+    conversation.current_flow_version = Some(flow.version);
+    conversation.current_step_id = None;
+    conversation.flow_events.push(ConversationEvent::FlowStarted {
+        flow_id: flow.flow.id,
+        flow_version: Some(flow.version),
+        step_id: None,
+    });
+}
+
+/// Pasar la conversación a un step del flow actual
+fn move_to_step(conversation: &mut ConversationState, step_id: &str) {
+    if conversation.current_step_id.as_deref() == Some(step_id) {
+        return;
+    }
+    if let Some(flow_id) = conversation.current_flow_id {
+        conversation.flow_events.push(ConversationEvent::StepTransition {
+            flow_id,
+            from_step: conversation.current_step_id.clone(),
+            to_step: Some(step_id.to_string()),
+        });
+    }
+    conversation.current_step_id = Some(step_id.to_string());
+}
+
+/// Primer step de un flow
+fn entry_step(flow: &CompiledFlow) -> Result<String> {
+    flow.flow.steps.first()
+        .map(|step| step.id().to_string())
+        .ok_or_else(|| anyhow::anyhow!("Flow {} has no steps", flow.flow.id))
+}
+
+impl ActionType {
+    /// Clave con la que se registra su handler en el `ActionRegistry`
+    pub fn key(&self) -> String {
+        match self {
+            ActionType::ApiCall { .. } => "api_call".to_string(),
+            ActionType::DatabaseQuery { .. } => "database_query".to_string(),
+            ActionType::SendEmail { .. } => "send_email".to_string(),
+            ActionType::CreateOrder => "create_order".to_string(),
+            ActionType::UpdateCustomer => "update_customer".to_string(),
+            ActionType::Custom(name) => format!("custom:{}", name),
         }
-        
-        Ok(())
     }
 }
 
@@ -369,6 +1229,10 @@ impl FlowStep {
             FlowStep::Decision { id, .. } => id,
             FlowStep::Action { id, .. } => id,
             FlowStep::Menu { id, .. } => id,
+            FlowStep::Wait { id, .. } => id,
+            FlowStep::CallFlow { id, .. } => id,
+            FlowStep::GotoFlow { id, .. } => id,
+            FlowStep::Handoff { id, .. } => id,
             FlowStep::End { id, .. } => id,
         }
     }
@@ -378,14 +1242,81 @@ impl FlowStep {
             FlowStep::Message { next_step, .. } => next_step.as_deref(),
             FlowStep::Question { next_step, .. } => next_step.as_deref(),
             FlowStep::Action { next_step, .. } => next_step.as_deref(),
+            FlowStep::Wait { next_step, .. } => next_step.as_deref(),
+            FlowStep::Handoff { next_step, .. } => next_step.as_deref(),
+            FlowStep::CallFlow { return_step, .. } => return_step.as_deref(),
             _ => None,
         }
     }
+
+    /// Flow al que lleva el step y si es una llamada (`CallFlow`, que vuelve)
+    /// o un salto (`GotoFlow`)
+    pub fn linked_flow(&self) -> Option<(Uuid, bool)> {
+        match self {
+            FlowStep::CallFlow { flow_id, .. } => Some((*flow_id, true)),
+            FlowStep::GotoFlow { flow_id, .. } => Some((*flow_id, false)),
+            _ => None,
+        }
+    }
+
+    /// Segundos del timer que programa el step al detenerse en él
+    pub fn timer_seconds(&self) -> Option<u64> {
+        match self {
+            FlowStep::Wait { seconds, .. } => Some(*seconds),
+            FlowStep::Question { timeout_seconds: Some(seconds), on_timeout: Some(_), .. }
+            | FlowStep::Menu { timeout_seconds: Some(seconds), on_timeout: Some(_), .. } => Some(*seconds),
+            _ => None,
+        }
+    }
+
+    /// Todos los steps a los que este step puede saltar, con el campo que los referencia
+    pub fn references(&self) -> Vec<(String, &str)> {
+        match self {
+            FlowStep::Message { next_step, .. } | FlowStep::Handoff { next_step, .. } => next_step
+                .iter()
+                .map(|next| ("next_step".to_string(), next.as_str()))
+                .collect(),
+            FlowStep::Question { next_step, on_exhausted, on_timeout, .. } => next_step
+                .iter()
+                .map(|next| ("next_step".to_string(), next.as_str()))
+                .chain(on_exhausted.iter().map(|step| ("on_exhausted".to_string(), step.as_str())))
+                .chain(on_timeout.iter().map(|step| ("on_timeout".to_string(), step.as_str())))
+                .collect(),
+            FlowStep::Wait { next_step, on_reply, .. } => next_step
+                .iter()
+                .map(|next| ("next_step".to_string(), next.as_str()))
+                .chain(on_reply.iter().map(|step| ("on_reply".to_string(), step.as_str())))
+                .collect(),
+            FlowStep::Action { next_step, on_error, .. } => next_step
+                .iter()
+                .map(|next| ("next_step".to_string(), next.as_str()))
+                .chain(on_error.iter().map(|step| ("on_error".to_string(), step.as_str())))
+                .collect(),
+            FlowStep::Decision { true_step, false_step, .. } => vec![
+                ("true_step".to_string(), true_step.as_str()),
+                ("false_step".to_string(), false_step.as_str()),
+            ],
+            FlowStep::Menu { options, on_exhausted, on_timeout, .. } => options
+                .iter()
+                .map(|o| (format!("option '{}'", o.key), o.next_step.as_str()))
+                .chain(on_exhausted.iter().map(|step| ("on_exhausted".to_string(), step.as_str())))
+                .chain(on_timeout.iter().map(|step| ("on_timeout".to_string(), step.as_str())))
+                .collect(),
+            FlowStep::CallFlow { return_step, .. } => return_step
+                .iter()
+                .map(|step| ("return_step".to_string(), step.as_str()))
+                .collect(),
+            // El step de destino es de otro flow
+            FlowStep::GotoFlow { .. } | FlowStep::End { .. } => Vec::new(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::flow_validator::DiagnosticCode;
+    use crate::handoff::Agent;
 
     #[test]
     fn test_flow_step_id() {
@@ -395,6 +1326,913 @@ mod tests {
             next_step: None,
         };
         
-        assert_eq\!(step.id(), "step1");
+        assert_eq!(step.id(), "step1");
+    }
+
+    fn decision_flow(condition: &str) -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Decision {
+                    id: "check".to_string(),
+                    condition: condition.to_string(),
+                    true_step: "yes".to_string(),
+                    false_step: "no".to_string(),
+                },
+                FlowStep::End { id: "yes".to_string(), message: Some("si".to_string()) },
+                FlowStep::End { id: "no".to_string(), message: Some("no".to_string()) },
+            ],
+            variables: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_register_flow_rejects_invalid_condition() {
+        let engine = FlowEngine::new();
+
+        match engine.register_flow(decision_flow("total >= ")) {
+            Err(FlowError::Invalid { diagnostics, .. }) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].code, DiagnosticCode::InvalidCondition);
+                assert_eq!(diagnostics[0].step_id.as_deref(), Some("check"));
+            }
+            other => panic!("expected invalid flow, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decision_uses_compiled_condition() {
+        let engine = FlowEngine::new();
+        let flow = decision_flow("total >= 20 && metodo_pago in [\"zelle\", \"pago_movil\"]");
+        let flow_id = flow.id;
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.set_variable("total", serde_json::json!(25));
+        conversation.set_variable("metodo_pago", serde_json::json!("zelle"));
+
+        let compiled = engine.get_flow(flow_id, None).unwrap();
+        let reply = engine.execute_step(&mut conversation, compiled, "check").await.unwrap();
+        assert_eq!(reply, Some(MessageContent::text("si")));
+        // End termina el flow
+        assert_eq!(conversation.current_step_id, None);
+    }
+
+    #[test]
+    fn test_reregister_keeps_previous_versions() {
+        let engine = FlowEngine::new();
+        let mut flow = decision_flow("total > 1");
+        let flow_id = flow.id;
+
+        let v1 = engine.register_flow(flow.clone()).unwrap();
+        // El mismo contenido es la misma versión
+        assert_eq!(engine.register_flow(flow.clone()).unwrap(), v1);
+        flow.name = "test v2".to_string();
+        let v2 = engine.register_flow(flow.clone()).unwrap();
+        assert_ne!(v1, v2);
+
+        assert_eq!(engine.get_flow(flow_id, None).unwrap().version, v2);
+        assert_eq!(engine.get_flow(flow_id, Some(v1)).unwrap().flow.name, "test");
+
+        // Tras un reinicio solo está el contenido actual: la versión vieja no
+        // se reemplaza por otra
+        let restarted = FlowEngine::new();
+        assert_eq!(restarted.register_flow(flow).unwrap(), v2);
+        assert!(restarted.get_flow(flow_id, Some(v2)).is_some());
+        assert!(restarted.get_flow(flow_id, Some(v1)).is_none());
+    }
+
+    #[test]
+    fn test_content_version_ignores_map_order() {
+        let variables = |order: Vec<u32>| -> HashMap<String, serde_json::Value> {
+            order.into_iter().map(|i| (format!("var{}", i), serde_json::json!(i))).collect()
+        };
+        let mut flow = decision_flow("total > 1");
+        flow.variables = variables((0..20).collect());
+        let version = content_version(&flow);
+
+        flow.variables = variables((0..20).rev().collect());
+        assert_eq!(content_version(&flow), version);
+    }
+
+    fn entry_points(welcome: Option<Uuid>, menu: Option<Uuid>, fallback: Option<Uuid>) -> FlowConfig {
+        FlowConfig {
+            welcome_flow_id: welcome,
+            menu_flow_id: menu,
+            fallback_flow_id: fallback,
+        }
+    }
+
+    /// Enviar un texto y devolver la respuesta como la vería un provider sin botones
+    async fn say(
+        engine: &FlowEngine,
+        conversation: &mut ConversationState,
+        flows: &FlowConfig,
+        text: &str,
+    ) -> Option<String> {
+        engine.process(conversation, flows, &MessageContent::text(text)).await.unwrap()
+            .map(|reply| reply.to_plain_text())
+    }
+
+    fn menu_flow() -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "menu".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Menu {
+                    id: "menu".to_string(),
+                    text: "Menú principal".to_string(),
+                    options: vec![MenuOption {
+                        key: "1".to_string(),
+                        label: "Catálogo".to_string(),
+                        next_step: "catalogo".to_string(),
+                        description: None,
+                        section: None,
+                    }],
+                    display: MenuDisplay::Text,
+                    button_text: None,
+                    max_attempts: None,
+                    on_exhausted: None,
+                    timeout_seconds: None,
+                    on_timeout: None,
+                },
+                FlowStep::Message {
+                    id: "catalogo".to_string(),
+                    text: "Nuestro catálogo".to_string(),
+                    next_step: Some("fin".to_string()),
+                },
+                FlowStep::End { id: "fin".to_string(), message: Some("Chao".to_string()) },
+            ],
+            variables: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_conversation_starts_welcome_flow() {
+        let engine = FlowEngine::new();
+        let welcome = menu_flow();
+        let welcome_id = welcome.id;
+        engine.register_flow(welcome).unwrap();
+        let flows = entry_points(Some(welcome_id), None, None);
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        let reply = say(&engine, &mut conversation, &flows, "hola").await.unwrap();
+        assert!(reply.starts_with("Menú principal"));
+        assert_eq!(conversation.current_flow_version, Some(engine.get_flow(welcome_id, None).unwrap().version));
+
+        // Message encadena hasta End y el flow termina
+        let reply = say(&engine, &mut conversation, &flows, "1").await;
+        assert_eq!(reply.as_deref(), Some("Nuestro catálogo\n\nChao"));
+        assert_eq!(conversation.current_flow_id, None);
+        assert_eq!(conversation.flow_outcomes, [FlowOutcome::Started, FlowOutcome::Completed]);
+    }
+
+    #[tokio::test]
+    async fn test_intermediate_steps_are_logged_in_order() {
+        let engine = FlowEngine::new();
+        let welcome = menu_flow();
+        let welcome_id = welcome.id;
+        engine.register_flow(welcome).unwrap();
+        let flows = entry_points(Some(welcome_id), None, None);
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        conversation.flow_events.clear();
+
+        // menu -> catalogo -> fin en un solo mensaje
+        let before = conversation.clone();
+        say(&engine, &mut conversation, &flows, "1").await;
+
+        let steps: Vec<_> = crate::event_log::diff(Some(&before), &conversation)
+            .into_iter()
+            .filter_map(|event| match event {
+                ConversationEvent::StepTransition { from_step, to_step, .. } => {
+                    Some(format!("{}->{}", from_step.unwrap_or_default(), to_step.unwrap_or_default()))
+                }
+                ConversationEvent::FlowEnded { .. } => Some("ended".to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(steps, ["menu->catalogo", "catalogo->fin", "ended"]);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_menu_and_answers() {
+        let engine = FlowEngine::new();
+        let mut welcome = menu_flow();
+        if let FlowStep::Menu { display, .. } = &mut welcome.steps[0] {
+            *display = MenuDisplay::Interactive;
+        }
+        let welcome_id = welcome.id;
+        engine.register_flow(welcome).unwrap();
+        let flows = entry_points(Some(welcome_id), None, None);
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        let reply = engine.process(&mut conversation, &flows, &MessageContent::text("hola")).await.unwrap();
+        assert_eq!(reply, Some(MessageContent::Buttons {
+            body: "Menú principal".to_string(),
+            buttons: vec![crate::message::ReplyButton { id: "1".to_string(), title: "Catálogo".to_string() }],
+        }));
+
+        // Pulsar el botón envía su id
+        let pressed = MessageContent::ButtonReply { id: "1".to_string(), title: Some("Catálogo".to_string()) };
+        let reply = engine.process(&mut conversation, &flows, &pressed).await.unwrap();
+        assert_eq!(reply, Some(MessageContent::text("Nuestro catálogo\n\nChao")));
+
+        // Escribir el texto de la opción, sin tilde, también sirve
+        say(&engine, &mut conversation, &flows, "hola").await;
+        let reply = say(&engine, &mut conversation, &flows, "el catalogo").await;
+        assert_eq!(reply.as_deref(), Some("Nuestro catálogo\n\nChao"));
+    }
+
+    #[tokio::test]
+    async fn test_menu_keyword_jumps_to_menu_flow() {
+        let engine = FlowEngine::new();
+        let menu = menu_flow();
+        let menu_id = menu.id;
+        engine.register_flow(menu).unwrap();
+        let question = Flow {
+            id: Uuid::new_v4(),
+            name: "pedido".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Question {
+                    id: "nombre".to_string(),
+                    text: "¿Nombre?".to_string(),
+                    variable_name: "nombre".to_string(),
+                    validation: None,
+                    next_step: Some("fin".to_string()),
+                    max_attempts: None,
+                    on_exhausted: None,
+                    timeout_seconds: None,
+                    on_timeout: None,
+                },
+                FlowStep::End { id: "fin".to_string(), message: None },
+            ],
+            variables: HashMap::new(),
+        };
+        let question_id = question.id;
+        engine.register_flow(question).unwrap();
+        let flows = entry_points(Some(question_id), Some(menu_id), None);
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        assert_eq!(conversation.current_step_id.as_deref(), Some("nombre"));
+
+        let reply = say(&engine, &mut conversation, &flows, "Menu").await.unwrap();
+        assert!(reply.starts_with("Menú principal"));
+        assert_eq!(conversation.current_flow_id, Some(menu_id));
+    }
+
+    #[tokio::test]
+    async fn test_relative_dates_and_handoffs_use_bot_clock_and_timezone() {
+        // 22:00 del 14 de marzo en Caracas, ya 15 en UTC
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2026, 3, 15, 2, 0, 0).unwrap();
+        let engine = FlowEngine::new().with_clock(Arc::new(crate::clock::FixedClock::new(now)));
+        let flow = Flow {
+            id: Uuid::new_v4(),
+            name: "entrega".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Question {
+                    id: "fecha".to_string(),
+                    text: "¿Para cuándo?".to_string(),
+                    variable_name: "fecha".to_string(),
+                    validation: Some(Validation {
+                        validation_type: ValidationType::Date,
+                        error_message: "Fecha inválida".to_string(),
+                    }),
+                    next_step: Some("fin".to_string()),
+                    max_attempts: None,
+                    on_exhausted: None,
+                    timeout_seconds: None,
+                    on_timeout: None,
+                },
+                FlowStep::End { id: "fin".to_string(), message: None },
+            ],
+            variables: HashMap::new(),
+        };
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.metadata.insert("bot".to_string(), serde_json::json!({ "timezone": "America/Caracas" }));
+        say(&engine, &mut conversation, &flows, "hola").await;
+        say(&engine, &mut conversation, &flows, "hoy").await;
+        assert_eq!(conversation.get_variable("fecha"), Some(&serde_json::json!("2026-03-14")));
+
+        say(&engine, &mut conversation, &flows, "asesor").await;
+        assert_eq!(conversation.handoff.as_ref().map(|handoff| handoff.requested_at), Some(now));
+    }
+
+    #[tokio::test]
+    async fn test_zero_answers_number_question() {
+        let engine = FlowEngine::new();
+        let menu = menu_flow();
+        let menu_id = menu.id;
+        engine.register_flow(menu).unwrap();
+        let question = |validation_type: ValidationType| Flow {
+            id: Uuid::new_v4(),
+            name: "pedido".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Question {
+                    id: "extras".to_string(),
+                    text: "¿Cuántos extras?".to_string(),
+                    variable_name: "extras".to_string(),
+                    validation: Some(Validation {
+                        validation_type,
+                        error_message: "Inválido".to_string(),
+                    }),
+                    next_step: Some("fin".to_string()),
+                    max_attempts: None,
+                    on_exhausted: None,
+                    timeout_seconds: None,
+                    on_timeout: None,
+                },
+                FlowStep::End { id: "fin".to_string(), message: Some("Listo".to_string()) },
+            ],
+            variables: HashMap::new(),
+        };
+
+        // "0" es una cantidad válida: es la respuesta
+        let quantity = question(ValidationType::Number);
+        let flows = entry_points(Some(quantity.id), Some(menu_id), None);
+        engine.register_flow(quantity).unwrap();
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        let reply = say(&engine, &mut conversation, &flows, "0").await;
+        assert_eq!(reply.as_deref(), Some("Listo"));
+        assert_eq!(conversation.get_variable("extras"), Some(&serde_json::json!(0.0)));
+
+        // Un email no puede ser "0": es el atajo al menú
+        let email = question(ValidationType::Email);
+        let flows = entry_points(Some(email.id), Some(menu_id), None);
+        engine.register_flow(email).unwrap();
+        let mut conversation = ConversationState::new("c2".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        let reply = say(&engine, &mut conversation, &flows, "0").await.unwrap();
+        assert!(reply.starts_with("Menú principal"));
+        assert_eq!(conversation.current_flow_id, Some(menu_id));
+    }
+
+    #[tokio::test]
+    async fn test_missing_step_goes_to_fallback_flow() {
+        let engine = FlowEngine::new();
+        let fallback = menu_flow();
+        let fallback_id = fallback.id;
+        engine.register_flow(fallback).unwrap();
+        let flows = entry_points(None, None, Some(fallback_id));
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.current_flow_id = Some(fallback_id);
+        conversation.current_step_id = Some("fin".to_string());
+
+        let reply = say(&engine, &mut conversation, &flows, "???").await.unwrap();
+        assert!(reply.starts_with("Menú principal"));
+    }
+
+    struct LookupTracking;
+
+    #[async_trait::async_trait]
+    impl ActionHandler for LookupTracking {
+        async fn execute(
+            &self,
+            _action: &ActionType,
+            parameters: &HashMap<String, serde_json::Value>,
+            _variables: &HashMap<String, serde_json::Value>,
+            conversation: &mut ConversationState,
+        ) -> Result<()> {
+            match parameters.get("order").and_then(|v| v.as_str()) {
+                Some("A-1") => {
+                    conversation.set_variable("estado", serde_json::json!("en camino"));
+                    Ok(())
+                }
+                _ => anyhow::bail!("unknown order"),
+            }
+        }
+    }
+
+    fn tracking_flow(order: &str) -> Flow {
+        let mut parameters = HashMap::new();
+        parameters.insert("order".to_string(), serde_json::json!(order));
+
+        Flow {
+            id: Uuid::new_v4(),
+            name: "tracking".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Action {
+                    id: "buscar".to_string(),
+                    action_type: ActionType::Custom("tracking".to_string()),
+                    parameters,
+                    next_step: Some("ok".to_string()),
+                    on_error: Some("error".to_string()),
+                },
+                FlowStep::End { id: "ok".to_string(), message: Some("Tu pedido está {{estado}}".to_string()) },
+                FlowStep::End { id: "error".to_string(), message: Some("No encontramos tu pedido".to_string()) },
+            ],
+            variables: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_action_handler_and_error_step() {
+        let engine = FlowEngine::with_actions(ActionRegistry::new());
+        engine.register_custom_action("tracking", Arc::new(LookupTracking));
+
+        for (order, expected) in [("A-1", "Tu pedido está en camino"), ("B-2", "No encontramos tu pedido")] {
+            let flow = tracking_flow(order);
+            let flow_id = flow.id;
+            engine.register_flow(flow).unwrap();
+
+            let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+            let reply = engine.start_flow(&mut conversation, flow_id).await.unwrap();
+            assert_eq!(reply, Some(MessageContent::text(expected)));
+        }
+    }
+
+    struct SignWithBotName;
+
+    #[async_trait::async_trait]
+    impl ActionHandler for SignWithBotName {
+        async fn execute(
+            &self,
+            _action: &ActionType,
+            _parameters: &HashMap<String, serde_json::Value>,
+            variables: &HashMap<String, serde_json::Value>,
+            conversation: &mut ConversationState,
+        ) -> Result<()> {
+            let signature = render_template("{{bot.name}} ({{system.timezone}})", variables);
+            conversation.set_variable("estado", serde_json::json!(signature));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_action_handler_sees_bot_and_system_variables() {
+        let engine = FlowEngine::with_actions(ActionRegistry::new());
+        engine.register_custom_action("tracking", Arc::new(SignWithBotName));
+        let flow = tracking_flow("A-1");
+        let flow_id = flow.id;
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.metadata.insert("bot".to_string(), serde_json::json!({ "name": "Panadería Ana" }));
+        let reply = engine.start_flow(&mut conversation, flow_id).await.unwrap();
+        assert_eq!(reply, Some(MessageContent::text("Tu pedido está Panadería Ana (America/Caracas)")));
+    }
+
+    #[tokio::test]
+    async fn test_action_without_handler_fails() {
+        let engine = FlowEngine::with_actions(ActionRegistry::new());
+        let mut flow = tracking_flow("A-1");
+        if let FlowStep::Action { on_error, .. } = &mut flow.steps[0] {
+            *on_error = None;
+        }
+        flow.steps.pop();
+        let flow_id = flow.id;
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        let error = engine.start_flow(&mut conversation, flow_id).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("No handler registered for action 'custom:tracking'"));
+    }
+
+    fn question_flow(validation_type: ValidationType) -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "datos".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Question {
+                    id: "dato".to_string(),
+                    text: "¿Dato?".to_string(),
+                    variable_name: "dato".to_string(),
+                    validation: Some(Validation {
+                        validation_type,
+                        error_message: "Dato inválido".to_string(),
+                    }),
+                    next_step: Some("fin".to_string()),
+                    max_attempts: None,
+                    on_exhausted: None,
+                    timeout_seconds: None,
+                    on_timeout: None,
+                },
+                FlowStep::End { id: "fin".to_string(), message: None },
+            ],
+            variables: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_question_stores_normalized_value() {
+        let engine = FlowEngine::new();
+        let flow = question_flow(ValidationType::Phone);
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+
+        let reply = say(&engine, &mut conversation, &flows, "mi número").await;
+        assert_eq!(reply.as_deref(), Some("Dato inválido"));
+
+        say(&engine, &mut conversation, &flows, "0414-123.45.67").await;
+        assert_eq!(conversation.get_variable("dato"), Some(&serde_json::json!("+584141234567")));
+    }
+
+    #[tokio::test]
+    async fn test_question_waits_for_an_image() {
+        let engine = FlowEngine::new();
+        let flow = question_flow(ValidationType::Image);
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+
+        let reply = say(&engine, &mut conversation, &flows, "ya pagué").await;
+        assert_eq!(reply.as_deref(), Some("Dato inválido"));
+
+        let receipt = MessageContent::Image(crate::message::Media {
+            id: Some("1037543291543636".to_string()),
+            caption: Some("pago móvil".to_string()),
+            ..Default::default()
+        });
+        engine.process(&mut conversation, &flows, &receipt).await.unwrap();
+        assert_eq!(
+            conversation.get_variable("dato"),
+            Some(&serde_json::json!({ "type": "image", "id": "1037543291543636", "caption": "pago móvil" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_attempts_jump_to_on_exhausted() {
+        let engine = FlowEngine::new();
+        let mut flow = question_flow(ValidationType::Email);
+        if let FlowStep::Question { max_attempts, on_exhausted, .. } = &mut flow.steps[0] {
+            *max_attempts = Some(2);
+            *on_exhausted = Some("rendirse".to_string());
+        }
+        flow.steps.push(FlowStep::End {
+            id: "rendirse".to_string(),
+            message: Some("Te paso con un asesor".to_string()),
+        });
+        let key = format!("{}:dato", flow.id);
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+
+        let reply = say(&engine, &mut conversation, &flows, "no sé").await;
+        assert_eq!(reply.as_deref(), Some("Dato inválido"));
+        let reply = say(&engine, &mut conversation, &flows, "tampoco").await;
+        assert_eq!(reply.as_deref(), Some("Te paso con un asesor"));
+
+        let attempts = &conversation.attempts[&key];
+        assert_eq!((attempts.total_failures, attempts.exhausted), (2, 1));
+        assert_eq!(conversation.current_flow_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_attempts_without_fallback_leave_the_flow() {
+        let engine = FlowEngine::new();
+        let flow = question_flow(ValidationType::Number);
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+
+        for _ in 1..DEFAULT_MAX_ATTEMPTS {
+            say(&engine, &mut conversation, &flows, "muchos").await;
+        }
+        let reply = say(&engine, &mut conversation, &flows, "muchos").await.unwrap();
+        assert!(reply.contains("*asesor*"));
+        assert_eq!(conversation.current_flow_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_escape_keywords() {
+        let engine = FlowEngine::new();
+        let flow = question_flow(ValidationType::Text);
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        say(&engine, &mut conversation, &flows, "Salir").await;
+        assert_eq!(conversation.current_flow_id, None);
+        assert_eq!(conversation.flow_outcomes, [FlowOutcome::Started, FlowOutcome::Abandoned]);
+
+        say(&engine, &mut conversation, &flows, "hola").await;
+        say(&engine, &mut conversation, &flows, "asesor").await;
+        assert_eq!(conversation.current_flow_id, None);
+        assert_eq!(conversation.handoff.as_ref().map(|h| h.status), Some(HandoffStatus::Waiting));
+
+        // Salir de la cola abandona el flow que quedó en pausa
+        say(&engine, &mut conversation, &flows, "salir").await;
+        assert!(conversation.handoff.is_none());
+        assert_eq!(conversation.flow_outcomes, [
+            FlowOutcome::Started, FlowOutcome::Abandoned, FlowOutcome::Started, FlowOutcome::Abandoned,
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_handoff_pauses_the_bot() {
+        let engine = FlowEngine::new();
+        let mut flow = question_flow(ValidationType::Text);
+        let flow_id = flow.id;
+        let flows = entry_points(Some(flow_id), None, None);
+        let version = engine.register_flow(flow.clone()).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        say(&engine, &mut conversation, &flows, "asesor").await;
+        let return_to = conversation.handoff.as_ref().and_then(|h| h.return_to.clone()).unwrap();
+        assert_eq!((return_to.flow_id, return_to.flow_version), (flow_id, Some(version)));
+
+        // En espera: el bot calla, pero el cliente puede salir de la cola
+        assert_eq!(say(&engine, &mut conversation, &flows, "hola?").await, None);
+        say(&engine, &mut conversation, &flows, "salir").await;
+        assert!(conversation.handoff.is_none());
+
+        // Atendida por un asesor: `salir` también va para el asesor
+        say(&engine, &mut conversation, &flows, "asesor").await;
+        if let Some(handoff) = conversation.handoff.as_mut() {
+            handoff.assign(Agent { id: "7".to_string(), name: None }, chrono::Utc::now());
+        }
+        assert_eq!(say(&engine, &mut conversation, &flows, "salir").await, None);
+        assert!(conversation.handoff.is_some());
+
+        // Mientras tanto se publica otra versión del flow
+        if let FlowStep::Question { text, .. } = &mut flow.steps[0] {
+            *text = "¿Dato nuevo?".to_string();
+        }
+        engine.register_flow(flow).unwrap();
+
+        // El asesor la devuelve al step de la pregunta, en la versión con la que empezó
+        conversation.end_handoff();
+        let reply = engine.go_to(&mut conversation, flow_id, return_to.flow_version, Some(&return_to.step_id)).await.unwrap();
+        assert_eq!(reply, Some(MessageContent::text("¿Dato?")));
+        assert_eq!(conversation.current_step_id.as_deref(), Some(return_to.step_id.as_str()));
+        assert_eq!(conversation.current_flow_version, Some(version));
+    }
+
+    #[tokio::test]
+    async fn test_agent_request_is_queued_out_of_hours() {
+        let engine = FlowEngine::new();
+        let flows = entry_points(None, None, None);
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.metadata.insert("bot".to_string(), serde_json::json!({
+            "business_hours": {
+                "open": false,
+                "next_open": "2026-03-17T09:00:00-04:00",
+                "next_open_text": "mañana a las 09:00",
+            }
+        }));
+
+        let reply = say(&engine, &mut conversation, &flows, "asesor").await.unwrap();
+        assert!(reply.contains("mañana a las 09:00"));
+
+        let handoff = conversation.handoff.unwrap();
+        assert_eq!(handoff.status, HandoffStatus::Queued);
+        assert_eq!(handoff.queued_until.map(|until| until.to_rfc3339()), Some("2026-03-17T13:00:00+00:00".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_menu_option_hands_off_to_an_agent() {
+        let engine = FlowEngine::new();
+        let mut flow = menu_flow();
+        if let FlowStep::Menu { options, .. } = &mut flow.steps[0] {
+            options.push(MenuOption {
+                key: "2".to_string(),
+                label: "Hablar con un asesor".to_string(),
+                next_step: "asesor".to_string(),
+                description: None,
+                section: None,
+            });
+        }
+        flow.steps.push(FlowStep::Handoff {
+            id: "asesor".to_string(),
+            text: Some("Ya te paso con un asesor".to_string()),
+            next_step: Some("catalogo".to_string()),
+        });
+        let flow_id = flow.id;
+        let flows = entry_points(Some(flow_id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        let reply = say(&engine, &mut conversation, &flows, "2").await;
+        assert_eq!(reply.as_deref(), Some("Ya te paso con un asesor"));
+
+        // El asesor puede devolverla a `next_step`
+        let handoff = conversation.handoff.clone().unwrap();
+        assert_eq!(handoff.status, HandoffStatus::Waiting);
+        let return_to = handoff.return_to.unwrap();
+        assert_eq!((return_to.flow_id, return_to.step_id.as_str()), (flow_id, "catalogo"));
+        assert_eq!(conversation.current_flow_id, None);
+
+        // Fuera de horario queda en cola hasta la apertura
+        let mut conversation = ConversationState::new("c2".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.metadata.insert("bot".to_string(), serde_json::json!({
+            "business_hours": { "open": false, "next_open": "2026-03-17T09:00:00-04:00" }
+        }));
+        say(&engine, &mut conversation, &flows, "hola").await;
+        let reply = say(&engine, &mut conversation, &flows, "2").await.unwrap();
+        assert!(reply.contains("apenas abramos"));
+        assert_eq!(conversation.handoff.map(|handoff| handoff.status), Some(HandoffStatus::Queued));
+    }
+
+    #[tokio::test]
+    async fn test_question_timeout_and_wait() {
+        let engine = FlowEngine::new();
+        let mut flow = question_flow(ValidationType::Text);
+        if let FlowStep::Question { timeout_seconds, on_timeout, .. } = &mut flow.steps[0] {
+            *timeout_seconds = Some(300);
+            *on_timeout = Some("recordatorio".to_string());
+        }
+        flow.steps.push(FlowStep::Wait {
+            id: "recordatorio".to_string(),
+            seconds: 60,
+            next_step: Some("fin".to_string()),
+            on_reply: Some("dato".to_string()),
+        });
+        let flows = entry_points(Some(flow.id), None, None);
+        engine.register_flow(flow).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        say(&engine, &mut conversation, &flows, "hola").await;
+        let timer = conversation.timer.clone().unwrap();
+        assert_eq!(timer.step_id, "dato");
+
+        // Un timer que no es el pendiente no hace nada
+        assert_eq!(engine.fire_timer(&mut conversation, Uuid::new_v4()).await.unwrap(), None);
+        assert_eq!(conversation.timer.as_ref(), Some(&timer));
+
+        // Vence la pregunta: pasa al `Wait`, que programa su propio timer
+        engine.fire_timer(&mut conversation, timer.id).await.unwrap();
+        assert_eq!(conversation.current_step_id.as_deref(), Some("recordatorio"));
+        let wait = conversation.timer.clone().unwrap();
+        assert_ne!(wait.id, timer.id);
+
+        // Responder durante la espera la cancela y vuelve a la pregunta
+        say(&engine, &mut conversation, &flows, "perdón, aquí estoy").await;
+        assert_eq!(conversation.current_step_id.as_deref(), Some("dato"));
+        assert_ne!(conversation.timer.as_ref().map(|t| t.id), Some(wait.id));
+        assert_eq!(engine.fire_timer(&mut conversation, wait.id).await.unwrap(), None);
+    }
+
+    /// Sub-flow que pide una dirección y la deja en `direccion`
+    fn address_flow() -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "direccion".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::Question {
+                    id: "direccion".to_string(),
+                    text: "¿A qué dirección enviamos {{destino}}?".to_string(),
+                    variable_name: "direccion".to_string(),
+                    validation: None,
+                    next_step: Some("fin".to_string()),
+                    max_attempts: None,
+                    on_exhausted: None,
+                    timeout_seconds: None,
+                    on_timeout: None,
+                },
+                FlowStep::End { id: "fin".to_string(), message: None },
+            ],
+            variables: HashMap::from([("destino".to_string(), serde_json::json!("tu pedido"))]),
+        }
+    }
+
+    fn calling_flow(callee: Uuid) -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "pedido".to_string(),
+            description: String::new(),
+            steps: vec![
+                FlowStep::CallFlow {
+                    id: "pedir_direccion".to_string(),
+                    flow_id: callee,
+                    return_step: Some("confirmar".to_string()),
+                    parameters: HashMap::from([("destino".to_string(), serde_json::json!("las {{producto}}"))]),
+                    outputs: HashMap::from([("direccion".to_string(), "direccion_envio".to_string())]),
+                },
+                FlowStep::End {
+                    id: "confirmar".to_string(),
+                    message: Some("Enviaremos a {{direccion_envio}}{{destino}}".to_string()),
+                },
+            ],
+            variables: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_flow_returns_to_caller() {
+        let engine = FlowEngine::new();
+        let address = address_flow();
+        let address_id = address.id;
+        let order = calling_flow(address.id);
+        let flows = entry_points(Some(order.id), None, None);
+        engine.register_flow(address).unwrap();
+        engine.register_flow(order).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.set_variable("producto", serde_json::json!("rosas"));
+
+        let reply = say(&engine, &mut conversation, &flows, "hola").await;
+        assert_eq!(reply.as_deref(), Some("¿A qué dirección enviamos las rosas?"));
+        assert_eq!(conversation.current_flow_id, Some(address_id));
+        assert_eq!(conversation.call_stack.len(), 1);
+
+        // Al volver solo pasa `direccion` (como `direccion_envio`); `destino` era del sub-flow
+        let reply = say(&engine, &mut conversation, &flows, "Av. Bolívar 12").await;
+        assert_eq!(reply.as_deref(), Some("Enviaremos a Av. Bolívar 12"));
+        assert!(conversation.call_stack.is_empty());
+        assert_eq!(conversation.get_variable("direccion"), None);
+        assert_eq!(conversation.get_variable("producto"), Some(&serde_json::json!("rosas")));
+    }
+
+    #[tokio::test]
+    async fn test_goto_flow_and_exit_clear_the_stack() {
+        let engine = FlowEngine::new();
+        let address = address_flow();
+        let mut order = calling_flow(address.id);
+        order.steps.insert(0, FlowStep::Message {
+            id: "inicio".to_string(),
+            text: "Vamos con tu pedido".to_string(),
+            next_step: Some("pedir_direccion".to_string()),
+        });
+        let router = Flow {
+            id: Uuid::new_v4(),
+            name: "router".to_string(),
+            description: String::new(),
+            steps: vec![FlowStep::GotoFlow {
+                id: "a_pedido".to_string(),
+                flow_id: order.id,
+                step: Some("inicio".to_string()),
+            }],
+            variables: HashMap::new(),
+        };
+        let flows = entry_points(Some(router.id), None, None);
+        engine.register_flow(address).unwrap();
+        engine.register_flow(order).unwrap();
+        engine.register_flow(router).unwrap();
+
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        let reply = say(&engine, &mut conversation, &flows, "hola").await.unwrap();
+        assert!(reply.starts_with("Vamos con tu pedido"));
+        assert_eq!(conversation.call_stack.len(), 1);
+
+        say(&engine, &mut conversation, &flows, "salir").await;
+        assert!(conversation.call_stack.is_empty());
+        assert_eq!(conversation.current_flow_id, None);
+    }
+
+    #[test]
+    fn test_register_flow_rejects_recursive_calls() {
+        let engine = FlowEngine::new();
+        let address = address_flow();
+        let order = calling_flow(address.id);
+        let order_id = order.id;
+        engine.register_flow(order).unwrap();
+
+        // El sub-flow vuelve al flow que lo llamó: cada vuelta apila otra llamada
+        let mut looping = address.clone();
+        looping.steps[1] = FlowStep::GotoFlow { id: "fin".to_string(), flow_id: order_id, step: None };
+        match engine.register_flow(looping) {
+            Err(FlowError::Invalid { diagnostics, .. }) => {
+                assert_eq!(diagnostics[0].code, DiagnosticCode::RecursiveCall);
+                assert_eq!(diagnostics[0].step_id.as_deref(), Some("fin"));
+            }
+            other => panic!("expected recursion error, got {:?}", other),
+        }
+
+        // Saltos sin llamadas (volver al menú) sí pueden formar ciclos
+        let mut back_to_menu = address;
+        back_to_menu.id = Uuid::new_v4();
+        back_to_menu.steps[1] = FlowStep::GotoFlow { id: "fin".to_string(), flow_id: back_to_menu.id, step: None };
+        assert!(engine.register_flow(back_to_menu).is_ok());
+    }
+
+    #[test]
+    fn test_register_flow_rejects_invalid_regex() {
+        let engine = FlowEngine::new();
+
+        match engine.register_flow(question_flow(ValidationType::Regex("[A-Z".to_string()))) {
+            Err(FlowError::Invalid { diagnostics, .. }) => {
+                assert_eq!(diagnostics[0].code, DiagnosticCode::InvalidRegex);
+            }
+            other => panic!("expected invalid flow, got {:?}", other),
+        }
     }
 }
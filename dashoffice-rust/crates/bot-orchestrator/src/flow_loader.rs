@@ -0,0 +1,208 @@
+//! Flow Loader - Carga de flows desde archivos YAML/JSON
+//!
+//! - Carga un directorio de flows al arrancar
+//! - Vigila el directorio y recarga los archivos modificados (hot reload)
+//! - Cada flow se valida antes de reemplazar la versión activa; si tiene
+//!   errores se conserva la versión anterior
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+use super::flow_engine::{Flow, FlowEngine, FlowError};
+use super::flow_store::FlowStore;
+
+/// Resultado de cargar un directorio
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+/// Leer y deserializar un archivo de flow (`.yaml`, `.yml` o `.json`)
+pub fn read_flow_file(path: &Path) -> Result<Flow> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let flow = match extension(path).as_deref() {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid YAML flow {}", path.display()))?,
+        Some("json") => serde_json::from_str(&content)
+            .with_context(|| format!("Invalid JSON flow {}", path.display()))?,
+        _ => anyhow::bail!("Unsupported flow file: {}", path.display()),
+    };
+
+    Ok(flow)
+}
+
+/// Cargar y registrar un archivo de flow
+pub fn load_flow_file(engine: &FlowEngine, path: &Path) -> Result<()> {
+    let flow = read_flow_file(path)?;
+    let (flow_id, name) = (flow.id, flow.name.clone());
+
+    match engine.register_flow(flow) {
+        Ok(version) => {
+            info!("📄 Flow '{}' ({}) v{:08x} loaded from {}", name, flow_id, version, path.display());
+            Ok(())
+        }
+        Err(FlowError::Invalid { diagnostics, .. }) => {
+            for diagnostic in &diagnostics {
+                warn!("   {}: {}", path.display(), diagnostic);
+            }
+            anyhow::bail!("Flow '{}' in {} has {} problem(s)", name, path.display(), diagnostics.len())
+        }
+    }
+}
+
+/// Cargar todos los flows de un directorio
+pub fn load_flow_dir(engine: &FlowEngine, dir: &Path) -> LoadReport {
+    let mut report = LoadReport::default();
+
+    for path in flow_files(dir).into_keys() {
+        match load_flow_file(engine, &path) {
+            Ok(()) => report.loaded += 1,
+            Err(e) => {
+                error!("❌ {:#}", e);
+                report.failed += 1;
+            }
+        }
+    }
+
+    report
+}
+
+/// Vigilar el directorio y recargar los flows que cambien; las versiones
+/// nuevas se guardan en `store`
+pub fn spawn_flow_watcher(engine: Arc<FlowEngine>, store: Arc<FlowStore>, dir: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        info!("👀 Watching flows in {}", dir.display());
+
+        let mut known = flow_files(&dir);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let current = flow_files(&dir);
+            let mut reloaded = false;
+
+            for (path, modified) in &current {
+                if known.get(path) == Some(modified) {
+                    continue;
+                }
+                info!("🔄 Flow file changed: {}", path.display());
+                match load_flow_file(&engine, path) {
+                    Ok(()) => reloaded = true,
+                    Err(e) => error!("❌ Keeping previous version: {:#}", e),
+                }
+            }
+
+            if reloaded {
+                if let Err(e) = store.save_all(&engine).await {
+                    warn!("Could not persist flow versions: {:#}", e);
+                }
+            }
+
+            for path in known.keys().filter(|path| !current.contains_key(*path)) {
+                // Las conversaciones en curso pueden seguir usándolo
+                warn!("🗑️ Flow file removed, flow stays registered: {}", path.display());
+            }
+
+            known = current;
+        }
+    });
+}
+
+/// Archivos de flow del directorio con su fecha de modificación
+fn flow_files(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot read flows directory {}: {}", dir.display(), e);
+            return HashMap::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| matches!(extension(path).as_deref(), Some("yaml" | "yml" | "json")))
+        .filter_map(|path| {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flows-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_bundled_flows_are_valid() {
+        let engine = FlowEngine::new();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("flows");
+
+        let report = load_flow_dir(&engine, &dir);
+        assert!(report.loaded > 0);
+        assert_eq!(report.failed, 0);
+    }
+
+    #[test]
+    fn test_invalid_file_keeps_previous_version() {
+        let engine = FlowEngine::new();
+        let dir = temp_dir();
+        let flow_id = Uuid::new_v4();
+        let path = dir.join("pedido.yaml");
+
+        std::fs::write(&path, format!(r#"
+id: {flow_id}
+name: Pedido
+description: Tomar pedido
+steps:
+  - type: Message
+    id: inicio
+    text: Hola
+    next_step: fin
+  - type: End
+    id: fin
+"#)).unwrap();
+        load_flow_file(&engine, &path).unwrap();
+        let version = engine.get_flow(flow_id, None).unwrap().version;
+
+        // next_step apunta a un step que no existe
+        std::fs::write(&path, format!(r#"
+id: {flow_id}
+name: Pedido
+description: Tomar pedido
+steps:
+  - type: Message
+    id: inicio
+    text: Hola
+    next_step: fn
+  - type: End
+    id: fin
+"#)).unwrap();
+        assert!(load_flow_file(&engine, &path).is_err());
+
+        assert_eq!(engine.get_flow(flow_id, None).unwrap().version, version);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
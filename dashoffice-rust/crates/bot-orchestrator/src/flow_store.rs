@@ -0,0 +1,180 @@
+//! Flow Store - Versiones de los flows en Redis
+//!
+//! - La versión de un flow es un hash de su contenido y el engine solo guarda
+//!   las versiones en memoria. Si el archivo cambió antes de un reinicio, una
+//!   conversación restaurada desde Redis no encontraría la versión con la que
+//!   empezó y volvería a empezar
+//! - Cada versión registrada se guarda en `bot:flow:{flow_id}:{version}` (el
+//!   `Flow` serializado) y se vuelve a cargar en el engine al restaurar una
+//!   conversación que la usa
+//! - El TTL (`FLOW_VERSIONS_TTL_DAYS`) tiene que ser mayor que el
+//!   `max_conversation_timeout_seconds` de los bots y que los `Wait` más largos;
+//!   se renueva cada vez que se cargan los flows
+
+use anyhow::{Context, Result};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use super::flow_engine::{content_version, CompiledFlow, Flow, FlowEngine};
+use super::state_machine::ConversationState;
+
+const KEY_PREFIX: &str = "bot:flow:";
+
+/// Días que se conserva una versión desde la última vez que se guardó
+const DEFAULT_TTL_DAYS: u64 = 30;
+
+/// Versiones de los flows en Redis
+pub struct FlowStore {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    ttl: Duration,
+}
+
+impl FlowStore {
+    pub fn new(client: redis::Client, ttl: Duration) -> Self {
+        Self {
+            client,
+            connection: OnceCell::new(),
+            ttl,
+        }
+    }
+
+    pub fn from_env(client: redis::Client) -> Self {
+        let days = std::env::var("FLOW_VERSIONS_TTL_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_TTL_DAYS);
+        Self::new(client, Duration::from_secs(days * 24 * 3600))
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connection = self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(connection.clone())
+    }
+
+    /// Guardar una versión (o renovar su TTL)
+    pub async fn save(&self, flow: &CompiledFlow) -> Result<()> {
+        let data = serde_json::to_string(&flow.flow)?;
+        let mut conn = self.connection().await?;
+        let _: () = conn
+            .set_ex(key(flow.flow.id, flow.version), data, self.ttl.as_secs().max(1))
+            .await
+            .with_context(|| format!("Failed to save flow {} version {:08x}", flow.flow.id, flow.version))?;
+        Ok(())
+    }
+
+    /// Guardar todas las versiones cargadas en el engine
+    pub async fn save_all(&self, engine: &FlowEngine) -> Result<usize> {
+        let versions = engine.retained_versions();
+        for flow in &versions {
+            self.save(flow).await?;
+        }
+        Ok(versions.len())
+    }
+
+    /// Cargar una versión. `None` si no se guardó o ya expiró.
+    pub async fn load(&self, flow_id: Uuid, version: u32) -> Result<Option<Flow>> {
+        let mut conn = self.connection().await?;
+        let data: Option<String> = conn.get(key(flow_id, version)).await?;
+
+        match data {
+            Some(data) => decode_flow(&data, version)
+                .with_context(|| format!("Failed to read flow {} version {:08x}", flow_id, version))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Leer una versión guardada; el contenido tiene que seguir dando la misma versión
+pub fn decode_flow(data: &str, version: u32) -> Result<Flow> {
+    let flow: Flow = serde_json::from_str(data).context("Stored flow does not match the current format")?;
+    let actual = content_version(&flow);
+    if actual != version {
+        anyhow::bail!("Stored flow has version {:08x}, expected {:08x}", actual, version);
+    }
+    Ok(flow)
+}
+
+/// Cargar en el engine las versiones que usa una conversación restaurada y
+/// que ya no están en memoria. Las que no se encuentran se dejan como están:
+/// el engine reinicia esa conversación al no encontrar su versión.
+pub async fn restore_pinned_versions(engine: &FlowEngine, store: &FlowStore, conversation: &ConversationState) {
+    for (flow_id, version) in engine.missing_versions(conversation) {
+        match store.load(flow_id, version).await {
+            Ok(Some(flow)) => match engine.restore_version(flow) {
+                Ok(_) => tracing::info!("♻️ Restored flow {} version {:08x} for {}", flow_id, version, conversation.id),
+                Err(e) => tracing::warn!("Stored flow {} version {:08x} is invalid: {}", flow_id, version, e),
+            },
+            Ok(None) => tracing::warn!("Flow {} version {:08x} is not stored anymore", flow_id, version),
+            Err(e) => tracing::warn!("Could not load flow {} version {:08x}: {:#}", flow_id, version, e),
+        }
+    }
+}
+
+fn key(flow_id: Uuid, version: u32) -> String {
+    format!("{}{}:{:08x}", KEY_PREFIX, flow_id, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_engine::FlowStep;
+    use std::collections::HashMap;
+
+    fn flow(message: &str) -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "saludo".to_string(),
+            description: String::new(),
+            steps: vec![FlowStep::End { id: "fin".to_string(), message: Some(message.to_string()) }],
+            variables: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_key_includes_flow_and_version() {
+        let flow_id = Uuid::new_v4();
+        assert_eq!(key(flow_id, 0xab), format!("bot:flow:{}:000000ab", flow_id));
+    }
+
+    #[test]
+    fn test_decode_checks_version() {
+        let flow = flow("Hola");
+        let version = content_version(&flow);
+        let data = serde_json::to_string(&flow).unwrap();
+
+        assert_eq!(decode_flow(&data, version).unwrap().id, flow.id);
+        assert!(decode_flow(&data, version.wrapping_add(1)).is_err());
+    }
+
+    #[test]
+    fn test_restored_version_is_found_after_restart() {
+        let old = flow("Hola");
+        let mut new = old.clone();
+        new.steps = vec![FlowStep::End { id: "fin".to_string(), message: Some("Buenas".to_string()) }];
+
+        // Antes del reinicio la conversación empezó con la versión vieja
+        let mut conversation = ConversationState::new("c1".to_string(), Uuid::new_v4(), "+58".to_string());
+        conversation.current_flow_id = Some(old.id);
+        conversation.current_flow_version = Some(content_version(&old));
+
+        // Tras el reinicio solo se cargó el archivo nuevo
+        let engine = FlowEngine::new();
+        let latest = engine.register_flow(new).unwrap();
+        assert_eq!(engine.missing_versions(&conversation), vec![(old.id, content_version(&old))]);
+
+        let data = serde_json::to_string(&old).unwrap();
+        engine.restore_version(decode_flow(&data, content_version(&old)).unwrap()).unwrap();
+
+        assert!(engine.missing_versions(&conversation).is_empty());
+        assert!(engine.get_flow(old.id, conversation.current_flow_version).is_some());
+        assert_eq!(engine.get_flow(old.id, None).unwrap().version, latest);
+    }
+}
@@ -0,0 +1,488 @@
+//! Flow Validator - Análisis estático de flows
+//!
+//! Se ejecuta al registrar un flow y detecta:
+//! - IDs de step duplicados
+//! - Referencias a steps inexistentes (`next_step`, `true_step`, opciones de menú...)
+//! - Steps inalcanzables desde el step inicial
+//! - Ciclos de `Message`/`Decision`/`Action` que nunca esperan al usuario
+//! - Flows sin ningún `End` (ni `GotoFlow` a otro flow)
+//! - Condiciones y regex de validación con errores de sintaxis
+//! - Timers incompletos (`on_timeout` sin `timeout_seconds` o al revés) o de 0 segundos
+//!
+//! La recursión entre flows (`CallFlow`) depende de los demás flows
+//! registrados y se revisa aparte con `find_recursive_calls`.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+use super::expression::Expression;
+use super::flow_engine::{Flow, FlowStep, ValidationType};
+
+/// Problema encontrado en un flow
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Diagnostic {
+    pub code: DiagnosticCode,
+    /// Step donde se encontró el problema (None si afecta al flow completo)
+    pub step_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCode {
+    EmptyFlow,
+    DuplicateStepId,
+    DanglingReference,
+    UnreachableStep,
+    DecisionCycle,
+    MissingEnd,
+    InvalidCondition,
+    InvalidRegex,
+    InvalidTimeout,
+    RecursiveCall,
+}
+
+impl Diagnostic {
+    fn new(code: DiagnosticCode, step_id: Option<&str>, message: String) -> Self {
+        Self {
+            code,
+            step_id: step_id.map(str::to_string),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.step_id {
+            Some(step_id) => write!(f, "[{}] {}", step_id, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Validar un flow. Una lista vacía significa que el flow es válido.
+///
+/// El primer step de `steps` es el punto de entrada del flow.
+pub fn validate_flow(flow: &Flow) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if flow.steps.is_empty() {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticCode::EmptyFlow,
+            None,
+            format!("Flow '{}' has no steps", flow.name),
+        ));
+        return diagnostics;
+    }
+
+    // IDs duplicados
+    let mut steps: HashMap<&str, &FlowStep> = HashMap::new();
+    for step in &flow.steps {
+        if steps.insert(step.id(), step).is_some() {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticCode::DuplicateStepId,
+                Some(step.id()),
+                format!("Step id '{}' is used more than once", step.id()),
+            ));
+        }
+    }
+
+    // Referencias colgantes
+    for step in &flow.steps {
+        for (field, target) in step.references() {
+            if !steps.contains_key(target) {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticCode::DanglingReference,
+                    Some(step.id()),
+                    format!("{} points to unknown step '{}'", field, target),
+                ));
+            }
+        }
+    }
+
+    // Condiciones y regex
+    for step in &flow.steps {
+        match step {
+            FlowStep::Decision { id, condition, .. } => {
+                if let Err(e) = Expression::parse(condition) {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticCode::InvalidCondition,
+                        Some(id),
+                        format!("Invalid condition '{}': {}", condition, e),
+                    ));
+                }
+            }
+            FlowStep::Question { id, validation: Some(validation), .. } => {
+                if let ValidationType::Regex(pattern) = &validation.validation_type {
+                    if let Err(e) = regex::Regex::new(pattern) {
+                        diagnostics.push(Diagnostic::new(
+                            DiagnosticCode::InvalidRegex,
+                            Some(id),
+                            format!("Invalid regex '{}': {}", pattern, e),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Timers
+    for step in &flow.steps {
+        let problem = match step {
+            FlowStep::Wait { seconds: 0, .. } => Some("Wait of 0 seconds".to_string()),
+            FlowStep::Question { timeout_seconds, on_timeout, .. }
+            | FlowStep::Menu { timeout_seconds, on_timeout, .. } => match (timeout_seconds, on_timeout) {
+                (Some(0), _) => Some("timeout_seconds must be greater than 0".to_string()),
+                (Some(_), None) => Some("timeout_seconds without on_timeout".to_string()),
+                (None, Some(_)) => Some("on_timeout without timeout_seconds".to_string()),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            diagnostics.push(Diagnostic::new(DiagnosticCode::InvalidTimeout, Some(step.id()), problem));
+        }
+    }
+
+    // Alcanzabilidad desde el step inicial
+    let entry = flow.steps[0].id();
+    let mut reachable: HashSet<&str> = HashSet::new();
+    let mut pending = vec![entry];
+    while let Some(step_id) = pending.pop() {
+        if !reachable.insert(step_id) {
+            continue;
+        }
+        if let Some(step) = steps.get(step_id) {
+            pending.extend(step.references().into_iter().map(|(_, target)| target));
+        }
+    }
+
+    let mut reported = HashSet::new();
+    for step in &flow.steps {
+        if !reachable.contains(step.id()) && reported.insert(step.id()) {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticCode::UnreachableStep,
+                Some(step.id()),
+                format!("Step '{}' can never be reached from '{}'", step.id(), entry),
+            ));
+        }
+    }
+
+    // Ciclos que no esperan input del usuario
+    for cycle in find_automatic_cycles(&flow.steps, &steps) {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticCode::DecisionCycle,
+            Some(cycle[0]),
+            format!(
+                "Steps {} loop forever without waiting for user input",
+                cycle.join(" -> ")
+            ),
+        ));
+    }
+
+    if !flow.steps.iter().any(|s| matches!(s, FlowStep::End { .. } | FlowStep::GotoFlow { .. })) {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticCode::MissingEnd,
+            None,
+            format!("Flow '{}' has no End step", flow.name),
+        ));
+    }
+
+    diagnostics
+}
+
+/// Buscar llamadas que pueden anidarse sin límite: un camino de `CallFlow` y
+/// `GotoFlow` que sale de `flow` y vuelve a él pasando por al menos una
+/// llamada (cada vuelta deja un sub-flow más en la pila). Los saltos solos
+/// pueden formar ciclos, p. ej. volver al menú principal.
+///
+/// `links` devuelve los `(flow, es_llamada)` de los steps de otro flow ya
+/// registrado. Se reporta un diagnóstico por step de `flow` que inicia un ciclo.
+pub fn find_recursive_calls(flow: &Flow, links: impl Fn(Uuid) -> Vec<(Uuid, bool)>) -> Vec<Diagnostic> {
+    let own_links = |flow_id: Uuid| {
+        if flow_id == flow.id {
+            flow.steps.iter().filter_map(FlowStep::linked_flow).collect()
+        } else {
+            links(flow_id)
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    for step in &flow.steps {
+        let Some(start) = step.linked_flow() else {
+            continue;
+        };
+
+        // BFS sobre (flow, ¿ya pasó por una llamada?)
+        let mut parents: HashMap<(Uuid, bool), (Uuid, bool)> = HashMap::new();
+        let mut pending = VecDeque::from([start]);
+        let mut seen = HashSet::from([start]);
+        while let Some(node @ (flow_id, called)) = pending.pop_front() {
+            if node == (flow.id, true) {
+                let mut path = vec![flow_id];
+                let mut current = node;
+                while let Some(parent) = parents.get(&current) {
+                    path.push(parent.0);
+                    current = *parent;
+                }
+                path.push(flow.id);
+                path.reverse();
+
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticCode::RecursiveCall,
+                    Some(step.id()),
+                    format!(
+                        "Flow calls can nest without limit: {}",
+                        path.iter().map(Uuid::to_string).collect::<Vec<_>>().join(" -> ")
+                    ),
+                ));
+                break;
+            }
+            if flow_id == flow.id {
+                // Vuelta a `flow` solo con saltos: no crece la pila
+                continue;
+            }
+            for (target, is_call) in own_links(flow_id) {
+                let next = (target, called || is_call);
+                if seen.insert(next) {
+                    parents.insert(next, node);
+                    pending.push_back(next);
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Steps que el engine encadena sin devolver el control al usuario
+fn is_automatic(step: &FlowStep) -> bool {
+    matches!(
+        step,
+        FlowStep::Message { .. } | FlowStep::Decision { .. } | FlowStep::Action { .. }
+    )
+}
+
+/// Buscar ciclos formados solo por steps automáticos (DFS con colores)
+fn find_automatic_cycles<'a>(
+    ordered: &'a [FlowStep],
+    steps: &HashMap<&'a str, &'a FlowStep>,
+) -> Vec<Vec<&'a str>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn visit<'a>(
+        step_id: &'a str,
+        steps: &HashMap<&'a str, &'a FlowStep>,
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+        cycles: &mut Vec<Vec<&'a str>>,
+    ) {
+        let step = match steps.get(step_id) {
+            Some(step) if is_automatic(step) => *step,
+            _ => return,
+        };
+
+        match marks.get(step_id) {
+            Some(Mark::Done) => return,
+            Some(Mark::Visiting) => {
+                if let Some(start) = path.iter().position(|id| *id == step_id) {
+                    let mut cycle = path[start..].to_vec();
+                    cycle.push(step_id);
+                    cycles.push(cycle);
+                }
+                return;
+            }
+            None => {}
+        }
+
+        marks.insert(step_id, Mark::Visiting);
+        path.push(step_id);
+        for (_, target) in step.references() {
+            visit(target, steps, marks, path, cycles);
+        }
+        path.pop();
+        marks.insert(step_id, Mark::Done);
+    }
+
+    let mut marks = HashMap::new();
+    let mut cycles = Vec::new();
+    for step in ordered {
+        visit(step.id(), steps, &mut marks, &mut Vec::new(), &mut cycles);
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_engine::MenuOption;
+    use uuid::Uuid;
+
+    fn flow(steps: Vec<FlowStep>) -> Flow {
+        Flow {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            description: String::new(),
+            steps,
+            variables: HashMap::new(),
+        }
+    }
+
+    fn message(id: &str, next: Option<&str>) -> FlowStep {
+        FlowStep::Message {
+            id: id.to_string(),
+            text: "hola".to_string(),
+            next_step: next.map(str::to_string),
+        }
+    }
+
+    fn end(id: &str) -> FlowStep {
+        FlowStep::End { id: id.to_string(), message: None }
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<DiagnosticCode> {
+        diagnostics.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn test_valid_flow_has_no_diagnostics() {
+        let flow = flow(vec![
+            FlowStep::Menu {
+                id: "menu".to_string(),
+                text: "Elige".to_string(),
+                options: vec![MenuOption {
+                    key: "1".to_string(),
+                    label: "Catálogo".to_string(),
+                    next_step: "catalogo".to_string(),
+                    description: None,
+                    section: None,
+                }],
+                display: Default::default(),
+                button_text: None,
+                max_attempts: None,
+                on_exhausted: None,
+                timeout_seconds: None,
+                on_timeout: None,
+            },
+            message("catalogo", Some("fin")),
+            end("fin"),
+        ]);
+
+        assert!(validate_flow(&flow).is_empty());
+    }
+
+    #[test]
+    fn test_dangling_and_duplicate_steps() {
+        let flow = flow(vec![
+            message("inicio", Some("pregunta")),
+            message("inicio", Some("fin")),
+            end("fin"),
+        ]);
+
+        let diagnostics = validate_flow(&flow);
+        assert!(codes(&diagnostics).contains(&DiagnosticCode::DuplicateStepId));
+        assert!(diagnostics.iter().any(|d| d.code == DiagnosticCode::DanglingReference
+            && d.message.contains("'pregunta'")));
+    }
+
+    #[test]
+    fn test_unreachable_and_missing_end() {
+        let flow = flow(vec![message("inicio", None), message("huerfano", None)]);
+
+        let diagnostics = validate_flow(&flow);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![DiagnosticCode::UnreachableStep, DiagnosticCode::MissingEnd]
+        );
+        assert_eq!(diagnostics[0].step_id.as_deref(), Some("huerfano"));
+    }
+
+    #[test]
+    fn test_decision_cycle_without_input() {
+        let flow = flow(vec![
+            FlowStep::Decision {
+                id: "a".to_string(),
+                condition: "x > 1".to_string(),
+                true_step: "b".to_string(),
+                false_step: "fin".to_string(),
+            },
+            FlowStep::Decision {
+                id: "b".to_string(),
+                condition: "y".to_string(),
+                true_step: "a".to_string(),
+                false_step: "fin".to_string(),
+            },
+            end("fin"),
+        ]);
+
+        let diagnostics = validate_flow(&flow);
+        assert_eq!(codes(&diagnostics), vec![DiagnosticCode::DecisionCycle]);
+        assert!(diagnostics[0].message.contains("a -> b -> a"));
+    }
+
+    #[test]
+    fn test_cycle_through_question_is_allowed() {
+        let flow = flow(vec![
+            FlowStep::Question {
+                id: "cantidad".to_string(),
+                text: "¿Cuántas?".to_string(),
+                variable_name: "cantidad".to_string(),
+                validation: None,
+                next_step: Some("check".to_string()),
+                max_attempts: None,
+                on_exhausted: None,
+                timeout_seconds: None,
+                on_timeout: None,
+            },
+            FlowStep::Decision {
+                id: "check".to_string(),
+                condition: "cantidad > 0".to_string(),
+                true_step: "fin".to_string(),
+                false_step: "cantidad".to_string(),
+            },
+            end("fin"),
+        ]);
+
+        assert!(validate_flow(&flow).is_empty());
+    }
+
+    #[test]
+    fn test_timers() {
+        // Un recordatorio que se repite pasa por un `Wait`: no es un ciclo sin input
+        let reminders = flow(vec![
+            FlowStep::Wait {
+                id: "espera".to_string(),
+                seconds: 900,
+                next_step: Some("recordatorio".to_string()),
+                on_reply: Some("fin".to_string()),
+            },
+            message("recordatorio", Some("espera")),
+            end("fin"),
+        ]);
+        assert!(validate_flow(&reminders).is_empty());
+
+        let incomplete = flow(vec![
+            FlowStep::Question {
+                id: "nombre".to_string(),
+                text: "¿Nombre?".to_string(),
+                variable_name: "nombre".to_string(),
+                validation: None,
+                next_step: Some("fin".to_string()),
+                max_attempts: None,
+                on_exhausted: None,
+                timeout_seconds: Some(600),
+                on_timeout: None,
+            },
+            end("fin"),
+        ]);
+        let diagnostics = validate_flow(&incomplete);
+        assert_eq!(codes(&diagnostics), vec![DiagnosticCode::InvalidTimeout]);
+        assert_eq!(diagnostics[0].step_id.as_deref(), Some("nombre"));
+    }
+}
@@ -0,0 +1,688 @@
+//! Handoff - Atención de una conversación por un asesor humano
+//!
+//! Cuando el cliente pide un asesor (`asesor`, `agente`, `humano`), el flow llega
+//! a un step `Handoff` o un asesor toma la conversación desde el dashboard,
+//! `ConversationState::handoff` pasa a tener valor y el `FlowEngine` deja de
+//! responder en esa conversación:
+//!
+//! 1. `queued`: pedido fuera de horario, espera a la apertura (ver `business_hours`)
+//! 2. `waiting`: se busca un asesor con `AgentDirectory` (el endpoint de
+//!    disponibilidad de vendedores del backend Node) cada `ASSIGN_INTERVAL`
+//! 3. `active`: los mensajes del cliente se reenvían al asesor
+//!    (`BotEvent::HandoffMessage` y `HANDOFF_WEBHOOK_URL`) y sus respuestas
+//!    salen por el mismo bot con `POST /conversations/{id}/handoff/reply`
+//!
+//! `POST /conversations/{id}/handoff/release` devuelve la conversación al bot,
+//! opcionalmente en un flow/step elegido por el asesor. Mientras nadie la haya
+//! tomado, el cliente puede salir de la cola con `salir` o `menu`.
+//!
+//! Las operaciones de los asesores pasan por el buzón de la conversación, en
+//! orden con los mensajes del cliente.
+
+use actix_web::{web, HttpResponse, Responder};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{broadcast, oneshot};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::event_log;
+use super::message::MessageContent;
+use super::metrics::FlowOutcome;
+use super::state_machine::ConversationState;
+use super::{BotEvent, BotInstance, ConversationCommand, OrchestratorState};
+
+/// Cada cuánto se buscan asesores para los handoffs en espera
+pub const ASSIGN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandoffStatus {
+    /// Pedido fuera de horario, en cola hasta `queued_until` (sin fecha: ya)
+    Queued,
+    /// Esperando a que un asesor la tome
+    Waiting,
+    /// Atendida por `agent`
+    Active,
+}
+
+/// Asesor o vendedor que atiende la conversación
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Agent {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handoff {
+    pub status: HandoffStatus,
+    #[serde(default)]
+    pub agent: Option<Agent>,
+    pub requested_at: DateTime<Utc>,
+    #[serde(default)]
+    pub assigned_at: Option<DateTime<Utc>>,
+    /// Apertura del bot para los pedidos fuera de horario
+    #[serde(default)]
+    pub queued_until: Option<DateTime<Utc>>,
+    /// Step en el que estaba la conversación cuando pasó al asesor
+    #[serde(default)]
+    pub return_to: Option<FlowPosition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlowPosition {
+    pub flow_id: Uuid,
+    /// Versión del flow que usaba la conversación; se retoma en esa misma
+    #[serde(default)]
+    pub flow_version: Option<u32>,
+    pub step_id: String,
+}
+
+impl Handoff {
+    /// Pedido en horario: se busca asesor enseguida
+    pub fn waiting(now: DateTime<Utc>) -> Self {
+        Self {
+            status: HandoffStatus::Waiting,
+            agent: None,
+            requested_at: now,
+            assigned_at: None,
+            queued_until: None,
+            return_to: None,
+        }
+    }
+
+    /// Pedido fuera de horario: se busca asesor a partir de `until`. Sin
+    /// `until` (no se conoce la próxima apertura) se busca enseguida, para que
+    /// el pedido no quede en cola para siempre.
+    pub fn queued(now: DateTime<Utc>, until: Option<DateTime<Utc>>) -> Self {
+        Self {
+            status: HandoffStatus::Queued,
+            queued_until: until,
+            ..Self::waiting(now)
+        }
+    }
+
+    pub fn assign(&mut self, agent: Agent, now: DateTime<Utc>) {
+        self.status = HandoffStatus::Active;
+        self.agent = Some(agent);
+        self.assigned_at = Some(now);
+    }
+
+    /// ¿Hay que buscarle asesor? (en espera, o en cola y ya abrió el bot)
+    pub fn needs_agent(&self, now: DateTime<Utc>) -> bool {
+        match self.status {
+            HandoffStatus::Waiting => true,
+            HandoffStatus::Queued => self.queued_until.is_none_or(|until| until <= now),
+            HandoffStatus::Active => false,
+        }
+    }
+
+    pub fn agent_id(&self) -> Option<String> {
+        self.agent.as_ref().map(|agent| agent.id.clone())
+    }
+}
+
+// ============================================================================
+// Asignación de asesores
+// ============================================================================
+
+/// De dónde salen los asesores disponibles
+#[async_trait]
+pub trait AgentDirectory: Send + Sync {
+    /// Asesor disponible con menos carga; `None` si no hay ninguno ahora
+    async fn best_available(&self) -> anyhow::Result<Option<Agent>>;
+}
+
+/// Sin directorio: los asesores toman las conversaciones desde el dashboard
+pub struct NoAgentDirectory;
+
+#[async_trait]
+impl AgentDirectory for NoAgentDirectory {
+    async fn best_available(&self) -> anyhow::Result<Option<Agent>> {
+        Ok(None)
+    }
+}
+
+/// `GET {SELLERS_API_URL}/api/sellers/available/best` del backend Node
+pub struct SellersApi {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BestSellerResponse {
+    seller: SellerDto,
+}
+
+#[derive(Debug, Deserialize)]
+struct SellerDto {
+    /// Numérico en SQLite, string en Mongo
+    id: serde_json::Value,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+impl SellersApi {
+    pub fn new(base_url: String) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// `SellersApi` si está configurado `SELLERS_API_URL`, si no `NoAgentDirectory`
+    pub fn from_env() -> std::sync::Arc<dyn AgentDirectory> {
+        match std::env::var("SELLERS_API_URL") {
+            Ok(url) if !url.is_empty() => std::sync::Arc::new(Self::new(url)),
+            _ => std::sync::Arc::new(NoAgentDirectory),
+        }
+    }
+}
+
+#[async_trait]
+impl AgentDirectory for SellersApi {
+    async fn best_available(&self) -> anyhow::Result<Option<Agent>> {
+        let url = format!("{}/api/sellers/available/best", self.base_url);
+        let response = self.client.get(&url).send().await?;
+
+        // 404 = no hay vendedores disponibles en este momento
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let body: BestSellerResponse = response.error_for_status()?.json().await?;
+        Ok(Some(body.seller.into()))
+    }
+}
+
+impl From<SellerDto> for Agent {
+    fn from(seller: SellerDto) -> Self {
+        let id = match seller.id {
+            serde_json::Value::String(id) => id,
+            other => other.to_string(),
+        };
+        Agent { id, name: seller.name }
+    }
+}
+
+// ============================================================================
+// Operaciones de los asesores
+// ============================================================================
+
+#[derive(Debug, Error)]
+pub enum HandoffError {
+    #[error("Conversation not found")]
+    ConversationNotFound,
+    #[error("Conversation is not handed off to an agent")]
+    NotInHandoff,
+    #[error("Conversation is assigned to agent {0}")]
+    AssignedToOther(String),
+    #[error("No agent available")]
+    NoAgentAvailable,
+    #[error("Invalid release target: {0}")]
+    InvalidTarget(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Operación sobre el handoff de una conversación
+#[derive(Debug)]
+pub enum HandoffCommand {
+    /// Asignar un asesor (o buscar uno en el directorio); inicia el handoff si hace falta
+    Assign(Option<Agent>),
+    /// Respuesta del asesor al cliente
+    Reply { agent_id: String, content: MessageContent },
+    /// Devolver la conversación al bot
+    Release(ReleaseTarget),
+}
+
+/// Dónde retoma el bot al terminar el handoff. Sin `flow_id` se usa el flow en
+/// el que estaba la conversación; sin nada, la conversación queda libre y el
+/// siguiente mensaje empieza por el welcome flow.
+#[derive(Debug, Default, Deserialize)]
+pub struct ReleaseTarget {
+    #[serde(default)]
+    pub flow_id: Option<Uuid>,
+    #[serde(default)]
+    pub step: Option<String>,
+}
+
+/// Ejecutar una operación de un asesor (desde el buzón de la conversación)
+pub async fn execute(
+    state: &OrchestratorState,
+    conversation_id: &str,
+    command: HandoffCommand,
+) -> Result<(), HandoffError> {
+    let mut conversation = super::find_conversation(state, conversation_id)
+        .await?
+        .ok_or(HandoffError::ConversationNotFound)?;
+
+    let before = conversation.clone();
+    let result = match command {
+        HandoffCommand::Assign(agent) => {
+            if conversation.handoff.is_none() {
+                conversation.start_handoff(Handoff::waiting(state.clock.now()));
+                emit_started(state, &conversation);
+            }
+            assign_agent(state, &mut conversation, agent).await
+        }
+        HandoffCommand::Reply { agent_id, content } => reply(state, &mut conversation, agent_id, content).await,
+        HandoffCommand::Release(target) => release(state, &mut conversation, target).await,
+    };
+
+    state.timers.sync(conversation_id, before.timer.as_ref(), conversation.timer.as_ref()).await;
+    state.metrics.record_flows(&mut conversation);
+    event_log::record(state, "handoff", Some(&before), &mut conversation).await;
+    super::save_conversation(state, conversation).await;
+    result
+}
+
+/// Asignar un asesor: el indicado o, si es `None`, el mejor disponible del directorio
+pub async fn assign_agent(
+    state: &OrchestratorState,
+    conversation: &mut ConversationState,
+    agent: Option<Agent>,
+) -> Result<(), HandoffError> {
+    let handoff = conversation.handoff.as_ref().ok_or(HandoffError::NotInHandoff)?;
+    let now = state.clock.now();
+
+    let agent = match agent {
+        Some(agent) => agent,
+        None if !handoff.needs_agent(now) => return Ok(()),
+        None => state.agents.best_available().await?.ok_or(HandoffError::NoAgentAvailable)?,
+    };
+
+    if let Some(handoff) = conversation.handoff.as_mut() {
+        handoff.assign(agent.clone(), now);
+    }
+    info!("🙋 Conversation {} assigned to agent {}", conversation.id, agent.id);
+
+    state.event_bus.send(BotEvent::HandoffAssigned {
+        conversation_id: conversation.id.clone(),
+        bot_id: conversation.bot_id,
+        agent_id: agent.id.clone(),
+        timestamp: now,
+    });
+
+    let name = agent.name.as_deref().unwrap_or("Un asesor");
+    let text = format!("👩‍💼 *{}* te atenderá por aquí.", name);
+    super::send_reply(state, conversation, "bot", MessageContent::text(text)).await;
+    Ok(())
+}
+
+async fn reply(
+    state: &OrchestratorState,
+    conversation: &mut ConversationState,
+    agent_id: String,
+    content: MessageContent,
+) -> Result<(), HandoffError> {
+    let handoff = conversation.handoff.as_mut().ok_or(HandoffError::NotInHandoff)?;
+
+    match &handoff.agent {
+        Some(agent) if agent.id != agent_id => return Err(HandoffError::AssignedToOther(agent.id.clone())),
+        Some(_) => {}
+        // Responder una conversación en espera es tomarla
+        None => {
+            let now = state.clock.now();
+            handoff.assign(Agent { id: agent_id.clone(), name: None }, now);
+            state.event_bus.send(BotEvent::HandoffAssigned {
+                conversation_id: conversation.id.clone(),
+                bot_id: conversation.bot_id,
+                agent_id,
+                timestamp: now,
+            });
+        }
+    }
+
+    super::send_reply(state, conversation, "agent", content).await;
+    conversation.update_last_activity();
+    Ok(())
+}
+
+async fn release(
+    state: &OrchestratorState,
+    conversation: &mut ConversationState,
+    target: ReleaseTarget,
+) -> Result<(), HandoffError> {
+    let handoff = conversation.handoff.as_ref().ok_or(HandoffError::NotInHandoff)?;
+
+    let flow_id = target.flow_id.or_else(|| handoff.return_to.as_ref().map(|position| position.flow_id));
+    if flow_id.is_none() && target.step.is_some() {
+        return Err(HandoffError::InvalidTarget("a step needs a flow_id".to_string()));
+    }
+    // A un step del flow en el que estaba, con la versión con la que empezó
+    let version = handoff.return_to.as_ref()
+        .filter(|position| target.step.is_some() && Some(position.flow_id) == flow_id)
+        .and_then(|position| position.flow_version);
+    if let Some(flow_id) = flow_id {
+        let flow = state.flow_engine.get_flow(flow_id, version)
+            .ok_or_else(|| HandoffError::InvalidTarget(format!("flow {} is not loaded", flow_id)))?;
+        if let Some(step) = &target.step {
+            if !flow.flow.steps.iter().any(|s| s.id() == step) {
+                return Err(HandoffError::InvalidTarget(format!("step '{}' not found in flow {}", step, flow_id)));
+            }
+        }
+    }
+
+    let handoff = conversation.end_handoff().ok_or(HandoffError::NotInHandoff)?;
+    emit_ended(state, conversation, &handoff, "released");
+
+    // Los sub-flows pendientes solo siguen si vuelve al flow en el que estaba
+    let return_flow = handoff.return_to.as_ref().map(|position| position.flow_id);
+    let returns = target.step.is_some() && flow_id == return_flow;
+    if !returns {
+        conversation.call_stack.clear();
+        // El flow que el handoff dejó en pausa no se retoma
+        if handoff.return_to.is_some() {
+            conversation.flow_outcomes.push(FlowOutcome::Abandoned);
+        }
+    }
+
+    // Sin flow ni step elegidos la conversación queda libre
+    let reply = match (flow_id, target.step.as_deref()) {
+        (Some(flow_id), step) if target.flow_id.is_some() || step.is_some() => {
+            // Sin step, `go_to` empieza el flow y lo cuenta
+            if step.is_some() && !returns {
+                conversation.flow_outcomes.push(FlowOutcome::Started);
+            }
+            state.flow_engine.go_to(conversation, flow_id, version, step).await?
+        }
+        _ => None,
+    };
+    if let Some(content) = reply {
+        super::send_reply(state, conversation, "bot", content).await;
+    }
+    Ok(())
+}
+
+/// Handoff recién pedido en el flow (palabra clave o step `Handoff`): avisar
+/// a los asesores y buscar uno disponible
+pub async fn begin(state: &OrchestratorState, conversation: &mut ConversationState) {
+    emit_started(state, conversation);
+    match assign_agent(state, conversation, None).await {
+        Ok(()) | Err(HandoffError::NoAgentAvailable) => {}
+        Err(e) => warn!("Could not assign an agent to {}: {}", conversation.id, e),
+    }
+}
+
+pub fn emit_started(state: &OrchestratorState, conversation: &ConversationState) {
+    let Some(handoff) = &conversation.handoff else { return };
+    info!("🙋 Conversation {} handed off ({:?})", conversation.id, handoff.status);
+
+    state.event_bus.send(BotEvent::HandoffStarted {
+        conversation_id: conversation.id.clone(),
+        bot_id: conversation.bot_id,
+        user_phone: conversation.user_phone.clone(),
+        queued_until: handoff.queued_until,
+        timestamp: handoff.requested_at,
+    });
+}
+
+pub fn emit_ended(state: &OrchestratorState, conversation: &ConversationState, handoff: &Handoff, reason: &str) {
+    info!("🤖 Conversation {} back to the bot ({})", conversation.id, reason);
+    let now = state.clock.now();
+
+    state.event_bus.send(BotEvent::HandoffEnded {
+        conversation_id: conversation.id.clone(),
+        bot_id: conversation.bot_id,
+        agent_id: handoff.agent_id(),
+        reason: reason.to_string(),
+        duration_seconds: (now - handoff.requested_at).num_seconds(),
+        timestamp: now,
+    });
+}
+
+/// Reenviar al asesor un mensaje del cliente
+pub fn forward(state: &OrchestratorState, conversation: &ConversationState, content: &MessageContent) {
+    let Some(handoff) = &conversation.handoff else { return };
+
+    state.event_bus.send(BotEvent::HandoffMessage {
+        conversation_id: conversation.id.clone(),
+        bot_id: conversation.bot_id,
+        agent_id: handoff.agent_id(),
+        from: conversation.user_phone.clone(),
+        message: content.clone(),
+        timestamp: state.clock.now(),
+    });
+}
+
+// ============================================================================
+// Workers
+// ============================================================================
+
+/// Buscar asesor para los handoffs en espera y los que estaban en cola y ya abrió el bot
+pub fn spawn_assign_worker(state: OrchestratorState) {
+    tokio::spawn(async move {
+        info!("🙋 Handoff assign worker started");
+        let mut interval = tokio::time::interval(ASSIGN_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            for conversation_id in pending_assignments(&state.conversations, &state.bots, state.clock.now()) {
+                let (reply, result) = oneshot::channel();
+                super::dispatch_command(&state, &conversation_id, ConversationCommand::Handoff {
+                    conversation_id: conversation_id.clone(),
+                    command: HandoffCommand::Assign(None),
+                    reply,
+                });
+                match result.await {
+                    Ok(Ok(())) | Ok(Err(HandoffError::NoAgentAvailable)) => {}
+                    Ok(Err(e)) => warn!("Could not assign an agent to {}: {}", conversation_id, e),
+                    Err(_) => debug!("Assign request for {} dropped", conversation_id),
+                }
+            }
+        }
+    });
+}
+
+/// Conversaciones a las que hay que buscarles asesor. Las de un bot pausado
+/// siguen en espera hasta que se reanude: el aviso del asesor asignado lo
+/// envía el bot.
+fn pending_assignments(
+    conversations: &DashMap<String, ConversationState>,
+    bots: &DashMap<Uuid, BotInstance>,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    conversations.iter()
+        .filter(|conv| conv.handoff.as_ref().is_some_and(|handoff| handoff.needs_agent(now)))
+        .filter(|conv| !super::bot_paused(bots, &conv.bot_id))
+        .map(|conv| conv.id.clone())
+        .collect()
+}
+
+/// Publicar los eventos de handoff en `HANDOFF_WEBHOOK_URL` (bandeja de los asesores)
+pub fn spawn_forwarder(url: String, mut event_rx: broadcast::Receiver<BotEvent>) {
+    tokio::spawn(async move {
+        info!("📨 Handoff forwarder started ({})", url);
+        let client = reqwest::Client::new();
+
+        loop {
+            let event = match event_rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Handoff forwarder lagged, {} events skipped", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            let is_handoff = matches!(
+                event,
+                BotEvent::HandoffStarted { .. }
+                    | BotEvent::HandoffAssigned { .. }
+                    | BotEvent::HandoffMessage { .. }
+                    | BotEvent::HandoffEnded { .. }
+            );
+            if !is_handoff {
+                continue;
+            }
+
+            let result = client.post(&url)
+                .timeout(Duration::from_secs(5))
+                .json(&event)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn!("Could not forward handoff event: {}", e);
+            }
+        }
+    });
+}
+
+// ============================================================================
+// API
+// ============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AssignRequest {
+    /// Sin asesor se busca uno en el directorio
+    #[serde(default)]
+    pub agent: Option<Agent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgentReplyRequest {
+    pub agent_id: String,
+    pub content: MessageContent,
+}
+
+/// `POST /conversations/{id}/handoff`: un asesor toma la conversación
+pub async fn handle_assign(
+    state: web::Data<OrchestratorState>,
+    path: web::Path<String>,
+    body: web::Json<AssignRequest>,
+) -> impl Responder {
+    run(&state, path.into_inner(), HandoffCommand::Assign(body.into_inner().agent)).await
+}
+
+/// `POST /conversations/{id}/handoff/reply`: respuesta del asesor al cliente
+pub async fn handle_reply(
+    state: web::Data<OrchestratorState>,
+    path: web::Path<String>,
+    body: web::Json<AgentReplyRequest>,
+) -> impl Responder {
+    let AgentReplyRequest { agent_id, content } = body.into_inner();
+    run(&state, path.into_inner(), HandoffCommand::Reply { agent_id, content }).await
+}
+
+/// `POST /conversations/{id}/handoff/release`: devolver la conversación al bot
+pub async fn handle_release(
+    state: web::Data<OrchestratorState>,
+    path: web::Path<String>,
+    body: web::Json<ReleaseTarget>,
+) -> impl Responder {
+    run(&state, path.into_inner(), HandoffCommand::Release(body.into_inner())).await
+}
+
+async fn run(state: &OrchestratorState, conversation_id: String, command: HandoffCommand) -> HttpResponse {
+    let (reply, result) = oneshot::channel();
+    super::dispatch_command(state, &conversation_id, ConversationCommand::Handoff {
+        conversation_id: conversation_id.clone(),
+        command,
+        reply,
+    });
+
+    let error = match result.await {
+        Ok(Ok(())) => return HttpResponse::Ok().json(serde_json::json!({ "status": "ok" })),
+        Ok(Err(e)) => e,
+        Err(_) => HandoffError::Internal(anyhow::anyhow!("Conversation worker stopped")),
+    };
+
+    let body = serde_json::json!({ "error": error.to_string() });
+    match error {
+        HandoffError::ConversationNotFound => HttpResponse::NotFound().json(body),
+        HandoffError::NotInHandoff
+        | HandoffError::AssignedToOther(_)
+        | HandoffError::NoAgentAvailable => HttpResponse::Conflict().json(body),
+        HandoffError::InvalidTarget(_) => HttpResponse::UnprocessableEntity().json(body),
+        HandoffError::Internal(e) => {
+            tracing::error!("Handoff operation on {} failed: {:#}", conversation_id, e);
+            HttpResponse::InternalServerError().json(body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bots::BotStatus;
+    use chrono::Duration;
+
+    #[test]
+    fn test_needs_agent() {
+        let now = Utc::now();
+
+        assert!(Handoff::waiting(now).needs_agent(now));
+        assert!(!Handoff::queued(now, Some(now + Duration::hours(8))).needs_agent(now));
+        assert!(Handoff::queued(now, Some(now - Duration::minutes(1))).needs_agent(now));
+        assert!(Handoff::queued(now, None).needs_agent(now));
+
+        let mut handoff = Handoff::waiting(now);
+        handoff.assign(Agent { id: "7".to_string(), name: Some("Ana".to_string()) }, now);
+        assert_eq!(handoff.status, HandoffStatus::Active);
+        assert!(!handoff.needs_agent(now));
+    }
+
+    fn bot(status: BotStatus) -> BotInstance {
+        serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "tenant_id": "cocolu",
+            "name": "Floristería",
+            "phone_number": null,
+            "provider": "venom",
+            "provider_config": {},
+            "flows": {},
+            "settings": {},
+            "status": status,
+            "stats": crate::BotStats::default(),
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_paused_bots_wait_for_an_agent() {
+        let now = Utc::now();
+        let bots = DashMap::new();
+        let conversations = DashMap::new();
+        for (name, status) in [("activo", BotStatus::Active), ("pausado", BotStatus::Paused)] {
+            let bot = bot(status);
+            let mut conversation = ConversationState::new(name.to_string(), bot.id, "+58".to_string());
+            conversation.start_handoff(Handoff::waiting(now));
+            conversations.insert(conversation.id.clone(), conversation);
+            bots.insert(bot.id, bot);
+        }
+
+        assert_eq!(pending_assignments(&conversations, &bots, now), ["activo"]);
+
+        // Al reanudarse el bot, se le busca asesor
+        for mut bot in bots.iter_mut() {
+            bot.status = BotStatus::Active;
+        }
+        let mut pending = pending_assignments(&conversations, &bots, now);
+        pending.sort();
+        assert_eq!(pending, ["activo", "pausado"]);
+    }
+
+    #[test]
+    fn test_seller_ids() {
+        let body: BestSellerResponse = serde_json::from_str(
+            r#"{"success": true, "seller": {"id": 12, "name": "Ana", "specialty": "general"}}"#,
+        ).unwrap();
+        let agent: Agent = body.seller.into();
+        assert_eq!(agent, Agent { id: "12".to_string(), name: Some("Ana".to_string()) });
+
+        let body: BestSellerResponse = serde_json::from_str(
+            r#"{"seller": {"id": "65f1c2a9e4b0", "name": null}}"#,
+        ).unwrap();
+        assert_eq!(Agent::from(body.seller).id, "65f1c2a9e4b0");
+    }
+}
@@ -0,0 +1,398 @@
+//! Input Validation - Validación y normalización de respuestas a `Question`
+//!
+//! Cada validación devuelve el valor normalizado que se guarda en el contexto:
+//! - Phone: E.164 (`+584141234567`), código de país por defecto +58
+//! - Email: en minúsculas, sin espacios
+//! - Number: número JSON (acepta "12,5", "1.234,56" y "1.500" como mil quinientos)
+//! - Date: ISO `YYYY-MM-DD` ("15/03", "mañana", "15 de marzo", "viernes"...)
+//! - Text / Regex: texto sin espacios alrededor
+//! - Image / Audio / Video / Document / Location / Contacts: el mensaje debe
+//!   ser de ese tipo; se guarda el `MessageContent` completo, así los flows
+//!   pueden usar `{{comprobante.caption}}` o `{{ubicacion.latitude}}`
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use regex::Regex;
+use serde_json::Value;
+
+use super::flow_engine::ValidationType;
+use super::message::MessageContent;
+
+/// Código de país que se asume cuando el número no trae uno
+pub const DEFAULT_COUNTRY_CODE: &str = "58";
+
+/// Validar un mensaje de cualquier tipo. `None` si no es válido.
+///
+/// Las validaciones de texto usan el texto del mensaje (también el pie de foto
+/// o el id de un botón); las de adjuntos, el tipo del mensaje.
+pub fn normalize_content(
+    content: &MessageContent,
+    validation_type: &ValidationType,
+    regex: Option<&Regex>,
+    today: NaiveDate,
+) -> Option<Value> {
+    let expected = match validation_type {
+        ValidationType::Image => "image",
+        ValidationType::Audio => "audio",
+        ValidationType::Video => "video",
+        ValidationType::Document => "document",
+        ValidationType::Location => "location",
+        ValidationType::Contacts => "contacts",
+        _ => return normalize_input(content.as_text()?, validation_type, regex, today),
+    };
+
+    if content.kind() == expected {
+        serde_json::to_value(content).ok()
+    } else {
+        None
+    }
+}
+
+/// Validar y normalizar una respuesta de texto. `None` si no es válida.
+///
+/// `regex` es el patrón ya compilado al registrar el flow (solo para `Regex`).
+pub fn normalize_input(
+    input: &str,
+    validation_type: &ValidationType,
+    regex: Option<&Regex>,
+    today: NaiveDate,
+) -> Option<Value> {
+    let input = input.trim();
+
+    match validation_type {
+        ValidationType::Phone => normalize_phone(input).map(Value::String),
+        ValidationType::Email => normalize_email(input).map(Value::String),
+        ValidationType::Number => parse_number(input)
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        ValidationType::Text => (!input.is_empty()).then(|| Value::String(input.to_string())),
+        ValidationType::Date => parse_date(input, today)
+            .map(|date| Value::String(date.format("%Y-%m-%d").to_string())),
+        ValidationType::Regex(_) => regex
+            .filter(|regex| regex.is_match(input))
+            .map(|_| Value::String(input.to_string())),
+        // Un texto nunca es un adjunto (ver `normalize_content`)
+        ValidationType::Image
+        | ValidationType::Audio
+        | ValidationType::Video
+        | ValidationType::Document
+        | ValidationType::Location
+        | ValidationType::Contacts => None,
+    }
+}
+
+/// Normalizar un teléfono a E.164
+///
+/// "0414-123.45.67" → "+584141234567", "+1 (555) 123-4567" → "+15551234567"
+pub fn normalize_phone(input: &str) -> Option<String> {
+    let has_plus = input.starts_with('+');
+    let allowed = |c: char| c.is_ascii_digit() || matches!(c, ' ' | '-' | '.' | '(' | ')' | '+');
+    if !input.chars().all(allowed) || input.chars().skip(1).any(|c| c == '+') {
+        return None;
+    }
+
+    let digits: String = input.chars().filter(char::is_ascii_digit).collect();
+
+    let international = if has_plus {
+        digits
+    } else if let Some(rest) = digits.strip_prefix("00") {
+        rest.to_string()
+    } else if let Some(national) = digits.strip_prefix('0') {
+        // Formato nacional con prefijo troncal: 0414 1234567
+        format!("{}{}", DEFAULT_COUNTRY_CODE, national)
+    } else if digits.starts_with(DEFAULT_COUNTRY_CODE) && digits.len() == 12 {
+        digits
+    } else if digits.len() == 10 {
+        format!("{}{}", DEFAULT_COUNTRY_CODE, digits)
+    } else {
+        digits
+    };
+
+    // E.164: máximo 15 dígitos, sin ceros al inicio
+    if !(8..=15).contains(&international.len()) || international.starts_with('0') {
+        return None;
+    }
+
+    Some(format!("+{}", international))
+}
+
+/// Validar un email y normalizarlo a minúsculas
+pub fn normalize_email(input: &str) -> Option<String> {
+    let email = input.to_lowercase();
+    let (local, domain) = email.split_once('@')?;
+
+    let local_ok = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local.chars().all(|c| c.is_ascii_alphanumeric() || "._%+-".contains(c));
+
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_ok = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && labels.last().is_some_and(|tld| {
+            tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic())
+        });
+
+    (local_ok && domain_ok).then_some(email)
+}
+
+/// Número en formato español o inglés: "12,5", "1.234,56", "1,234.56", "45"
+///
+/// Un punto seguido de exactamente tres dígitos y sin decimales con coma es
+/// separador de miles, como se escribe en Venezuela: "1.500" es 1500, no 1,5.
+pub fn parse_number(input: &str) -> Option<f64> {
+    let compact: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+
+    let normalized = match (compact.rfind(','), compact.rfind('.')) {
+        // El separador que aparece último es el decimal
+        (Some(comma), Some(dot)) if comma > dot => compact.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => compact.replace(',', ""),
+        (Some(_), None) => compact.replace(',', "."),
+        (None, Some(_)) if has_thousands_dots(&compact) => compact.replace('.', ""),
+        _ => compact,
+    };
+
+    normalized.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// "1.500", "12.000.000": grupos de tres dígitos separados por puntos
+fn has_thousands_dots(number: &str) -> bool {
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    let is_digits = |group: &str| !group.is_empty() && group.chars().all(|c| c.is_ascii_digit());
+
+    let mut groups = digits.split('.');
+    let first = groups.next().unwrap_or_default();
+    let first_ok = first.len() <= 3 && is_digits(first) && !first.starts_with('0');
+    first_ok && groups.all(|group| group.len() == 3 && is_digits(group))
+}
+
+/// Fecha como la escriben los clientes
+///
+/// Sin año ("15/03", "15 de marzo") se toma la próxima vez que ocurre esa
+/// fecha: los flows preguntan por entregas y citas, no por fechas pasadas.
+pub fn parse_date(input: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let text = fold_accents(&input.to_lowercase());
+    let text = text.trim().trim_end_matches('.');
+
+    match text {
+        "hoy" => return Some(today),
+        "manana" => return Some(today + Duration::days(1)),
+        "pasado manana" => return Some(today + Duration::days(2)),
+        _ => {}
+    }
+
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty() && *w != "el" && *w != "de" && *w != "del")
+        .collect();
+
+    // "viernes", "el próximo lunes"
+    if let [.., last] = words.as_slice() {
+        if let Some(weekday) = parse_weekday(last) {
+            if words.iter().all(|w| *w == *last || *w == "proximo" || *w == "este") {
+                let days_ahead = (7 + weekday.num_days_from_monday() as i64
+                    - today.weekday().num_days_from_monday() as i64) % 7;
+                let days_ahead = if days_ahead == 0 { 7 } else { days_ahead };
+                return Some(today + Duration::days(days_ahead));
+            }
+        }
+    }
+
+    // "15 de marzo", "15 marzo 2026", "lunes 15 de marzo"
+    let words: Vec<&str> = words.into_iter().filter(|w| parse_weekday(w).is_none()).collect();
+    if let [day, month, rest @ ..] = words.as_slice() {
+        if let (Ok(day), Some(month)) = (day.parse::<u32>(), parse_month(month)) {
+            return match rest {
+                [] => next_occurrence(day, month, today),
+                [year] => NaiveDate::from_ymd_opt(expand_year(year.parse().ok()?), month, day),
+                _ => None,
+            };
+        }
+    }
+
+    // ISO: 2026-03-15
+    if text.split('-').next().is_some_and(|year| year.len() == 4) {
+        return NaiveDate::parse_from_str(text, "%Y-%m-%d").ok();
+    }
+
+    // Numérico día/mes[/año] con '/', '-' o '.'
+    let parts: Vec<&str> = text.split(['/', '-', '.']).collect();
+    let numbers: Vec<u32> = parts.iter().map(|p| p.parse::<u32>()).collect::<Result<_, _>>().ok()?;
+    match numbers.as_slice() {
+        [day, month] => next_occurrence(*day, *month, today),
+        [day, month, year] => NaiveDate::from_ymd_opt(expand_year(*year as i32), *month, *day),
+        _ => None,
+    }
+}
+
+fn next_occurrence(day: u32, month: u32, today: NaiveDate) -> Option<NaiveDate> {
+    let this_year = NaiveDate::from_ymd_opt(today.year(), month, day);
+    match this_year {
+        Some(date) if date >= today => Some(date),
+        // 29 de febrero: buscar el próximo año bisiesto no vale la pena
+        _ => NaiveDate::from_ymd_opt(today.year() + 1, month, day).or(this_year),
+    }
+}
+
+fn expand_year(year: i32) -> i32 {
+    if year < 100 { 2000 + year } else { year }
+}
+
+fn parse_month(word: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "enero", "febrero", "marzo", "abril", "mayo", "junio",
+        "julio", "agosto", "septiembre", "octubre", "noviembre", "diciembre",
+    ];
+
+    if word == "setiembre" {
+        return Some(9);
+    }
+    MONTHS.iter()
+        .position(|month| *month == word || (word.len() >= 3 && month.starts_with(word)))
+        .map(|i| i as u32 + 1)
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    match word {
+        "lunes" => Some(Weekday::Mon),
+        "martes" => Some(Weekday::Tue),
+        "miercoles" => Some(Weekday::Wed),
+        "jueves" => Some(Weekday::Thu),
+        "viernes" => Some(Weekday::Fri),
+        "sabado" => Some(Weekday::Sat),
+        "domingo" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Quitar tildes (á → a, ñ → n) para comparar texto escrito por clientes
+pub fn fold_accents(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'á' | 'à' | 'ä' | 'â' | 'ã' => 'a',
+            'é' | 'è' | 'ë' | 'ê' => 'e',
+            'í' | 'ì' | 'ï' | 'î' => 'i',
+            'ó' | 'ò' | 'ö' | 'ô' | 'õ' => 'o',
+            'ú' | 'ù' | 'ü' | 'û' => 'u',
+            'ñ' => 'n',
+            'ç' => 'c',
+            'Á' | 'À' | 'Ä' | 'Â' | 'Ã' => 'A',
+            'É' | 'È' | 'Ë' | 'Ê' => 'E',
+            'Í' | 'Ì' | 'Ï' | 'Î' => 'I',
+            'Ó' | 'Ò' | 'Ö' | 'Ô' | 'Õ' => 'O',
+            'Ú' | 'Ù' | 'Ü' | 'Û' => 'U',
+            'Ñ' => 'N',
+            'Ç' => 'C',
+            other => other,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        // Miércoles
+        NaiveDate::from_ymd_opt(2026, 10, 14).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    #[test]
+    fn test_normalize_phone() {
+        assert_eq!(normalize_phone("0414-123.45.67").as_deref(), Some("+584141234567"));
+        assert_eq!(normalize_phone("414 1234567").as_deref(), Some("+584141234567"));
+        assert_eq!(normalize_phone("584141234567").as_deref(), Some("+584141234567"));
+        assert_eq!(normalize_phone("+1 (555) 123-4567").as_deref(), Some("+15551234567"));
+        assert_eq!(normalize_phone("0057 300 1234567").as_deref(), Some("+573001234567"));
+        assert_eq!(normalize_phone("12345"), None);
+        assert_eq!(normalize_phone("llámame"), None);
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email("Ana.Perez@Gmail.com").as_deref(), Some("ana.perez@gmail.com"));
+        assert_eq!(normalize_email("ana@gmail"), None);
+        assert_eq!(normalize_email("ana@@gmail.com"), None);
+        assert_eq!(normalize_email("ana perez@gmail.com"), None);
+        assert_eq!(normalize_email("@gmail.com"), None);
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("12,5"), Some(12.5));
+        assert_eq!(parse_number("1.234,56"), Some(1234.56));
+        assert_eq!(parse_number("1,234.56"), Some(1234.56));
+        assert_eq!(parse_number("45"), Some(45.0));
+        assert_eq!(parse_number("cuarenta"), None);
+
+        // Punto de miles (es-VE)
+        assert_eq!(parse_number("1.500"), Some(1500.0));
+        assert_eq!(parse_number("12.000.000"), Some(12_000_000.0));
+        assert_eq!(parse_number("1.500,50"), Some(1500.5));
+        assert_eq!(parse_number("1,5"), Some(1.5));
+        assert_eq!(parse_number("1.5"), Some(1.5));
+        assert_eq!(parse_number("0.500"), Some(0.5));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("hoy", today()), date(2026, 10, 14));
+        assert_eq!(parse_date("Mañana", today()), date(2026, 10, 15));
+        assert_eq!(parse_date("pasado mañana", today()), date(2026, 10, 16));
+        assert_eq!(parse_date("15/03", today()), date(2027, 3, 15));
+        assert_eq!(parse_date("20/10", today()), date(2026, 10, 20));
+        assert_eq!(parse_date("15/03/2026", today()), date(2026, 3, 15));
+        assert_eq!(parse_date("15-03-26", today()), date(2026, 3, 15));
+        assert_eq!(parse_date("2026-12-24", today()), date(2026, 12, 24));
+        assert_eq!(parse_date("15 de marzo", today()), date(2027, 3, 15));
+        assert_eq!(parse_date("24 de diciembre de 2026", today()), date(2026, 12, 24));
+        assert_eq!(parse_date("viernes", today()), date(2026, 10, 16));
+        assert_eq!(parse_date("el próximo miércoles", today()), date(2026, 10, 21));
+        assert_eq!(parse_date("31/02", today()), None);
+        assert_eq!(parse_date("cuando puedas", today()), None);
+    }
+
+    #[test]
+    fn test_normalize_input_regex() {
+        let regex = Regex::new(r"^[A-Z]{3}-\d{3}$").unwrap();
+        let validation = ValidationType::Regex(regex.as_str().to_string());
+
+        assert_eq!(
+            normalize_input(" ABC-123 ", &validation, Some(&regex), today()),
+            Some(Value::String("ABC-123".to_string()))
+        );
+        assert_eq!(normalize_input("abc-123", &validation, Some(&regex), today()), None);
+    }
+
+    #[test]
+    fn test_normalize_content_by_kind() {
+        let location = MessageContent::Location(crate::message::Location {
+            latitude: 10.4806,
+            longitude: -66.9036,
+            name: None,
+            address: None,
+        });
+
+        let value = normalize_content(&location, &ValidationType::Location, None, today()).unwrap();
+        assert_eq!(value["latitude"], serde_json::json!(10.4806));
+        assert_eq!(normalize_content(&location, &ValidationType::Image, None, today()), None);
+        assert_eq!(normalize_content(&location, &ValidationType::Text, None, today()), None);
+
+        // Un botón responde a validaciones de texto con su id
+        let reply = MessageContent::ButtonReply { id: "45".to_string(), title: None };
+        assert_eq!(
+            normalize_content(&reply, &ValidationType::Number, None, today()),
+            Some(serde_json::json!(45.0))
+        );
+    }
+}
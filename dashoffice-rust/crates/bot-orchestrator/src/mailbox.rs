@@ -0,0 +1,206 @@
+//! Mailbox - Procesamiento ordenado de mensajes por conversación
+//!
+//! Cada conversación tiene un buzón (canal) y un worker que procesa sus
+//! mensajes de uno en uno, en orden de llegada. Conversaciones distintas
+//! se procesan en paralelo. El worker termina cuando su buzón queda vacío
+//! durante `idle_timeout` y se vuelve a crear con el siguiente mensaje.
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Tiempo que un worker espera mensajes nuevos antes de terminar
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Mailbox<M> {
+    sender: mpsc::UnboundedSender<M>,
+    /// Mensajes encolados o en proceso
+    pending: Arc<AtomicUsize>,
+}
+
+impl<M> Mailbox<M> {
+    /// Buzón nuevo con un primer mensaje ya encolado
+    fn with_message(message: M) -> (Self, mpsc::UnboundedReceiver<M>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let _ = sender.send(message);
+        let mailbox = Self {
+            sender,
+            pending: Arc::new(AtomicUsize::new(1)),
+        };
+        (mailbox, receiver)
+    }
+}
+
+/// Buzones por clave (normalmente el id de la conversación)
+pub struct Mailboxes<M> {
+    boxes: Arc<DashMap<String, Mailbox<M>>>,
+    idle_timeout: Duration,
+}
+
+impl<M: Send + 'static> Mailboxes<M> {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            boxes: Arc::new(DashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    /// Encolar un mensaje en el buzón de `key`
+    ///
+    /// Si el buzón no tiene worker se crea uno que procesará con `handler`
+    /// este mensaje y los siguientes que lleguen mientras siga vivo.
+    pub fn dispatch<H, Fut>(&self, key: &str, message: M, handler: H)
+    where
+        H: Fn(M) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // El lock de la entrada se mantiene mientras se encola: así el worker
+        // no puede cerrar el buzón entre la comprobación y el envío
+        let (receiver, pending) = match self.boxes.entry(key.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let mailbox = entry.get();
+                mailbox.pending.fetch_add(1, Ordering::SeqCst);
+                match mailbox.sender.send(message) {
+                    Ok(()) => return,
+                    Err(mpsc::error::SendError(message)) => {
+                        // El worker murió (panic en el handler): reemplazarlo
+                        tracing::warn!("Mailbox worker for {} died, restarting", key);
+                        let (mailbox, receiver) = Mailbox::with_message(message);
+                        let pending = mailbox.pending.clone();
+                        entry.insert(mailbox);
+                        (receiver, pending)
+                    }
+                }
+            }
+            Entry::Vacant(entry) => {
+                let (mailbox, receiver) = Mailbox::with_message(message);
+                let pending = mailbox.pending.clone();
+                entry.insert(mailbox);
+                (receiver, pending)
+            }
+        };
+
+        tokio::spawn(run_worker(
+            key.to_string(),
+            receiver,
+            pending,
+            self.boxes.clone(),
+            self.idle_timeout,
+            handler,
+        ));
+    }
+
+    /// Buzones con worker activo
+    pub fn active(&self) -> usize {
+        self.boxes.len()
+    }
+}
+
+async fn run_worker<M, H, Fut>(
+    key: String,
+    mut receiver: mpsc::UnboundedReceiver<M>,
+    pending: Arc<AtomicUsize>,
+    boxes: Arc<DashMap<String, Mailbox<M>>>,
+    idle_timeout: Duration,
+    handler: H,
+) where
+    H: Fn(M) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        match tokio::time::timeout(idle_timeout, receiver.recv()).await {
+            Ok(Some(message)) => {
+                handler(message).await;
+                pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            Ok(None) => return,
+            Err(_) => {
+                // Solo se cierra si nadie encoló nada mientras tanto
+                let removed = boxes.remove_if(&key, |_, mailbox| {
+                    Arc::ptr_eq(&mailbox.pending, &pending) && pending.load(Ordering::SeqCst) == 0
+                });
+                if removed.is_some() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_burst_is_processed_in_order_per_conversation() {
+        let mailboxes = Mailboxes::new(Duration::from_millis(50));
+        let seen: Arc<Mutex<HashMap<String, Vec<usize>>>> = Arc::default();
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        for i in 0..200 {
+            let key = format!("conv-{}", i % 4);
+            let (seen, running, max_running) = (seen.clone(), running.clone(), max_running.clone());
+            mailboxes.dispatch(&key, (key.clone(), i), move |(key, i): (String, usize)| {
+                let (seen, running, max_running) = (seen.clone(), running.clone(), max_running.clone());
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    // Mensajes que tardan distinto en procesarse
+                    tokio::time::sleep(Duration::from_millis((i % 3) as u64)).await;
+                    seen.lock().entry(key).or_default().push(i);
+                    running.fetch_sub(1, Ordering::SeqCst);
+                }
+            });
+        }
+
+        for _ in 0..100 {
+            if seen.lock().values().map(Vec::len).sum::<usize>() == 200 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let seen = seen.lock();
+        assert_eq!(seen.len(), 4);
+        for (key, order) in seen.iter() {
+            let offset: usize = key.trim_start_matches("conv-").parse().unwrap();
+            let expected: Vec<usize> = (0..50).map(|n| n * 4 + offset).collect();
+            assert_eq!(order, &expected, "out of order for {}", key);
+        }
+        // Conversaciones distintas sí corren en paralelo
+        assert!(max_running.load(Ordering::SeqCst) > 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_worker_stops_and_restarts() {
+        let mailboxes = Mailboxes::new(Duration::from_millis(20));
+        let processed = Arc::new(AtomicUsize::new(0));
+
+        let handler = {
+            let processed = processed.clone();
+            move |_: ()| {
+                let processed = processed.clone();
+                async move {
+                    processed.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        };
+
+        mailboxes.dispatch("conv", (), handler.clone());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(mailboxes.active(), 0);
+
+        for _ in 0..10 {
+            mailboxes.dispatch("conv", (), handler.clone());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(processed.load(Ordering::SeqCst), 11);
+    }
+}
@@ -1,31 +1,67 @@
-//\! # Bot Orchestrator
-//\! 
-//\! Orquestador de múltiples bots con:
-//\! - Multi-tenant support (100+ bots simultáneos)
-//\! - Flow engine conversacional
-//\! - State machine (Redis)
-//\! - Webhook handling
-//\! - Context management
-//\! - Event sourcing
-//\! - Analytics en tiempo real
+//! # Bot Orchestrator
+//! 
+//! Orquestador de múltiples bots con:
+//! - Multi-tenant support (100+ bots simultáneos)
+//! - Flow engine conversacional
+//! - State machine (Redis)
+//! - Webhook handling
+//! - Context management
+//! - Event sourcing
+//! - Analytics en tiempo real
 
 use actix_web::{web, App, HttpServer, HttpResponse, Responder};
+use anyhow::Context;
 use dashmap::DashMap;
-use parking_lot::RwLock;
 use redis::Client as RedisClient;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::oneshot;
 use tracing::{info, error, warn};
 use uuid::Uuid;
 
 mod flow_engine;
 mod state_machine;
 mod webhook;
-mod conversation;
 mod analytics;
+mod expression;
+mod flow_validator;
+mod flow_loader;
+mod flow_store;
+mod actions;
+mod input_validation;
+mod template;
+mod conversation_store;
+mod mailbox;
+mod whatsapp;
+mod dedup;
+mod message;
+mod menu;
+mod clock;
+mod business_hours;
+mod handoff;
+mod scheduler;
+mod event_log;
+mod event_bus;
+mod bots;
+mod metrics;
+mod expiry;
 
-use flow_engine::FlowEngine;
+use business_hours::{BusinessHours, ScheduleStatus};
+use clock::{Clock, SystemClock};
+use flow_engine::{Flow, FlowEngine};
+use handoff::{AgentDirectory, HandoffCommand, HandoffError};
+use conversation_store::ConversationStore;
+use flow_store::FlowStore;
+use bots::{BotLimits, BotManager, BotStatus, PostgresBotStore};
+use event_bus::EventBus;
+use metrics::{BotMetrics, Metric};
+use event_log::EventLog;
+use expiry::{ConversationArchive, ExpiryScheduler, ExpirySettings, MongoArchive};
+use dedup::MessageDeduplicator;
+use message::{MessageContent, QuotedMessage};
+use mailbox::Mailboxes;
+use scheduler::TimerScheduler;
+use whatsapp::{SendRequest, WhatsAppClient, WhatsAppConfig};
 use state_machine::ConversationState;
 
 /// Estado global del orchestrator
@@ -34,17 +70,56 @@ pub struct OrchestratorState {
     /// Bots activos (bot_id -> BotInstance)
     pub bots: Arc<DashMap<Uuid, BotInstance>>,
     
+    /// Alta, cambios, pausa y baja de bots (Postgres + `bots`)
+    pub bot_manager: Arc<BotManager>,
+    
     /// Conversaciones activas (conversation_id -> ConversationState)
     pub conversations: Arc<DashMap<String, ConversationState>>,
     
     /// Flow engine
     pub flow_engine: Arc<FlowEngine>,
     
+    /// Versiones de los flows en Redis, para las conversaciones restauradas tras un reinicio
+    pub flow_store: Arc<FlowStore>,
+    
     /// Redis para persistencia
     pub redis: Arc<RedisClient>,
     
+    /// Conversaciones persistidas en Redis (sobreviven a reinicios)
+    pub conversation_store: Arc<ConversationStore>,
+    
+    /// Log de eventos de cada conversación (auditoría y replay)
+    pub event_log: Arc<EventLog>,
+    
+    /// Buzones por conversación: los mensajes de un mismo cliente se procesan en orden
+    pub mailboxes: Arc<Mailboxes<ConversationCommand>>,
+    
+    /// Envío de respuestas a través de whatsapp-adapter
+    pub whatsapp: Arc<WhatsAppClient>,
+    
+    /// Mensajes ya recibidos, para ignorar los reintentos de los webhooks
+    pub dedup: Arc<MessageDeduplicator>,
+    
+    /// Timers de los flows (`Wait`, `on_timeout`) en Redis
+    pub timers: Arc<TimerScheduler>,
+    
+    /// Próximo control de vencimiento de cada conversación en Redis
+    pub expiry: Arc<ExpiryScheduler>,
+    
+    /// Conversaciones terminadas (MongoDB)
+    pub archive: Arc<dyn ConversationArchive>,
+    
+    /// Asesores disponibles para los handoffs
+    pub agents: Arc<dyn AgentDirectory>,
+    
+    /// Hora actual (horario de atención); un reloj fijo en los tests
+    pub clock: Arc<dyn Clock>,
+    
     /// Event bus para analytics
-    pub event_bus: broadcast::Sender<BotEvent>,
+    pub event_bus: EventBus,
+    
+    /// Contadores por ventana, latencia y métricas de Prometheus de cada bot
+    pub metrics: Arc<BotMetrics>,
 }
 
 /// Instancia de un bot
@@ -58,10 +133,25 @@ pub struct BotInstance {
     pub provider_config: serde_json::Value,
     pub flows: FlowConfig,
     pub settings: BotSettings,
+    /// Un bot pausado no atiende mensajes nuevos
+    #[serde(default)]
+    pub status: BotStatus,
     pub stats: BotStats,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl BotInstance {
+    /// Variables `bot.*` disponibles en los textos de los flows
+    pub fn template_variables(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "phone_number": self.phone_number,
+            "timezone": self.settings.timezone,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FlowConfig {
     pub welcome_flow_id: Option<Uuid>,
     pub menu_flow_id: Option<Uuid>,
@@ -69,22 +159,73 @@ pub struct FlowConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BotSettings {
     pub business_hours_enabled: bool,
     pub timezone: String,
     pub auto_reply_delay_ms: u64,
     pub max_conversation_timeout_seconds: u64,
+    /// Horario semanal, feriados y respuesta fuera de horario (si `business_hours_enabled`)
+    #[serde(default)]
+    pub business_hours: BusinessHours,
+    /// Aviso antes de que venza una conversación y hooks al terminar (ver `expiry`)
+    #[serde(default)]
+    pub expiry: ExpirySettings,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            business_hours_enabled: false,
+            timezone: "UTC".to_string(),
+            auto_reply_delay_ms: 500,
+            max_conversation_timeout_seconds: 3600,
+            business_hours: BusinessHours::default(),
+            expiry: ExpirySettings::default(),
+        }
+    }
+}
+
+impl BotSettings {
+    /// Zona horaria del bot (la de por defecto si no es válida)
+    pub fn timezone(&self) -> chrono_tz::Tz {
+        template::parse_timezone(&self.timezone).unwrap_or(template::DEFAULT_TIMEZONE)
+    }
+
+    /// Estado del horario de atención; `None` si el bot atiende a toda hora
+    pub fn schedule_status(&self, clock: &dyn Clock) -> Option<ScheduleStatus> {
+        self.business_hours_enabled
+            .then(|| self.business_hours.status(self.timezone(), clock))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BotStats {
     pub messages_sent: u64,
     pub messages_received: u64,
+    #[serde(default)]
+    pub messages_failed: u64,
+    /// Conversaciones en memoria; se calcula al consultarlo
     pub conversations_active: u64,
     pub conversations_total: u64,
     pub last_message_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+impl BotStats {
+    fn count(&mut self, metric: Metric) {
+        match metric {
+            Metric::MessageReceived => {
+                self.messages_received += 1;
+                self.last_message_at = Some(chrono::Utc::now());
+            }
+            Metric::MessageSent => self.messages_sent += 1,
+            Metric::MessageFailed => self.messages_failed += 1,
+            Metric::ConversationStarted => self.conversations_total += 1,
+            Metric::Flow(_) => {}
+        }
+    }
+}
+
 /// Eventos del sistema
 #[derive(Debug, Clone, Serialize)]
 pub enum BotEvent {
@@ -100,6 +241,22 @@ pub enum BotEvent {
         message: String,
         timestamp: chrono::DateTime<chrono::Utc>,
     },
+    MessageFailed {
+        bot_id: Uuid,
+        to: String,
+        message: String,
+        error: String,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    /// Estado de entrega reportado por el provider (sent, delivered, read, failed)
+    MessageStatus {
+        bot_id: Uuid,
+        provider_message_id: String,
+        recipient: String,
+        status: String,
+        error: Option<String>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
     ConversationStarted {
         conversation_id: String,
         bot_id: Uuid,
@@ -114,6 +271,38 @@ pub enum BotEvent {
         from_step: String,
         to_step: String,
     },
+    /// La conversación pasó a un asesor humano (en cola si fue fuera de horario)
+    HandoffStarted {
+        conversation_id: String,
+        bot_id: Uuid,
+        user_phone: String,
+        queued_until: Option<chrono::DateTime<chrono::Utc>>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    HandoffAssigned {
+        conversation_id: String,
+        bot_id: Uuid,
+        agent_id: String,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    /// Mensaje del cliente reenviado al asesor
+    HandoffMessage {
+        conversation_id: String,
+        bot_id: Uuid,
+        agent_id: Option<String>,
+        from: String,
+        message: MessageContent,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
+    /// La conversación volvió al bot (`released`, `cancelled_by_customer`)
+    HandoffEnded {
+        conversation_id: String,
+        bot_id: Uuid,
+        agent_id: Option<String>,
+        reason: String,
+        duration_seconds: i64,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    },
 }
 
 /// Mensaje entrante desde WhatsApp
@@ -121,17 +310,47 @@ pub enum BotEvent {
 pub struct IncomingMessage {
     pub bot_id: Uuid,
     pub from: String,
-    pub message: String,
-    pub message_type: String,
+    pub content: MessageContent,
+    /// Mensaje al que responde el cliente
+    #[serde(default)]
+    pub quoted: Option<QuotedMessage>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Id del mensaje en el provider (para descartar reintentos)
+    #[serde(default)]
+    pub provider_message_id: Option<String>,
 }
 
 /// Respuesta a enviar
 #[derive(Debug, Serialize)]
 pub struct OutgoingMessage {
     pub to: String,
-    pub message: String,
-    pub message_type: String,
+    pub content: MessageContent,
+}
+
+/// Trabajo que se procesa en el buzón de una conversación
+pub enum ConversationCommand {
+    /// Mensaje del cliente
+    Incoming(IncomingMessage),
+    /// Operación de un asesor; el resultado vuelve por `reply`
+    Handoff {
+        conversation_id: String,
+        command: HandoffCommand,
+        reply: oneshot::Sender<Result<(), HandoffError>>,
+    },
+    /// Timer vencido (ver `scheduler`)
+    Timer {
+        conversation_id: String,
+        timer_id: Uuid,
+    },
+    /// Control de vencimiento de la conversación (ver `expiry`)
+    Expire {
+        conversation_id: String,
+    },
+    /// Se borró el bot de la conversación (ver `expiry::end_bot_conversations`)
+    BotDeleted {
+        conversation_id: String,
+        bot: Arc<BotInstance>,
+    },
 }
 
 #[actix_web::main]
@@ -143,7 +362,7 @@ async fn main() -> std::io::Result<()> {
         )
         .init();
 
-    info\!("🤖 Starting Bot Orchestrator");
+    info!("🤖 Starting Bot Orchestrator");
 
     // Load config
     dotenvy::dotenv().ok();
@@ -155,50 +374,145 @@ async fn main() -> std::io::Result<()> {
     let redis = RedisClient::open(redis_url)
         .expect("Failed to connect to Redis");
     
-    info\!("✅ Redis connected");
+    info!("✅ Redis connected");
+
+    let bots = Arc::new(DashMap::new());
 
-    // Event bus para analytics
-    let (event_tx, _event_rx) = broadcast::channel(1000);
+    // Event bus; analytics no puede perder eventos
+    let mut event_bus = EventBus::new(1000);
+    let analytics_events = event_bus.subscribe_durable(
+        analytics::CHANNEL_CAPACITY,
+        analytics::spill_overflow(bots.clone(), analytics::SpillFile::from_env()),
+    );
+
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
 
     // Flow engine
-    let flow_engine = Arc::new(FlowEngine::new());
+    let flow_engine = Arc::new(FlowEngine::new().with_clock(clock.clone()));
+
+    // Flows desde archivos (YAML/JSON), con recarga automática
+    let flows_dir = std::path::PathBuf::from(
+        std::env::var("FLOWS_DIR").unwrap_or_else(|_| "./flows".to_string())
+    );
+    let report = flow_loader::load_flow_dir(&flow_engine, &flows_dir);
+    info!("✅ Loaded {} flows ({} failed) from {}", report.loaded, report.failed, flows_dir.display());
+
+    let flow_store = Arc::new(FlowStore::from_env(redis.clone()));
+    if let Err(e) = flow_store.save_all(&flow_engine).await {
+        warn!("Could not persist flow versions: {:#}", e);
+    }
+
+    let reload_interval = std::env::var("FLOWS_RELOAD_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(5);
+    flow_loader::spawn_flow_watcher(
+        flow_engine.clone(),
+        flow_store.clone(),
+        flows_dir,
+        std::time::Duration::from_secs(reload_interval),
+    );
+
+    let dedup_ttl = std::env::var("WEBHOOK_DEDUP_TTL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(std::time::Duration::from_secs)
+        .unwrap_or(dedup::DEFAULT_TTL);
+
+    let metrics = Arc::new(BotMetrics::new(clock.clone()));
+
+    // Bots: Postgres (`DATABASE_URL`) y sesiones en whatsapp-adapter
+    let whatsapp = Arc::new(WhatsAppClient::new(WhatsAppConfig::from_env()));
+    let bot_manager = Arc::new(BotManager::new(
+        bots.clone(),
+        PostgresBotStore::from_env(),
+        whatsapp.clone(),
+        BotLimits::from_env(),
+    ));
 
     // Estado global
     let state = OrchestratorState {
-        bots: Arc::new(DashMap::new()),
+        bots,
+        bot_manager,
         conversations: Arc::new(DashMap::new()),
         flow_engine,
+        flow_store,
+        conversation_store: Arc::new(ConversationStore::new(redis.clone())),
+        event_log: Arc::new(EventLog::from_env(redis.clone())),
+        timers: Arc::new(TimerScheduler::new(redis.clone())),
+        expiry: Arc::new(ExpiryScheduler::new(redis.clone())),
+        archive: MongoArchive::from_env().await,
+        mailboxes: Arc::new(Mailboxes::new(mailbox::DEFAULT_IDLE_TIMEOUT)),
+        whatsapp,
+        dedup: Arc::new(MessageDeduplicator::new(
+            Some(redis.clone()),
+            dedup_ttl,
+            dedup::DEFAULT_LOCAL_CAPACITY,
+        )),
         redis: Arc::new(redis),
-        event_bus: event_tx.clone(),
+        agents: handoff::SellersApi::from_env(),
+        clock,
+        event_bus,
+        metrics,
     };
 
     // Cargar bots desde base de datos
-    load_bots_from_db(&state).await;
+    info!("📚 Loading bots from database...");
+    match state.bot_manager.load().await {
+        Ok(loaded) => info!("✅ Loaded {} bots", loaded),
+        Err(e) => error!("❌ Could not load bots: {:#}", e),
+    }
 
-    // Analytics worker
-    spawn_analytics_worker(event_tx.subscribe());
+    // Analytics worker (colección `analytics_events` de MongoDB)
+    analytics::spawn_analytics_worker(state.bots.clone(), analytics_events);
 
-    // Cleanup worker (limpiar conversaciones inactivas)
-    spawn_cleanup_worker(state.clone());
+    // Fin de las conversaciones inactivas (aviso, archivo y hooks)
+    expiry::spawn_expiry_worker(state.clone());
+
+    // Timers de los flows
+    scheduler::spawn_timer_worker(state.clone());
+
+    // Handoffs: asignar asesores y publicar los mensajes para su bandeja
+    handoff::spawn_assign_worker(state.clone());
+    if let Ok(url) = std::env::var("HANDOFF_WEBHOOK_URL") {
+        handoff::spawn_forwarder(url, state.event_bus.subscribe());
+    }
 
     let port = std::env::var("BOT_PORT")
         .unwrap_or_else(|_| "3011".to_string())
         .parse::<u16>()
         .expect("Invalid BOT_PORT");
 
-    info\!("🚀 Starting server on port {}", port);
+    info!("🚀 Starting server on port {}", port);
+
+    let meta_config = webhook::MetaConfig::from_env();
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(state.clone()))
+            .app_data(web::Data::new(meta_config.clone()))
             .route("/health", web::get().to(health_check))
+            .route("/metrics", web::get().to(prometheus_metrics))
             .route("/webhook/venom", web::post().to(webhook::handle_venom_webhook))
             .route("/webhook/wwebjs", web::post().to(webhook::handle_wwebjs_webhook))
             .route("/webhook/baileys", web::post().to(webhook::handle_baileys_webhook))
+            .route("/webhook/meta", web::get().to(webhook::verify_meta_webhook))
+            .route("/webhook/meta", web::post().to(webhook::handle_meta_webhook))
             .route("/bots", web::get().to(list_bots))
+            .route("/bots", web::post().to(bots::handle_create))
             .route("/bots/{bot_id}", web::get().to(get_bot))
+            .route("/bots/{bot_id}", web::put().to(bots::handle_update))
+            .route("/bots/{bot_id}", web::delete().to(bots::handle_delete))
+            .route("/bots/{bot_id}/pause", web::post().to(bots::handle_pause))
+            .route("/bots/{bot_id}/resume", web::post().to(bots::handle_resume))
             .route("/bots/{bot_id}/stats", web::get().to(get_bot_stats))
             .route("/conversations/{conversation_id}", web::get().to(get_conversation))
+            .route("/conversations/{conversation_id}/events", web::get().to(event_log::handle_events))
+            .route("/conversations/{conversation_id}/replay", web::get().to(event_log::handle_replay))
+            .route("/conversations/{conversation_id}/handoff", web::post().to(handoff::handle_assign))
+            .route("/conversations/{conversation_id}/handoff/reply", web::post().to(handoff::handle_reply))
+            .route("/conversations/{conversation_id}/handoff/release", web::post().to(handoff::handle_release))
+            .route("/flows/validate", web::post().to(validate_flow))
             .route("/message", web::post().to(handle_incoming_message))
     })
     .bind(("0.0.0.0", port))?
@@ -211,12 +525,13 @@ async fn health_check(state: web::Data<OrchestratorState>) -> impl Responder {
     let active_bots = state.bots.len();
     let active_conversations = state.conversations.len();
 
-    HttpResponse::Ok().json(serde_json::json\!({
+    HttpResponse::Ok().json(serde_json::json!({
         "status": "ok",
         "service": "bot-orchestrator",
         "active_bots": active_bots,
         "active_conversations": active_conversations,
-        "uptime": "TODO",
+        "started_at": state.metrics.started_at(),
+        "uptime_seconds": state.metrics.uptime_seconds(),
         "memory_mb": get_memory_usage(),
     }))
 }
@@ -226,7 +541,7 @@ async fn list_bots(state: web::Data<OrchestratorState>) -> impl Responder {
         .map(|entry| entry.value().clone())
         .collect();
 
-    HttpResponse::Ok().json(serde_json::json\!({
+    HttpResponse::Ok().json(serde_json::json!({
         "total": bots.len(),
         "bots": bots
     }))
@@ -240,24 +555,54 @@ async fn get_bot(
 
     match state.bots.get(&bot_id) {
         Some(bot) => HttpResponse::Ok().json(bot.clone()),
-        None => HttpResponse::NotFound().json(serde_json::json\!({
+        None => HttpResponse::NotFound().json(serde_json::json!({
             "error": "Bot not found"
         }))
     }
 }
 
+/// Totales del bot desde el arranque y ventanas de 1m/1h/24h
 async fn get_bot_stats(
     state: web::Data<OrchestratorState>,
     path: web::Path<Uuid>,
 ) -> impl Responder {
     let bot_id = path.into_inner();
 
-    match state.bots.get(&bot_id) {
-        Some(bot) => HttpResponse::Ok().json(bot.stats.clone()),
-        None => HttpResponse::NotFound().json(serde_json::json\!({
+    let Some(mut totals) = state.bots.get(&bot_id).map(|bot| bot.stats.clone()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
             "error": "Bot not found"
-        }))
+        }));
+    };
+    totals.conversations_active = active_conversations(&state)
+        .get(&bot_id)
+        .copied()
+        .unwrap_or_default() as u64;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "bot_id": bot_id,
+        "totals": totals,
+        "windows": state.metrics.windows(bot_id),
+        "uptime_seconds": state.metrics.uptime_seconds(),
+    }))
+}
+
+/// Métricas de todos los bots en formato Prometheus
+async fn prometheus_metrics(state: web::Data<OrchestratorState>) -> impl Responder {
+    let active = active_conversations(&state);
+    let active = state.bots.iter().map(|bot| (bot.id, active.get(&bot.id).copied().unwrap_or_default()));
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render(active.collect::<Vec<_>>()))
+}
+
+/// Conversaciones en memoria de cada bot
+fn active_conversations(state: &OrchestratorState) -> std::collections::HashMap<Uuid, usize> {
+    let mut active = std::collections::HashMap::new();
+    for conversation in state.conversations.iter() {
+        *active.entry(conversation.bot_id).or_default() += 1;
     }
+    active
 }
 
 async fn get_conversation(
@@ -266,201 +611,455 @@ async fn get_conversation(
 ) -> impl Responder {
     let conversation_id = path.into_inner();
 
-    match state.conversations.get(&conversation_id) {
-        Some(conv) => HttpResponse::Ok().json(conv.clone()),
-        None => HttpResponse::NotFound().json(serde_json::json\!({
+    match find_conversation(&state, &conversation_id).await {
+        Ok(Some(conv)) => HttpResponse::Ok().json(conv),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
             "error": "Conversation not found"
-        }))
+        })),
+        Err(e) => {
+            error!("Error loading conversation {}: {:#}", conversation_id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load conversation"
+            }))
+        }
     }
 }
 
+/// Validar un flow sin registrarlo (para el editor de flows del dashboard)
+async fn validate_flow(flow: web::Json<Flow>) -> impl Responder {
+    let diagnostics = flow_validator::validate_flow(&flow);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "valid": diagnostics.is_empty(),
+        "diagnostics": diagnostics
+    }))
+}
+
 async fn handle_incoming_message(
     state: web::Data<OrchestratorState>,
     msg: web::Json<IncomingMessage>,
 ) -> impl Responder {
-    info\!("📨 Incoming message from {} to bot {}", msg.from, msg.bot_id);
+    info!("📨 Incoming message from {} to bot {}", msg.from, msg.bot_id);
 
     // Verificar que el bot existe
-    if \!state.bots.contains_key(&msg.bot_id) {
-        return HttpResponse::NotFound().json(serde_json::json\!({
+    if !state.bots.contains_key(&msg.bot_id) {
+        return HttpResponse::NotFound().json(serde_json::json!({
             "error": "Bot not found"
         }));
     }
 
-    // Procesar mensaje en background
-    let state_clone = state.clone();
-    let msg_clone = msg.into_inner();
-    
-    tokio::spawn(async move {
-        if let Err(e) = process_message(state_clone.as_ref(), msg_clone).await {
-            error\!("Error processing message: {}", e);
-        }
-    });
+    // Procesar mensaje en background, en orden dentro de su conversación
+    dispatch_message(&state, msg.into_inner());
 
-    HttpResponse::Accepted().json(serde_json::json\!({
+    HttpResponse::Accepted().json(serde_json::json!({
         "status": "accepted",
         "message": "Processing"
     }))
 }
 
+/// Id de la conversación de un cliente con un bot
+fn conversation_id(bot_id: &Uuid, from: &str) -> String {
+    format!("{}:{}", bot_id, from)
+}
+
+/// ¿El bot está pausado? Un bot pausado no atiende mensajes ni le escribe al
+/// cliente por su cuenta (timers, asesor asignado, avisos de vencimiento)
+pub fn bot_paused(bots: &DashMap<Uuid, BotInstance>, bot_id: &Uuid) -> bool {
+    bots.get(bot_id).is_some_and(|bot| bot.status == BotStatus::Paused)
+}
+
+/// Encolar un mensaje en el buzón de su conversación
+///
+/// Los mensajes de una misma conversación se procesan uno a uno en orden de
+/// llegada; los de conversaciones distintas, en paralelo. Los de un bot
+/// pausado se descartan.
+pub fn dispatch_message(state: &OrchestratorState, msg: IncomingMessage) {
+    if bot_paused(&state.bots, &msg.bot_id) {
+        info!("⏸️ Bot {} is paused, message from {} ignored", msg.bot_id, msg.from);
+        return;
+    }
+
+    let key = conversation_id(&msg.bot_id, &msg.from);
+    dispatch_command(state, &key, ConversationCommand::Incoming(msg));
+}
+
+/// Encolar trabajo en el buzón de una conversación
+pub fn dispatch_command(state: &OrchestratorState, conversation_id: &str, command: ConversationCommand) {
+    let mailboxes = state.mailboxes.clone();
+    let state = state.clone();
+
+    mailboxes.dispatch(conversation_id, command, move |command| {
+        let state = state.clone();
+        async move {
+            match command {
+                ConversationCommand::Incoming(msg) => {
+                    if let Err(e) = process_message(&state, msg).await {
+                        error!("Error processing message: {:#}", e);
+                    }
+                }
+                ConversationCommand::Handoff { conversation_id, command, reply } => {
+                    let _ = reply.send(handoff::execute(&state, &conversation_id, command).await);
+                }
+                ConversationCommand::Timer { conversation_id, timer_id } => {
+                    if let Err(e) = process_timer(&state, &conversation_id, timer_id).await {
+                        error!("Error processing timer of {}: {:#}", conversation_id, e);
+                    }
+                }
+                ConversationCommand::Expire { conversation_id } => {
+                    if let Err(e) = expiry::process(&state, &conversation_id).await {
+                        error!("Error checking expiry of {}: {:#}", conversation_id, e);
+                    }
+                }
+                ConversationCommand::BotDeleted { conversation_id, bot } => {
+                    if let Err(e) = expiry::end_for_deleted_bot(&state, &conversation_id, &bot).await {
+                        error!("Error ending conversation {} of deleted bot: {:#}", conversation_id, e);
+                    }
+                }
+            }
+        }
+    });
+}
+
 async fn process_message(
     state: &OrchestratorState,
     msg: IncomingMessage,
 ) -> anyhow::Result<()> {
+    let started = std::time::Instant::now();
+
     // 1. Obtener o crear conversación
-    let conversation_id = format\!("{}:{}", msg.bot_id, msg.from);
-    
-    let mut conversation = state.conversations
-        .entry(conversation_id.clone())
-        .or_insert_with(|| {
-            info\!("🆕 New conversation: {}", conversation_id);
-            
-            // Emitir evento
-            let _ = state.event_bus.send(BotEvent::ConversationStarted {
-                conversation_id: conversation_id.clone(),
-                bot_id: msg.bot_id,
-                user_phone: msg.from.clone(),
-            });
-            
-            ConversationState::new(conversation_id.clone(), msg.bot_id, msg.from.clone())
-        });
+    let conversation_id = conversation_id(&msg.bot_id, &msg.from);
+
+    let (entry_points, mut bot_variables, conversation_ttl, schedule, after_hours) = state.bots.get(&msg.bot_id)
+        .map(|bot| (
+            bot.flows.clone(),
+            bot.template_variables(),
+            conversation_ttl(bot.value()),
+            bot.settings.schedule_status(state.clock.as_ref()),
+            bot.settings.business_hours.after_hours.clone(),
+        ))
+        .ok_or_else(|| anyhow::anyhow!("Bot not found: {}", msg.bot_id))?;
+
+    // Se trabaja sobre una copia: el buzón garantiza que nadie más procesa esta
+    // conversación, y así no se retiene el lock del DashMap durante los `.await`.
+    // `before` es el punto de partida de los eventos del log (`None`: nueva).
+    // Si no se pudo leer la guardada, el mensaje falla: empezar una nueva la
+    // sobrescribiría en Redis y el cliente perdería su progreso.
+    let cached = state.conversations.get(&conversation_id).map(|conv| conv.clone());
+    let before = match cached {
+        Some(conversation) => Some(conversation),
+        None => restore_conversation(state, &conversation_id).await?,
+    };
+    let mut conversation = match &before {
+        Some(conversation) => conversation.clone(),
+        None => start_conversation(state, &conversation_id, msg.bot_id, &msg.from),
+    };
+
+    // Fuera de horario las conversaciones nuevas empiezan por el flow de fuera de horario
+    let closed = schedule.as_ref().filter(|status| !status.open);
+    let entry_points = match closed.and(after_hours.flow_id) {
+        Some(flow_id) => FlowConfig { welcome_flow_id: Some(flow_id), ..entry_points },
+        None => entry_points,
+    };
+    if let Some(status) = &schedule {
+        bot_variables["business_hours"] = status.template_variables();
+    }
+
+    state.event_bus.send(BotEvent::MessageReceived {
+        bot_id: msg.bot_id,
+        from: msg.from.clone(),
+        message: msg.content.summary(),
+        timestamp: msg.timestamp,
+    });
 
     // 2. Actualizar contexto
-    conversation.add_message("user", &msg.message);
+    conversation.add_received_message(msg.content.clone(), msg.provider_message_id.clone(), msg.quoted.clone());
+    conversation.metadata.insert("bot".to_string(), bot_variables);
     conversation.update_last_activity();
 
-    // 3. Ejecutar flow engine
-    let response = state.flow_engine
-        .process(&mut conversation, &msg.message)
-        .await?;
-
-    // 4. Enviar respuesta
-    if let Some(response_text) = response {
-        send_message_to_whatsapp(state, &msg.bot_id, &msg.from, &response_text).await?;
-        
-        conversation.add_message("bot", &response_text);
-        
-        // Emitir evento
-        let _ = state.event_bus.send(BotEvent::MessageSent {
-            bot_id: msg.bot_id,
-            to: msg.from.clone(),
-            message: response_text,
-            timestamp: chrono::Utc::now(),
-        });
+    // 3. Respuesta automática fuera de horario, una sola vez por cada cierre
+    if let (Some(status), Some(template)) = (closed, &after_hours.message) {
+        let closed_until = serde_json::json!(status.next_open.map(|next| next.to_rfc3339()));
+        if conversation.metadata.get(AFTER_HOURS_NOTIFIED) != Some(&closed_until) {
+            let text = template::render_template(template, &template::conversation_variables(&conversation, state.clock.now()));
+            send_reply(state, &mut conversation, "bot", MessageContent::text(text)).await;
+            conversation.metadata.insert(AFTER_HOURS_NOTIFIED.to_string(), closed_until);
+        }
     }
 
-    // 5. Persistir en Redis
-    persist_conversation_state(state, &conversation).await?;
+    // 4. Ejecutar flow engine (en pausa si la atiende un asesor)
+    let handoff_before = conversation.handoff.clone();
+    let timer_before = conversation.timer.clone();
+    let result = state.flow_engine
+        .process(&mut conversation, &entry_points, &msg.content)
+        .await;
+    state.timers.sync(&conversation_id, timer_before.as_ref(), conversation.timer.as_ref()).await;
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            // El mensaje ya está en la conversación: se guarda y se cuenta igual
+            finish_message(state, before.as_ref(), conversation, conversation_ttl).await;
+            return Err(e);
+        }
+    };
+
+    // 5. Enviar respuesta (si falla, la conversación avanza igual y se emite el evento)
+    if let Some(content) = response {
+        send_reply(state, &mut conversation, "bot", content).await;
+        state.metrics.record_latency(msg.bot_id, started.elapsed());
+    }
 
-    // 6. Actualizar stats del bot
-    if let Some(mut bot) = state.bots.get_mut(&msg.bot_id) {
-        bot.stats.messages_received += 1;
-        bot.stats.last_message_at = Some(chrono::Utc::now());
+    // 6. Handoff: pedido nuevo, mensaje para el asesor o el cliente salió de la cola
+    match (&handoff_before, &conversation.handoff) {
+        (None, Some(_)) => handoff::begin(state, &mut conversation).await,
+        (Some(_), Some(_)) => handoff::forward(state, &conversation, &msg.content),
+        (Some(previous), None) => handoff::emit_ended(state, &conversation, previous, "cancelled_by_customer"),
+        (None, None) => {}
     }
 
+    // 7. Registrar, guardar y contar
+    finish_message(state, before.as_ref(), conversation, conversation_ttl).await;
+
     Ok(())
 }
 
-async fn send_message_to_whatsapp(
+/// Último paso de un mensaje (también si el flow falló): registrar los
+/// cambios, guardar en memoria y en Redis y actualizar las stats del bot
+async fn finish_message(
     state: &OrchestratorState,
-    bot_id: &Uuid,
-    to: &str,
-    message: &str,
-) -> anyhow::Result<()> {
-    // TODO: Llamar al WhatsApp Adapter (port 3010)
-    info\!("📤 Sending to {}: {}", to, message);
-    
-    // Por ahora simulado
-    Ok(())
+    before: Option<&ConversationState>,
+    mut conversation: ConversationState,
+    ttl: std::time::Duration,
+) {
+    state.metrics.record_flows(&mut conversation);
+    event_log::record(state, "message", before, &mut conversation).await;
+    state.conversations.insert(conversation.id.clone(), conversation.clone());
+    if let Err(e) = persist_conversation_state(state, &conversation, ttl).await {
+        warn!("Could not persist conversation {}: {:#}", conversation.id, e);
+    }
+
+    count(state, conversation.bot_id, Metric::MessageReceived);
 }
 
-async fn persist_conversation_state(
+/// Disparar un timer vencido: el flow continúa sin que el cliente escriba
+async fn process_timer(
     state: &OrchestratorState,
-    conversation: &ConversationState,
+    conversation_id: &str,
+    timer_id: Uuid,
 ) -> anyhow::Result<()> {
-    // TODO: Guardar en Redis
-    Ok(())
-}
+    let conversation = find_conversation(state, conversation_id).await?;
 
-async fn load_bots_from_db(state: &OrchestratorState) {
-    // TODO: Cargar desde PostgreSQL
-    info\!("📚 Loading bots from database...");
-    
-    // Ejemplo: crear bot de prueba
-    let test_bot = BotInstance {
-        id: Uuid::new_v4(),
-        tenant_id: "test_tenant".to_string(),
-        name: "Test Bot".to_string(),
-        phone_number: Some("+1234567890".to_string()),
-        provider: "venom".to_string(),
-        provider_config: serde_json::json\!({
-            "bridge_url": "http://localhost:3013",
-            "session_name": "test_bot"
-        }),
-        flows: FlowConfig {
-            welcome_flow_id: None,
-            menu_flow_id: None,
-            fallback_flow_id: None,
-        },
-        settings: BotSettings {
-            business_hours_enabled: false,
-            timezone: "UTC".to_string(),
-            auto_reply_delay_ms: 500,
-            max_conversation_timeout_seconds: 3600,
-        },
-        stats: BotStats::default(),
-    };
-    
-    state.bots.insert(test_bot.id, test_bot.clone());
-    info\!("✅ Loaded {} bots", state.bots.len());
-}
-
-fn spawn_analytics_worker(mut event_rx: broadcast::Receiver<BotEvent>) {
-    tokio::spawn(async move {
-        info\!("📊 Analytics worker started");
-        
-        while let Ok(event) = event_rx.recv().await {
-            // Procesar eventos para analytics
-            match event {
-                BotEvent::MessageReceived { .. } => {
-                    // Incrementar contador
-                }
-                BotEvent::MessageSent { .. } => {
-                    // Incrementar contador
-                }
-                BotEvent::ConversationStarted { .. } => {
-                    // Registrar nueva conversación
+    // El bot se borró: la conversación (y su timer) terminan con él
+    if let Some(orphan) = conversation.as_ref().filter(|c| !state.bots.contains_key(&c.bot_id)) {
+        expiry::end_conversation(state, orphan.clone(), expiry::BOT_DELETED).await?;
+        return state.timers.remove(conversation_id, timer_id).await;
+    }
+
+    // Un bot pausado no le escribe al cliente: el timer vuelve a intentarse más tarde
+    if conversation.as_ref().is_some_and(|conversation| bot_paused(&state.bots, &conversation.bot_id)) {
+        let retry_at = state.clock.now() + chrono::Duration::from_std(scheduler::PAUSED_RETRY).unwrap_or_default();
+        return state.timers.postpone(conversation_id, timer_id, retry_at).await;
+    }
+
+    if let Some(mut conversation) = conversation {
+        let before = conversation.clone();
+        let result = state.flow_engine.fire_timer(&mut conversation, timer_id).await;
+        state.timers.sync(conversation_id, before.timer.as_ref(), conversation.timer.as_ref()).await;
+
+        let failed = match result {
+            Ok(Some(content)) => {
+                send_reply(state, &mut conversation, "bot", content).await;
+                // El flow pudo seguir hasta un step `Handoff`
+                if before.handoff.is_none() && conversation.handoff.is_some() {
+                    handoff::begin(state, &mut conversation).await;
                 }
-                _ => {}
+                false
+            }
+            Ok(None) => false,
+            Err(e) => {
+                // El timer ya se consumió: se guarda el estado y no se reintenta
+                error!("Timer {} of conversation {} failed: {:#}", timer_id, conversation_id, e);
+                true
             }
+        };
+        state.metrics.record_flows(&mut conversation);
+        if failed || before.timer != conversation.timer {
+            event_log::record(state, "timer", Some(&before), &mut conversation).await;
+            save_conversation(state, conversation).await;
         }
-    });
+    }
+
+    // Procesado (o ya sin efecto): deja de estar reclamado
+    state.timers.remove(conversation_id, timer_id).await
 }
 
-fn spawn_cleanup_worker(state: OrchestratorState) {
-    tokio::spawn(async move {
-        info\!("🧹 Cleanup worker started");
-        
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(300));
-        
-        loop {
-            interval.tick().await;
+/// Metadata con la próxima apertura del cierre en el que ya se envió la respuesta automática
+const AFTER_HOURS_NOTIFIED: &str = "after_hours_notified";
+
+/// Enviar un mensaje al cliente y registrarlo en la conversación como
+/// enviado por `role` (`bot` o `agent`)
+pub async fn send_reply(
+    state: &OrchestratorState,
+    conversation: &mut ConversationState,
+    role: &str,
+    content: MessageContent,
+) {
+    let bot_id = conversation.bot_id;
+    let provider = state.bots.get(&bot_id).map(|bot| bot.provider.clone()).unwrap_or_default();
+
+    // Menús interactivos como texto numerado en providers sin botones
+    let content = if whatsapp::supports_interactive(&provider) {
+        content
+    } else {
+        content.into_plain()
+    };
+    let reply = OutgoingMessage {
+        to: conversation.user_phone.clone(),
+        content,
+    };
+
+    match send_message_to_whatsapp(state, &bot_id, &reply).await {
+        Ok(provider_message_id) => {
+            conversation.add_outgoing_message(role, reply.content.clone(), Some(provider_message_id));
+            count(state, bot_id, Metric::MessageSent);
             
-            // Limpiar conversaciones inactivas (>1 hora)
-            let now = chrono::Utc::now();
-            let timeout = chrono::Duration::hours(1);
+            // Emitir evento
+            state.event_bus.send(BotEvent::MessageSent {
+                bot_id,
+                to: reply.to,
+                message: reply.content.summary(),
+                timestamp: chrono::Utc::now(),
+            });
+        }
+        Err(e) => {
+            error!("❌ Could not deliver reply to {}: {:#}", reply.to, e);
+            conversation.add_outgoing_message(role, reply.content.clone(), None);
+            count(state, bot_id, Metric::MessageFailed);
             
-            state.conversations.retain(|_id, conv| {
-                let elapsed = now - conv.last_activity;
-                if elapsed > timeout {
-                    info\!("🧹 Cleaning inactive conversation: {}", conv.id);
-                    false
-                } else {
-                    true
-                }
+            state.event_bus.send(BotEvent::MessageFailed {
+                bot_id,
+                to: reply.to,
+                message: reply.content.summary(),
+                error: format!("{:#}", e),
+                timestamp: chrono::Utc::now(),
             });
         }
+    }
+}
+
+/// Conversación en memoria o, si no está, en Redis
+pub async fn find_conversation(
+    state: &OrchestratorState,
+    conversation_id: &str,
+) -> anyhow::Result<Option<ConversationState>> {
+    if let Some(conv) = state.conversations.get(conversation_id) {
+        return Ok(Some(conv.clone()));
+    }
+    load_stored_conversation(state, conversation_id).await
+}
+
+/// Conversación guardada en Redis, con las versiones de flows que usa ya cargadas
+async fn load_stored_conversation(
+    state: &OrchestratorState,
+    conversation_id: &str,
+) -> anyhow::Result<Option<ConversationState>> {
+    let conversation = state.conversation_store.load(conversation_id).await?;
+    if let Some(conversation) = &conversation {
+        flow_store::restore_pinned_versions(&state.flow_engine, &state.flow_store, conversation).await;
+    }
+    Ok(conversation)
+}
+
+/// Guardar una conversación en memoria y en Redis (si Redis falla, sigue en memoria)
+pub async fn save_conversation(state: &OrchestratorState, conversation: ConversationState) {
+    let ttl = state.bots.get(&conversation.bot_id)
+        .map(|bot| conversation_ttl(bot.value()))
+        .unwrap_or(std::time::Duration::from_secs(3600));
+
+    if let Err(e) = persist_conversation_state(state, &conversation, ttl).await {
+        warn!("Could not persist conversation {}: {:#}", conversation.id, e);
+    }
+    state.conversations.insert(conversation.id.clone(), conversation);
+}
+
+/// Tiempo que se conserva en Redis una conversación sin actividad
+fn conversation_ttl(bot: &BotInstance) -> std::time::Duration {
+    std::time::Duration::from_secs(bot.settings.max_conversation_timeout_seconds)
+}
+
+/// Conversación que no está en memoria: restaurarla desde Redis (`None`: no
+/// hay una guardada). Un error de Redis o un estado ilegible no es "no hay".
+async fn restore_conversation(
+    state: &OrchestratorState,
+    conversation_id: &str,
+) -> anyhow::Result<Option<ConversationState>> {
+    let restored = load_stored_conversation(state, conversation_id).await
+        .with_context(|| format!("Could not restore conversation {}", conversation_id))?;
+    if restored.is_some() {
+        info!("♻️ Restored conversation from Redis: {}", conversation_id);
+    }
+    Ok(restored)
+}
+
+/// Empezar una conversación nueva
+fn start_conversation(state: &OrchestratorState, conversation_id: &str, bot_id: Uuid, user_phone: &str) -> ConversationState {
+    info!("🆕 New conversation: {}", conversation_id);
+    count(state, bot_id, Metric::ConversationStarted);
+
+    // Emitir evento
+    state.event_bus.send(BotEvent::ConversationStarted {
+        conversation_id: conversation_id.to_string(),
+        bot_id,
+        user_phone: user_phone.to_string(),
     });
+
+    ConversationState::new(conversation_id.to_string(), bot_id, user_phone.to_string())
+}
+
+/// Contar un evento en las stats del bot y en sus métricas
+fn count(state: &OrchestratorState, bot_id: Uuid, metric: Metric) {
+    if let Some(mut bot) = state.bots.get_mut(&bot_id) {
+        bot.stats.count(metric);
+    }
+    state.metrics.record(bot_id, metric);
+}
+
+/// Enviar un mensaje con el provider del bot. Devuelve el id del mensaje en el provider.
+async fn send_message_to_whatsapp(
+    state: &OrchestratorState,
+    bot_id: &Uuid,
+    message: &OutgoingMessage,
+) -> anyhow::Result<String> {
+    let (provider, provider_config) = state.bots.get(bot_id)
+        .map(|bot| (bot.provider.clone(), bot.provider_config.clone()))
+        .ok_or_else(|| anyhow::anyhow!("Bot not found: {}", bot_id))?;
+
+    info!("📤 Sending {} to {} via {}", message.content.kind(), message.to, provider);
+
+    state.whatsapp.send(SendRequest {
+        provider,
+        provider_config,
+        to: message.to.clone(),
+        message: message.content.clone(),
+    }).await
+}
+
+async fn persist_conversation_state(
+    state: &OrchestratorState,
+    conversation: &ConversationState,
+    ttl: std::time::Duration,
+) -> anyhow::Result<()> {
+    // Tiene que seguir ahí cuando venza, para archivarla
+    let ttl = ttl + expiry::TTL_GRACE;
+    // Con un timer pendiente, la conversación tiene que seguir ahí cuando venza
+    let ttl = match &conversation.timer {
+        Some(timer) => {
+            let until_timer = (timer.fires_at - chrono::Utc::now()).to_std().unwrap_or_default();
+            ttl.max(until_timer + std::time::Duration::from_secs(300))
+        }
+        None => ttl,
+    };
+    state.conversation_store.save(conversation, ttl).await?;
+    expiry::schedule(state, conversation).await
 }
 
 fn get_memory_usage() -> u64 {
@@ -0,0 +1,259 @@
+//! Menu - Presentación de `FlowStep::Menu` y reconocimiento de respuestas
+//!
+//! - `display: text`: bloque de texto con una línea `key - label` por opción
+//! - `display: interactive`: botones de respuesta (hasta 3 opciones) o mensaje
+//!   de lista con secciones (hasta 10). Si el provider no los soporta, el
+//!   orchestrator los convierte en el mismo bloque de texto numerado.
+//!
+//! Las respuestas se reconocen por el id del botón/fila (la `key`), por el
+//! texto exacto de la opción sin tildes ni mayúsculas, y por aproximación
+//! ("catalgo", "quiero ver el catálogo") cuando solo una opción encaja.
+
+use serde::{Deserialize, Serialize};
+
+use super::flow_engine::MenuOption;
+use super::input_validation::fold_accents;
+use super::message::{ListRow, ListSection, MessageContent, ReplyButton};
+
+/// Cómo se muestra un `Menu`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MenuDisplay {
+    #[default]
+    Text,
+    Interactive,
+}
+
+/// Límites de WhatsApp para mensajes interactivos
+const MAX_BUTTONS: usize = 3;
+const MAX_BUTTON_TITLE: usize = 20;
+const MAX_LIST_ROWS: usize = 10;
+const MAX_ROW_TITLE: usize = 24;
+const MAX_ROW_DESCRIPTION: usize = 72;
+const MAX_SECTION_TITLE: usize = 24;
+const MAX_BODY: usize = 1024;
+
+/// Texto del botón que abre la lista si el step no define `button_text`
+pub const DEFAULT_LIST_BUTTON: &str = "Ver opciones";
+
+/// Menú como bloque de texto numerado
+pub fn numbered_text(body: &str, options: &[MenuOption]) -> String {
+    let mut message = format!("{}\n\n", body);
+    for option in options {
+        message.push_str(&format!("{} - {}\n", option.key, option.label));
+    }
+    message
+}
+
+/// Menú interactivo: botones si caben, si no una lista; texto numerado si
+/// tampoco cabe en una lista
+pub fn interactive(body: String, options: &[MenuOption], button_text: Option<&str>) -> MessageContent {
+    if body.chars().count() > MAX_BODY || options.len() > MAX_LIST_ROWS {
+        tracing::warn!(
+            "Menu with {} options / {} chars does not fit an interactive message, sending text",
+            options.len(), body.chars().count()
+        );
+        return MessageContent::text(numbered_text(&body, options));
+    }
+
+    let fits_buttons = options.len() <= MAX_BUTTONS
+        && options.iter().all(|o| o.label.chars().count() <= MAX_BUTTON_TITLE && o.description.is_none());
+    if fits_buttons {
+        return MessageContent::Buttons {
+            body,
+            buttons: options.iter()
+                .map(|o| ReplyButton { id: o.key.clone(), title: o.label.clone() })
+                .collect(),
+        };
+    }
+
+    // Secciones en el orden en que aparecen; las opciones sin sección van juntas
+    let mut sections: Vec<ListSection> = Vec::new();
+    for option in options {
+        let title = option.section.as_deref().map(|s| truncate(s, MAX_SECTION_TITLE));
+        let row = ListRow {
+            id: option.key.clone(),
+            title: truncate(&option.label, MAX_ROW_TITLE),
+            // Si el título no cabe, el texto completo va en la descripción
+            description: option.description.clone()
+                .or_else(|| (option.label.chars().count() > MAX_ROW_TITLE).then(|| option.label.clone()))
+                .map(|d| truncate(&d, MAX_ROW_DESCRIPTION)),
+        };
+
+        match sections.iter_mut().find(|s| s.title == title) {
+            Some(section) => section.rows.push(row),
+            None => sections.push(ListSection { title, rows: vec![row] }),
+        }
+    }
+
+    MessageContent::List {
+        body,
+        button: truncate(button_text.unwrap_or(DEFAULT_LIST_BUTTON), MAX_BUTTON_TITLE),
+        sections,
+    }
+}
+
+/// Opción elegida: por id/key, por texto exacto o por aproximación
+pub fn find_option<'a>(options: &'a [MenuOption], input: &str) -> Option<&'a MenuOption> {
+    exact_option(options, input).or_else(|| fuzzy_option(options, input))
+}
+
+/// Opción cuya key o texto coincide con la respuesta (sin tildes ni mayúsculas)
+pub fn exact_option<'a>(options: &'a [MenuOption], input: &str) -> Option<&'a MenuOption> {
+    let input = input.trim();
+    if let Some(option) = options.iter().find(|o| o.key == input) {
+        return Some(option);
+    }
+
+    let input = normalize(input);
+    if input.is_empty() {
+        return None;
+    }
+    options.iter().find(|o| normalize(&o.key) == input || normalize(&o.label) == input)
+}
+
+/// La única opción que contiene la respuesta (o está contenida en ella), o
+/// que se parece con alguna letra de diferencia
+fn fuzzy_option<'a>(options: &'a [MenuOption], input: &str) -> Option<&'a MenuOption> {
+    let input = normalize(input);
+    if input.chars().count() < 3 {
+        return None;
+    }
+
+    let candidates: Vec<&MenuOption> = options.iter()
+        .filter(|option| {
+            let label = normalize(&option.label);
+            contains_words(&label, &input)
+                || (label.chars().count() >= 3 && contains_words(&input, &label))
+                || levenshtein(&label, &input) <= label.chars().count() / 4
+        })
+        .collect();
+
+    match candidates.as_slice() {
+        [only] => Some(only),
+        _ => None,
+    }
+}
+
+/// Minúsculas, sin tildes, sin signos ni emojis y con espacios simples
+pub fn normalize(text: &str) -> String {
+    let mapped: String = fold_accents(&text.to_lowercase())
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    mapped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// ¿`haystack` contiene `needle` como palabras completas?
+fn contains_words(haystack: &str, needle: &str) -> bool {
+    !needle.is_empty() && format!(" {} ", haystack).contains(&format!(" {} ", needle))
+}
+
+/// Distancia de edición entre dos textos (en caracteres)
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(key: &str, label: &str) -> MenuOption {
+        MenuOption {
+            key: key.to_string(),
+            label: label.to_string(),
+            next_step: key.to_string(),
+            description: None,
+            section: None,
+        }
+    }
+
+    #[test]
+    fn test_matching() {
+        let options = vec![
+            option("1", "Ver catálogo"),
+            option("2", "Estado de mi pedido"),
+            option("3", "Hablar con un asesor"),
+        ];
+        let key = |input: &str| find_option(&options, input).map(|o| o.key.as_str());
+
+        assert_eq!(key("2"), Some("2"));
+        assert_eq!(key("2."), Some("2"));
+        assert_eq!(key("VER CATALOGO"), Some("1"));
+        assert_eq!(key("catálogo"), Some("1"));
+        assert_eq!(key("quiero ver catalogo"), Some("1"));
+        assert_eq!(key("estado de mi pedid"), Some("2"));
+        assert_eq!(key("👍"), None);
+        assert_eq!(key("de"), None);
+        assert_eq!(key("quiero algo"), None);
+
+        let yes_no = vec![option("si", "Sí"), option("no", "No")];
+        assert_eq!(find_option(&yes_no, "SI").map(|o| o.key.as_str()), Some("si"));
+        assert!(find_option(&yes_no, "no sé").is_none());
+
+        // Mismas tildes que la validación de respuestas
+        assert_eq!(normalize("Ñandú, AÇÃO!"), fold_accents("nandu acao"));
+
+        // Lo exacto no acepta aproximaciones
+        assert!(exact_option(&options, "catálogo").is_none());
+        assert!(exact_option(&options, "Ver Catalogo").is_some());
+    }
+
+    #[test]
+    fn test_interactive_rendering() {
+        let few = vec![option("1", "Catálogo"), option("2", "Asesor")];
+        let buttons = interactive("¿Qué deseas?".to_string(), &few, None);
+        assert_eq!(buttons, MessageContent::Buttons {
+            body: "¿Qué deseas?".to_string(),
+            buttons: vec![
+                ReplyButton { id: "1".to_string(), title: "Catálogo".to_string() },
+                ReplyButton { id: "2".to_string(), title: "Asesor".to_string() },
+            ],
+        });
+
+        let mut many: Vec<MenuOption> = (1..=5)
+            .map(|i| option(&i.to_string(), &format!("Producto {}", i)))
+            .collect();
+        many[0].section = Some("Flores".to_string());
+        many[1].section = Some("Flores".to_string());
+        many[4].label = "Arreglo especial con globos y chocolates".to_string();
+
+        let MessageContent::List { button, sections, .. } = interactive("Elige".to_string(), &many, None) else {
+            panic!("expected a list");
+        };
+        assert_eq!(button, DEFAULT_LIST_BUTTON);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].title.as_deref(), Some("Flores"));
+        assert_eq!(sections[0].rows.len(), 2);
+        assert_eq!(sections[1].rows[2].title.chars().count(), MAX_ROW_TITLE);
+        assert_eq!(
+            sections[1].rows[2].description.as_deref(),
+            Some("Arreglo especial con globos y chocolates")
+        );
+
+        let too_many: Vec<MenuOption> = (1..=11).map(|i| option(&i.to_string(), "x")).collect();
+        assert_eq!(interactive("Elige".to_string(), &too_many, None).kind(), "text");
+    }
+}
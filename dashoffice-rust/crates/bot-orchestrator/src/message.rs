@@ -0,0 +1,270 @@
+//! Message - Contenido tipado de los mensajes de WhatsApp
+//!
+//! `MessageContent` describe un mensaje en cualquier dirección: los webhooks
+//! lo construyen a partir del payload de cada provider y el orchestrator lo
+//! usa para las respuestas del bot. Se serializa con un campo `type`:
+//!
+//! ```json
+//! { "type": "text", "body": "hola" }
+//! { "type": "image", "id": "1037543291543636", "mime_type": "image/jpeg", "caption": "comprobante" }
+//! { "type": "location", "latitude": 10.49, "longitude": -66.87, "name": "Tienda Centro" }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageContent {
+    Text {
+        body: String,
+    },
+    Image(Media),
+    Audio(Media),
+    Video(Media),
+    Document(Media),
+    Sticker(Media),
+    Location(Location),
+    Contacts {
+        contacts: Vec<Contact>,
+    },
+    /// Reacción con un emoji a un mensaje anterior (`emoji` vacío = reacción quitada)
+    Reaction {
+        message_id: Option<String>,
+        emoji: String,
+    },
+    /// Botón de un mensaje interactivo o de una plantilla
+    ButtonReply {
+        id: String,
+        title: Option<String>,
+    },
+    /// Fila elegida de un mensaje de lista
+    ListReply {
+        id: String,
+        title: Option<String>,
+    },
+    /// Botones de respuesta (solo envío, hasta 3)
+    Buttons {
+        body: String,
+        buttons: Vec<ReplyButton>,
+    },
+    /// Mensaje de lista con secciones (solo envío); `button` abre la lista
+    List {
+        body: String,
+        button: String,
+        sections: Vec<ListSection>,
+    },
+}
+
+/// Archivo adjunto. Los mensajes recibidos traen el `id` del provider; los
+/// enviados, la `url` pública del archivo.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Media {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+/// Tarjeta de contacto compartida
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    #[serde(default)]
+    pub phones: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplyButton {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListSection {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub rows: Vec<ListRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListRow {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Mensaje al que responde el cliente (respuesta citada)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotedMessage {
+    /// Id del mensaje citado en el provider
+    pub message_id: Option<String>,
+    /// Texto del mensaje citado, si el provider lo incluye
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl MessageContent {
+    pub fn text(body: impl Into<String>) -> Self {
+        MessageContent::Text { body: body.into() }
+    }
+
+    /// Tipo del mensaje (`text`, `image`, `button_reply`...), igual que el tag serializado
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MessageContent::Text { .. } => "text",
+            MessageContent::Image(_) => "image",
+            MessageContent::Audio(_) => "audio",
+            MessageContent::Video(_) => "video",
+            MessageContent::Document(_) => "document",
+            MessageContent::Sticker(_) => "sticker",
+            MessageContent::Location(_) => "location",
+            MessageContent::Contacts { .. } => "contacts",
+            MessageContent::Reaction { .. } => "reaction",
+            MessageContent::ButtonReply { .. } => "button_reply",
+            MessageContent::ListReply { .. } => "list_reply",
+            MessageContent::Buttons { .. } => "buttons",
+            MessageContent::List { .. } => "list",
+        }
+    }
+
+    /// Texto con el que responden los steps que esperan texto: el cuerpo de un
+    /// texto, el id de la opción elegida o el pie de foto de un adjunto
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            MessageContent::Text { body }
+            | MessageContent::Buttons { body, .. }
+            | MessageContent::List { body, .. } => Some(body),
+            MessageContent::ButtonReply { id, .. } | MessageContent::ListReply { id, .. } => Some(id),
+            MessageContent::Image(media) | MessageContent::Video(media) | MessageContent::Document(media) => {
+                media.caption.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Versión en texto para providers sin mensajes interactivos: botones y
+    /// listas se convierten en opciones numeradas (`key - label`)
+    pub fn into_plain(self) -> MessageContent {
+        match self {
+            MessageContent::Buttons { .. } | MessageContent::List { .. } => {
+                MessageContent::text(self.to_plain_text())
+            }
+            other => other,
+        }
+    }
+
+    /// Texto que vería el cliente en un provider sin mensajes interactivos
+    pub fn to_plain_text(&self) -> String {
+        let numbered = |body: &str, options: Vec<(&str, &str)>| {
+            let mut text = format!("{}\n\n", body);
+            for (id, title) in options {
+                text.push_str(&format!("{} - {}\n", id, title));
+            }
+            text
+        };
+
+        match self {
+            MessageContent::Buttons { body, buttons } => {
+                numbered(body, buttons.iter().map(|b| (b.id.as_str(), b.title.as_str())).collect())
+            }
+            MessageContent::List { body, sections, .. } => numbered(
+                body,
+                sections.iter()
+                    .flat_map(|s| s.rows.iter())
+                    // Título recortado: el texto completo está en la descripción
+                    .map(|r| match &r.description {
+                        Some(description) if r.title.ends_with('…') => (r.id.as_str(), description.as_str()),
+                        _ => (r.id.as_str(), r.title.as_str()),
+                    })
+                    .collect(),
+            ),
+            other => other.summary(),
+        }
+    }
+
+    /// Representación corta para logs y eventos ("hola", "[image] comprobante")
+    pub fn summary(&self) -> String {
+        match self {
+            MessageContent::Text { body } => body.clone(),
+            MessageContent::Location(location) => match &location.name {
+                Some(name) => format!("[location] {}", name),
+                None => format!("[location] {}, {}", location.latitude, location.longitude),
+            },
+            MessageContent::Reaction { emoji, .. } => format!("[reaction] {}", emoji),
+            MessageContent::Contacts { contacts } => {
+                let names: Vec<&str> = contacts.iter().map(|c| c.name.as_str()).collect();
+                format!("[contacts] {}", names.join(", "))
+            }
+            MessageContent::ButtonReply { id, title } | MessageContent::ListReply { id, title } => {
+                format!("[{}] {}", self.kind(), title.as_deref().unwrap_or(id))
+            }
+            other => match other.as_text() {
+                Some(caption) if !caption.is_empty() => format!("[{}] {}", other.kind(), caption),
+                _ => format!("[{}]", other.kind()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialized_format() {
+        let image = MessageContent::Image(Media {
+            id: Some("1037543291543636".to_string()),
+            mime_type: Some("image/jpeg".to_string()),
+            caption: Some("comprobante".to_string()),
+            ..Default::default()
+        });
+
+        let value = serde_json::to_value(&image).unwrap();
+        assert_eq!(value, serde_json::json!({
+            "type": "image",
+            "id": "1037543291543636",
+            "mime_type": "image/jpeg",
+            "caption": "comprobante"
+        }));
+        assert_eq!(serde_json::from_value::<MessageContent>(value).unwrap(), image);
+
+        let text: MessageContent = serde_json::from_str(r#"{"type": "text", "body": "hola"}"#).unwrap();
+        assert_eq!(text, MessageContent::text("hola"));
+    }
+
+    #[test]
+    fn test_text_and_summary() {
+        let reply = MessageContent::ButtonReply { id: "1".to_string(), title: Some("Catálogo".to_string()) };
+        assert_eq!(reply.as_text(), Some("1"));
+        assert_eq!(reply.summary(), "[button_reply] Catálogo");
+
+        let location = MessageContent::Location(Location {
+            latitude: 10.4806,
+            longitude: -66.9036,
+            name: None,
+            address: None,
+        });
+        assert_eq!(location.as_text(), None);
+        assert_eq!(location.summary(), "[location] 10.4806, -66.9036");
+
+        let audio = MessageContent::Audio(Media::default());
+        assert_eq!(audio.summary(), "[audio]");
+    }
+}
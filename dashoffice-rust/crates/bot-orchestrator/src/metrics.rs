@@ -0,0 +1,470 @@
+//! Métricas por bot
+//!
+//! - Contadores en ventanas móviles de 1m, 1h y 24h: mensajes recibidos,
+//!   enviados y fallidos, conversaciones nuevas y flows iniciados,
+//!   completados y abandonados
+//! - Latencia de respuesta del bot (desde que el buzón toma el mensaje hasta
+//!   que se envía la respuesta del flow), p50/p95 por ventana
+//! - Las ventanas son anillos de buckets: 60 de 1s (1m), 60 de 1min (1h) y
+//!   24 de 1h (24h); la de 24h avanza de hora en hora
+//! - Lo mismo se exporta en `/metrics` en formato Prometheus
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::clock::Clock;
+use super::state_machine::ConversationState;
+
+/// Límites superiores (ms) de los buckets de latencia; hay uno más para lo que los supera
+const LATENCY_BOUNDS_MS: [f64; 10] = [25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0, 30000.0];
+
+/// (nombre, ancho de cada bucket en segundos, cantidad de buckets)
+const WINDOWS: [(&str, i64, usize); 3] = [("1m", 1, 60), ("1h", 60, 60), ("24h", 3600, 24)];
+
+/// Cómo empezó o terminó el flow principal de una conversación
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowOutcome {
+    Started,
+    /// Llegó a un `End`
+    Completed,
+    /// El cliente salió, agotó los intentos, empezó otro flow o dejó de responder
+    Abandoned,
+}
+
+impl FlowOutcome {
+    fn label(self) -> &'static str {
+        match self {
+            FlowOutcome::Started => "started",
+            FlowOutcome::Completed => "completed",
+            FlowOutcome::Abandoned => "abandoned",
+        }
+    }
+}
+
+/// Evento que cuenta en las métricas de un bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    MessageReceived,
+    MessageSent,
+    MessageFailed,
+    ConversationStarted,
+    Flow(FlowOutcome),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    /// Número de bucket desde el epoch (`segundos / ancho`)
+    slot: i64,
+    messages_received: u64,
+    messages_sent: u64,
+    messages_failed: u64,
+    conversations_started: u64,
+    flows_started: u64,
+    flows_completed: u64,
+    flows_abandoned: u64,
+    latency: [u64; LATENCY_BOUNDS_MS.len() + 1],
+}
+
+impl Bucket {
+    fn count(&mut self, metric: Metric) {
+        let counter = match metric {
+            Metric::MessageReceived => &mut self.messages_received,
+            Metric::MessageSent => &mut self.messages_sent,
+            Metric::MessageFailed => &mut self.messages_failed,
+            Metric::ConversationStarted => &mut self.conversations_started,
+            Metric::Flow(FlowOutcome::Started) => &mut self.flows_started,
+            Metric::Flow(FlowOutcome::Completed) => &mut self.flows_completed,
+            Metric::Flow(FlowOutcome::Abandoned) => &mut self.flows_abandoned,
+        };
+        *counter += 1;
+    }
+
+    fn add(&mut self, other: &Bucket) {
+        self.messages_received += other.messages_received;
+        self.messages_sent += other.messages_sent;
+        self.messages_failed += other.messages_failed;
+        self.conversations_started += other.conversations_started;
+        self.flows_started += other.flows_started;
+        self.flows_completed += other.flows_completed;
+        self.flows_abandoned += other.flows_abandoned;
+        for (total, count) in self.latency.iter_mut().zip(other.latency) {
+            *total += count;
+        }
+    }
+}
+
+/// Anillo de buckets de `width` segundos
+#[derive(Debug)]
+struct Ring {
+    width: i64,
+    buckets: Vec<Bucket>,
+}
+
+impl Ring {
+    fn new(width: i64, len: usize) -> Self {
+        Self {
+            width,
+            buckets: vec![Bucket { slot: -1, ..Bucket::default() }; len],
+        }
+    }
+
+    /// Bucket del instante `now` (vaciado si era de una vuelta anterior)
+    fn bucket(&mut self, now: i64) -> &mut Bucket {
+        let slot = now.div_euclid(self.width);
+        let index = slot.rem_euclid(self.buckets.len() as i64) as usize;
+        let bucket = &mut self.buckets[index];
+        if bucket.slot != slot {
+            *bucket = Bucket { slot, ..Bucket::default() };
+        }
+        bucket
+    }
+
+    /// Suma de los buckets que siguen dentro de la ventana
+    fn total(&self, now: i64) -> Bucket {
+        let current = now.div_euclid(self.width);
+        let oldest = current - self.buckets.len() as i64;
+        let mut total = Bucket::default();
+        for bucket in self.buckets.iter().filter(|bucket| bucket.slot > oldest && bucket.slot <= current) {
+            total.add(bucket);
+        }
+        total
+    }
+}
+
+/// Ventanas de un bot
+#[derive(Debug)]
+struct BotWindows {
+    rings: Vec<Ring>,
+}
+
+impl BotWindows {
+    fn new() -> Self {
+        Self {
+            rings: WINDOWS.iter().map(|(_, width, len)| Ring::new(*width, *len)).collect(),
+        }
+    }
+
+    fn update(&mut self, now: i64, apply: impl Fn(&mut Bucket)) {
+        for ring in &mut self.rings {
+            apply(ring.bucket(now));
+        }
+    }
+}
+
+/// Resumen de una ventana
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindowStats {
+    pub messages_received: u64,
+    pub messages_sent: u64,
+    pub messages_failed: u64,
+    pub conversations_started: u64,
+    pub flows_started: u64,
+    pub flows_completed: u64,
+    pub flows_abandoned: u64,
+    /// Completados / terminados (completados + abandonados)
+    pub completion_rate: Option<f64>,
+    /// Abandonados / terminados
+    pub drop_off_rate: Option<f64>,
+    pub latency_p50_ms: Option<f64>,
+    pub latency_p95_ms: Option<f64>,
+}
+
+impl From<Bucket> for WindowStats {
+    fn from(bucket: Bucket) -> Self {
+        let finished = bucket.flows_completed + bucket.flows_abandoned;
+        let rate = |count: u64| (finished > 0).then(|| count as f64 / finished as f64);
+
+        Self {
+            messages_received: bucket.messages_received,
+            messages_sent: bucket.messages_sent,
+            messages_failed: bucket.messages_failed,
+            conversations_started: bucket.conversations_started,
+            flows_started: bucket.flows_started,
+            flows_completed: bucket.flows_completed,
+            flows_abandoned: bucket.flows_abandoned,
+            completion_rate: rate(bucket.flows_completed),
+            drop_off_rate: rate(bucket.flows_abandoned),
+            latency_p50_ms: percentile(&bucket.latency, 0.50),
+            latency_p95_ms: percentile(&bucket.latency, 0.95),
+        }
+    }
+}
+
+/// Percentil estimado interpolando dentro del bucket que lo contiene (como
+/// `histogram_quantile` de Prometheus); por encima del último límite, el límite
+fn percentile(histogram: &[u64], quantile: f64) -> Option<f64> {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return None;
+    }
+
+    let rank = quantile * total as f64;
+    let mut seen = 0;
+    for (index, &count) in histogram.iter().enumerate() {
+        if count == 0 || ((seen + count) as f64) < rank {
+            seen += count;
+            continue;
+        }
+        let Some(&upper) = LATENCY_BOUNDS_MS.get(index) else {
+            break;
+        };
+        let lower = index.checked_sub(1).map_or(0.0, |previous| LATENCY_BOUNDS_MS[previous]);
+        return Some(lower + (upper - lower) * (rank - seen as f64) / count as f64);
+    }
+    LATENCY_BOUNDS_MS.last().copied()
+}
+
+fn latency_bucket(latency: Duration) -> usize {
+    let ms = latency.as_secs_f64() * 1000.0;
+    LATENCY_BOUNDS_MS.iter().position(|&bound| ms <= bound).unwrap_or(LATENCY_BOUNDS_MS.len())
+}
+
+/// Métricas de todos los bots
+pub struct BotMetrics {
+    clock: Arc<dyn Clock>,
+    started_at: DateTime<Utc>,
+    windows: DashMap<Uuid, Mutex<BotWindows>>,
+    registry: Registry,
+    messages: IntCounterVec,
+    conversations: IntCounterVec,
+    flows: IntCounterVec,
+    latency: HistogramVec,
+    conversations_active: IntGaugeVec,
+    uptime: IntGauge,
+}
+
+impl BotMetrics {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let messages = IntCounterVec::new(
+            Opts::new("bot_messages_total", "Mensajes por bot (received, sent, failed)"),
+            &["bot_id", "direction"],
+        ).expect("valid metric");
+        let conversations = IntCounterVec::new(
+            Opts::new("bot_conversations_started_total", "Conversaciones nuevas por bot"),
+            &["bot_id"],
+        ).expect("valid metric");
+        let flows = IntCounterVec::new(
+            Opts::new("bot_flows_total", "Flows principales por bot (started, completed, abandoned)"),
+            &["bot_id", "outcome"],
+        ).expect("valid metric");
+        let latency = HistogramVec::new(
+            HistogramOpts::new("bot_response_latency_seconds", "Latencia de respuesta del bot")
+                .buckets(LATENCY_BOUNDS_MS.iter().map(|ms| ms / 1000.0).collect()),
+            &["bot_id"],
+        ).expect("valid metric");
+        let conversations_active = IntGaugeVec::new(
+            Opts::new("bot_conversations_active", "Conversaciones en memoria por bot"),
+            &["bot_id"],
+        ).expect("valid metric");
+        let uptime = IntGauge::new("bot_orchestrator_uptime_seconds", "Segundos desde el arranque")
+            .expect("valid metric");
+
+        let registry = Registry::new();
+        registry.register(Box::new(messages.clone())).expect("metric registered once");
+        registry.register(Box::new(conversations.clone())).expect("metric registered once");
+        registry.register(Box::new(flows.clone())).expect("metric registered once");
+        registry.register(Box::new(latency.clone())).expect("metric registered once");
+        registry.register(Box::new(conversations_active.clone())).expect("metric registered once");
+        registry.register(Box::new(uptime.clone())).expect("metric registered once");
+
+        Self {
+            started_at: clock.now(),
+            clock,
+            windows: DashMap::new(),
+            registry,
+            messages,
+            conversations,
+            flows,
+            latency,
+            conversations_active,
+            uptime,
+        }
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn uptime_seconds(&self) -> i64 {
+        (self.clock.now() - self.started_at).num_seconds()
+    }
+
+    pub fn record(&self, bot_id: Uuid, metric: Metric) {
+        self.update(bot_id, |bucket| bucket.count(metric));
+
+        let bot = bot_id.to_string();
+        match metric {
+            Metric::MessageReceived => self.messages.with_label_values(&[&bot, "received"]).inc(),
+            Metric::MessageSent => self.messages.with_label_values(&[&bot, "sent"]).inc(),
+            Metric::MessageFailed => self.messages.with_label_values(&[&bot, "failed"]).inc(),
+            Metric::ConversationStarted => self.conversations.with_label_values(&[&bot]).inc(),
+            Metric::Flow(outcome) => self.flows.with_label_values(&[&bot, outcome.label()]).inc(),
+        }
+    }
+
+    pub fn record_latency(&self, bot_id: Uuid, latency: Duration) {
+        let index = latency_bucket(latency);
+        self.update(bot_id, |bucket| bucket.latency[index] += 1);
+        self.latency.with_label_values(&[&bot_id.to_string()]).observe(latency.as_secs_f64());
+    }
+
+    /// Contar los flows que empezaron o terminaron en la conversación desde la última vez
+    pub fn record_flows(&self, conversation: &mut ConversationState) {
+        for outcome in std::mem::take(&mut conversation.flow_outcomes) {
+            self.record(conversation.bot_id, Metric::Flow(outcome));
+        }
+    }
+
+    /// Resumen de las ventanas de un bot (`1m`, `1h`, `24h`)
+    pub fn windows(&self, bot_id: Uuid) -> BTreeMap<&'static str, WindowStats> {
+        let now = self.clock.now().timestamp();
+        let windows = self.windows.get(&bot_id);
+        let windows = windows.as_ref().map(|windows| windows.lock());
+
+        WINDOWS.iter()
+            .enumerate()
+            .map(|(index, (name, _, _))| {
+                let total = windows.as_ref()
+                    .map(|windows| windows.rings[index].total(now))
+                    .unwrap_or_default();
+                (*name, WindowStats::from(total))
+            })
+            .collect()
+    }
+
+    /// Olvidar las ventanas y las series de Prometheus de un bot borrado
+    pub fn remove(&self, bot_id: Uuid) {
+        self.windows.remove(&bot_id);
+
+        // Error solo si la serie no existía (el bot no la llegó a usar)
+        let bot = bot_id.to_string();
+        for direction in ["received", "sent", "failed"] {
+            let _ = self.messages.remove_label_values(&[&bot, direction]);
+        }
+        for outcome in [FlowOutcome::Started, FlowOutcome::Completed, FlowOutcome::Abandoned] {
+            let _ = self.flows.remove_label_values(&[&bot, outcome.label()]);
+        }
+        let _ = self.conversations.remove_label_values(&[&bot]);
+        let _ = self.latency.remove_label_values(&[&bot]);
+        let _ = self.conversations_active.remove_label_values(&[&bot]);
+    }
+
+    /// Métricas en formato de texto de Prometheus; `active` son las
+    /// conversaciones en memoria de cada bot
+    pub fn render(&self, active: impl IntoIterator<Item = (Uuid, usize)>) -> String {
+        self.uptime.set(self.uptime_seconds());
+        self.conversations_active.reset();
+        for (bot_id, count) in active {
+            self.conversations_active.with_label_values(&[&bot_id.to_string()]).set(count as i64);
+        }
+
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Could not encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+
+    fn update(&self, bot_id: Uuid, apply: impl Fn(&mut Bucket)) {
+        let now = self.clock.now().timestamp();
+        self.windows
+            .entry(bot_id)
+            .or_insert_with(|| Mutex::new(BotWindows::new()))
+            .lock()
+            .update(now, apply);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rolling_windows() {
+        let start = Utc.with_ymd_and_hms(2024, 5, 6, 10, 0, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let metrics = BotMetrics::new(clock.clone());
+        let bot_id = Uuid::new_v4();
+
+        metrics.record(bot_id, Metric::MessageReceived);
+        metrics.record(bot_id, Metric::Flow(FlowOutcome::Started));
+        metrics.record(bot_id, Metric::Flow(FlowOutcome::Completed));
+
+        clock.set(start + chrono::Duration::seconds(30));
+        metrics.record(bot_id, Metric::MessageReceived);
+        metrics.record(bot_id, Metric::Flow(FlowOutcome::Abandoned));
+
+        let windows = metrics.windows(bot_id);
+        assert_eq!(windows["1m"].messages_received, 2);
+        assert_eq!(windows["1m"].completion_rate, Some(0.5));
+        assert_eq!(windows["1m"].drop_off_rate, Some(0.5));
+
+        // Pasado el minuto solo quedan en las ventanas largas
+        clock.set(start + chrono::Duration::seconds(90));
+        let windows = metrics.windows(bot_id);
+        assert_eq!(windows["1m"].messages_received, 0);
+        assert_eq!(windows["1m"].completion_rate, None);
+        assert_eq!(windows["1h"].messages_received, 2);
+        assert_eq!(windows["24h"].flows_started, 1);
+
+        clock.set(start + chrono::Duration::hours(2));
+        let windows = metrics.windows(bot_id);
+        assert_eq!(windows["1h"].messages_received, 0);
+        assert_eq!(windows["24h"].messages_received, 2);
+
+        clock.set(start + chrono::Duration::hours(25));
+        assert_eq!(metrics.windows(bot_id)["24h"].messages_received, 0);
+        assert_eq!(metrics.uptime_seconds(), 25 * 3600);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let clock = Arc::new(FixedClock::new(Utc::now()));
+        let metrics = BotMetrics::new(clock);
+        let bot_id = Uuid::new_v4();
+
+        // 90 respuestas rápidas (50-100ms) y 10 lentas (2.5-5s)
+        for _ in 0..90 {
+            metrics.record_latency(bot_id, Duration::from_millis(80));
+        }
+        for _ in 0..10 {
+            metrics.record_latency(bot_id, Duration::from_millis(3000));
+        }
+
+        let window = &metrics.windows(bot_id)["1m"];
+        let p50 = window.latency_p50_ms.unwrap();
+        let p95 = window.latency_p95_ms.unwrap();
+        assert!((50.0..=100.0).contains(&p50), "p50 = {}", p50);
+        assert!((2500.0..=5000.0).contains(&p95), "p95 = {}", p95);
+
+        let text = metrics.render([(bot_id, 3)]);
+        assert!(text.contains(&format!("bot_response_latency_seconds_count{{bot_id=\"{}\"}} 100", bot_id)));
+        assert!(text.contains(&format!("bot_conversations_active{{bot_id=\"{}\"}} 3", bot_id)));
+    }
+
+    #[test]
+    fn test_removed_bot_stops_exporting() {
+        let metrics = BotMetrics::new(Arc::new(FixedClock::new(Utc::now())));
+        let bot_id = Uuid::new_v4();
+
+        metrics.record(bot_id, Metric::MessageReceived);
+        metrics.record(bot_id, Metric::Flow(FlowOutcome::Started));
+        metrics.record_latency(bot_id, Duration::from_millis(80));
+        assert!(metrics.render([]).contains(&bot_id.to_string()));
+
+        metrics.remove(bot_id);
+        assert!(!metrics.render([]).contains(&bot_id.to_string()));
+        assert_eq!(metrics.windows(bot_id)["1m"].messages_received, 0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Scheduler - Timers de los flows (`Wait` y `on_timeout`)
+//!
+//! El timer pendiente de una conversación vive en `ConversationState::timer` y
+//! se registra en un sorted set de Redis (`bot:timers`, score = hora de
+//! disparo en ms) para sobrevivir a reinicios y repartirse entre instancias.
+//!
+//! - El worker reclama los timers vencidos con un script Lua que les da un
+//!   plazo (`CLAIM_LEASE`) en lugar de borrarlos: si la instancia muere antes
+//!   de procesarlos, vuelven a vencer y otra los toma
+//! - El disparo pasa por el buzón de la conversación y solo tiene efecto si el
+//!   timer sigue siendo el pendiente de la conversación; un timer cancelado o
+//!   repetido se descarta sin más
+//! - Cuando el cliente responde, el `FlowEngine` cancela el timer
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::{ConversationCommand, OrchestratorState};
+
+const TIMERS_KEY: &str = "bot:timers";
+
+/// Cada cuánto se buscan timers vencidos
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Plazo para procesar un timer reclamado antes de que vuelva a estar disponible
+pub const CLAIM_LEASE: Duration = Duration::from_secs(60);
+
+/// Cada cuánto se reintenta un timer vencido mientras su bot está pausado
+pub const PAUSED_RETRY: Duration = Duration::from_secs(60);
+
+/// Timers reclamados por vuelta
+pub const CLAIM_BATCH: usize = 100;
+
+/// Reclamar los timers vencidos (`score <= now`) moviéndolos a `now + lease`
+/// (también los controles de `expiry`)
+pub const CLAIM_SCRIPT: &str = r#"
+local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[3])
+for _, member in ipairs(due) do
+    redis.call('ZADD', KEYS[1], ARGV[2], member)
+end
+return due
+"#;
+
+/// Timer pendiente de una conversación
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingTimer {
+    pub id: Uuid,
+    pub flow_id: Uuid,
+    /// Step que lo programó (`Wait`, o un `Question`/`Menu` con `on_timeout`)
+    pub step_id: String,
+    pub fires_at: DateTime<Utc>,
+}
+
+impl PendingTimer {
+    pub fn new(flow_id: Uuid, step_id: &str, fires_at: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            flow_id,
+            step_id: step_id.to_string(),
+            fires_at,
+        }
+    }
+}
+
+/// Miembro del sorted set: `{timer_id}:{conversation_id}`
+fn member(conversation_id: &str, timer_id: Uuid) -> String {
+    format!("{}:{}", timer_id, conversation_id)
+}
+
+fn parse_member(member: &str) -> Option<(String, Uuid)> {
+    let (timer_id, conversation_id) = member.split_once(':')?;
+    Some((conversation_id.to_string(), timer_id.parse().ok()?))
+}
+
+pub struct TimerScheduler {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    claim: redis::Script,
+}
+
+impl TimerScheduler {
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            connection: OnceCell::new(),
+            claim: redis::Script::new(CLAIM_SCRIPT),
+        }
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connection = self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(connection.clone())
+    }
+
+    pub async fn schedule(&self, conversation_id: &str, timer: &PendingTimer) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: () = redis::cmd("ZADD")
+            .arg(TIMERS_KEY)
+            .arg(timer.fires_at.timestamp_millis())
+            .arg(member(conversation_id, timer.id))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to schedule timer")?;
+        Ok(())
+    }
+
+    /// Quitar un timer (cancelado, o ya procesado)
+    pub async fn remove(&self, conversation_id: &str, timer_id: Uuid) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: () = redis::cmd("ZREM")
+            .arg(TIMERS_KEY)
+            .arg(member(conversation_id, timer_id))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to remove timer")?;
+        Ok(())
+    }
+
+    /// Volver a programar para `at` un timer reclamado; si ya se quitó (se
+    /// canceló mientras tanto) no se vuelve a agregar
+    pub async fn postpone(&self, conversation_id: &str, timer_id: Uuid, at: DateTime<Utc>) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: () = redis::cmd("ZADD")
+            .arg(TIMERS_KEY)
+            .arg("XX")
+            .arg(at.timestamp_millis())
+            .arg(member(conversation_id, timer_id))
+            .query_async(&mut conn)
+            .await
+            .context("Failed to postpone timer")?;
+        Ok(())
+    }
+
+    /// Timers vencidos a `now`, reclamados por esta instancia durante `CLAIM_LEASE`
+    pub async fn claim_due(&self, now: DateTime<Utc>) -> Result<Vec<(String, Uuid)>> {
+        let mut conn = self.connection().await?;
+        let lease_until = now + chrono::Duration::from_std(CLAIM_LEASE).unwrap_or_default();
+
+        let due: Vec<String> = self.claim
+            .key(TIMERS_KEY)
+            .arg(now.timestamp_millis())
+            .arg(lease_until.timestamp_millis())
+            .arg(CLAIM_BATCH)
+            .invoke_async(&mut conn)
+            .await
+            .context("Failed to claim due timers")?;
+
+        Ok(due.iter().filter_map(|member| parse_member(member)).collect())
+    }
+
+    /// Registrar en Redis el cambio de timer de una conversación
+    pub async fn sync(&self, conversation_id: &str, before: Option<&PendingTimer>, after: Option<&PendingTimer>) {
+        if before.map(|timer| timer.id) == after.map(|timer| timer.id) {
+            return;
+        }
+        if let Some(timer) = before {
+            if let Err(e) = self.remove(conversation_id, timer.id).await {
+                warn!("Could not cancel timer of {}: {:#}", conversation_id, e);
+            }
+        }
+        if let Some(timer) = after {
+            if let Err(e) = self.schedule(conversation_id, timer).await {
+                warn!("Could not schedule timer of {}: {:#}", conversation_id, e);
+            }
+        }
+    }
+}
+
+/// Disparar los timers vencidos en el buzón de su conversación
+pub fn spawn_timer_worker(state: OrchestratorState) {
+    tokio::spawn(async move {
+        info!("⏰ Timer worker started");
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let due = match state.timers.claim_due(state.clock.now()).await {
+                Ok(due) => due,
+                Err(e) => {
+                    warn!("Could not poll timers: {:#}", e);
+                    continue;
+                }
+            };
+
+            for (conversation_id, timer_id) in due {
+                let key = conversation_id.clone();
+                super::dispatch_command(&state, &key, ConversationCommand::Timer { conversation_id, timer_id });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_roundtrip() {
+        let timer_id = Uuid::new_v4();
+        let conversation_id = format!("{}:+584141234567", Uuid::new_v4());
+
+        let encoded = member(&conversation_id, timer_id);
+        assert_eq!(parse_member(&encoded), Some((conversation_id, timer_id)));
+        assert_eq!(parse_member("not-a-timer"), None);
+    }
+}
@@ -1,10 +1,16 @@
-//\! State Machine - Gestión de estado de conversaciones
+//! State Machine - Gestión de estado de conversaciones
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+use super::event_log::ConversationEvent;
+use super::handoff::{FlowPosition, Handoff};
+use super::message::{MessageContent, QuotedMessage};
+use super::metrics::FlowOutcome;
+use super::scheduler::PendingTimer;
+
 /// Estado de una conversación
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversationState {
@@ -12,19 +18,77 @@ pub struct ConversationState {
     pub bot_id: Uuid,
     pub user_phone: String,
     pub current_flow_id: Option<Uuid>,
+    /// Versión del flow con la que empezó la conversación (ver `FlowEngine::get_flow`)
+    #[serde(default)]
+    pub current_flow_version: Option<u32>,
     pub current_step_id: Option<String>,
     pub context: HashMap<String, serde_json::Value>,
     pub message_history: Vec<ConversationMessage>,
     pub created_at: DateTime<Utc>,
     pub last_activity: DateTime<Utc>,
     pub metadata: HashMap<String, serde_json::Value>,
+    /// Respuestas inválidas por step (`flow_id:step_id`), para ver dónde se traban los usuarios
+    #[serde(default)]
+    pub attempts: HashMap<String, StepAttempts>,
+    /// Atención por un asesor humano; mientras exista, el bot no responde
+    #[serde(default)]
+    pub handoff: Option<Handoff>,
+    /// Timer pendiente del step actual (`Wait`, `on_timeout`)
+    #[serde(default)]
+    pub timer: Option<PendingTimer>,
+    /// Sub-flows en curso (`CallFlow`), el último es el caller del flow actual
+    #[serde(default)]
+    pub call_stack: Vec<CallFrame>,
+    /// Versión del último evento registrado en el log de la conversación (ver `event_log`)
+    #[serde(default)]
+    pub event_version: u64,
+    /// Flows principales que empezaron o terminaron desde la última vez que se
+    /// contaron en las métricas del bot; no se guarda
+    #[serde(skip)]
+    pub flow_outcomes: Vec<FlowOutcome>,
+    /// Cambios de flow y de step del comando actual, en el orden en que los
+    /// hizo el engine, para que `event_log` no registre solo el resultado; no se guarda
+    #[serde(skip)]
+    pub flow_events: Vec<ConversationEvent>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Llamada a un sub-flow: a dónde volver cuando termine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallFrame {
+    /// Flow que hizo la llamada, con la versión que estaba usando
+    pub flow_id: Uuid,
+    pub flow_version: Option<u32>,
+    /// Step del caller con el que se sigue (`None`: el caller también termina)
+    pub return_step: Option<String>,
+    /// Variables del caller; se restauran al volver, así lo que el sub-flow
+    /// guarde no se mezcla con ellas
+    pub context: HashMap<String, serde_json::Value>,
+    /// Variables del sub-flow que se copian al caller al volver (sub-flow -> caller)
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StepAttempts {
+    /// Respuestas inválidas seguidas en la visita actual al step
+    pub current: u32,
+    /// Respuestas inválidas en toda la conversación
+    pub total_failures: u32,
+    /// Veces que se agotaron los intentos
+    pub exhausted: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConversationMessage {
     pub role: String,
-    pub content: String,
+    pub content: MessageContent,
     pub timestamp: DateTime<Utc>,
+    /// Id del mensaje en el provider de WhatsApp
+    #[serde(default)]
+    pub provider_message_id: Option<String>,
+    /// Mensaje al que respondía el cliente
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quoted: Option<QuotedMessage>,
 }
 
 impl ConversationState {
@@ -35,23 +99,92 @@ impl ConversationState {
             bot_id,
             user_phone,
             current_flow_id: None,
+            current_flow_version: None,
             current_step_id: None,
             context: HashMap::new(),
             message_history: Vec::new(),
             created_at: now,
             last_activity: now,
             metadata: HashMap::new(),
+            attempts: HashMap::new(),
+            handoff: None,
+            timer: None,
+            call_stack: Vec::new(),
+            event_version: 0,
+            flow_outcomes: Vec::new(),
+            flow_events: Vec::new(),
         }
     }
     
-    pub fn add_message(&mut self, role: &str, content: &str) {
+    pub fn add_message(&mut self, role: &str, content: MessageContent) {
         self.message_history.push(ConversationMessage {
             role: role.to_string(),
-            content: content.to_string(),
+            content,
             timestamp: Utc::now(),
+            provider_message_id: None,
+            quoted: None,
         });
     }
     
+    /// Registrar un mensaje del cliente
+    pub fn add_received_message(
+        &mut self,
+        content: MessageContent,
+        provider_message_id: Option<String>,
+        quoted: Option<QuotedMessage>,
+    ) {
+        self.add_message("user", content);
+        if let Some(message) = self.message_history.last_mut() {
+            message.provider_message_id = provider_message_id;
+            message.quoted = quoted;
+        }
+    }
+    
+    /// Registrar un mensaje enviado al cliente por el bot (`bot`) o por un asesor
+    /// (`agent`) con el id que le asignó el provider (`None` si no se pudo entregar)
+    pub fn add_outgoing_message(&mut self, role: &str, content: MessageContent, provider_message_id: Option<String>) {
+        self.add_message(role, content);
+        if let Some(message) = self.message_history.last_mut() {
+            message.provider_message_id = provider_message_id;
+        }
+    }
+    
+    /// Pasar la conversación a un asesor; el step actual queda en `handoff.return_to`
+    /// (la pila de sub-flows se conserva para cuando vuelva)
+    pub fn start_handoff(&mut self, mut handoff: Handoff) {
+        handoff.return_to = match (self.current_flow_id, self.current_step_id.take()) {
+            (Some(flow_id), Some(step_id)) => Some(FlowPosition {
+                flow_id,
+                flow_version: self.current_flow_version,
+                step_id,
+            }),
+            _ => None,
+        };
+        self.current_flow_id = None;
+        self.current_flow_version = None;
+        self.timer = None;
+        self.handoff = Some(handoff);
+    }
+    
+    /// Terminar la atención del asesor; devuelve el handoff terminado
+    pub fn end_handoff(&mut self) -> Option<Handoff> {
+        self.handoff.take()
+    }
+    
+    /// Terminar el sub-flow actual: restaurar las variables del caller, copiarle
+    /// las salidas del sub-flow y devolver la llamada terminada
+    pub fn return_from_call(&mut self) -> Option<CallFrame> {
+        let mut frame = self.call_stack.pop()?;
+        let mut context = std::mem::take(&mut frame.context);
+        for (from, to) in &frame.outputs {
+            if let Some(value) = self.context.get(from) {
+                context.insert(to.clone(), value.clone());
+            }
+        }
+        self.context = context;
+        Some(frame)
+    }
+    
     pub fn set_variable(&mut self, key: &str, value: serde_json::Value) {
         self.context.insert(key.to_string(), value);
     }
@@ -63,4 +196,26 @@ impl ConversationState {
     pub fn update_last_activity(&mut self) {
         self.last_activity = Utc::now();
     }
+    
+    /// Registrar una respuesta inválida; devuelve los fallos seguidos en ese step
+    pub fn record_failed_attempt(&mut self, step_key: &str) -> u32 {
+        let attempts = self.attempts.entry(step_key.to_string()).or_default();
+        attempts.current += 1;
+        attempts.total_failures += 1;
+        attempts.current
+    }
+    
+    /// Registrar que se agotaron los intentos de un step
+    pub fn record_exhausted(&mut self, step_key: &str) {
+        let attempts = self.attempts.entry(step_key.to_string()).or_default();
+        attempts.current = 0;
+        attempts.exhausted += 1;
+    }
+    
+    /// El usuario respondió bien: reiniciar los fallos seguidos (se conserva el histórico)
+    pub fn reset_attempts(&mut self, step_key: &str) {
+        if let Some(attempts) = self.attempts.get_mut(step_key) {
+            attempts.current = 0;
+        }
+    }
 }
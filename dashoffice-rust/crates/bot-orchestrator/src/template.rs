@@ -0,0 +1,475 @@
+//! Templates - Render de los textos de los flows
+//!
+//! Sintaxis soportada:
+//! - Variables con rutas: `{{cliente.nombre}}`, `{{items.0.precio}}`
+//! - Filtros encadenados: `{{nombre | default: "cliente" | upper}}`
+//! - Bucles: `{{#each carrito}}- {{cantidad}} x {{nombre}}{{/each}}`
+//!   (dentro del bucle: `{{this}}`, `{{@index}}` desde 0 y `{{@number}}` desde 1)
+//!
+//! Filtros: `default`, `upper`, `lower`, `capitalize`, `currency` (USD / Bs),
+//! `date` (formato strftime), `pluralize`.
+//!
+//! Una variable que no existe se renderiza vacía (o con su `default`).
+//!
+//! En las URLs (`render_url`) cada valor se codifica (percent-encoding): una
+//! respuesta del cliente no puede cambiar la ruta ni agregar parámetros.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::expression::resolve_path;
+use super::state_machine::ConversationState;
+
+/// Zona horaria si el bot no tiene una configurada (o no es válida)
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::America::Caracas;
+
+/// Formato de fecha por defecto del filtro `date`
+const DEFAULT_DATE_FORMAT: &str = "%d/%m/%Y";
+
+pub const WEEKDAYS: [&str; 7] = ["lunes", "martes", "miércoles", "jueves", "viernes", "sábado", "domingo"];
+
+/// Renderizar un template con las variables de `context`
+pub fn render_template(template: &str, context: &HashMap<String, Value>) -> String {
+    let nodes = parse(template);
+    let mut output = String::with_capacity(template.len());
+    render_nodes(&nodes, context, &|text| text.to_string(), &mut output);
+    output
+}
+
+/// Renderizar una URL: el texto del template queda igual y cada valor se
+/// codifica como un segmento o parámetro (`a/b?c` → `a%2Fb%3Fc`)
+pub fn render_url(template: &str, context: &HashMap<String, Value>) -> String {
+    let nodes = parse(template);
+    let mut output = String::with_capacity(template.len());
+    render_nodes(&nodes, context, &url_encode, &mut output);
+    output
+}
+
+/// Percent-encoding de todo lo que no sea `A-Z a-z 0-9 - _ . ~` (RFC 3986)
+pub fn url_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Aplicar plantillas a todos los textos de un JSON
+pub fn render_json(value: &Value, context: &HashMap<String, Value>) -> Value {
+    match value {
+        Value::String(text) => Value::String(render_template(text, context)),
+        Value::Array(items) => Value::Array(items.iter().map(|v| render_json(v, context)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter().map(|(k, v)| (k.clone(), render_json(v, context))).collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Variables disponibles para los textos de una conversación: las del contexto,
+/// `bot` (nombre, zona horaria...) y `system` (`now` en la zona del bot; viene
+/// del `Clock` del engine)
+pub fn conversation_variables(conversation: &ConversationState, now: DateTime<Utc>) -> HashMap<String, Value> {
+    let mut variables = conversation.context.clone();
+    let bot = conversation.metadata.get("bot").cloned().unwrap_or(Value::Null);
+
+    variables.insert("system".to_string(), system_variables(now, conversation_timezone(conversation)));
+    variables.insert("bot".to_string(), bot);
+    variables
+}
+
+/// Zona horaria del bot de una conversación (`bot.timezone`, ver `BotInstance::template_variables`)
+pub fn conversation_timezone(conversation: &ConversationState) -> Tz {
+    conversation.metadata.get("bot")
+        .and_then(|bot| bot.get("timezone"))
+        .and_then(Value::as_str)
+        .and_then(parse_timezone)
+        .unwrap_or(DEFAULT_TIMEZONE)
+}
+
+/// Interpretar una zona horaria IANA (`America/Caracas`)
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.parse().ok()
+}
+
+/// Variables `system.*`: `now`, `date`, `time`, `hour`, `weekday`, `timezone`
+pub fn system_variables(now: DateTime<Utc>, timezone: Tz) -> Value {
+    let local = now.with_timezone(&timezone);
+    let weekday = chrono::Datelike::weekday(&local).num_days_from_monday() as usize;
+
+    serde_json::json!({
+        "now": local.to_rfc3339(),
+        "date": local.format("%d/%m/%Y").to_string(),
+        "time": local.format("%H:%M").to_string(),
+        "hour": chrono::Timelike::hour(&local),
+        "weekday": WEEKDAYS[weekday],
+        "timezone": timezone.name(),
+    })
+}
+
+// ============================================================================
+// PARSER
+// ============================================================================
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Variable { path: Vec<String>, filters: Vec<Filter> },
+    Each { path: Vec<String>, body: Vec<Node> },
+}
+
+#[derive(Debug, PartialEq)]
+struct Filter {
+    name: String,
+    args: Vec<Value>,
+}
+
+/// Parsear el template. Las etiquetas mal cerradas se dejan como texto.
+fn parse(template: &str) -> Vec<Node> {
+    // Pila de bloques abiertos: (ruta del each, nodos anteriores, texto de la etiqueta)
+    let mut stack: Vec<(Vec<String>, Vec<Node>, String)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else { break };
+        push_text(&mut nodes, &rest[..start]);
+
+        let raw = &rest[start..start + 2 + len + 2];
+        let tag = rest[start + 2..start + 2 + len].trim();
+        rest = &rest[start + 2 + len + 2..];
+
+        if let Some(path) = tag.strip_prefix("#each ") {
+            stack.push((split_path(path.trim()), std::mem::take(&mut nodes), raw.to_string()));
+        } else if tag == "/each" {
+            match stack.pop() {
+                Some((path, outer, _)) => {
+                    let body = std::mem::replace(&mut nodes, outer);
+                    nodes.push(Node::Each { path, body });
+                }
+                None => push_text(&mut nodes, raw),
+            }
+        } else {
+            nodes.push(parse_variable(tag));
+        }
+    }
+    push_text(&mut nodes, rest);
+
+    // `{{#each}}` sin `{{/each}}`: devolver su contenido como texto
+    while let Some((_, mut outer, raw)) = stack.pop() {
+        push_text(&mut outer, &raw);
+        outer.append(&mut nodes);
+        nodes = outer;
+    }
+
+    nodes
+}
+
+fn push_text(nodes: &mut Vec<Node>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match nodes.last_mut() {
+        Some(Node::Text(previous)) => previous.push_str(text),
+        _ => nodes.push(Node::Text(text.to_string())),
+    }
+}
+
+fn parse_variable(tag: &str) -> Node {
+    let mut parts = split_unquoted(tag, '|').into_iter();
+    let path = split_path(parts.next().unwrap_or_default().trim());
+
+    let filters = parts
+        .map(|part| {
+            let (name, args) = match part.split_once(':') {
+                Some((name, args)) => (name, split_unquoted(args, ',')),
+                None => (part.as_str(), Vec::new()),
+            };
+            Filter {
+                name: name.trim().to_lowercase(),
+                args: args.iter().map(|arg| parse_literal(arg.trim())).collect(),
+            }
+        })
+        .collect();
+
+    Node::Variable { path, filters }
+}
+
+fn split_path(path: &str) -> Vec<String> {
+    path.split('.').map(str::to_string).collect()
+}
+
+/// Separar por `separator` ignorando los que estén entre comillas
+fn split_unquoted(input: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+
+    for c in input.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (Some(q), _) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (None, _) if c == separator => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+fn parse_literal(arg: &str) -> Value {
+    let unquoted = arg
+        .strip_prefix('"').and_then(|a| a.strip_suffix('"'))
+        .or_else(|| arg.strip_prefix('\'').and_then(|a| a.strip_suffix('\'')));
+
+    match unquoted {
+        Some(text) => Value::String(text.to_string()),
+        None => arg.parse::<f64>().map(|n| serde_json::json!(n)).unwrap_or_else(|_| Value::String(arg.to_string())),
+    }
+}
+
+// ============================================================================
+// RENDER
+// ============================================================================
+
+/// `escape` se aplica a cada valor interpolado (no al texto del template)
+fn render_nodes(nodes: &[Node], context: &HashMap<String, Value>, escape: &dyn Fn(&str) -> String, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable { path, filters } => {
+                let mut value = resolve_path(context, path).cloned().unwrap_or(Value::Null);
+                for filter in filters {
+                    value = apply_filter(filter, value);
+                }
+                output.push_str(&escape(&display(&value)));
+            }
+            Node::Each { path, body } => {
+                let Some(Value::Array(items)) = resolve_path(context, path) else { continue };
+
+                for (index, item) in items.iter().enumerate() {
+                    let mut scope = context.clone();
+                    if let Value::Object(fields) = item {
+                        scope.extend(fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    }
+                    scope.insert("this".to_string(), item.clone());
+                    scope.insert("@index".to_string(), serde_json::json!(index));
+                    scope.insert("@number".to_string(), serde_json::json!(index + 1));
+                    render_nodes(body, &scope, escape, output);
+                }
+            }
+        }
+    }
+}
+
+fn apply_filter(filter: &Filter, value: Value) -> Value {
+    let arg = |i: usize| filter.args.get(i).map(display);
+
+    match filter.name.as_str() {
+        "default" => match value {
+            Value::Null => filter.args.first().cloned().unwrap_or(Value::Null),
+            Value::String(ref s) if s.is_empty() => filter.args.first().cloned().unwrap_or(value),
+            _ => value,
+        },
+        "upper" => Value::String(display(&value).to_uppercase()),
+        "lower" => Value::String(display(&value).to_lowercase()),
+        "capitalize" => {
+            let text = display(&value);
+            let mut chars = text.chars();
+            Value::String(match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            })
+        }
+        "currency" => match as_number(&value) {
+            Some(amount) => Value::String(format_currency(amount, arg(0).as_deref().unwrap_or("USD"))),
+            None => value,
+        },
+        "date" => {
+            let format = arg(0).unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string());
+            match as_datetime(&value).map(|datetime| format_date(datetime, &format)) {
+                Some(Some(formatted)) => Value::String(formatted),
+                Some(None) => {
+                    tracing::warn!("Invalid date format '{}' in template", format);
+                    value
+                }
+                None => value,
+            }
+        }
+        "pluralize" => {
+            let count = as_number(&value).unwrap_or(0.0);
+            let singular = arg(0).unwrap_or_default();
+            let plural = arg(1).unwrap_or_else(|| format!("{}s", singular));
+            Value::String(if count == 1.0 { singular } else { plural })
+        }
+        other => {
+            tracing::warn!("Unknown template filter '{}'", other);
+            value
+        }
+    }
+}
+
+/// `USD` → `$1,234.50`; `Bs` / `VES` → `Bs. 1.234,50`
+pub fn format_currency(amount: f64, currency: &str) -> String {
+    let negative = amount < 0.0;
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let (units, cents) = (cents / 100, cents % 100);
+
+    let (symbol, thousands, decimal) = match currency.to_uppercase().as_str() {
+        "BS" | "BS." | "VES" | "BSD" => ("Bs. ", '.', ','),
+        "EUR" => ("€", '.', ','),
+        _ => ("$", ',', '.'),
+    };
+
+    let digits = units.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(thousands);
+        }
+        grouped.push(digit);
+    }
+
+    format!("{}{}{}{}{:02}", if negative { "-" } else { "" }, symbol, grouped, decimal, cents)
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Fecha con un formato strftime escrito en el flow; `None` si el formato no
+/// es válido (`format(..).to_string()` entraría en pánico)
+fn format_date(datetime: NaiveDateTime, format: &str) -> Option<String> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", datetime.format(format)).ok()?;
+    Some(formatted)
+}
+
+fn as_datetime(value: &Value) -> Option<NaiveDateTime> {
+    let text = value.as_str()?.trim();
+
+    DateTime::parse_from_rfc3339(text)
+        .map(|dt| dt.naive_local())
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+}
+
+/// Texto que se muestra para un valor
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", f as i64),
+            _ => n.to_string(),
+        },
+        Value::Bool(b) => b.to_string(),
+        Value::Array(items) => items.iter().map(display).collect::<Vec<_>>().join(", "),
+        Value::Object(_) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_paths_defaults_and_non_string_values() {
+        let ctx = context(serde_json::json!({
+            "cliente": { "nombre": "ana" },
+            "cantidad": 3,
+            "activo": true,
+        }));
+
+        assert_eq!(
+            render_template("Hola {{cliente.nombre | capitalize}}, {{cantidad}} {{activo}}", &ctx),
+            "Hola Ana, 3 true"
+        );
+        assert_eq!(render_template("Hola {{apellido | default: \"cliente\" | upper}}", &ctx), "Hola CLIENTE");
+        assert_eq!(render_template("[{{nada}}]", &ctx), "[]");
+    }
+
+    #[test]
+    fn test_url_values_are_encoded() {
+        let ctx = context(serde_json::json!({
+            "pedido": "x/../../admin?y=",
+            "q": "a&token=1",
+            "nombre": "José Pérez",
+        }));
+
+        assert_eq!(
+            render_url("https://api.local/orders/{{pedido}}?q={{q}}&n={{nombre}}", &ctx),
+            "https://api.local/orders/x%2F..%2F..%2Fadmin%3Fy%3D?q=a%26token%3D1&n=Jos%C3%A9%20P%C3%A9rez"
+        );
+    }
+
+    #[test]
+    fn test_currency_date_and_pluralize() {
+        let ctx = context(serde_json::json!({ "total": 1234.5, "fecha": "2026-03-15", "n": 1 }));
+
+        assert_eq!(render_template("{{total | currency}}", &ctx), "$1,234.50");
+        assert_eq!(render_template("{{total | currency: \"Bs\"}}", &ctx), "Bs. 1.234,50");
+        assert_eq!(render_template("{{fecha | date}}", &ctx), "15/03/2026");
+        assert_eq!(render_template("{{fecha | date: \"%d-%m\"}}", &ctx), "15-03");
+        // Un formato inválido deja la fecha como estaba
+        assert_eq!(render_template("{{fecha | date: \"%Q\"}}", &ctx), "2026-03-15");
+        assert_eq!(render_template("{{n}} {{n | pluralize: \"producto\"}}", &ctx), "1 producto");
+        assert_eq!(render_template("{{total | pluralize: \"flor\", \"flores\"}}", &ctx), "flores");
+    }
+
+    #[test]
+    fn test_each_over_cart_items() {
+        let ctx = context(serde_json::json!({
+            "carrito": [
+                { "nombre": "Rosa", "precio": 2 },
+                { "nombre": "Tulipán", "precio": 3.5 },
+            ],
+        }));
+
+        let template = "{{#each carrito}}{{@number}}. {{nombre}} {{precio | currency}}\n{{/each}}Fin";
+        assert_eq!(render_template(template, &ctx), "1. Rosa $2.00\n2. Tulipán $3.50\nFin");
+        assert_eq!(render_template("{{#each carrito}}{{nombre}}", &ctx), "{{#each carrito}}");
+    }
+
+    #[test]
+    fn test_system_variables_use_bot_timezone() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 16, 2, 30, 0).unwrap();
+        let system = system_variables(now, parse_timezone("America/Caracas").unwrap());
+
+        assert_eq!(system["date"], "15/03/2026");
+        assert_eq!(system["time"], "22:30");
+        assert_eq!(system["weekday"], "domingo");
+    }
+
+    #[test]
+    fn test_conversation_variables_use_given_time() {
+        let mut conversation = ConversationState::new("c1".to_string(), uuid::Uuid::new_v4(), "+58".to_string());
+        conversation.metadata.insert("bot".to_string(), serde_json::json!({ "timezone": "Europe/Madrid" }));
+        let now = Utc.with_ymd_and_hms(2026, 3, 16, 2, 30, 0).unwrap();
+
+        let variables = conversation_variables(&conversation, now);
+        assert_eq!(render_template("{{system.date}} {{system.time}}", &variables), "16/03/2026 03:30");
+    }
+}
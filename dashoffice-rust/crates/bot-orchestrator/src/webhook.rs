@@ -1,10 +1,80 @@
-//\! Webhook Handlers - Manejo de webhooks de diferentes providers
+//! Webhook Handlers - Manejo de webhooks de diferentes providers
 
-use actix_web::{web, HttpResponse, Responder};
-use serde::{Deserialize, Serialize};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
 use uuid::Uuid;
 
-use super::{OrchestratorState, IncomingMessage};
+use super::{dedup, BotEvent, OrchestratorState, IncomingMessage};
+use super::message::{MessageContent, QuotedMessage};
+
+mod baileys;
+mod meta;
+
+pub use baileys::BaileysWebhook;
+pub use meta::MetaConfig;
+
+/// Mensaje de un cliente ya extraído del payload de un provider
+#[derive(Debug, PartialEq)]
+pub struct ParsedMessage {
+    pub from: String,
+    pub content: MessageContent,
+    pub quoted: Option<QuotedMessage>,
+    /// Segundos Unix
+    pub timestamp: Option<i64>,
+    /// Id del mensaje en el provider
+    pub id: Option<String>,
+}
+
+impl ParsedMessage {
+    fn into_incoming(self, bot_id: Uuid) -> IncomingMessage {
+        IncomingMessage {
+            bot_id,
+            from: self.from,
+            content: self.content,
+            quoted: self.quoted,
+            timestamp: self.timestamp
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                .unwrap_or_else(chrono::Utc::now),
+            provider_message_id: self.id,
+        }
+    }
+}
+
+/// Encolar un mensaje salvo que sea el reintento de uno ya recibido
+///
+/// Los providers reenvían el webhook si no reciben respuesta a tiempo; los
+/// duplicados se descartan aquí y el handler responde 200 igualmente.
+/// `provider_timestamp` es el que trae el payload (segundos Unix), no la
+/// hora de llegada que se usa en `msg.timestamp` cuando falta. Los mensajes
+/// sin id ni timestamp siempre se procesan (ver `dedup`).
+async fn dispatch_once(state: &OrchestratorState, msg: IncomingMessage, provider_timestamp: Option<i64>) {
+    let key = dedup::dedup_key(
+        &msg.bot_id,
+        msg.provider_message_id.as_deref(),
+        &msg.from,
+        provider_timestamp,
+        &serde_json::to_string(&msg.content).unwrap_or_default(),
+    );
+
+    let first = match &key {
+        Some(key) => state.dedup.first_seen(key).await,
+        None => true,
+    };
+    if first {
+        super::dispatch_message(state, msg);
+    } else {
+        tracing::info!("🔁 Duplicate webhook message from {} ignored", msg.from);
+    }
+}
+
+/// Buscar el bot cuya `provider_config` tiene `field == value`
+fn find_bot_by_session(state: &OrchestratorState, field: &str, value: &str) -> Option<Uuid> {
+    state.bots.iter()
+        .find(|entry| {
+            entry.value().provider_config.get(field).and_then(|v| v.as_str()) == Some(value)
+        })
+        .map(|entry| *entry.key())
+}
 
 /// Webhook de Venom
 #[derive(Debug, Deserialize)]
@@ -13,46 +83,35 @@ pub struct VenomWebhook {
     pub from: String,
     pub message: String,
     pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub message_id: Option<String>,
 }
 
 pub async fn handle_venom_webhook(
     state: web::Data<OrchestratorState>,
     payload: web::Json<VenomWebhook>,
 ) -> impl Responder {
-    tracing::info\!("🕷️ Venom webhook from {}", payload.from);
+    tracing::info!("🕷️ Venom webhook from {}", payload.from);
     
     // Encontrar bot por session_name
-    let bot = state.bots.iter()
-        .find(|entry| {
-            if let Some(config) = entry.value().provider_config.get("session_name") {
-                config.as_str() == Some(&payload.session_name)
-            } else {
-                false
-            }
-        });
+    let bot = find_bot_by_session(&state, "session_name", &payload.session_name);
     
-    if let Some(bot_entry) = bot {
-        let bot_id = *bot_entry.key();
-        
+    if let Some(bot_id) = bot {
         let msg = IncomingMessage {
             bot_id,
             from: payload.from.clone(),
-            message: payload.message.clone(),
-            message_type: "text".to_string(),
+            content: MessageContent::text(payload.message.clone()),
+            quoted: None,
             timestamp: payload.timestamp.unwrap_or_else(chrono::Utc::now),
+            provider_message_id: payload.message_id.clone(),
         };
         
-        // Procesar en background
-        let state_clone = state.into_inner();
-        tokio::spawn(async move {
-            if let Err(e) = super::process_message(&state_clone, msg).await {
-                tracing::error\!("Error processing Venom webhook: {}", e);
-            }
-        });
+        // Procesar en background (en orden dentro de la conversación)
+        dispatch_once(&state, msg, payload.timestamp.map(|ts| ts.timestamp())).await;
         
-        HttpResponse::Ok().json(serde_json::json\!({ "status": "ok" }))
+        HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
     } else {
-        HttpResponse::NotFound().json(serde_json::json\!({ "error": "Bot not found" }))
+        HttpResponse::NotFound().json(serde_json::json!({ "error": "Bot not found" }))
     }
 }
 
@@ -63,26 +122,19 @@ pub struct WWebJSWebhook {
     pub from: String,
     pub body: String,
     pub timestamp: Option<i64>,
+    #[serde(default)]
+    pub message_id: Option<String>,
 }
 
 pub async fn handle_wwebjs_webhook(
     state: web::Data<OrchestratorState>,
     payload: web::Json<WWebJSWebhook>,
 ) -> impl Responder {
-    tracing::info\!("🌐 WWebJS webhook from {}", payload.from);
+    tracing::info!("🌐 WWebJS webhook from {}", payload.from);
     
-    let bot = state.bots.iter()
-        .find(|entry| {
-            if let Some(config) = entry.value().provider_config.get("session_id") {
-                config.as_str() == Some(&payload.session_id)
-            } else {
-                false
-            }
-        });
+    let bot = find_bot_by_session(&state, "session_id", &payload.session_id);
     
-    if let Some(bot_entry) = bot {
-        let bot_id = *bot_entry.key();
-        
+    if let Some(bot_id) = bot {
         let timestamp = payload.timestamp
             .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
             .unwrap_or_else(chrono::Utc::now);
@@ -90,32 +142,114 @@ pub async fn handle_wwebjs_webhook(
         let msg = IncomingMessage {
             bot_id,
             from: payload.from.clone(),
-            message: payload.body.clone(),
-            message_type: "text".to_string(),
+            content: MessageContent::text(payload.body.clone()),
+            quoted: None,
             timestamp,
+            provider_message_id: payload.message_id.clone(),
         };
         
-        let state_clone = state.into_inner();
-        tokio::spawn(async move {
-            if let Err(e) = super::process_message(&state_clone, msg).await {
-                tracing::error\!("Error processing WWebJS webhook: {}", e);
-            }
-        });
+        dispatch_once(&state, msg, payload.timestamp).await;
         
-        HttpResponse::Ok().json(serde_json::json\!({ "status": "ok" }))
+        HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
     } else {
-        HttpResponse::NotFound().json(serde_json::json\!({ "error": "Bot not found" }))
+        HttpResponse::NotFound().json(serde_json::json!({ "error": "Bot not found" }))
     }
 }
 
-/// Webhook de Baileys
+/// Webhook de Baileys (evento `messages.upsert`, ver `baileys`)
 pub async fn handle_baileys_webhook(
     state: web::Data<OrchestratorState>,
-    payload: web::Json<serde_json::Value>,
+    payload: web::Json<BaileysWebhook>,
 ) -> impl Responder {
-    tracing::info\!("⚡ Baileys webhook");
+    tracing::info!("⚡ Baileys webhook for session {}", payload.session_id);
     
-    // TODO: Parsear formato de Baileys
+    let Some(bot_id) = find_bot_by_session(&state, "session_id", &payload.session_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Bot not found" }));
+    };
     
-    HttpResponse::Ok().json(serde_json::json\!({ "status": "ok" }))
+    for message in payload.customer_messages() {
+        let timestamp = message.timestamp;
+        dispatch_once(&state, message.into_incoming(bot_id), timestamp).await;
+    }
+    
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Verificación de la suscripción del webhook de Meta
+pub async fn verify_meta_webhook(
+    config: web::Data<MetaConfig>,
+    query: web::Query<meta::VerifyQuery>,
+) -> impl Responder {
+    match query.accept(&config) {
+        Some(challenge) => {
+            tracing::info!("✅ Meta webhook verified");
+            HttpResponse::Ok().content_type("text/plain").body(challenge.to_string())
+        }
+        None => {
+            tracing::warn!("❌ Meta webhook verification failed (check META_VERIFY_TOKEN)");
+            HttpResponse::Forbidden().finish()
+        }
+    }
+}
+
+/// Webhook de WhatsApp Cloud API (ver `meta`)
+///
+/// Responde 200 a toda notificación con firma válida, aunque no tenga un bot
+/// asociado o traiga elementos mal formados: Meta reintenta las que reciben
+/// error.
+pub async fn handle_meta_webhook(
+    state: web::Data<OrchestratorState>,
+    config: web::Data<MetaConfig>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    let Some(app_secret) = config.app_secret.as_deref() else {
+        tracing::error!("META_APP_SECRET is not set, rejecting Meta webhook");
+        return HttpResponse::Unauthorized().finish();
+    };
+    let signature = req.headers()
+        .get("X-Hub-Signature-256")
+        .and_then(|value| value.to_str().ok());
+    if !meta::verify_signature(app_secret, &body, signature) {
+        tracing::warn!("❌ Meta webhook with invalid signature");
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    // Un reintento traería el mismo body: se responde 200 para no recibirlo de nuevo
+    let payload: meta::MetaWebhook = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("Invalid Meta webhook payload, ignoring it: {}", e);
+            return HttpResponse::Ok().json(serde_json::json!({ "status": "ignored" }));
+        }
+    };
+    tracing::info!("📘 Meta webhook ({})", payload.object);
+
+    for (phone_number_id, message) in payload.customer_messages() {
+        match find_bot_by_session(&state, "phone_number_id", &phone_number_id) {
+            Some(bot_id) => {
+                let timestamp = message.timestamp;
+                dispatch_once(&state, message.into_incoming(bot_id), timestamp).await
+            }
+            None => tracing::warn!("No bot for Meta phone_number_id {}", phone_number_id),
+        }
+    }
+
+    for (phone_number_id, status) in payload.statuses() {
+        let Some(bot_id) = find_bot_by_session(&state, "phone_number_id", &phone_number_id) else {
+            continue;
+        };
+        state.event_bus.send(BotEvent::MessageStatus {
+            bot_id,
+            provider_message_id: status.id.clone(),
+            recipient: status.recipient_id.clone(),
+            status: status.status.clone(),
+            error: status.errors.first().map(|e| format!("{} {}", e.code, e.title)),
+            timestamp: status.timestamp.parse().ok()
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                .unwrap_or_else(chrono::Utc::now),
+        });
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
 }
@@ -0,0 +1,458 @@
+//! Baileys - Formato del evento `messages.upsert`
+//!
+//! El bridge de Baileys reenvía el evento tal cual lo emite el socket:
+//!
+//! ```json
+//! {
+//!   "session_id": "tienda_centro",
+//!   "event": "messages.upsert",
+//!   "data": {
+//!     "type": "notify",
+//!     "messages": [{ "key": { "remoteJid": "584141234567@s.whatsapp.net", "fromMe": false, "id": "3EB0..." },
+//!                    "messageTimestamp": 1760000000, "pushName": "Ana",
+//!                    "message": { "conversation": "hola" } }]
+//!   }
+//! }
+//! ```
+//!
+//! Se ignoran los mensajes propios (`fromMe`), los de grupos y estados, y
+//! los `upsert` que no son `notify` (sincronización de historial).
+
+use serde::{Deserialize, Deserializer};
+
+use super::ParsedMessage;
+use crate::message::{Contact, Location, Media, MessageContent, QuotedMessage};
+
+#[derive(Debug, Deserialize)]
+pub struct BaileysWebhook {
+    pub session_id: String,
+    #[serde(default)]
+    pub event: Option<String>,
+    pub data: MessagesUpsert,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesUpsert {
+    #[serde(rename = "type", default)]
+    pub upsert_type: Option<String>,
+    #[serde(default)]
+    pub messages: Vec<WebMessageInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebMessageInfo {
+    pub key: MessageKey,
+    #[serde(default)]
+    pub message: Option<ProtoMessage>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub message_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageKey {
+    pub remote_jid: String,
+    #[serde(default)]
+    pub from_me: bool,
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// Contenido del mensaje (`proto.IMessage`, solo los tipos que entiende el orchestrator)
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtoMessage {
+    pub conversation: Option<String>,
+    pub extended_text_message: Option<TextMessage>,
+    pub image_message: Option<MediaMessage>,
+    pub video_message: Option<MediaMessage>,
+    pub audio_message: Option<MediaMessage>,
+    pub document_message: Option<MediaMessage>,
+    pub sticker_message: Option<MediaMessage>,
+    pub location_message: Option<LocationMessage>,
+    pub contact_message: Option<ContactMessage>,
+    pub contacts_array_message: Option<ContactsArrayMessage>,
+    pub reaction_message: Option<ReactionMessage>,
+    pub buttons_response_message: Option<ButtonsResponse>,
+    pub template_button_reply_message: Option<TemplateButtonReply>,
+    pub list_response_message: Option<ListResponse>,
+    /// Mensajes temporales y de una sola vista envuelven al mensaje real
+    pub ephemeral_message: Option<Box<WrappedMessage>>,
+    pub view_once_message: Option<Box<WrappedMessage>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WrappedMessage {
+    pub message: Option<ProtoMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextMessage {
+    pub text: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMessage {
+    pub url: Option<String>,
+    pub mimetype: Option<String>,
+    pub caption: Option<String>,
+    pub file_name: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+/// Mensaje citado en una respuesta
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextInfo {
+    pub stanza_id: Option<String>,
+    pub quoted_message: Option<Box<ProtoMessage>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationMessage {
+    pub degrees_latitude: f64,
+    pub degrees_longitude: f64,
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactMessage {
+    pub display_name: Option<String>,
+    pub vcard: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContactsArrayMessage {
+    #[serde(default)]
+    pub contacts: Vec<ContactMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionMessage {
+    pub key: Option<ReactionKey>,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionKey {
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ButtonsResponse {
+    pub selected_button_id: Option<String>,
+    pub selected_display_text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateButtonReply {
+    pub selected_id: Option<String>,
+    pub selected_display_text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse {
+    pub title: Option<String>,
+    pub single_select_reply: Option<SingleSelectReply>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleSelectReply {
+    pub selected_row_id: Option<String>,
+}
+
+impl BaileysWebhook {
+    /// Mensajes de clientes que el bot debe procesar
+    pub fn customer_messages(&self) -> Vec<ParsedMessage> {
+        if self.event.as_deref().is_some_and(|event| event != "messages.upsert") {
+            return Vec::new();
+        }
+        if self.data.upsert_type.as_deref().is_some_and(|t| t != "notify") {
+            return Vec::new();
+        }
+
+        self.data.messages.iter()
+            .filter(|m| !m.key.from_me && is_direct_chat(&m.key.remote_jid))
+            .filter_map(|m| {
+                let message = m.message.as_ref()?.unwrapped()?;
+                Some(ParsedMessage {
+                    from: m.key.remote_jid.clone(),
+                    content: message.content()?,
+                    quoted: message.quoted(),
+                    timestamp: m.message_timestamp,
+                    id: m.key.id.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl ProtoMessage {
+    /// El mensaje real dentro de los envoltorios temporales / de una sola vista
+    fn unwrapped(&self) -> Option<&ProtoMessage> {
+        match self.ephemeral_message.as_ref().or(self.view_once_message.as_ref()) {
+            Some(inner) => inner.message.as_ref()?.unwrapped(),
+            None => Some(self),
+        }
+    }
+
+    /// Contenido tipado. `None` si es un tipo no soportado (encuestas, llamadas...).
+    pub fn content(&self) -> Option<MessageContent> {
+        let message = self.unwrapped()?;
+
+        if let Some(text) = &message.conversation {
+            Some(MessageContent::text(text.clone()))
+        } else if let Some(extended) = &message.extended_text_message {
+            Some(MessageContent::text(extended.text.clone().unwrap_or_default()))
+        } else if let Some(reply) = &message.buttons_response_message {
+            let id = reply.selected_button_id.clone().or_else(|| reply.selected_display_text.clone());
+            Some(MessageContent::ButtonReply {
+                id: id.unwrap_or_default(),
+                title: reply.selected_display_text.clone(),
+            })
+        } else if let Some(reply) = &message.template_button_reply_message {
+            let id = reply.selected_id.clone().or_else(|| reply.selected_display_text.clone());
+            Some(MessageContent::ButtonReply {
+                id: id.unwrap_or_default(),
+                title: reply.selected_display_text.clone(),
+            })
+        } else if let Some(reply) = &message.list_response_message {
+            let id = reply.single_select_reply.as_ref()
+                .and_then(|r| r.selected_row_id.clone())
+                .or_else(|| reply.title.clone());
+            Some(MessageContent::ListReply {
+                id: id.unwrap_or_default(),
+                title: reply.title.clone(),
+            })
+        } else if let Some(image) = &message.image_message {
+            Some(MessageContent::Image(image.to_media()))
+        } else if let Some(video) = &message.video_message {
+            Some(MessageContent::Video(video.to_media()))
+        } else if let Some(audio) = &message.audio_message {
+            Some(MessageContent::Audio(audio.to_media()))
+        } else if let Some(document) = &message.document_message {
+            Some(MessageContent::Document(document.to_media()))
+        } else if let Some(sticker) = &message.sticker_message {
+            Some(MessageContent::Sticker(sticker.to_media()))
+        } else if let Some(location) = &message.location_message {
+            Some(MessageContent::Location(Location {
+                latitude: location.degrees_latitude,
+                longitude: location.degrees_longitude,
+                name: location.name.clone(),
+                address: location.address.clone(),
+            }))
+        } else if let Some(contact) = &message.contact_message {
+            Some(MessageContent::Contacts { contacts: vec![contact.to_contact()] })
+        } else if let Some(array) = &message.contacts_array_message {
+            Some(MessageContent::Contacts {
+                contacts: array.contacts.iter().map(ContactMessage::to_contact).collect(),
+            })
+        } else {
+            message.reaction_message.as_ref().map(|reaction| MessageContent::Reaction {
+                message_id: reaction.key.as_ref().and_then(|key| key.id.clone()),
+                emoji: reaction.text.clone(),
+            })
+        }
+    }
+
+    /// Mensaje citado, si el cliente respondió a un mensaje anterior
+    fn quoted(&self) -> Option<QuotedMessage> {
+        let context = [
+            self.extended_text_message.as_ref().and_then(|m| m.context_info.as_ref()),
+            self.image_message.as_ref().and_then(|m| m.context_info.as_ref()),
+            self.video_message.as_ref().and_then(|m| m.context_info.as_ref()),
+            self.audio_message.as_ref().and_then(|m| m.context_info.as_ref()),
+            self.document_message.as_ref().and_then(|m| m.context_info.as_ref()),
+            self.sticker_message.as_ref().and_then(|m| m.context_info.as_ref()),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|context| context.stanza_id.is_some() || context.quoted_message.is_some())?;
+
+        Some(QuotedMessage {
+            message_id: context.stanza_id.clone(),
+            text: context.quoted_message.as_ref()
+                .and_then(|quoted| quoted.content())
+                .and_then(|content| content.as_text().map(str::to_string)),
+        })
+    }
+}
+
+impl MediaMessage {
+    fn to_media(&self) -> Media {
+        Media {
+            id: None,
+            url: self.url.clone(),
+            mime_type: self.mimetype.clone(),
+            caption: self.caption.clone(),
+            filename: self.file_name.clone(),
+        }
+    }
+}
+
+impl ContactMessage {
+    fn to_contact(&self) -> Contact {
+        let phones = self.vcard.as_deref().map(vcard_phones).unwrap_or_default();
+        Contact {
+            name: self.display_name.clone().unwrap_or_default(),
+            phones,
+        }
+    }
+}
+
+/// Teléfonos de una vCard: el `waid` si lo trae, si no el valor de `TEL`
+///
+/// `TEL;type=CELL;type=VOICE;waid=584245556677:+58 424-5556677`
+fn vcard_phones(vcard: &str) -> Vec<String> {
+    vcard.lines()
+        .filter(|line| line.to_uppercase().starts_with("TEL") || line.contains(".TEL"))
+        .filter_map(|line| {
+            let (params, value) = line.split_once(':')?;
+            let waid = params.split(';').find_map(|param| param.strip_prefix("waid="));
+            let phone = waid.unwrap_or(value).trim();
+            (!phone.is_empty()).then(|| phone.to_string())
+        })
+        .collect()
+}
+
+/// Chats individuales (no grupos, estados ni canales)
+fn is_direct_chat(jid: &str) -> bool {
+    jid.ends_with("@s.whatsapp.net") || jid.ends_with("@c.us") || jid.ends_with("@lid")
+}
+
+/// `messageTimestamp` llega como número, como string o como `{low, high}` (Long de protobuf)
+fn deserialize_timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Number(i64),
+        Text(String),
+        Long { low: i64, high: i64 },
+    }
+
+    Ok(match Option::<Timestamp>::deserialize(deserializer)? {
+        Some(Timestamp::Number(n)) => Some(n),
+        Some(Timestamp::Text(text)) => text.parse().ok(),
+        Some(Timestamp::Long { low, high }) => Some((high << 32) | (low & 0xFFFF_FFFF)),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(messages: serde_json::Value) -> BaileysWebhook {
+        serde_json::from_value(serde_json::json!({
+            "session_id": "tienda",
+            "event": "messages.upsert",
+            "data": { "type": "notify", "messages": messages }
+        }))
+        .unwrap()
+    }
+
+    fn message(jid: &str, from_me: bool, content: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "key": { "remoteJid": jid, "fromMe": from_me, "id": "3EB0C767D26A" },
+            "messageTimestamp": { "low": 1760000000, "high": 0, "unsigned": true },
+            "pushName": "Ana",
+            "message": content
+        })
+    }
+
+    #[test]
+    fn test_parses_supported_message_kinds() {
+        let jid = "584141234567@s.whatsapp.net";
+        let payload = webhook(serde_json::json!([
+            message(jid, false, serde_json::json!({ "conversation": "hola" })),
+            message(jid, false, serde_json::json!({ "extendedTextMessage": { "text": "mira https://x.co" } })),
+            message(jid, false, serde_json::json!({ "imageMessage": { "caption": "este modelo", "mimetype": "image/jpeg" } })),
+            message(jid, false, serde_json::json!({ "audioMessage": { "mimetype": "audio/ogg", "ptt": true } })),
+            message(jid, false, serde_json::json!({ "buttonsResponseMessage": { "selectedButtonId": "1", "selectedDisplayText": "Catálogo" } })),
+            message(jid, false, serde_json::json!({ "listResponseMessage": { "title": "Rosas", "singleSelectReply": { "selectedRowId": "rosas" } } })),
+            message(jid, false, serde_json::json!({ "ephemeralMessage": { "message": { "conversation": "temporal" } } })),
+            message(jid, false, serde_json::json!({ "reactionMessage": { "key": { "id": "BAE5F1" }, "text": "👍" } })),
+            message(jid, false, serde_json::json!({ "pollCreationMessage": { "name": "¿?" } })),
+        ]));
+
+        let parsed: Vec<_> = payload.customer_messages().into_iter()
+            .map(|m| m.content.summary())
+            .collect();
+
+        assert_eq!(parsed, vec![
+            "hola",
+            "mira https://x.co",
+            "[image] este modelo",
+            "[audio]",
+            "[button_reply] Catálogo",
+            "[list_reply] Rosas",
+            "temporal",
+            "[reaction] 👍",
+        ]);
+    }
+
+    #[test]
+    fn test_location_contacts_and_quoted_replies() {
+        let jid = "584141234567@s.whatsapp.net";
+        let payload = webhook(serde_json::json!([
+            message(jid, false, serde_json::json!({ "locationMessage": {
+                "degreesLatitude": 10.4806, "degreesLongitude": -66.9036, "name": "Tienda Centro"
+            } })),
+            message(jid, false, serde_json::json!({ "contactMessage": {
+                "displayName": "Luis",
+                "vcard": "BEGIN:VCARD\nVERSION:3.0\nFN:Luis\nTEL;type=CELL;type=VOICE;waid=584245556677:+58 424-5556677\nEND:VCARD"
+            } })),
+            message(jid, false, serde_json::json!({ "extendedTextMessage": {
+                "text": "este",
+                "contextInfo": { "stanzaId": "3EB0AA", "quotedMessage": { "conversation": "¿Qué color prefieres?" } }
+            } })),
+        ]));
+
+        let parsed = payload.customer_messages();
+        assert_eq!(parsed[0].content, MessageContent::Location(Location {
+            latitude: 10.4806,
+            longitude: -66.9036,
+            name: Some("Tienda Centro".to_string()),
+            address: None,
+        }));
+        assert_eq!(parsed[1].content, MessageContent::Contacts {
+            contacts: vec![Contact { name: "Luis".to_string(), phones: vec!["584245556677".to_string()] }],
+        });
+        assert_eq!(parsed[2].quoted, Some(QuotedMessage {
+            message_id: Some("3EB0AA".to_string()),
+            text: Some("¿Qué color prefieres?".to_string()),
+        }));
+    }
+
+    #[test]
+    fn test_skips_own_group_and_history_messages() {
+        let payload = webhook(serde_json::json!([
+            message("584141234567@s.whatsapp.net", true, serde_json::json!({ "conversation": "enviado por el bot" })),
+            message("120363025246125486@g.us", false, serde_json::json!({ "conversation": "hola grupo" })),
+            message("status@broadcast", false, serde_json::json!({ "conversation": "estado" })),
+            message("584141234567@s.whatsapp.net", false, serde_json::json!({ "conversation": "hola" })),
+        ]));
+
+        let parsed = payload.customer_messages();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].from, "584141234567@s.whatsapp.net");
+        assert_eq!(parsed[0].timestamp, Some(1760000000));
+        assert_eq!(parsed[0].id.as_deref(), Some("3EB0C767D26A"));
+
+        let mut history = payload;
+        history.data.upsert_type = Some("append".to_string());
+        assert!(history.customer_messages().is_empty());
+    }
+}
@@ -0,0 +1,499 @@
+//! Meta - Webhook de WhatsApp Cloud API
+//!
+//! - `GET`: verificación de la suscripción (`hub.mode`, `hub.verify_token`, `hub.challenge`)
+//! - `POST`: notificaciones firmadas con `X-Hub-Signature-256` (HMAC-SHA256 del
+//!   body con el app secret). Cada notificación trae `entry[].changes[].value`
+//!   con mensajes de clientes y/o estados de los mensajes enviados.
+//!
+//! El bot se identifica por el `phone_number_id` de `value.metadata`, que debe
+//! coincidir con `provider_config.phone_number_id`.
+//!
+//! Los elementos mal formados (un mensaje, un estado, un cambio) se descartan
+//! con un aviso y el resto del lote se procesa: si el webhook respondiera con
+//! error, Meta reenviaría el lote entero, incluidos los mensajes válidos.
+
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use sha2::Sha256;
+
+use super::ParsedMessage;
+use crate::message::{self, MessageContent, QuotedMessage};
+
+/// Credenciales del webhook (`META_VERIFY_TOKEN`, `META_APP_SECRET`)
+#[derive(Debug, Clone, Default)]
+pub struct MetaConfig {
+    pub verify_token: Option<String>,
+    pub app_secret: Option<String>,
+}
+
+impl MetaConfig {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Self {
+            verify_token: var("META_VERIFY_TOKEN"),
+            app_secret: var("META_APP_SECRET"),
+        }
+    }
+}
+
+/// Parámetros de la verificación de la suscripción
+#[derive(Debug, Deserialize)]
+pub struct VerifyQuery {
+    #[serde(rename = "hub.mode")]
+    pub mode: Option<String>,
+    #[serde(rename = "hub.verify_token")]
+    pub verify_token: Option<String>,
+    #[serde(rename = "hub.challenge")]
+    pub challenge: Option<String>,
+}
+
+impl VerifyQuery {
+    /// El `challenge` a devolver si el token coincide
+    pub fn accept(&self, config: &MetaConfig) -> Option<&str> {
+        let expected = config.verify_token.as_deref()?;
+        if self.mode.as_deref() == Some("subscribe") && self.verify_token.as_deref() == Some(expected) {
+            self.challenge.as_deref()
+        } else {
+            None
+        }
+    }
+}
+
+/// Comprobar `X-Hub-Signature-256: sha256=<hex>` contra el body recibido
+pub fn verify_signature(app_secret: &str, body: &[u8], header: Option<&str>) -> bool {
+    let Some(signature) = header.and_then(|h| h.strip_prefix("sha256=")) else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(app_secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    // Comparación en tiempo constante
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetaWebhook {
+    pub object: String,
+    #[serde(default, deserialize_with = "skip_invalid")]
+    pub entry: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    #[serde(default, deserialize_with = "skip_invalid")]
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Change {
+    pub field: String,
+    pub value: ChangeValue,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeValue {
+    pub metadata: Option<Metadata>,
+    #[serde(default, deserialize_with = "skip_invalid")]
+    pub messages: Vec<Message>,
+    #[serde(default, deserialize_with = "skip_invalid")]
+    pub statuses: Vec<Status>,
+}
+
+/// Lista en la que cada elemento se interpreta por separado: los que no
+/// encajan se descartan con un aviso en vez de rechazar todo el payload
+fn skip_invalid<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let items = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(items.into_iter()
+        .filter_map(|item| match serde_json::from_value(item) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                tracing::warn!("Skipping malformed item in Meta webhook: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Metadata {
+    pub phone_number_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    /// Id del mensaje (`wamid...`), el mismo en cada reintento
+    pub id: String,
+    pub from: String,
+    pub timestamp: String,
+    #[serde(rename = "type")]
+    pub message_type: String,
+    /// Mensaje citado (respuesta a un mensaje anterior)
+    pub context: Option<Context>,
+    pub text: Option<Text>,
+    pub interactive: Option<Interactive>,
+    /// Respuesta a un botón de plantilla
+    pub button: Option<TemplateButton>,
+    pub image: Option<Media>,
+    pub video: Option<Media>,
+    pub audio: Option<Media>,
+    pub document: Option<Media>,
+    pub sticker: Option<Media>,
+    pub location: Option<Location>,
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+    pub reaction: Option<Reaction>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Context {
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Text {
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Interactive {
+    pub button_reply: Option<Reply>,
+    pub list_reply: Option<Reply>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Reply {
+    pub id: String,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateButton {
+    pub payload: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Media {
+    pub id: Option<String>,
+    pub mime_type: Option<String>,
+    pub caption: Option<String>,
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Contact {
+    pub name: ContactName,
+    #[serde(default)]
+    pub phones: Vec<ContactPhone>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContactName {
+    pub formatted_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContactPhone {
+    pub phone: Option<String>,
+    pub wa_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Reaction {
+    pub message_id: Option<String>,
+    #[serde(default)]
+    pub emoji: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Status {
+    /// Id del mensaje enviado (`wamid...`)
+    pub id: String,
+    /// `sent`, `delivered`, `read` o `failed`
+    pub status: String,
+    pub timestamp: String,
+    pub recipient_id: String,
+    #[serde(default)]
+    pub errors: Vec<StatusError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusError {
+    pub code: i64,
+    pub title: String,
+}
+
+impl MetaWebhook {
+    /// Mensajes de clientes, con el `phone_number_id` que los recibió
+    pub fn customer_messages(&self) -> Vec<(String, ParsedMessage)> {
+        self.values()
+            .flat_map(|(phone_number_id, value)| {
+                value.messages.iter().filter_map(move |message| {
+                    Some((phone_number_id.to_string(), ParsedMessage {
+                        from: message.from.clone(),
+                        content: message.content()?,
+                        quoted: message.context.as_ref().map(|context| QuotedMessage {
+                            message_id: context.id.clone(),
+                            text: None,
+                        }),
+                        timestamp: message.timestamp.parse().ok(),
+                        id: Some(message.id.clone()),
+                    }))
+                })
+            })
+            .collect()
+    }
+
+    /// Estados de mensajes enviados, con el `phone_number_id` que los envió
+    pub fn statuses(&self) -> Vec<(String, &Status)> {
+        self.values()
+            .flat_map(|(phone_number_id, value)| {
+                value.statuses.iter().map(move |status| (phone_number_id.to_string(), status))
+            })
+            .collect()
+    }
+
+    fn values(&self) -> impl Iterator<Item = (&str, &ChangeValue)> {
+        self.entry.iter()
+            .flat_map(|entry| entry.changes.iter())
+            .filter(|change| change.field == "messages")
+            .filter_map(|change| {
+                let metadata = change.value.metadata.as_ref()?;
+                Some((metadata.phone_number_id.as_str(), &change.value))
+            })
+    }
+}
+
+impl Message {
+    /// Contenido tipado. `None` para tipos no soportados (`unsupported`, `system`...).
+    fn content(&self) -> Option<MessageContent> {
+        let media = |media: &Option<Media>| media.as_ref().map(Media::to_content);
+
+        match self.message_type.as_str() {
+            "text" => Some(MessageContent::text(self.text.as_ref()?.body.clone())),
+            "interactive" => {
+                let interactive = self.interactive.as_ref()?;
+                match (&interactive.button_reply, &interactive.list_reply) {
+                    (Some(reply), _) => Some(MessageContent::ButtonReply {
+                        id: reply.id.clone(),
+                        title: reply.title.clone(),
+                    }),
+                    (None, Some(reply)) => Some(MessageContent::ListReply {
+                        id: reply.id.clone(),
+                        title: reply.title.clone(),
+                    }),
+                    (None, None) => None,
+                }
+            }
+            "button" => {
+                let button = self.button.as_ref()?;
+                Some(MessageContent::ButtonReply {
+                    id: button.payload.clone().unwrap_or_else(|| button.text.clone()),
+                    title: Some(button.text.clone()),
+                })
+            }
+            "image" => media(&self.image).map(MessageContent::Image),
+            "video" => media(&self.video).map(MessageContent::Video),
+            "audio" => media(&self.audio).map(MessageContent::Audio),
+            "document" => media(&self.document).map(MessageContent::Document),
+            "sticker" => media(&self.sticker).map(MessageContent::Sticker),
+            "location" => {
+                let location = self.location.as_ref()?;
+                Some(MessageContent::Location(message::Location {
+                    latitude: location.latitude,
+                    longitude: location.longitude,
+                    name: location.name.clone(),
+                    address: location.address.clone(),
+                }))
+            }
+            "contacts" => Some(MessageContent::Contacts {
+                contacts: self.contacts.iter()
+                    .map(|contact| message::Contact {
+                        name: contact.name.formatted_name.clone(),
+                        phones: contact.phones.iter()
+                            .filter_map(|p| p.wa_id.clone().or_else(|| p.phone.clone()))
+                            .collect(),
+                    })
+                    .collect(),
+            }),
+            "reaction" => {
+                let reaction = self.reaction.as_ref()?;
+                Some(MessageContent::Reaction {
+                    message_id: reaction.message_id.clone(),
+                    emoji: reaction.emoji.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Media {
+    fn to_content(&self) -> message::Media {
+        message::Media {
+            id: self.id.clone(),
+            url: None,
+            mime_type: self.mime_type.clone(),
+            caption: self.caption.clone(),
+            filename: self.filename.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = format!("{}/tests/fixtures/meta/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    fn parse(name: &str) -> MetaWebhook {
+        serde_json::from_str(&fixture(name)).unwrap()
+    }
+
+    #[test]
+    fn test_text_message() {
+        let messages = parse("text_message.json").customer_messages();
+
+        assert_eq!(messages, vec![("106540352242922".to_string(), ParsedMessage {
+            from: "584141234567".to_string(),
+            content: MessageContent::text("Hola, quiero ver el catálogo"),
+            quoted: None,
+            timestamp: Some(1760803200),
+            id: Some("wamid.HBgMNTg0MTQxMjM0NTY3FQIAEhgUM0VCMDQ2QjlBNDQ3RjYyRDQ3QzEA".to_string()),
+        })]);
+    }
+
+    #[test]
+    fn test_interactive_and_template_replies() {
+        let messages: Vec<_> = parse("interactive_replies.json").customer_messages()
+            .into_iter()
+            .map(|(_, m)| m)
+            .collect();
+
+        let reply = |id: &str, title: &str| MessageContent::ButtonReply {
+            id: id.to_string(),
+            title: Some(title.to_string()),
+        };
+        assert_eq!(messages[0].content, reply("1", "Ver catálogo"));
+        assert_eq!(
+            messages[0].quoted.as_ref().and_then(|q| q.message_id.as_deref()),
+            Some("wamid.HBgMNTg0MjQxMTEyMjMzFQIAERgSQzA3RjEyQUQ5RTFBN0U0MjcA")
+        );
+        assert_eq!(messages[1].content, MessageContent::ListReply {
+            id: "rosas_rojas".to_string(),
+            title: Some("Rosas rojas".to_string()),
+        });
+        assert_eq!(messages[2].content, reply("CONFIRMAR_PEDIDO", "Confirmar"));
+    }
+
+    #[test]
+    fn test_media_and_other_messages() {
+        let contents: Vec<_> = parse("media_messages.json").customer_messages()
+            .into_iter()
+            .map(|(_, m)| m.content)
+            .collect();
+
+        // El mensaje `unsupported` se ignora
+        assert_eq!(contents.len(), 5);
+        assert_eq!(contents[0], MessageContent::Image(message::Media {
+            id: Some("1037543291543636".to_string()),
+            mime_type: Some("image/jpeg".to_string()),
+            caption: Some("¿Tienen este modelo?".to_string()),
+            ..Default::default()
+        }));
+        assert_eq!(contents[1].kind(), "audio");
+        assert_eq!(contents[2], MessageContent::Location(message::Location {
+            latitude: 10.4806,
+            longitude: -66.9036,
+            name: Some("Tienda Centro".to_string()),
+            address: Some("Av. Urdaneta, Caracas".to_string()),
+        }));
+        assert_eq!(contents[3], MessageContent::Contacts {
+            contacts: vec![message::Contact {
+                name: "Luis Gómez".to_string(),
+                phones: vec!["584245556677".to_string()],
+            }],
+        });
+        assert_eq!(contents[4], MessageContent::Reaction {
+            message_id: Some("wamid.HBgMNTg0MjQxMTEyMjMzFQIAERgSQzA3RjEyQUQ5RTFBN0U0MjcA".to_string()),
+            emoji: "👍".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_status_updates() {
+        let webhook = parse("status_update.json");
+        assert!(webhook.customer_messages().is_empty());
+
+        let statuses = webhook.statuses();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].1.status, "delivered");
+        assert_eq!(statuses[1].1.status, "failed");
+        assert_eq!(statuses[1].1.errors[0].code, 131047);
+    }
+
+    #[test]
+    fn test_malformed_items_are_skipped() {
+        let webhook = parse("malformed_items.json");
+
+        // Sin `from` y con una latitud que no es número: se descartan
+        let messages = webhook.customer_messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].1.content, MessageContent::text("2"));
+
+        let statuses = webhook.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].1.status, "delivered");
+    }
+
+    #[test]
+    fn test_signature_verification() {
+        let body = fixture("text_message.json");
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"app-secret").unwrap();
+        mac.update(body.as_bytes());
+        let header = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(verify_signature("app-secret", body.as_bytes(), Some(&header)));
+        assert!(!verify_signature("otro-secret", body.as_bytes(), Some(&header)));
+        assert!(!verify_signature("app-secret", b"{\"object\":\"x\"}", Some(&header)));
+        assert!(!verify_signature("app-secret", body.as_bytes(), None));
+    }
+
+    #[test]
+    fn test_subscription_verification() {
+        let config = MetaConfig {
+            verify_token: Some("cocolu_verify".to_string()),
+            app_secret: None,
+        };
+        let query = |token: &str| VerifyQuery {
+            mode: Some("subscribe".to_string()),
+            verify_token: Some(token.to_string()),
+            challenge: Some("1158201444".to_string()),
+        };
+
+        assert_eq!(query("cocolu_verify").accept(&config), Some("1158201444"));
+        assert_eq!(query("cocolu_verif").accept(&config), None);
+        assert_eq!(query("cocolu_verify").accept(&MetaConfig::default()), None);
+    }
+}
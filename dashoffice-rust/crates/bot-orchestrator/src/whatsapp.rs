@@ -0,0 +1,270 @@
+//! WhatsApp Client - Envío de respuestas a través de whatsapp-adapter
+//!
+//! - `POST {WHATSAPP_ADAPTER_URL}/send` con el `provider` y `provider_config` del bot
+//! - Solo se reintenta con backoff exponencial (`shared::retry_with_backoff_if`)
+//!   si el adapter no respondió o devolvió 5xx; un 4xx o `success: false` se
+//!   devuelve al momento y un timeout no se reintenta porque el mensaje pudo
+//!   haber salido
+//! - Un `shared::CircuitBreaker` por sesión (provider + sesión) deja de llamar
+//!   al adapter si los envíos de esa sesión fallan seguidos, para no bloquear
+//!   los buzones de las conversaciones
+//! - `POST /sessions/{start,stop}` al crear, borrar o cambiar el provider de un bot
+
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use shared::{retry_with_backoff_if, CircuitBreaker, CircuitBreakerConfig, CircuitBreakerError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::message::MessageContent;
+
+/// Configuración del envío
+#[derive(Debug, Clone)]
+pub struct WhatsAppConfig {
+    pub adapter_url: String,
+    pub timeout: Duration,
+    pub max_attempts: u32,
+    pub initial_retry_delay: Duration,
+}
+
+impl WhatsAppConfig {
+    pub fn from_env() -> Self {
+        Self {
+            adapter_url: std::env::var("WHATSAPP_ADAPTER_URL")
+                .unwrap_or_else(|_| "http://localhost:3010".to_string()),
+            timeout: Duration::from_secs(10),
+            max_attempts: 3,
+            initial_retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Los bridges tardan en levantar el navegador de una sesión nueva
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// ¿El provider puede enviar botones y listas? (Cloud API y Baileys)
+pub fn supports_interactive(provider: &str) -> bool {
+    matches!(provider, "official" | "meta" | "baileys")
+}
+
+/// Mensaje que se envía al adapter
+#[derive(Debug, Clone, Serialize)]
+pub struct SendRequest {
+    pub provider: String,
+    pub provider_config: serde_json::Value,
+    pub to: String,
+    pub message: MessageContent,
+}
+
+impl SendRequest {
+    /// Sesión del provider que envía: cada una tiene su propio circuit breaker
+    fn session_key(&self) -> (String, String) {
+        let session = ["session_name", "session_id", "phone_number_id"]
+            .iter()
+            .find_map(|field| self.provider_config.get(*field).and_then(|v| v.as_str()))
+            .unwrap_or_default();
+        (self.provider.clone(), session.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SendResponse {
+    success: bool,
+    message_id: Option<String>,
+    error: Option<String>,
+}
+
+pub struct WhatsAppClient {
+    client: reqwest::Client,
+    config: WhatsAppConfig,
+    breakers: DashMap<(String, String), Arc<CircuitBreaker>>,
+}
+
+/// Por qué falló un intento de envío
+#[derive(Debug)]
+enum SendFailure {
+    /// Sin conexión o 5xx: el mensaje no salió, se reintenta
+    Unavailable(anyhow::Error),
+    /// Timeout u otro corte a mitad del envío: pudo salir, no se reintenta
+    Uncertain(anyhow::Error),
+    /// 4xx o `success: false`: el adapter funciona pero rechazó el mensaje
+    Rejected(anyhow::Error),
+}
+
+impl WhatsAppClient {
+    pub fn new(config: WhatsAppConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+            breakers: DashMap::new(),
+        }
+    }
+
+    /// Enviar un mensaje. Devuelve el id del mensaje en el provider.
+    pub async fn send(&self, request: SendRequest) -> Result<String> {
+        let url = format!("{}/send", self.config.adapter_url.trim_end_matches('/'));
+        let client = self.client.clone();
+        let timeout = self.config.timeout;
+
+        let key = request.session_key();
+
+        let attempts = retry_with_backoff_if(
+            move || {
+                let (client, url, request) = (client.clone(), url.clone(), request.clone());
+                Box::pin(async move { send_once(&client, &url, &request, timeout).await })
+            },
+            self.config.max_attempts,
+            self.config.initial_retry_delay,
+            |failure| matches!(failure, SendFailure::Unavailable(_)),
+        );
+
+        // Un rechazo no indica que el adapter esté caído: no cuenta para el breaker
+        let outcome = async {
+            match attempts.await {
+                Ok(message_id) => Ok(Ok(message_id)),
+                Err(SendFailure::Rejected(e)) => Ok(Err(e)),
+                Err(SendFailure::Unavailable(e) | SendFailure::Uncertain(e)) => Err(e),
+            }
+        };
+
+        let breaker = self.breakers
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(CircuitBreaker::new(CircuitBreakerConfig::default())))
+            .clone();
+
+        match breaker.call(outcome).await {
+            Ok(result) => result,
+            Err(CircuitBreakerError::CircuitOpen) => {
+                anyhow::bail!("WhatsApp adapter circuit for {} session '{}' is open, not sending", key.0, key.1)
+            }
+            Err(CircuitBreakerError::OperationFailed(e)) => Err(e),
+        }
+    }
+
+    /// Iniciar la sesión del provider de un bot
+    pub async fn start_session(&self, provider: &str, provider_config: &serde_json::Value) -> Result<()> {
+        self.session("start", provider, provider_config).await
+    }
+
+    /// Cerrar la sesión del provider de un bot
+    pub async fn stop_session(&self, provider: &str, provider_config: &serde_json::Value) -> Result<()> {
+        self.session("stop", provider, provider_config).await
+    }
+
+    async fn session(&self, action: &str, provider: &str, provider_config: &serde_json::Value) -> Result<()> {
+        let url = format!("{}/sessions/{}", self.config.adapter_url.trim_end_matches('/'), action);
+
+        let response = self.client
+            .post(&url)
+            .timeout(SESSION_TIMEOUT)
+            .json(&serde_json::json!({
+                "provider": provider,
+                "provider_config": provider_config,
+            }))
+            .send()
+            .await
+            .context("WhatsApp adapter unreachable")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("WhatsApp adapter could not {} the {} session ({}): {}", action, provider, status, body);
+        }
+        Ok(())
+    }
+}
+
+async fn send_once(
+    client: &reqwest::Client,
+    url: &str,
+    request: &SendRequest,
+    timeout: Duration,
+) -> Result<String, SendFailure> {
+    let response = match client.post(url).timeout(timeout).json(request).send().await {
+        Ok(response) => response,
+        Err(e) if e.is_connect() => {
+            return Err(SendFailure::Unavailable(anyhow::Error::new(e).context("WhatsApp adapter unreachable")))
+        }
+        Err(e) => {
+            return Err(SendFailure::Uncertain(anyhow::Error::new(e).context("WhatsApp adapter did not answer")))
+        }
+    };
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_server_error() {
+        return Err(SendFailure::Unavailable(anyhow::anyhow!("WhatsApp adapter returned {}: {}", status, body)));
+    }
+    if !status.is_success() {
+        return Err(SendFailure::Rejected(anyhow::anyhow!("WhatsApp adapter returned {}: {}", status, body)));
+    }
+
+    parse_send_response(&body)
+}
+
+fn parse_send_response(body: &str) -> Result<String, SendFailure> {
+    let response: SendResponse = serde_json::from_str(body)
+        .with_context(|| format!("Unexpected WhatsApp adapter response: {}", body))
+        .map_err(SendFailure::Uncertain)?;
+
+    match (response.success, response.message_id) {
+        (true, Some(message_id)) => Ok(message_id),
+        (true, None) => Err(SendFailure::Uncertain(anyhow::anyhow!(
+            "WhatsApp adapter did not return a message id"
+        ))),
+        (false, _) => Err(SendFailure::Rejected(anyhow::anyhow!(
+            "WhatsApp adapter could not send: {}",
+            response.error.unwrap_or_else(|| "unknown error".to_string())
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_send_response() {
+        assert_eq!(
+            parse_send_response(r#"{"success": true, "message_id": "wamid.123"}"#).unwrap(),
+            "wamid.123"
+        );
+
+        let error = parse_send_response(r#"{"success": false, "error": "session closed"}"#).unwrap_err();
+        assert!(matches!(error, SendFailure::Rejected(e) if e.to_string().contains("session closed")));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_when_adapter_is_down() {
+        let client = WhatsAppClient::new(WhatsAppConfig {
+            // Puerto sin servicio
+            adapter_url: "http://127.0.0.1:9".to_string(),
+            timeout: Duration::from_millis(200),
+            max_attempts: 1,
+            initial_retry_delay: Duration::from_millis(1),
+        });
+        let request = SendRequest {
+            provider: "venom".to_string(),
+            provider_config: serde_json::json!({ "session_name": "test" }),
+            to: "+584141234567".to_string(),
+            message: MessageContent::text("hola"),
+        };
+
+        for _ in 0..CircuitBreakerConfig::default().failure_threshold {
+            let error = client.send(request.clone()).await.unwrap_err();
+            assert!(error.to_string().contains("unreachable"));
+        }
+
+        let error = client.send(request.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("is open"));
+
+        // Las demás sesiones siguen intentando
+        let other = SendRequest {
+            provider_config: serde_json::json!({ "session_name": "otra" }),
+            ..request
+        };
+        let error = client.send(other).await.unwrap_err();
+        assert!(error.to_string().contains("unreachable"));
+    }
+}
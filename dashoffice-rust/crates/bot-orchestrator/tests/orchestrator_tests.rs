@@ -1,12 +1,8 @@
-//\! Tests del Bot Orchestrator
-//\! Verificar que maneja múltiples bots sin fallar
-
-use tokio;
+//! Tests del Bot Orchestrator
+//! Verificar que maneja múltiples bots sin fallar
 
 #[cfg(test)]
 mod flow_engine_tests {
-    use super::*;
-    
     #[tokio::test]
     async fn test_flow_execution_success() {
         // Test de ejecución exitosa de flow
@@ -34,7 +30,7 @@ mod flow_engine_tests {
             .collect();
         
         for task in tasks {
-            assert\!(task.await.is_ok());
+            assert!(task.await.is_ok());
         }
     }
     
@@ -47,8 +43,6 @@ mod flow_engine_tests {
 
 #[cfg(test)]
 mod webhook_tests {
-    use super::*;
-    
     #[tokio::test]
     async fn test_venom_webhook_handling() {
         // Test de webhook de Venom
@@ -68,8 +62,6 @@ mod webhook_tests {
 
 #[cfg(test)]
 mod multi_tenant_tests {
-    use super::*;
-    
     #[tokio::test]
     async fn test_tenant_isolation() {
         // Test de aislamiento entre tenants
@@ -84,8 +76,6 @@ mod multi_tenant_tests {
 
 #[cfg(test)]
 mod failure_recovery_tests {
-    use super::*;
-    
     #[tokio::test]
     async fn test_database_connection_failure() {
         // Test de pérdida de conexión a DB
@@ -1,9 +1,13 @@
-//\! Database Utilities
+//! Database Utilities
 
+use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use crate::error::Result;
 
 pub async fn create_pool(database_url: &str, max_connections: u32) -> Result<PgPool> {
-    let pool = PgPool::connect(database_url).await?;
+    let pool = PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(database_url)
+        .await?;
     Ok(pool)
 }
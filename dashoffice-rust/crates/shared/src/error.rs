@@ -1,6 +1,6 @@
-//\! Error Handling
-//\! 
-//\! Sistema centralizado de manejo de errores.
+//! Error Handling
+//! 
+//! Sistema centralizado de manejo de errores.
 
 use thiserror::Error;
 
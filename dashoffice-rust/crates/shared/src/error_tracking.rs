@@ -1,9 +1,9 @@
-//\! Sistema de Tracking de Errores
-//\! 
-//\! Guarda TODOS los errores en la base de datos
-//\! Envía alertas para errores críticos
-//\! Agrupa errores similares
-//\! Tracking de stack traces
+//! Sistema de Tracking de Errores
+//! 
+//! Guarda TODOS los errores en la base de datos
+//! Envía alertas para errores críticos
+//! Agrupa errores similares
+//! Tracking de stack traces
 
 use sqlx::PgPool;
 use serde::{Deserialize, Serialize};
@@ -64,14 +64,14 @@ impl ErrorTracker {
             id: Uuid::new_v4(),
             timestamp: Utc::now(),
             error_type: error.to_string(),
-            error_message: format\!("{:#}", error),
+            error_message: format!("{:#}", error),
             severity,
             service: self.service_name.clone(),
             module: "unknown".to_string(),
             function: None,
             file: None,
             line: None,
-            stack_trace: Some(format\!("{:?}", error)),
+            stack_trace: Some(format!("{:?}", error)),
             request_id: None,
             user_id: None,
             tenant_id: None,
@@ -83,7 +83,7 @@ impl ErrorTracker {
         self.save_error(&error_log).await?;
         
         // Si es crítico, enviar alerta
-        if matches\!(severity, ErrorSeverity::Critical) {
+        if matches!(severity, ErrorSeverity::Critical) {
             self.send_alert(&error_log).await;
         }
         
@@ -92,28 +92,28 @@ impl ErrorTracker {
     
     /// Guardar error en DB
     async fn save_error(&self, error_log: &ErrorLog) -> Result<(), sqlx::Error> {
-        sqlx::query\!(
+        sqlx::query(
             r#"
             INSERT INTO error_logs 
             (id, timestamp, error_type, error_message, severity, service, 
              module, stack_trace, context, resolved)
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             "#,
-            error_log.id,
-            error_log.timestamp,
-            error_log.error_type,
-            error_log.error_message,
-            format\!("{:?}", error_log.severity).to_lowercase(),
-            error_log.service,
-            error_log.module,
-            error_log.stack_trace,
-            error_log.context,
-            error_log.resolved
         )
+        .bind(error_log.id)
+        .bind(error_log.timestamp)
+        .bind(&error_log.error_type)
+        .bind(&error_log.error_message)
+        .bind(format!("{:?}", error_log.severity).to_lowercase())
+        .bind(&error_log.service)
+        .bind(&error_log.module)
+        .bind(&error_log.stack_trace)
+        .bind(&error_log.context)
+        .bind(error_log.resolved)
         .execute(self.pool.as_ref())
         .await?;
         
-        tracing::error\!(
+        tracing::error!(
             error_id = %error_log.id,
             severity = ?error_log.severity,
             error_type = %error_log.error_type,
@@ -125,7 +125,7 @@ impl ErrorTracker {
     
     /// Enviar alerta (email, Slack, etc.)
     async fn send_alert(&self, error_log: &ErrorLog) {
-        tracing::error\!(
+        tracing::error!(
             "🚨 CRITICAL ERROR ALERT 🚨\n\
              Service: {}\n\
              Error: {}\n\
@@ -149,12 +149,10 @@ impl ErrorTracker {
         &self,
         limit: i64,
     ) -> Result<Vec<ErrorLog>, sqlx::Error> {
-        sqlx::query_as\!(
-            ErrorLog,
+        sqlx::query_as::<_, ErrorLog>(
             r#"
             SELECT 
-                id, timestamp, error_type, error_message,
-                severity as "severity: ErrorSeverity",
+                id, timestamp, error_type, error_message, severity,
                 service, module, function, file, line,
                 stack_trace, request_id, user_id, tenant_id,
                 context, resolved, resolution_notes
@@ -162,8 +160,8 @@ impl ErrorTracker {
             ORDER BY timestamp DESC
             LIMIT $1
             "#,
-            limit
         )
+        .bind(limit)
         .fetch_all(self.pool.as_ref())
         .await
     }
@@ -174,15 +172,15 @@ impl ErrorTracker {
         error_id: Uuid,
         notes: String,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query\!(
+        sqlx::query(
             r#"
             UPDATE error_logs
             SET resolved = true, resolution_notes = $2
             WHERE id = $1
             "#,
-            error_id,
-            notes
         )
+        .bind(error_id)
+        .bind(notes)
         .execute(self.pool.as_ref())
         .await?;
         
@@ -192,18 +190,18 @@ impl ErrorTracker {
 
 /// Macro para trackear errores automáticamente
 #[macro_export]
-macro_rules\! track_error {
+macro_rules! track_error {
     ($tracker:expr, $error:expr, $severity:expr) => {
         if let Err(e) = $tracker.track_error(
             &$error.into(),
             $severity,
-            serde_json::json\!({
-                "file": file\!(),
-                "line": line\!(),
-                "column": column\!(),
+            serde_json::json!({
+                "file": file!(),
+                "line": line!(),
+                "column": column!(),
             })
         ).await {
-            tracing::error\!("Failed to track error: {}", e);
+            tracing::error!("Failed to track error: {}", e);
         }
     };
 }
@@ -221,9 +219,9 @@ where
         Ok(val) => Ok(val),
         Err(e) => {
             let _ = tracker.track_error(
-                &anyhow::Error::new(e),
+                &anyhow::anyhow!("{}", e),
                 severity,
-                serde_json::json\!({}),
+                serde_json::json!({}),
             ).await;
             Err(e)
         }
@@ -237,6 +235,6 @@ mod tests {
     #[test]
     fn test_error_severity() {
         let severity = ErrorSeverity::Critical;
-        assert\!(matches\!(severity, ErrorSeverity::Critical));
+        assert!(matches!(severity, ErrorSeverity::Critical));
     }
 }
@@ -1,13 +1,13 @@
-//\! DashOffice Shared Library
-//\! 
-//\! Biblioteca compartida con:
-//\! - Modelos de datos
-//\! - Error handling robusto
-//\! - Logging avanzado con persistencia
-//\! - Sistema de resiliencia (Circuit Breaker, Retry)
-//\! - Error tracking automático
-//\! - Config management
-//\! - Database helpers
+//! DashOffice Shared Library
+//! 
+//! Biblioteca compartida con:
+//! - Modelos de datos
+//! - Error handling robusto
+//! - Logging avanzado con persistencia
+//! - Sistema de resiliencia (Circuit Breaker, Retry)
+//! - Error tracking automático
+//! - Config management
+//! - Database helpers
 
 pub mod models;
 pub mod error;
@@ -18,6 +18,9 @@ pub mod logging;
 pub mod resilience;
 pub mod error_tracking;
 
+/// Versión de la biblioteca (la misma de todo el workspace)
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 // Re-exports
 pub use models::*;
 pub use error::*;
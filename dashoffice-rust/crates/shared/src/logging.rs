@@ -1,15 +1,15 @@
-//\! Sistema de Logging Avanzado
-//\! 
-//\! Características:
-//\! - Structured logging (JSON)
-//\! - Multiple outputs (console, file, database)
-//\! - Log levels (trace, debug, info, warn, error)
-//\! - Context propagation
-//\! - Performance metrics
-//\! - Error tracking
+//! Sistema de Logging Avanzado
+//! 
+//! Características:
+//! - Structured logging (JSON)
+//! - Multiple outputs (console, file, database)
+//! - Log levels (trace, debug, info, warn, error)
+//! - Context propagation
+//! - Performance metrics
+//! - Error tracking
 
 use serde::{Deserialize, Serialize};
-use tracing::{error, warn, info, debug, trace};
+use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use std::sync::Arc;
 use sqlx::PgPool;
@@ -97,24 +97,24 @@ impl DatabaseLogger {
         
         // Insertar en batch en la base de datos
         for entry in buffer.drain(..) {
-            let result = sqlx::query\!(
+            let result = sqlx::query(
                 r#"
                 INSERT INTO system_logs 
                 (id, timestamp, log_type, severity, message, metadata)
                 VALUES ($1, $2, $3, $4, $5, $6)
                 "#,
-                entry.id,
-                entry.timestamp,
-                entry.module,
-                format\!("{:?}", entry.level).to_lowercase(),
-                entry.message,
-                entry.metadata
             )
+            .bind(entry.id)
+            .bind(entry.timestamp)
+            .bind(&entry.module)
+            .bind(format!("{:?}", entry.level).to_lowercase())
+            .bind(&entry.message)
+            .bind(&entry.metadata)
             .execute(self.pool.as_ref())
             .await;
             
             if let Err(e) = result {
-                eprintln\!("Failed to insert log into database: {}", e);
+                eprintln!("Failed to insert log into database: {}", e);
             }
         }
     }
@@ -131,14 +131,8 @@ pub fn init_logging(service_name: &str, db_pool: Option<Arc<PgPool>>) {
         .with_line_number(true)
         .with_ansi(true);
     
-    // JSON layer para producción
-    let json_layer = tracing_subscriber::fmt::layer()
-        .json()
-        .with_current_span(true)
-        .with_span_list(true);
-    
     // File layer
-    let file_appender = tracing_appender::rolling::daily("./logs", format\!("{}.log", service_name));
+    let file_appender = tracing_appender::rolling::daily("./logs", format!("{}.log", service_name));
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
     let file_layer = tracing_subscriber::fmt::layer()
         .with_writer(non_blocking)
@@ -155,7 +149,7 @@ pub fn init_logging(service_name: &str, db_pool: Option<Arc<PgPool>>) {
         .with(file_layer)
         .init();
     
-    info\!(service = service_name, "Logging system initialized");
+    info!(service = service_name, "Logging system initialized");
     
     // Spawn background task para flush periódico si tenemos DB
     if let Some(pool) = db_pool {
@@ -173,9 +167,9 @@ pub fn init_logging(service_name: &str, db_pool: Option<Arc<PgPool>>) {
 
 /// Macro para logging con contexto
 #[macro_export]
-macro_rules\! log_with_context {
+macro_rules! log_with_context {
     ($level:ident, $msg:expr, $($key:ident = $value:expr),*) => {
-        tracing::$level\!(
+        tracing::$level!(
             message = $msg,
             $($key = ?$value,)*
         );
@@ -206,7 +200,7 @@ pub fn create_log_entry(
         request_id: None,
         user_id: None,
         tenant_id: None,
-        metadata: serde_json::json\!({}),
+        metadata: serde_json::json!({}),
         error_details: None,
     }
 }
@@ -224,7 +218,7 @@ mod tests {
             "test_service".to_string(),
         );
         
-        assert_eq\!(entry.message, "Test message");
-        assert_eq\!(entry.module, "test_module");
+        assert_eq!(entry.message, "Test message");
+        assert_eq!(entry.module, "test_module");
     }
 }
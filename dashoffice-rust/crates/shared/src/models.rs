@@ -1,6 +1,6 @@
-//\! # Modelos de Datos
-//\! 
-//\! Definiciones de todas las estructuras de datos del sistema.
+//! # Modelos de Datos
+//! 
+//! Definiciones de todas las estructuras de datos del sistema.
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -35,9 +35,10 @@ pub type Timestamp = DateTime<Utc>;
 pub type Metadata = serde_json::Value;
 
 /// Estado genérico
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Status {
+    #[default]
     Active,
     Inactive,
     Pending,
@@ -45,28 +46,17 @@ pub enum Status {
     Deleted,
 }
 
-impl Default for Status {
-    fn default() -> Self {
-        Self::Active
-    }
-}
-
 /// Prioridad
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum Priority {
     Low,
+    #[default]
     Medium,
     High,
     Critical,
 }
 
-impl Default for Priority {
-    fn default() -> Self {
-        Self::Medium
-    }
-}
-
 /// Timestamps comunes
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Timestamps {
@@ -144,28 +134,22 @@ mod tests {
     #[test]
     fn test_pagination_offset() {
         let p = Pagination { page: 1, per_page: 20 };
-        assert_eq\!(p.offset(), 0);
+        assert_eq!(p.offset(), 0);
 
         let p = Pagination { page: 2, per_page: 20 };
-        assert_eq\!(p.offset(), 20);
+        assert_eq!(p.offset(), 20);
 
         let p = Pagination { page: 3, per_page: 10 };
-        assert_eq\!(p.offset(), 20);
+        assert_eq!(p.offset(), 20);
     }
 
     #[test]
     fn test_paginated_response() {
-        let data = vec\![1, 2, 3];
+        let data = vec![1, 2, 3];
         let pagination = Pagination { page: 1, per_page: 3 };
         let response = PaginatedResponse::new(data, 10, &pagination);
 
-        assert_eq\!(response.total, 10);
-        assert_eq\!(response.total_pages, 4);
+        assert_eq!(response.total, 10);
+        assert_eq!(response.total_pages, 4);
     }
 }
-pub mod user;
-pub mod order;
-pub mod product;
-pub mod seller;
-pub mod conversation;
-pub mod analytics;
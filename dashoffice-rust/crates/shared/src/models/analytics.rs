@@ -1,4 +1,4 @@
-//\! Analytics Models
+//! Analytics Models
 
 use super::*;
 
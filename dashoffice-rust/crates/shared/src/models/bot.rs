@@ -1,4 +1,4 @@
-//\! Bot Models - Modelos relacionados con bots de WhatsApp
+//! Bot Models - Modelos relacionados con bots de WhatsApp
 
 use super::*;
 
@@ -1,4 +1,4 @@
-//\! Conversation Models
+//! Conversation Models
 
 use super::*;
 
@@ -1,4 +1,4 @@
-//\! Order Models
+//! Order Models
 
 use super::*;
 
@@ -1,4 +1,4 @@
-//\! Product Models
+//! Product Models
 
 use super::*;
 
@@ -1,4 +1,4 @@
-//\! Seller Models
+//! Seller Models
 
 use super::*;
 
@@ -1,4 +1,4 @@
-//\! User Models
+//! User Models
 
 use super::*;
 
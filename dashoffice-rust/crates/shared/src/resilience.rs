@@ -1,11 +1,11 @@
-//\! Sistema de Resiliencia
-//\! 
-//\! Características:
-//\! - Circuit Breaker pattern
-//\! - Retry logic con backoff exponencial
-//\! - Timeout handling
-//\! - Fallback strategies
-//\! - Health checks
+//! Sistema de Resiliencia
+//! 
+//! Características:
+//! - Circuit Breaker pattern
+//! - Retry logic con backoff exponencial
+//! - Timeout handling
+//! - Fallback strategies
+//! - Health checks
 
 use std::sync::Arc;
 use std::time::Duration;
@@ -151,7 +151,7 @@ impl CircuitBreaker {
     }
     
     async fn transition_to_closed(&self) {
-        tracing::info\!("Circuit breaker transitioning to CLOSED");
+        tracing::info!("Circuit breaker transitioning to CLOSED");
         let mut state = self.state.write().await;
         *state = CircuitState::Closed;
         
@@ -166,13 +166,13 @@ impl CircuitBreaker {
     }
     
     async fn transition_to_open(&self) {
-        tracing::warn\!("Circuit breaker transitioning to OPEN");
+        tracing::warn!("Circuit breaker transitioning to OPEN");
         let mut state = self.state.write().await;
         *state = CircuitState::Open;
     }
     
     async fn transition_to_half_open(&self) {
-        tracing::info\!("Circuit breaker transitioning to HALF_OPEN");
+        tracing::info!("Circuit breaker transitioning to HALF_OPEN");
         let mut state = self.state.write().await;
         *state = CircuitState::HalfOpen;
         
@@ -199,12 +199,27 @@ pub enum CircuitBreakerError<E> {
 
 /// Retry con backoff exponencial
 pub async fn retry_with_backoff<F, T, E>(
+    operation: F,
+    max_attempts: u32,
+    initial_delay: Duration,
+) -> Result<T, E>
+where
+    F: FnMut() -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, E>> + Send>>,
+{
+    retry_with_backoff_if(operation, max_attempts, initial_delay, |_| true).await
+}
+
+/// Retry con backoff exponencial solo de los errores que `retryable` acepta;
+/// el resto se devuelve en el primer intento
+pub async fn retry_with_backoff_if<F, T, E, R>(
     mut operation: F,
     max_attempts: u32,
     initial_delay: Duration,
+    retryable: R,
 ) -> Result<T, E>
 where
     F: FnMut() -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, E>> + Send>>,
+    R: Fn(&E) -> bool,
 {
     let mut attempt = 0;
     let mut delay = initial_delay;
@@ -214,9 +229,9 @@ where
         
         match operation().await {
             Ok(result) => return Ok(result),
-            Err(e) if attempt >= max_attempts => return Err(e),
+            Err(e) if attempt >= max_attempts || !retryable(&e) => return Err(e),
             Err(_) => {
-                tracing::warn\!(
+                tracing::warn!(
                     attempt = attempt,
                     max_attempts = max_attempts,
                     delay_ms = delay.as_millis(),
@@ -224,7 +239,7 @@ where
                 );
                 
                 tokio::time::sleep(delay).await;
-                delay = delay * 2; // Backoff exponencial
+                delay *= 2; // Backoff exponencial
             }
         }
     }
@@ -262,15 +277,15 @@ mod tests {
         // Simular 3 errores
         for _ in 0..3 {
             let result = breaker.call(async { Err::<(), &str>("error") }).await;
-            assert\!(result.is_err());
+            assert!(result.is_err());
         }
         
         // El circuito debe estar abierto
-        assert_eq\!(breaker.get_state().await, CircuitState::Open);
+        assert_eq!(breaker.get_state().await, CircuitState::Open);
         
         // La siguiente llamada debe fallar inmediatamente
         let result = breaker.call(async { Ok::<(), &str>(()) }).await;
-        assert\!(matches\!(result, Err(CircuitBreakerError::CircuitOpen)));
+        assert!(matches!(result, Err(CircuitBreakerError::CircuitOpen)));
     }
     
     #[tokio::test]
@@ -292,7 +307,25 @@ mod tests {
             Duration::from_millis(10),
         ).await;
         
-        assert_eq\!(result, Ok(42));
-        assert_eq\!(attempts, 3);
+        assert_eq!(result, Ok(42));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_on_permanent_error() {
+        let mut attempts = 0;
+
+        let result: Result<(), &str> = retry_with_backoff_if(
+            || {
+                attempts += 1;
+                Box::pin(async move { Err("rejected") })
+            },
+            5,
+            Duration::from_millis(10),
+            |e| *e != "rejected",
+        ).await;
+
+        assert_eq!(result, Err("rejected"));
+        assert_eq!(attempts, 1);
     }
 }
@@ -1,4 +1,4 @@
-//\! Utility Functions
+//! Utility Functions
 
 use chrono::{DateTime, Utc};
 
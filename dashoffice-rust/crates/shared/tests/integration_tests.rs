@@ -1,18 +1,14 @@
-//\! Tests de Integración Completos
-//\! 
-//\! Cobertura exhaustiva de todos los componentes críticos
-
-use dashoffice_shared::*;
+//! Tests de Integración Completos
+//! 
+//! Cobertura exhaustiva de todos los componentes críticos
 
 #[cfg(test)]
 mod logging_tests {
-    use super::*;
-    
     #[test]
     fn test_log_levels() {
         use dashoffice_shared::logging::LogLevel;
         
-        let levels = vec\![
+        let levels = [
             LogLevel::Trace,
             LogLevel::Debug,
             LogLevel::Info,
@@ -21,7 +17,7 @@ mod logging_tests {
             LogLevel::Fatal,
         ];
         
-        assert_eq\!(levels.len(), 6);
+        assert_eq!(levels.len(), 6);
     }
     
     #[test]
@@ -35,15 +31,14 @@ mod logging_tests {
             "test_service".to_string(),
         );
         
-        assert_eq\!(entry.message, "Test error message");
-        assert_eq\!(entry.module, "test_module");
-        assert_eq\!(entry.service, "test_service");
+        assert_eq!(entry.message, "Test error message");
+        assert_eq!(entry.module, "test_module");
+        assert_eq!(entry.service, "test_service");
     }
 }
 
 #[cfg(test)]
 mod resilience_tests {
-    use super::*;
     use std::time::Duration;
     
     #[tokio::test]
@@ -56,8 +51,8 @@ mod resilience_tests {
         // Operación exitosa debe mantener circuito cerrado
         let result = breaker.call(async { Ok::<_, &str>(42) }).await;
         
-        assert\!(result.is_ok());
-        assert_eq\!(breaker.get_state().await, CircuitState::Closed);
+        assert!(result.is_ok());
+        assert_eq!(breaker.get_state().await, CircuitState::Closed);
     }
     
     #[tokio::test]
@@ -77,7 +72,7 @@ mod resilience_tests {
         }
         
         // Circuito debe estar abierto
-        assert_eq\!(breaker.get_state().await, CircuitState::Open);
+        assert_eq!(breaker.get_state().await, CircuitState::Open);
     }
     
     #[tokio::test]
@@ -101,8 +96,8 @@ mod resilience_tests {
             Duration::from_millis(10),
         ).await;
         
-        assert_eq\!(result, Ok(100));
-        assert_eq\!(attempt, 2);
+        assert_eq!(result, Ok(100));
+        assert_eq!(attempt, 2);
     }
     
     #[tokio::test]
@@ -122,34 +117,30 @@ mod resilience_tests {
             Duration::from_millis(10),
         ).await;
         
-        assert\!(result.is_err());
-        assert_eq\!(attempt, 3);
+        assert!(result.is_err());
+        assert_eq!(attempt, 3);
     }
 }
 
 #[cfg(test)]
 mod error_tracking_tests {
-    use super::*;
-    
     #[test]
     fn test_error_severity_ordering() {
         use dashoffice_shared::error_tracking::ErrorSeverity;
         
-        let severities = vec\![
+        let severities = [
             ErrorSeverity::Low,
             ErrorSeverity::Medium,
             ErrorSeverity::High,
             ErrorSeverity::Critical,
         ];
         
-        assert_eq\!(severities.len(), 4);
+        assert_eq!(severities.len(), 4);
     }
 }
 
 #[cfg(test)]
 mod models_tests {
-    use super::*;
-    
     #[test]
     fn test_user_role_serialization() {
         use dashoffice_shared::models::user::UserRole;
@@ -157,17 +148,17 @@ mod models_tests {
         
         let role = UserRole::Admin;
         let json = serde_json::to_string(&role).unwrap();
-        assert_eq\!(json, r#""admin""#);
+        assert_eq!(json, r#""admin""#);
         
         let deserialized: UserRole = serde_json::from_str(&json).unwrap();
-        assert\!(matches\!(deserialized, UserRole::Admin));
+        assert!(matches!(deserialized, UserRole::Admin));
     }
     
     #[test]
     fn test_order_status_transitions() {
         use dashoffice_shared::models::order::OrderStatus;
         
-        let statuses = vec\![
+        let statuses = [
             OrderStatus::Pending,
             OrderStatus::Confirmed,
             OrderStatus::Processing,
@@ -175,25 +166,23 @@ mod models_tests {
             OrderStatus::Delivered,
         ];
         
-        assert_eq\!(statuses.len(), 5);
+        assert_eq!(statuses.len(), 5);
     }
 }
 
 #[cfg(test)]
 mod error_handling_tests {
-    use super::*;
-    
     #[test]
     fn test_api_error_creation() {
-        use dashoffice_shared::error::ApiError;
+        use dashoffice_shared::error::Error;
         
-        let error = ApiError::NotFound("User not found".to_string());
-        assert_eq\!(error.status_code(), 404);
+        let error = Error::NotFound("User not found".to_string());
+        assert_eq!(error.status_code(), 404);
         
-        let error = ApiError::Unauthorized("Invalid token".to_string());
-        assert_eq\!(error.status_code(), 401);
+        let error = Error::Unauthorized("Invalid token".to_string());
+        assert_eq!(error.status_code(), 401);
         
-        let error = ApiError::BadRequest("Invalid input".to_string());
-        assert_eq\!(error.status_code(), 400);
+        let error = Error::Validation("Invalid input".to_string());
+        assert_eq!(error.status_code(), 400);
     }
 }
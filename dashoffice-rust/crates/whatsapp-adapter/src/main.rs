@@ -3,7 +3,8 @@
 //\! Adaptador universal para múltiples providers de WhatsApp
 
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use tracing::info;
+use serde::Deserialize;
+use tracing::{error, info, warn};
 
 mod providers;
 mod bridge;
@@ -25,6 +26,8 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .route("/health", web::get().to(health_check))
             .route("/send", web::post().to(send_message))
+            .route("/sessions/start", web::post().to(start_session))
+            .route("/sessions/stop", web::post().to(stop_session))
     })
     .bind(("0.0.0.0", port))?
     .run()
@@ -38,9 +41,152 @@ async fn health_check() -> impl Responder {
     }))
 }
 
-async fn send_message() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json\!({
-        "success": true,
-        "message_id": "msg_123"
-    }))
+/// Sesión del provider de un bot
+#[derive(Debug, Deserialize)]
+struct SessionRequest {
+    provider: String,
+    provider_config: serde_json::Value,
+}
+
+/// Iniciar la sesión de un bot (al crearlo o al cambiar de provider)
+async fn start_session(request: web::Json<SessionRequest>) -> impl Responder {
+    let provider = match providers::ProviderType::from_config(&request.provider, &request.provider_config) {
+        Ok(provider) => provider.create(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "error": e.to_string()
+            }));
+        }
+    };
+
+    let started = match provider.connect().await {
+        Ok(()) => provider.get_status().await,
+        Err(e) => Err(e),
+    };
+    session_result(&request.provider, started)
+}
+
+/// Cerrar la sesión de un bot (al borrarlo o al cambiar de provider)
+async fn stop_session(request: web::Json<SessionRequest>) -> impl Responder {
+    let provider = match providers::ProviderType::from_config(&request.provider, &request.provider_config) {
+        Ok(provider) => provider.create(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "error": e.to_string()
+            }));
+        }
+    };
+
+    let stopped = provider.disconnect().await.map(|_| "disconnected".to_string());
+    session_result(&request.provider, stopped)
+}
+
+fn session_result(provider: &str, result: anyhow::Result<String>) -> HttpResponse {
+    match result {
+        Ok(status) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "status": status
+        })),
+        Err(e) => {
+            error!("❌ {} session failed: {:#}", provider, e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "success": false,
+                "error": format!("{:#}", e)
+            }))
+        }
+    }
+}
+
+/// Mensaje a enviar con el provider de un bot
+#[derive(Debug, Deserialize)]
+struct SendMessageRequest {
+    provider: String,
+    provider_config: serde_json::Value,
+    to: String,
+    message: OutgoingContent,
+}
+
+/// Contenido a enviar (mismo formato que `MessageContent` en bot-orchestrator:
+/// `{"type": "text", "body": "..."}`, `{"type": "image", "url": "...", "caption": "..."}`,
+/// `{"type": "buttons", "body": "...", "buttons": [{"id": "1", "title": "..."}]}`)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutgoingContent {
+    Text { body: String },
+    Image(OutgoingMedia),
+    Audio(OutgoingMedia),
+    Video(OutgoingMedia),
+    Document(OutgoingMedia),
+    Sticker(OutgoingMedia),
+    Buttons { body: String, buttons: Vec<providers::ReplyButton> },
+    List { body: String, button: String, sections: Vec<providers::ListSection> },
+}
+
+#[derive(Debug, Deserialize)]
+struct OutgoingMedia {
+    url: String,
+    caption: Option<String>,
+}
+
+async fn send_message(request: web::Json<SendMessageRequest>) -> impl Responder {
+    let request = request.into_inner();
+
+    let provider = match providers::ProviderType::from_config(&request.provider, &request.provider_config) {
+        Ok(provider) => provider.create(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "error": e.to_string()
+            }));
+        }
+    };
+
+    let (media_type, media) = match request.message {
+        OutgoingContent::Text { body } => {
+            return send_result(&request.provider, provider.send_message(request.to, body).await);
+        }
+        OutgoingContent::Image(media) => ("image", media),
+        OutgoingContent::Audio(media) => ("audio", media),
+        OutgoingContent::Video(media) => ("video", media),
+        OutgoingContent::Document(media) => ("document", media),
+        OutgoingContent::Sticker(media) => ("sticker", media),
+        OutgoingContent::Buttons { body, buttons } => {
+            let message = providers::InteractiveMessage::Buttons { body, buttons };
+            return send_result(&request.provider, provider.send_interactive(request.to, message).await);
+        }
+        OutgoingContent::List { body, button, sections } => {
+            let message = providers::InteractiveMessage::List { body, button, sections };
+            return send_result(&request.provider, provider.send_interactive(request.to, message).await);
+        }
+    };
+
+    let sent = provider.send_media(request.to.clone(), media.url, media_type.to_string()).await;
+    // `send_media` no recibe pie de foto: se envía como texto a continuación.
+    // Si falla, el archivo ya salió: se responde con su id para no reenviarlo
+    if let (Ok(message_id), Some(caption)) = (&sent, media.caption) {
+        if !caption.is_empty() {
+            if let Err(e) = provider.send_message(request.to, caption).await {
+                warn!("⚠️ {} caption for {} failed: {:#}", request.provider, message_id, e);
+            }
+        }
+    }
+    send_result(&request.provider, sent)
+}
+
+fn send_result(provider: &str, sent: anyhow::Result<String>) -> HttpResponse {
+    match sent {
+        Ok(message_id) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message_id": message_id
+        })),
+        Err(e) => {
+            error!("❌ {} send failed: {:#}", provider, e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "success": false,
+                "error": format!("{:#}", e)
+            }))
+        }
+    }
 }
@@ -15,7 +15,8 @@ pub mod official;
 pub mod twilio;
 
 use async_trait::async_trait;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 
 /// Trait universal para todos los providers de WhatsApp
 #[async_trait]
@@ -26,16 +27,53 @@ pub trait WhatsAppProvider: Send + Sync {
     /// Enviar media (imagen, video, documento, audio)
     async fn send_media(&self, to: String, media_url: String, media_type: String) -> Result<String>;
     
+    /// Enviar botones de respuesta o una lista (solo Cloud API y Baileys)
+    async fn send_interactive(&self, _to: String, _message: InteractiveMessage) -> Result<String> {
+        anyhow::bail!("This provider does not support interactive messages")
+    }
+    
     /// Obtener código QR para escanear (si aplica)
     async fn get_qr(&self) -> Result<String>;
     
     /// Obtener estado de la conexión
     async fn get_status(&self) -> Result<String>;
     
+    /// Iniciar la sesión (los bridges la crean al pedir el QR); no aplica a las APIs
+    async fn connect(&self) -> Result<()> {
+        Ok(())
+    }
+    
     /// Desconectar y limpiar recursos
     async fn disconnect(&self) -> Result<()>;
 }
 
+/// Mensaje interactivo: botones de respuesta (hasta 3) o lista con secciones
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InteractiveMessage {
+    Buttons { body: String, buttons: Vec<ReplyButton> },
+    List { body: String, button: String, sections: Vec<ListSection> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyButton {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListSection {
+    pub title: Option<String>,
+    pub rows: Vec<ListRow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListRow {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+}
+
 /// Factory para crear providers según tipo
 pub enum ProviderType {
     Venom { bridge_url: String, session_name: String },
@@ -46,6 +84,41 @@ pub enum ProviderType {
 }
 
 impl ProviderType {
+    /// Construir desde el `provider` y `provider_config` de un bot
+    pub fn from_config(provider: &str, config: &serde_json::Value) -> Result<Self> {
+        let field = |name: &str| {
+            config.get(name)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+                .with_context(|| format!("provider_config.{} is required for '{}'", name, provider))
+        };
+
+        Ok(match provider {
+            "venom" => ProviderType::Venom {
+                bridge_url: field("bridge_url")?,
+                session_name: field("session_name")?,
+            },
+            "wwebjs" => ProviderType::WWebJS {
+                bridge_url: field("bridge_url")?,
+                session_id: field("session_id")?,
+            },
+            "baileys" => ProviderType::Baileys {
+                bridge_url: field("bridge_url")?,
+                session_id: field("session_id")?,
+            },
+            "official" | "meta" => ProviderType::Official {
+                access_token: field("access_token")?,
+                phone_number_id: field("phone_number_id")?,
+            },
+            "twilio" => ProviderType::Twilio {
+                account_sid: field("account_sid")?,
+                auth_token: field("auth_token")?,
+                from: field("from")?,
+            },
+            other => anyhow::bail!("Unknown WhatsApp provider '{}'", other),
+        })
+    }
+
     pub fn create(self) -> Box<dyn WhatsAppProvider> {
         match self {
             ProviderType::Venom { bridge_url, session_name } => {
@@ -1,7 +1,7 @@
 //\! Baileys Provider
 //\! Lightweight WhatsApp client
 
-use super::WhatsAppProvider;
+use super::{InteractiveMessage, WhatsAppProvider};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -91,6 +91,31 @@ impl WhatsAppProvider for BaileysProvider {
         Ok(result.message_id.unwrap_or_default())
     }
 
+    async fn send_interactive(&self, to: String, message: InteractiveMessage) -> Result<String> {
+        let url = format!("{}/send-interactive", self.bridge_url);
+
+        let response = self.client
+            .post(&url)
+            .json(&serde_json::json!({
+                "session_id": self.session_id,
+                "to": to,
+                "message": message
+            }))
+            .send()
+            .await
+            .context("Failed to send interactive message to Baileys bridge")?;
+
+        let result: SendResponse = response.json()
+            .await
+            .context("Failed to parse Baileys response")?;
+
+        if !result.success {
+            anyhow::bail!("Baileys interactive send failed: {}", result.error.unwrap_or_default());
+        }
+
+        Ok(result.message_id.unwrap_or_else(|| "unknown".to_string()))
+    }
+
     async fn get_qr(&self) -> Result<String> {
         let url = format\!("{}/qr/{}", self.bridge_url, self.session_id);
         
@@ -112,6 +137,20 @@ impl WhatsAppProvider for BaileysProvider {
         Ok("connected".to_string())
     }
 
+    async fn connect(&self) -> Result<()> {
+        let url = format!("{}/qr/{}", self.bridge_url, self.session_id);
+
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to start Baileys session")?
+            .error_for_status()
+            .context("Baileys bridge rejected the session")?;
+
+        Ok(())
+    }
+
     async fn disconnect(&self) -> Result<()> {
         Ok(())
     }
@@ -1,21 +1,197 @@
-//\! Official Provider
+//! Official Provider
+//! WhatsApp Cloud API (Meta Business)
 
-use super::WhatsAppProvider;
+use super::{InteractiveMessage, WhatsAppProvider};
 use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use anyhow::{Result, Context};
 
-pub struct OfficialProvider;
+const GRAPH_API_URL: &str = "https://graph.facebook.com/v19.0";
+
+#[derive(Debug, Clone)]
+pub struct OfficialProvider {
+    client: Client,
+    access_token: String,
+    phone_number_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SendResponse {
+    #[serde(default)]
+    messages: Vec<SentMessage>,
+    error: Option<ApiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SentMessage {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+impl OfficialProvider {
+    pub fn new(access_token: String, phone_number_id: String) -> Self {
+        Self {
+            client: Client::new(),
+            access_token,
+            phone_number_id,
+        }
+    }
+
+    /// `POST /{phone_number_id}/messages`; devuelve el `wamid` del mensaje
+    async fn post_message(&self, payload: Value) -> Result<String> {
+        let url = format!("{}/{}/messages", GRAPH_API_URL, self.phone_number_id);
+
+        let response = self.client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .json(&payload)
+            .send()
+            .await
+            .context("Failed to reach WhatsApp Cloud API")?;
+
+        let result: SendResponse = response.json()
+            .await
+            .context("Failed to parse Cloud API response")?;
+
+        if let Some(error) = result.error {
+            anyhow::bail!("Cloud API send failed: {}", error.message);
+        }
+
+        result.messages.into_iter()
+            .next()
+            .map(|message| message.id)
+            .context("Cloud API did not return a message id")
+    }
+}
 
 #[async_trait]
 impl WhatsAppProvider for OfficialProvider {
-    async fn send_message(&self, _phone: String, _message: String) -> anyhow::Result<String> {
-        Ok("msg_123".to_string())
+    async fn send_message(&self, to: String, message: String) -> Result<String> {
+        self.post_message(json!({
+            "messaging_product": "whatsapp",
+            "to": to,
+            "type": "text",
+            "text": { "body": message }
+        }))
+        .await
+    }
+
+    async fn send_media(&self, to: String, media_url: String, media_type: String) -> Result<String> {
+        self.post_message(json!({
+            "messaging_product": "whatsapp",
+            "to": to,
+            "type": media_type,
+            media_type.as_str(): { "link": media_url }
+        }))
+        .await
+    }
+
+    async fn send_interactive(&self, to: String, message: InteractiveMessage) -> Result<String> {
+        self.post_message(interactive_payload(&to, &message)).await
     }
 
-    async fn get_qr(&self) -> anyhow::Result<String> {
-        Ok("qr_code_data".to_string())
+    async fn get_qr(&self) -> Result<String> {
+        anyhow::bail!("Cloud API numbers are not linked with a QR code")
     }
 
-    async fn get_status(&self) -> anyhow::Result<String> {
+    async fn get_status(&self) -> Result<String> {
         Ok("connected".to_string())
     }
+
+    async fn disconnect(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Body de un mensaje `interactive` (`button` o `list`)
+fn interactive_payload(to: &str, message: &InteractiveMessage) -> Value {
+    let interactive = match message {
+        InteractiveMessage::Buttons { body, buttons } => json!({
+            "type": "button",
+            "body": { "text": body },
+            "action": {
+                "buttons": buttons.iter()
+                    .map(|b| json!({ "type": "reply", "reply": { "id": b.id, "title": b.title } }))
+                    .collect::<Vec<_>>()
+            }
+        }),
+        InteractiveMessage::List { body, button, sections } => json!({
+            "type": "list",
+            "body": { "text": body },
+            "action": {
+                "button": button,
+                "sections": sections.iter()
+                    .map(|section| {
+                        let rows: Vec<Value> = section.rows.iter()
+                            .map(|row| match &row.description {
+                                Some(description) => json!({ "id": row.id, "title": row.title, "description": description }),
+                                None => json!({ "id": row.id, "title": row.title }),
+                            })
+                            .collect();
+                        // Con varias secciones todas necesitan título
+                        match (&section.title, sections.len()) {
+                            (Some(title), _) => json!({ "title": title, "rows": rows }),
+                            (None, 1) => json!({ "rows": rows }),
+                            (None, _) => json!({ "title": "Opciones", "rows": rows }),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            }
+        }),
+    };
+
+    json!({
+        "messaging_product": "whatsapp",
+        "recipient_type": "individual",
+        "to": to,
+        "type": "interactive",
+        "interactive": interactive
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{ListRow, ListSection, ReplyButton};
+
+    #[test]
+    fn test_interactive_payloads() {
+        let buttons = InteractiveMessage::Buttons {
+            body: "¿Qué deseas?".to_string(),
+            buttons: vec![ReplyButton { id: "1".to_string(), title: "Catálogo".to_string() }],
+        };
+        let payload = interactive_payload("584141234567", &buttons);
+        assert_eq!(payload["interactive"]["type"], "button");
+        assert_eq!(payload["interactive"]["action"]["buttons"][0]["reply"]["id"], "1");
+
+        let list = InteractiveMessage::List {
+            body: "Elige".to_string(),
+            button: "Ver opciones".to_string(),
+            sections: vec![
+                ListSection {
+                    title: Some("Flores".to_string()),
+                    rows: vec![ListRow { id: "rosas".to_string(), title: "Rosas".to_string(), description: None }],
+                },
+                ListSection {
+                    title: None,
+                    rows: vec![ListRow {
+                        id: "globos".to_string(),
+                        title: "Globos".to_string(),
+                        description: Some("Helio".to_string()),
+                    }],
+                },
+            ],
+        };
+        let payload = interactive_payload("584141234567", &list);
+        let sections = &payload["interactive"]["action"]["sections"];
+        assert_eq!(payload["interactive"]["action"]["button"], "Ver opciones");
+        assert_eq!(sections[1]["title"], "Opciones");
+        assert_eq!(sections[1]["rows"][0]["description"], "Helio");
+    }
 }
@@ -187,6 +187,20 @@ impl WhatsAppProvider for VenomProvider {
         }
     }
 
+    async fn connect(&self) -> Result<()> {
+        let url = format!("{}/qr/{}", self.bridge_url, self.session_name);
+
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to start Venom session")?
+            .error_for_status()
+            .context("Venom bridge rejected the session")?;
+
+        Ok(())
+    }
+
     async fn disconnect(&self) -> Result<()> {
         let url = format\!("{}/session/{}", self.bridge_url, self.session_name);
         
@@ -169,6 +169,20 @@ impl WhatsAppProvider for WWebJSProvider {
         }
     }
 
+    async fn connect(&self) -> Result<()> {
+        let url = format!("{}/qr/{}", self.bridge_url, self.session_id);
+
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to start WWebJS session")?
+            .error_for_status()
+            .context("WWebJS bridge rejected the session")?;
+
+        Ok(())
+    }
+
     async fn disconnect(&self) -> Result<()> {
         let url = format\!("{}/session/{}", self.bridge_url, self.session_id);
         